@@ -5,4 +5,5 @@ pub mod wba_prereq;
 #[cfg(feature = "client")]
 pub mod wba_vault;
 
-pub mod wba_vault_program;
\ No newline at end of file
+// The on-chain vault program lives in its own crate under `vault-pg`.
+pub use wba_vault_program;
\ No newline at end of file
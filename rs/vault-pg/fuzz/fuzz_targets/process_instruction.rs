@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use wba_vault_fuzz::{check, FuzzInput};
+
+fuzz_target!(|input: FuzzInput| {
+    if let Err(violation) = check(&input) {
+        panic!("{violation:?} for {input:#?}");
+    }
+});
@@ -0,0 +1,691 @@
+//! Fuzzing harness for `wba_vault_program::process_instruction`.
+//!
+//! An input picks instruction data and an account list from a small world of
+//! related keys: an initialized vault, a vault owned by a stranger, a mint
+//! with the owner's, stranger's and both vaults' ATAs, a ledger and the
+//! programs. Any account's owner, lamports and data can be overridden. The
+//! accounts are serialized the way the BPF loader lays them out, so repeated
+//! keys and reallocs behave as on chain, and CPIs into the system and token
+//! programs are emulated (see `runtime`).
+//!
+//! `check` runs one input and, when the instruction succeeds, asserts:
+//!
+//! - no lamports are created or destroyed across the accounts passed;
+//! - SOL only leaves a vault PDA when a valid vault_state for it is passed
+//!   and its owner (or a multisig member) signed, except for the
+//!   instructions in `authorized_by_record`;
+//! - tokens only leave an ATA owned by a vault_auth PDA when a valid
+//!   vault_state for it is passed.
+
+mod runtime;
+
+use std::sync::OnceLock;
+
+use arbitrary::Arbitrary;
+use borsh::BorshDeserialize;
+use bytemuck::Zeroable;
+use solana_program::{
+    clock::Clock, program_option::COption, program_pack::Pack, pubkey, pubkey::Pubkey, rent::Rent,
+    system_program,
+};
+use spl_associated_token_account::get_associated_token_address;
+use spl_token::state::{Account as TokenAccount, AccountState as TokenState, Mint};
+use wba_vault_program::{id, Custody, Ledger, Vault, WbaVaultInstruction, ZeroCopy};
+
+pub use runtime::AccountState;
+
+/// Number of `WbaVaultInstruction` variants; `FuzzInstruction::Fields` tags
+/// are taken modulo this so most inputs decode.
+const INSTRUCTION_COUNT: u8 = 52;
+/// The runtime's cap on accounts per instruction is far higher, but no
+/// handler reads more than this.
+const MAX_ACCOUNTS: usize = 24;
+
+const NATIVE_LOADER: Pubkey = pubkey!("NativeLoader1111111111111111111111111111111");
+const BPF_LOADER: Pubkey = pubkey!("BPFLoader2111111111111111111111111111111111");
+
+const OWNER: usize = 0;
+const MEMBER: usize = 1;
+const STRANGER: usize = 2;
+const VAULT_STATE: usize = 3;
+const VAULT_AUTH: usize = 4;
+const VAULT: usize = 5;
+const FOREIGN_STATE: usize = 6;
+const FOREIGN_AUTH: usize = 7;
+const FOREIGN_VAULT: usize = 8;
+const MINT: usize = 9;
+const OWNER_ATA: usize = 10;
+const VAULT_ATA: usize = 11;
+const STRANGER_ATA: usize = 12;
+const FOREIGN_ATA: usize = 13;
+const LEDGER: usize = 14;
+const CONFIG: usize = 15;
+const SYSTEM_PROGRAM: usize = 16;
+const TOKEN_PROGRAM: usize = 17;
+const ATA_PROGRAM: usize = 18;
+const WORLD_SIZE: usize = 19;
+
+/// One fuzz case.
+#[derive(Arbitrary, Debug)]
+pub struct FuzzInput {
+    pub instruction: FuzzInstruction,
+    pub accounts: FuzzAccounts,
+    /// Replaces the world's default state of `key % WORLD_SIZE`.
+    pub overrides: Vec<(u8, FuzzAccount)>,
+    pub unix_timestamp: i64,
+    pub slot: u64,
+}
+
+#[derive(Arbitrary, Debug)]
+pub enum FuzzInstruction {
+    /// An instruction tag followed by Borsh-encoded fields, so keys and
+    /// lengths can come from the world rather than random bytes.
+    Fields {
+        tag: u8,
+        fields: Vec<FuzzField>,
+    },
+    Raw(Vec<u8>),
+}
+
+#[derive(Arbitrary, Debug)]
+pub enum FuzzField {
+    Key(u8),
+    U64(u64),
+    Byte(u8),
+    /// A `Vec` length prefix.
+    Len(u8),
+    Bytes([u8; 32]),
+}
+
+#[derive(Arbitrary, Debug)]
+pub enum FuzzAccounts {
+    List(Vec<FuzzMeta>),
+    /// A handler's usual account list with some positions replaced, which
+    /// gets far deeper than random lists.
+    Template {
+        template: FuzzTemplate,
+        edits: Vec<(u8, FuzzMeta)>,
+    },
+}
+
+#[derive(Arbitrary, Clone, Copy, Debug)]
+pub enum FuzzTemplate {
+    /// `Deposit` and `Withdraw`.
+    Sol,
+    /// `DepositSpl` and `WithdrawSpl`.
+    Spl,
+    Reconcile,
+    CloseAccount,
+}
+
+#[derive(Arbitrary, Clone, Copy, Debug)]
+pub struct FuzzMeta {
+    pub key: u8,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+#[derive(Arbitrary, Debug)]
+pub struct FuzzAccount {
+    pub owner: FuzzOwner,
+    pub lamports: u64,
+    pub data: FuzzData,
+}
+
+#[derive(Arbitrary, Debug)]
+pub enum FuzzOwner {
+    System,
+    Program,
+    Token,
+    Key(u8),
+}
+
+#[derive(Arbitrary, Debug)]
+pub enum FuzzData {
+    Empty,
+    Raw(Vec<u8>),
+    Vault(FuzzVault),
+    Token {
+        mint: u8,
+        owner: u8,
+        amount: u64,
+        delegate: Option<(u8, u64)>,
+    },
+    Mint {
+        authority: Option<u8>,
+        supply: u64,
+        decimals: u8,
+    },
+    Ledger {
+        vault_state: u8,
+        mint: u8,
+        balance: u64,
+    },
+}
+
+#[derive(Arbitrary, Debug)]
+pub struct FuzzVault {
+    pub owner: u8,
+    /// Derives the PDAs from this key instead of the account's own.
+    pub bumps_for: Option<u8>,
+    pub signers: Vec<u8>,
+    pub threshold: u8,
+    pub program_custody: bool,
+    pub locked_until: i64,
+    pub pool_count: u32,
+    pub receipts: bool,
+}
+
+/// An invariant broken by an instruction that succeeded.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Violation {
+    LamportsNotConserved { before: u128, after: u128 },
+    UnauthorizedSolOutflow { vault: Pubkey, lamports: u64 },
+    UnauthorizedTokenOutflow { account: Pubkey, amount: u64 },
+}
+
+struct World {
+    keys: [Pubkey; WORLD_SIZE],
+    /// `(vault_state, vault_auth, vault)` of both vaults.
+    vaults: [(usize, usize, usize); 2],
+}
+
+fn world() -> &'static World {
+    static WORLD: OnceLock<World> = OnceLock::new();
+    WORLD.get_or_init(|| {
+        let mut keys = [Pubkey::default(); WORLD_SIZE];
+        for (key, index) in [OWNER, MEMBER, STRANGER, VAULT_STATE, FOREIGN_STATE, MINT]
+            .into_iter()
+            .zip(1u8..)
+        {
+            keys[key] = keypair_address(index);
+        }
+        for (state, auth, vault) in [
+            (VAULT_STATE, VAULT_AUTH, VAULT),
+            (FOREIGN_STATE, FOREIGN_AUTH, FOREIGN_VAULT),
+        ] {
+            keys[auth] = Pubkey::find_program_address(&[b"auth", keys[state].as_ref()], &id()).0;
+            keys[vault] = Pubkey::find_program_address(&[b"vault", keys[auth].as_ref()], &id()).0;
+        }
+        keys[OWNER_ATA] = get_associated_token_address(&keys[OWNER], &keys[MINT]);
+        keys[VAULT_ATA] = get_associated_token_address(&keys[VAULT_AUTH], &keys[MINT]);
+        keys[STRANGER_ATA] = get_associated_token_address(&keys[STRANGER], &keys[MINT]);
+        keys[FOREIGN_ATA] = get_associated_token_address(&keys[FOREIGN_AUTH], &keys[MINT]);
+        keys[LEDGER] = Pubkey::find_program_address(
+            &[b"ledger", keys[VAULT_STATE].as_ref(), keys[MINT].as_ref()],
+            &id(),
+        )
+        .0;
+        keys[CONFIG] = Pubkey::find_program_address(&[b"config"], &id()).0;
+        keys[SYSTEM_PROGRAM] = system_program::id();
+        keys[TOKEN_PROGRAM] = spl_token::id();
+        keys[ATA_PROGRAM] = spl_associated_token_account::id();
+        World {
+            keys,
+            vaults: [
+                (VAULT_STATE, VAULT_AUTH, VAULT),
+                (FOREIGN_STATE, FOREIGN_AUTH, FOREIGN_VAULT),
+            ],
+        }
+    })
+}
+
+/// A key that could belong to a keypair, so it can sign, unlike a PDA.
+fn keypair_address(index: u8) -> Pubkey {
+    (0..=u8::MAX)
+        .map(|salt| {
+            let mut bytes = [index; 32];
+            bytes[0] = salt;
+            Pubkey::new_from_array(bytes)
+        })
+        .find(Pubkey::is_on_curve)
+        .unwrap()
+}
+
+impl World {
+    fn key(&self, index: u8) -> Pubkey {
+        self.keys[usize::from(index) % WORLD_SIZE]
+    }
+
+    /// The state every account starts in unless the input overrides it.
+    fn default_account(&self, index: usize) -> AccountState {
+        let k = &self.keys;
+        let wallet = |lamports| AccountState {
+            owner: system_program::id(),
+            lamports,
+            data: Vec::new(),
+            executable: false,
+        };
+        match index {
+            OWNER | MEMBER | STRANGER => wallet(10_000_000_000),
+            VAULT | FOREIGN_VAULT => wallet(5_000_000_000),
+            VAULT_AUTH | FOREIGN_AUTH | CONFIG => wallet(0),
+            VAULT_STATE => self.vault_account(&FuzzVault::default_for(OWNER), VAULT_STATE),
+            FOREIGN_STATE => self.vault_account(&FuzzVault::default_for(STRANGER), FOREIGN_STATE),
+            MINT => token_account(Mint {
+                mint_authority: COption::Some(k[OWNER]),
+                supply: 4_000,
+                decimals: 0,
+                is_initialized: true,
+                freeze_authority: COption::None,
+            }),
+            OWNER_ATA => self.token(OWNER, 1_000, None),
+            VAULT_ATA => self.token(VAULT_AUTH, 1_000, None),
+            STRANGER_ATA => self.token(STRANGER, 1_000, None),
+            FOREIGN_ATA => self.token(FOREIGN_AUTH, 1_000, None),
+            LEDGER => self.account(
+                LEDGER,
+                &FuzzAccount {
+                    owner: FuzzOwner::Program,
+                    lamports: Rent::default().minimum_balance(Ledger::SPACE),
+                    data: FuzzData::Ledger {
+                        vault_state: VAULT_STATE as u8,
+                        mint: MINT as u8,
+                        balance: 1_000,
+                    },
+                },
+            ),
+            SYSTEM_PROGRAM => AccountState {
+                owner: NATIVE_LOADER,
+                lamports: 1,
+                data: b"system_program".to_vec(),
+                executable: true,
+            },
+            TOKEN_PROGRAM | ATA_PROGRAM => AccountState {
+                owner: BPF_LOADER,
+                lamports: 1,
+                data: Vec::new(),
+                executable: true,
+            },
+            _ => unreachable!("world has {WORLD_SIZE} keys"),
+        }
+    }
+
+    fn token(&self, owner: usize, amount: u64, delegate: Option<(u8, u64)>) -> AccountState {
+        token_account(TokenAccount {
+            mint: self.keys[MINT],
+            owner: self.keys[owner],
+            amount,
+            delegate: delegate.map_or(COption::None, |(key, _)| COption::Some(self.key(key))),
+            state: TokenState::Initialized,
+            is_native: COption::None,
+            delegated_amount: delegate.map_or(0, |(_, amount)| amount),
+            close_authority: COption::None,
+        })
+    }
+
+    fn vault_account(&self, spec: &FuzzVault, index: usize) -> AccountState {
+        let state_key = spec.bumps_for.map_or(self.keys[index], |key| self.key(key));
+        let (auth, auth_bump) = Pubkey::find_program_address(&[b"auth", state_key.as_ref()], &id());
+        let (_, vault_bump) = Pubkey::find_program_address(&[b"vault", auth.as_ref()], &id());
+        let mut vault = Vault::zeroed();
+        vault.owner = self.key(spec.owner);
+        vault.auth_bump = auth_bump;
+        vault.vault_bump = vault_bump;
+        vault.threshold = spec.threshold;
+        vault.locked_until = spec.locked_until;
+        vault.pool_count = spec.pool_count;
+        vault.receipts = spec.receipts.into();
+        let signers: Vec<_> = spec.signers.iter().map(|&key| self.key(key)).collect();
+        vault
+            .set_signers(&signers[..signers.len().min(Vault::MAX_SIGNERS)])
+            .unwrap();
+        vault.set_custody(if spec.program_custody {
+            Custody::ProgramOwned
+        } else {
+            Custody::SystemOwned
+        });
+        // The foreign vault keeps the original Borsh layout, which only holds
+        // the owner, bumps and score, so both stay covered.
+        let data = if index == FOREIGN_STATE {
+            bytemuck::bytes_of(&vault)[..Vault::LEGACY_LEN].to_vec()
+        } else {
+            let mut data = vec![0; Vault::SPACE];
+            vault.init(&mut data).unwrap();
+            data
+        };
+        AccountState {
+            owner: id(),
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            executable: false,
+        }
+    }
+
+    fn account(&self, index: usize, spec: &FuzzAccount) -> AccountState {
+        let mut state = match &spec.data {
+            FuzzData::Empty => AccountState {
+                owner: system_program::id(),
+                lamports: 0,
+                data: Vec::new(),
+                executable: false,
+            },
+            FuzzData::Raw(data) => AccountState {
+                owner: system_program::id(),
+                lamports: 0,
+                data: data.clone(),
+                executable: false,
+            },
+            FuzzData::Vault(vault) => self.vault_account(vault, index),
+            FuzzData::Token {
+                mint,
+                owner,
+                amount,
+                delegate,
+            } => {
+                let mut state = self.token(OWNER, *amount, *delegate);
+                let mut account = TokenAccount::unpack(&state.data).unwrap();
+                account.mint = self.key(*mint);
+                account.owner = self.key(*owner);
+                account.pack_into_slice(&mut state.data);
+                state
+            }
+            FuzzData::Mint {
+                authority,
+                supply,
+                decimals,
+            } => token_account(Mint {
+                mint_authority: authority.map_or(COption::None, |key| COption::Some(self.key(key))),
+                supply: *supply,
+                decimals: *decimals,
+                is_initialized: true,
+                freeze_authority: COption::None,
+            }),
+            FuzzData::Ledger {
+                vault_state,
+                mint,
+                balance,
+            } => {
+                let vault_state = self.key(*vault_state);
+                let mint = self.key(*mint);
+                let (_, bump) = Pubkey::find_program_address(
+                    &[b"ledger", vault_state.as_ref(), mint.as_ref()],
+                    &id(),
+                );
+                let mut ledger = Ledger::default();
+                ledger.vault_state = vault_state;
+                ledger.mint = mint;
+                ledger.balance = *balance;
+                ledger.first_deposit_slot = 1;
+                ledger.last_deposit_slot = 1;
+                ledger.bump = bump;
+                let mut data = vec![0; Ledger::SPACE];
+                ledger.init(&mut data).unwrap();
+                AccountState {
+                    owner: id(),
+                    lamports: 0,
+                    data,
+                    executable: false,
+                }
+            }
+        };
+        state.owner = match spec.owner {
+            FuzzOwner::System => system_program::id(),
+            FuzzOwner::Program => id(),
+            FuzzOwner::Token => spl_token::id(),
+            FuzzOwner::Key(key) => self.key(key),
+        };
+        state.lamports = spec.lamports;
+        state
+    }
+
+    /// The vault that `vault_state` describes, if it is one the program
+    /// would accept: owned by the program, decodable, and with bumps that
+    /// derive this world's PDAs for it.
+    fn valid_vault(
+        &self,
+        accounts: &[(Pubkey, AccountState)],
+        vault_index: usize,
+    ) -> Option<Vault> {
+        let (state, auth, vault) = self.vaults[vault_index];
+        let (_, account) = accounts.iter().find(|(key, _)| key == &self.keys[state])?;
+        if account.owner != id() {
+            return None;
+        }
+        let data = Vault::unpack(&account.data).ok()?;
+        let derived_auth = Pubkey::create_program_address(
+            &[b"auth", self.keys[state].as_ref(), &[data.auth_bump]],
+            &id(),
+        );
+        let derived_vault = Pubkey::create_program_address(
+            &[b"vault", self.keys[auth].as_ref(), &[data.vault_bump]],
+            &id(),
+        );
+        (derived_auth == Ok(self.keys[auth]) && derived_vault == Ok(self.keys[vault]))
+            .then_some(data)
+    }
+}
+
+impl FuzzVault {
+    fn default_for(owner: usize) -> Self {
+        FuzzVault {
+            owner: owner as u8,
+            bumps_for: None,
+            signers: Vec::new(),
+            threshold: 0,
+            program_custody: false,
+            locked_until: 0,
+            pool_count: 0,
+            receipts: false,
+        }
+    }
+}
+
+fn token_account<T: Pack>(state: T) -> AccountState {
+    let mut data = vec![0; T::LEN];
+    state.pack_into_slice(&mut data);
+    AccountState {
+        owner: spl_token::id(),
+        lamports: Rent::default().minimum_balance(T::LEN),
+        data,
+        executable: false,
+    }
+}
+
+impl FuzzTemplate {
+    fn metas(self) -> Vec<FuzzMeta> {
+        let meta = |key: usize, is_signer, is_writable| FuzzMeta {
+            key: key as u8,
+            is_signer,
+            is_writable,
+        };
+        match self {
+            FuzzTemplate::Sol => vec![
+                meta(OWNER, true, true),
+                meta(VAULT_STATE, false, true),
+                meta(VAULT_AUTH, false, false),
+                meta(VAULT, false, true),
+                meta(SYSTEM_PROGRAM, false, false),
+            ],
+            FuzzTemplate::Spl => vec![
+                meta(OWNER, true, true),
+                meta(OWNER_ATA, false, true),
+                meta(VAULT_STATE, false, true),
+                meta(VAULT_AUTH, false, false),
+                meta(VAULT_ATA, false, true),
+                meta(MINT, false, false),
+                meta(TOKEN_PROGRAM, false, false),
+                meta(ATA_PROGRAM, false, false),
+                meta(SYSTEM_PROGRAM, false, false),
+                meta(LEDGER, false, true),
+            ],
+            FuzzTemplate::Reconcile => vec![
+                meta(OWNER, true, true),
+                meta(VAULT_STATE, false, false),
+                meta(VAULT_AUTH, false, false),
+                meta(VAULT_ATA, false, false),
+                meta(MINT, false, false),
+                meta(LEDGER, false, true),
+                meta(SYSTEM_PROGRAM, false, false),
+            ],
+            FuzzTemplate::CloseAccount => vec![
+                meta(OWNER, true, true),
+                meta(OWNER, true, true),
+                meta(VAULT_STATE, false, true),
+                meta(SYSTEM_PROGRAM, false, false),
+            ],
+        }
+    }
+}
+
+impl FuzzAccounts {
+    fn metas(&self) -> Vec<FuzzMeta> {
+        let mut metas = match self {
+            FuzzAccounts::List(metas) => metas.clone(),
+            FuzzAccounts::Template { template, edits } => {
+                let mut metas = template.metas();
+                for (position, meta) in edits {
+                    let len = metas.len();
+                    metas[usize::from(*position) % len] = *meta;
+                }
+                metas
+            }
+        };
+        metas.truncate(MAX_ACCOUNTS);
+        metas
+    }
+}
+
+impl FuzzInstruction {
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            FuzzInstruction::Raw(data) => data.clone(),
+            FuzzInstruction::Fields { tag, fields } => {
+                let mut data = vec![tag % INSTRUCTION_COUNT];
+                for field in fields {
+                    match field {
+                        FuzzField::Key(key) => data.extend_from_slice(world().key(*key).as_ref()),
+                        FuzzField::U64(value) => data.extend_from_slice(&value.to_le_bytes()),
+                        FuzzField::Byte(value) => data.push(*value),
+                        FuzzField::Len(len) => {
+                            data.extend_from_slice(&u32::from(*len).to_le_bytes())
+                        }
+                        FuzzField::Bytes(bytes) => data.extend_from_slice(bytes),
+                    }
+                }
+                data
+            }
+        }
+    }
+}
+
+/// Instructions that pay a vault out on an authorization recorded earlier
+/// rather than on this transaction's signatures: approved proposals,
+/// delegate allowances, Ed25519 permits and pool shares.
+fn authorized_by_record(instruction_data: &[u8]) -> bool {
+    matches!(
+        WbaVaultInstruction::try_from_slice(instruction_data),
+        Ok(WbaVaultInstruction::Execute
+            | WbaVaultInstruction::DelegateWithdraw { .. }
+            | WbaVaultInstruction::WithdrawWithPermit { .. }
+            | WbaVaultInstruction::RedeemShares { .. })
+    )
+}
+
+/// Runs `input` and checks the invariants listed in the crate docs. Errors
+/// from the program are expected and pass; panics propagate.
+pub fn check(input: &FuzzInput) -> Result<(), Violation> {
+    let world = world();
+    let mut states: Vec<AccountState> = (0..WORLD_SIZE)
+        .map(|index| world.default_account(index))
+        .collect();
+    for (index, spec) in &input.overrides {
+        let index = usize::from(*index) % WORLD_SIZE;
+        states[index] = world.account(index, spec);
+    }
+
+    let metas = input.accounts.metas();
+    let accounts: Vec<_> = metas
+        .iter()
+        .map(|meta| {
+            let index = usize::from(meta.key) % WORLD_SIZE;
+            let key = world.keys[index];
+            runtime::InputAccount {
+                key,
+                // Nobody holds a private key for a PDA or a program.
+                is_signer: meta.is_signer && key.is_on_curve(),
+                is_writable: meta.is_writable,
+                state: &states[index],
+            }
+        })
+        .collect();
+    let mut before: Vec<(Pubkey, AccountState)> = Vec::new();
+    for account in &accounts {
+        if !before.iter().any(|(key, _)| key == &account.key) {
+            before.push((account.key, account.state.clone()));
+        }
+    }
+
+    let data = input.instruction.encode();
+    let clock = Clock {
+        slot: input.slot,
+        unix_timestamp: input.unix_timestamp,
+        ..Clock::default()
+    };
+    let (result, after) = runtime::process(&accounts, &data, clock);
+    if result.is_err() {
+        return Ok(());
+    }
+
+    let total = |accounts: &[(Pubkey, AccountState)]| -> u128 {
+        accounts
+            .iter()
+            .map(|(_, state)| u128::from(state.lamports))
+            .sum()
+    };
+    if total(&before) != total(&after) {
+        return Err(Violation::LamportsNotConserved {
+            before: total(&before),
+            after: total(&after),
+        });
+    }
+
+    for (vault_index, &(_, auth, vault)) in world.vaults.iter().enumerate() {
+        let valid = world.valid_vault(&before, vault_index);
+
+        // Vault PDAs are created without data, so one that carries some is
+        // an override the program could never have produced.
+        let lamports = |accounts: &[(Pubkey, AccountState)]| {
+            accounts
+                .iter()
+                .find(|(key, state)| key == &world.keys[vault] && state.data.is_empty())
+                .map(|(_, state)| state.lamports)
+        };
+        if let (Some(was), Some(now)) = (lamports(&before), lamports(&after)) {
+            let authorized = valid.as_ref().is_some_and(|vault| {
+                authorized_by_record(&data)
+                    || accounts
+                        .iter()
+                        .any(|a| a.is_signer && vault.is_member(&a.key))
+            });
+            if now < was && !authorized {
+                return Err(Violation::UnauthorizedSolOutflow {
+                    vault: world.keys[vault],
+                    lamports: was - now,
+                });
+            }
+        }
+
+        for (key, was) in &before {
+            let Ok(held) = TokenAccount::unpack(&was.data) else {
+                continue;
+            };
+            if was.owner != spl_token::id() || held.owner != world.keys[auth] {
+                continue;
+            }
+            let now = after
+                .iter()
+                .find(|(after_key, _)| after_key == key)
+                .filter(|(_, state)| state.owner == spl_token::id())
+                .and_then(|(_, state)| TokenAccount::unpack(&state.data).ok())
+                .map_or(0, |account| account.amount);
+            if now < held.amount && valid.is_none() {
+                return Err(Violation::UnauthorizedTokenOutflow {
+                    account: *key,
+                    amount: held.amount - now,
+                });
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,287 @@
+//! Just enough of the Solana runtime to run `process_instruction` natively:
+//! the BPF loader's input serialization, the clock and rent sysvars, and
+//! CPIs into the system and token programs.
+
+use std::{cell::RefCell, mem::size_of, sync::Once};
+
+use solana_program::{
+    account_info::AccountInfo,
+    clock::Clock,
+    entrypoint::{self, ProgramResult, MAX_PERMITTED_DATA_INCREASE, NON_DUP_MARKER, SUCCESS},
+    instruction::Instruction,
+    program_error::ProgramError,
+    program_stubs::{set_syscall_stubs, SyscallStubs},
+    program_utils::limited_deserialize,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction::SystemInstruction,
+    system_program,
+};
+
+/// An account as the runtime holds it between instructions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccountState {
+    pub owner: Pubkey,
+    pub lamports: u64,
+    pub data: Vec<u8>,
+    pub executable: bool,
+}
+
+/// One entry of the instruction's account list.
+pub(crate) struct InputAccount<'a> {
+    pub key: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+    pub state: &'a AccountState,
+}
+
+thread_local! {
+    static CLOCK: RefCell<Clock> = RefCell::new(Clock::default());
+}
+
+/// Runs the program over `accounts` and returns its result together with the
+/// final state of every distinct key, in order of first appearance.
+///
+/// As on chain, accounts sharing a key share their lamports and data, and
+/// `instruction_data` goes through the program's own entrypoint decoding.
+pub(crate) fn process(
+    accounts: &[InputAccount],
+    instruction_data: &[u8],
+    clock: Clock,
+) -> (ProgramResult, Vec<(Pubkey, AccountState)>) {
+    static STUBS: Once = Once::new();
+    STUBS.call_once(|| {
+        set_syscall_stubs(Box::new(Runtime));
+    });
+    CLOCK.with(|cell| *cell.borrow_mut() = clock);
+
+    let mut input = serialize(accounts, instruction_data, &wba_vault_program::id());
+    // SAFETY: `input` is 8-byte aligned, laid out by `serialize` the way
+    // `deserialize` reads it, and outlives every reference handed out.
+    let (program_id, infos, data) =
+        unsafe { entrypoint::deserialize(input.as_mut_ptr() as *mut u8) };
+    let result = wba_vault_program::process_instruction(program_id, &infos, data);
+
+    let mut after: Vec<(Pubkey, AccountState)> = Vec::new();
+    for info in &infos {
+        if after.iter().any(|(key, _)| key == info.key) {
+            continue;
+        }
+        after.push((
+            *info.key,
+            AccountState {
+                owner: *info.owner,
+                lamports: info.lamports(),
+                data: info.data.borrow().to_vec(),
+                executable: info.executable,
+            },
+        ));
+    }
+    (result, after)
+}
+
+/// Lays the accounts out as the BPF loader does, including the realloc
+/// headroom after each account's data and one-byte markers for repeated keys.
+fn serialize(accounts: &[InputAccount], instruction_data: &[u8], program_id: &Pubkey) -> Vec<u64> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&(accounts.len() as u64).to_le_bytes());
+    for (position, account) in accounts.iter().enumerate() {
+        if let Some(first) = accounts[..position]
+            .iter()
+            .position(|a| a.key == account.key)
+        {
+            bytes.push(first as u8);
+            bytes.extend_from_slice(&[0; 7]);
+            continue;
+        }
+
+        // The runtime takes signer and writable privileges per key.
+        let is_signer = accounts.iter().any(|a| a.key == account.key && a.is_signer);
+        let is_writable = accounts
+            .iter()
+            .any(|a| a.key == account.key && a.is_writable);
+        let state = account.state;
+        bytes.extend_from_slice(&[
+            NON_DUP_MARKER,
+            is_signer as u8,
+            is_writable as u8,
+            state.executable as u8,
+        ]);
+        bytes.extend_from_slice(&[0; size_of::<u32>()]);
+        bytes.extend_from_slice(account.key.as_ref());
+        bytes.extend_from_slice(state.owner.as_ref());
+        bytes.extend_from_slice(&state.lamports.to_le_bytes());
+        bytes.extend_from_slice(&(state.data.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&state.data);
+        bytes.resize(bytes.len() + MAX_PERMITTED_DATA_INCREASE, 0);
+        bytes.resize(bytes.len().next_multiple_of(8), 0);
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+    }
+    bytes.extend_from_slice(&(instruction_data.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(instruction_data);
+    bytes.extend_from_slice(program_id.as_ref());
+
+    let mut words = vec![0u64; bytes.len().div_ceil(8)];
+    for (word, chunk) in words.iter_mut().zip(bytes.chunks(8)) {
+        let mut buf = [0; 8];
+        buf[..chunk.len()].copy_from_slice(chunk);
+        *word = u64::from_ne_bytes(buf);
+    }
+    words
+}
+
+struct Runtime;
+
+impl SyscallStubs for Runtime {
+    fn sol_log(&self, _message: &str) {}
+
+    fn sol_log_data(&self, _fields: &[&[u8]]) {}
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        // SAFETY: `Clock::get` passes a pointer to a `Clock`.
+        CLOCK
+            .with(|cell| unsafe { std::ptr::write(var_addr as *mut Clock, cell.borrow().clone()) });
+        SUCCESS
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        // SAFETY: `Rent::get` passes a pointer to a `Rent`.
+        unsafe { std::ptr::write(var_addr as *mut Rent, Rent::default()) };
+        SUCCESS
+    }
+
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let signers = signers_seeds
+            .iter()
+            .map(|seeds| Pubkey::create_program_address(seeds, &wba_vault_program::id()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| ProgramError::InvalidSeeds)?;
+
+        // The callee gets exactly the privileges the caller holds or signs for.
+        let mut callee = Vec::with_capacity(instruction.accounts.len());
+        for meta in &instruction.accounts {
+            let info = account_infos
+                .iter()
+                .find(|info| info.key == &meta.pubkey)
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+            if meta.is_signer && !info.is_signer && !signers.contains(&meta.pubkey) {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            if meta.is_writable && !info.is_writable {
+                return Err(ProgramError::InvalidArgument);
+            }
+            let mut info = info.clone();
+            info.is_signer = meta.is_signer;
+            info.is_writable = meta.is_writable;
+            callee.push(info);
+        }
+
+        if instruction.program_id == system_program::id() {
+            process_system(&callee, &instruction.data)
+        } else if instruction.program_id == spl_token::id() {
+            spl_token::processor::Processor::process(
+                &instruction.program_id,
+                &callee,
+                &instruction.data,
+            )
+        } else {
+            Err(ProgramError::IncorrectProgramId)
+        }
+    }
+}
+
+/// The system program instructions the vault uses.
+fn process_system(accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let instruction: SystemInstruction =
+        limited_deserialize(data, 1232).map_err(|_| ProgramError::InvalidInstructionData)?;
+    match instruction {
+        SystemInstruction::Transfer { lamports } => {
+            let [from, to] = accounts else {
+                return Err(ProgramError::NotEnoughAccountKeys);
+            };
+            if !from.data_is_empty() {
+                return Err(ProgramError::InvalidArgument);
+            }
+            debit(from, lamports)?;
+            credit(to, lamports)
+        }
+        SystemInstruction::CreateAccount {
+            lamports,
+            space,
+            owner,
+        } => {
+            let [from, to] = accounts else {
+                return Err(ProgramError::NotEnoughAccountKeys);
+            };
+            if to.lamports() > 0 {
+                return Err(ProgramError::AccountAlreadyInitialized);
+            }
+            allocate(to, space)?;
+            debit(from, lamports)?;
+            credit(to, lamports)?;
+            to.assign(&owner);
+            Ok(())
+        }
+        SystemInstruction::Allocate { space } => {
+            let [account] = accounts else {
+                return Err(ProgramError::NotEnoughAccountKeys);
+            };
+            allocate(account, space)
+        }
+        SystemInstruction::Assign { owner } => {
+            let [account] = accounts else {
+                return Err(ProgramError::NotEnoughAccountKeys);
+            };
+            assert_system_signer(account)?;
+            account.assign(&owner);
+            Ok(())
+        }
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+
+fn assert_system_signer(account: &AccountInfo) -> ProgramResult {
+    if !account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !account.is_writable || account.owner != &system_program::id() {
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+fn allocate(account: &AccountInfo, space: u64) -> ProgramResult {
+    assert_system_signer(account)?;
+    if !account.data_is_empty() {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+    let space = usize::try_from(space).map_err(|_| ProgramError::InvalidRealloc)?;
+    account.realloc(space, true)
+}
+
+fn debit(account: &AccountInfo, lamports: u64) -> ProgramResult {
+    assert_system_signer(account)?;
+    let remaining = account
+        .lamports()
+        .checked_sub(lamports)
+        .ok_or(ProgramError::InsufficientFunds)?;
+    **account.lamports.borrow_mut() = remaining;
+    Ok(())
+}
+
+fn credit(account: &AccountInfo, lamports: u64) -> ProgramResult {
+    if !account.is_writable {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let credited = account
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    **account.lamports.borrow_mut() = credited;
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use std::{fs, path::Path};
+
+use arbitrary::{Arbitrary, Unstructured};
+use wba_vault_fuzz::{
+    check, FuzzAccount, FuzzAccounts, FuzzField, FuzzInput, FuzzInstruction, FuzzMeta, FuzzTemplate,
+};
+
+fn assert_holds(input: &FuzzInput) {
+    if let Err(violation) = check(input) {
+        panic!("{violation:?} for {input:#?}");
+    }
+}
+
+#[test]
+fn saved_crashes_stay_fixed() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("regressions");
+    let mut replayed = 0;
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|ext| ext == "md") {
+            continue;
+        }
+        // libFuzzer decodes its inputs the same way, so a saved crash file
+        // reproduces here byte for byte.
+        let bytes = fs::read(&path).unwrap();
+        if let Ok(input) = FuzzInput::arbitrary_take_rest(Unstructured::new(&bytes)) {
+            println!("replaying {}", path.display());
+            assert_holds(&input);
+            replayed += 1;
+        }
+    }
+    assert!(replayed > 0);
+}
+
+/// A fixed xorshift stream, so failures reproduce.
+struct Stream(u64);
+
+impl Stream {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    fn meta(&mut self) -> FuzzMeta {
+        FuzzMeta {
+            key: self.next() as u8,
+            is_signer: self.below(2) == 0,
+            is_writable: self.below(2) == 0,
+        }
+    }
+
+    /// Mostly small amounts and well-formed account lists, which reach far
+    /// more of each handler than uniform bytes do.
+    fn input(&mut self) -> FuzzInput {
+        let mut tag = self.next() as u8;
+        let mut fields: Vec<_> = (0..self.below(4))
+            .map(|_| match self.below(4) {
+                0 => FuzzField::Key(self.next() as u8),
+                1 => FuzzField::Byte(self.below(3) as u8),
+                2 => FuzzField::Len(self.below(3) as u8),
+                _ => FuzzField::U64(self.below(2_000)),
+            })
+            .collect();
+        let accounts = if self.below(5) == 0 {
+            FuzzAccounts::List((0..self.below(12)).map(|_| self.meta()).collect())
+        } else {
+            let template = [
+                FuzzTemplate::Sol,
+                FuzzTemplate::Spl,
+                FuzzTemplate::Reconcile,
+                FuzzTemplate::CloseAccount,
+            ][self.below(4) as usize];
+            let edits = (0..self.below(3))
+                .map(|_| (self.next() as u8, self.meta()))
+                .collect();
+            if self.below(2) == 0 {
+                // One of the instructions the template was written for.
+                let tags: &[u8] = match template {
+                    FuzzTemplate::Sol => &[1, 2],
+                    FuzzTemplate::Spl => &[3, 4],
+                    FuzzTemplate::Reconcile => &[50],
+                    FuzzTemplate::CloseAccount => &[7],
+                };
+                tag = tags[self.below(tags.len() as u64) as usize];
+                fields = match template {
+                    FuzzTemplate::Reconcile => vec![FuzzField::Byte(self.below(2) as u8)],
+                    FuzzTemplate::CloseAccount => Vec::new(),
+                    _ => vec![FuzzField::U64(self.below(2_000))],
+                };
+            }
+            FuzzAccounts::Template { template, edits }
+        };
+        let overrides = (0..self.below(3) / 2)
+            .filter_map(|_| {
+                let bytes: Vec<u8> = (0..64).map(|_| self.next() as u8).collect();
+                let account = FuzzAccount::arbitrary(&mut Unstructured::new(&bytes)).ok()?;
+                Some((self.next() as u8, account))
+            })
+            .collect();
+        FuzzInput {
+            instruction: FuzzInstruction::Fields { tag, fields },
+            accounts,
+            overrides,
+            unix_timestamp: 1_700_000_000 + self.below(1_000) as i64,
+            slot: self.below(1_000),
+        }
+    }
+}
+
+#[test]
+fn generated_inputs_hold_the_invariants() {
+    // `cargo fuzz` does the real exploration; this keeps the harness and
+    // the common paths exercised on every test run.
+    let mut stream = Stream(0x9e37_79b9_7f4a_7c15);
+    for _ in 0..2_000 {
+        assert_holds(&stream.input());
+    }
+}
@@ -0,0 +1,361 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::{Pod, Zeroable};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    assert_system_program,
+    checks::{assert_distinct, assert_writable},
+    create_pda_account, load_vault_state,
+    multisig::assert_threshold,
+    save_vault_state,
+    zero_copy::{self, PodBool, ZeroCopy},
+    Vault, WbaVaultError,
+};
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct AllowlistEntry {
+    pub address: Pubkey,
+    /// Entries only count from this timestamp on.
+    pub active_at: i64,
+}
+
+/// Config change that loosens the allowlist, applied once `active_at` passes.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct PendingAllowlistConfig {
+    pub enabled: PodBool,
+    _padding: [u8; 7],
+    pub add_delay_secs: i64,
+    pub active_at: i64,
+}
+
+/// Destination allowlist at `["allowlist", vaultState]`.
+///
+/// New entries and anything that loosens the config (disabling, a shorter
+/// delay) only take effect after `add_delay_secs`, so a stolen owner key
+/// cannot add its own address and withdraw straight away. Removing entries
+/// and tightening are immediate.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct Allowlist {
+    pub vault_state: Pubkey,
+    pub enabled: PodBool,
+    has_pending: PodBool,
+    entry_count: u8,
+    pub bump: u8,
+    _padding: [u8; 4],
+    pub add_delay_secs: i64,
+    pending: PendingAllowlistConfig,
+    entries: [AllowlistEntry; Allowlist::MAX_ENTRIES],
+}
+
+impl ZeroCopy for Allowlist {
+    const DISCRIMINATOR: [u8; 8] = *b"wbaallow";
+}
+
+impl PendingAllowlistConfig {
+    pub fn new(enabled: bool, add_delay_secs: i64, active_at: i64) -> Self {
+        Self {
+            enabled: enabled.into(),
+            _padding: [0; 7],
+            add_delay_secs,
+            active_at,
+        }
+    }
+}
+
+impl Allowlist {
+    pub const MAX_ENTRIES: usize = 16;
+
+    pub fn pending(&self) -> Option<&PendingAllowlistConfig> {
+        self.has_pending.get().then_some(&self.pending)
+    }
+
+    pub fn set_pending(&mut self, pending: Option<PendingAllowlistConfig>) {
+        self.has_pending = pending.is_some().into();
+        self.pending = pending.unwrap_or_else(PendingAllowlistConfig::zeroed);
+    }
+
+    pub fn entries(&self) -> &[AllowlistEntry] {
+        zero_copy::list(&self.entries, self.entry_count)
+    }
+
+    pub fn set_entries(&mut self, entries: &[AllowlistEntry]) -> ProgramResult {
+        zero_copy::set_list(&mut self.entries, &mut self.entry_count, entries)
+    }
+
+    /// Applies a pending config change whose delay has passed.
+    pub fn settle(&mut self, now: i64) {
+        if let Some(pending) = self.pending().copied() {
+            if now >= pending.active_at {
+                self.enabled = pending.enabled;
+                self.add_delay_secs = pending.add_delay_secs;
+                self.set_pending(None);
+            }
+        }
+    }
+
+    /// Whether `address` may receive funds at `now`.
+    pub fn allows(&self, address: &Pubkey, now: i64) -> bool {
+        let enabled = match self.pending() {
+            Some(pending) if now >= pending.active_at => pending.enabled,
+            _ => self.enabled,
+        };
+        !enabled.get()
+            || self
+                .entries()
+                .iter()
+                .any(|entry| entry.address == *address && now >= entry.active_at)
+    }
+}
+
+fn allowlist_address(program_id: &Pubkey, vault_state: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"allowlist", vault_state.as_ref()], program_id)
+}
+
+pub(crate) fn is_allowlist(
+    program_id: &Pubkey,
+    account: &AccountInfo,
+    vault_state: &Pubkey,
+) -> bool {
+    account.key == &allowlist_address(program_id, vault_state).0
+}
+
+fn load_allowlist(
+    program_id: &Pubkey,
+    vault_state: &AccountInfo,
+    allowlist: &AccountInfo,
+) -> Result<Allowlist, ProgramError> {
+    if allowlist.owner != program_id {
+        return Err(WbaVaultError::InvalidAllowlist.into());
+    }
+
+    let state = Allowlist::read(&allowlist.data.borrow())?;
+
+    if state.vault_state != *vault_state.key {
+        return Err(WbaVaultError::InvalidAllowlist.into());
+    }
+
+    Ok(state)
+}
+
+fn save_allowlist(allowlist: &AccountInfo, state: &Allowlist) -> ProgramResult {
+    state.write(&mut allowlist.data.borrow_mut())
+}
+
+/// Fails unless `recipient` is allowed to receive funds from the vault. Once a
+/// vault has an allowlist its PDA must be among `accounts`. It is looked up by
+/// address and conventionally goes last, after any optional accounts.
+///
+/// For token payouts pass the wallet, not the ATA: the ATA is derived from it.
+pub(crate) fn assert_allowed_recipient(
+    program_id: &Pubkey,
+    vault_state: &AccountInfo,
+    state: &Vault,
+    accounts: &[AccountInfo],
+    recipient: &Pubkey,
+) -> ProgramResult {
+    if !state.has_allowlist.get() {
+        return Ok(());
+    }
+
+    let (expected_allowlist, _bump) = allowlist_address(program_id, vault_state.key);
+    let allowlist = accounts
+        .iter()
+        .find(|account| account.key == &expected_allowlist)
+        .ok_or(WbaVaultError::MissingAllowlist)?;
+    if allowlist.owner != program_id {
+        return Err(WbaVaultError::InvalidAllowlist.into());
+    }
+    let data = allowlist.data.borrow();
+    let list = Allowlist::view(&data)?;
+    if list.vault_state != *vault_state.key {
+        return Err(WbaVaultError::InvalidAllowlist.into());
+    }
+
+    if !list.allows(recipient, Clock::get()?.unix_timestamp) {
+        msg!("Recipient {} is not on the allowlist", recipient);
+        return Err(WbaVaultError::RecipientNotAllowed.into());
+    }
+    Ok(())
+}
+
+/// Loads the vault and its allowlist for an admin instruction, creating the
+/// allowlist on first use.
+fn load_for_update<'a>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo<'a>],
+    owner: &AccountInfo<'a>,
+    vault_state: &AccountInfo<'a>,
+    allowlist: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+) -> Result<Allowlist, ProgramError> {
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[owner, vault_state, allowlist, system_program])?;
+    assert_writable(&[allowlist])?;
+
+    assert_system_program(system_program)?;
+    let mut state = load_vault_state(program_id, owner, vault_state)?;
+    assert_threshold(&state, accounts)?;
+
+    // allowlist PDA = ["allowlist", vaultState]
+    let (expected_allowlist, bump) = allowlist_address(program_id, vault_state.key);
+    if allowlist.key != &expected_allowlist {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+
+    if allowlist.owner == program_id {
+        let mut list = load_allowlist(program_id, vault_state, allowlist)?;
+        list.settle(Clock::get()?.unix_timestamp);
+        return Ok(list);
+    }
+
+    create_pda_account(
+        owner,
+        allowlist,
+        system_program,
+        Allowlist::SPACE,
+        program_id,
+        &[b"allowlist", vault_state.key.as_ref(), &[bump]],
+    )?;
+
+    state.has_allowlist = true.into();
+    save_vault_state(owner, vault_state, system_program, &state)?;
+
+    let list = Allowlist {
+        vault_state: *vault_state.key,
+        bump,
+        ..Allowlist::zeroed()
+    };
+    list.init(&mut allowlist.data.borrow_mut())?;
+    Ok(list)
+}
+
+pub(crate) fn set_allowlist_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    enabled: bool,
+    add_delay_secs: i64,
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let allowlist = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if add_delay_secs < 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let mut list = load_for_update(
+        program_id,
+        accounts,
+        owner,
+        vault_state,
+        allowlist,
+        system_program,
+    )?;
+
+    let loosens = (list.enabled.get() && !enabled) || add_delay_secs < list.add_delay_secs;
+    if loosens {
+        let active_at = Clock::get()?
+            .unix_timestamp
+            .saturating_add(list.add_delay_secs);
+        list.set_pending(Some(PendingAllowlistConfig::new(
+            enabled,
+            add_delay_secs,
+            active_at,
+        )));
+    } else {
+        list.enabled = enabled.into();
+        list.add_delay_secs = add_delay_secs;
+        list.set_pending(None);
+    }
+    save_allowlist(allowlist, &list)?;
+
+    msg!("Set allowlist config successful");
+    Ok(())
+}
+
+pub(crate) fn add_allowlist_entry(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    address: Pubkey,
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let allowlist = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    let mut list = load_for_update(
+        program_id,
+        accounts,
+        owner,
+        vault_state,
+        allowlist,
+        system_program,
+    )?;
+
+    if list.entries().iter().any(|entry| entry.address == address) {
+        return Err(WbaVaultError::InvalidAllowlist.into());
+    }
+    if list.entries().len() >= Allowlist::MAX_ENTRIES {
+        return Err(WbaVaultError::InvalidAllowlist.into());
+    }
+
+    let active_at = Clock::get()?
+        .unix_timestamp
+        .saturating_add(list.add_delay_secs);
+    let mut entries = list.entries().to_vec();
+    entries.push(AllowlistEntry { address, active_at });
+    list.set_entries(&entries)?;
+    save_allowlist(allowlist, &list)?;
+
+    msg!("Add allowlist entry successful: active at {}", active_at);
+    Ok(())
+}
+
+pub(crate) fn remove_allowlist_entry(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    address: Pubkey,
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let allowlist = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    let mut list = load_for_update(
+        program_id,
+        accounts,
+        owner,
+        vault_state,
+        allowlist,
+        system_program,
+    )?;
+
+    let mut entries = list.entries().to_vec();
+    entries.retain(|entry| entry.address != address);
+    if entries.len() == list.entries().len() {
+        return Err(WbaVaultError::InvalidAllowlist.into());
+    }
+    list.set_entries(&entries)?;
+    save_allowlist(allowlist, &list)?;
+
+    msg!("Remove allowlist entry successful");
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use solana_program::{account_info::AccountInfo, entrypoint::ProgramResult, msg};
+
+use crate::WbaVaultError;
+
+/// Rejects an instruction that passes one account in two roles that must
+/// differ, such as `owner_ata` as `vault_ata` or `vault` as `owner`.
+pub(crate) fn assert_distinct(accounts: &[&AccountInfo]) -> ProgramResult {
+    assert_no_aliases(accounts, &[])
+}
+
+/// Like `assert_distinct`, but the `shared` roles may be filled by the same
+/// account (e.g. a proposer who also executes), as long as none of them
+/// aliases one of the `distinct` accounts.
+pub(crate) fn assert_no_aliases<'a>(
+    distinct: &[&AccountInfo<'a>],
+    shared: &[&AccountInfo<'a>],
+) -> ProgramResult {
+    for (i, account) in distinct.iter().enumerate() {
+        let aliased = distinct[i + 1..]
+            .iter()
+            .chain(shared)
+            .any(|other| other.key == account.key);
+        if aliased {
+            msg!("Account {} is passed in more than one role", account.key);
+            return Err(WbaVaultError::DuplicateAccount.into());
+        }
+    }
+    Ok(())
+}
+
+/// Rejects read-only accounts the instruction is about to modify, with a
+/// clearer error than the runtime's after-the-fact check.
+pub(crate) fn assert_writable(accounts: &[&AccountInfo]) -> ProgramResult {
+    for account in accounts {
+        if !account.is_writable {
+            msg!("Account {} must be writable", account.key);
+            return Err(WbaVaultError::AccountNotWritable.into());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo, entrypoint::ProgramResult, program::invoke_signed,
+    program_error::ProgramError, pubkey::Pubkey, system_instruction,
+};
+
+use crate::{save_vault_state, Vault, WbaVaultError};
+
+/// Who owns the `["vault", vaultAuth]` PDA that holds a vault's SOL. Chosen
+/// at `Initialize` and fixed for the life of the vault.
+#[repr(u8)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Custody {
+    /// The system program owns the PDA; SOL leaves through a signed
+    /// `system_instruction::transfer`. Vaults created before custody modes
+    /// existed read back as this.
+    #[default]
+    SystemOwned,
+    /// This program owns the PDA; SOL leaves by debiting it directly, which
+    /// needs no CPI.
+    ProgramOwned,
+}
+
+impl Custody {
+    pub fn vault_owner(&self, program_id: &Pubkey) -> Pubkey {
+        match self {
+            Custody::SystemOwned => solana_program::system_program::id(),
+            Custody::ProgramOwned => *program_id,
+        }
+    }
+}
+
+impl TryFrom<u8> for Custody {
+    type Error = ProgramError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Custody::SystemOwned),
+            1 => Ok(Custody::ProgramOwned),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+/// Moves `amount` lamports from the vault PDA to `recipient` the way the
+/// vault's custody mode allows, saving `state` first. Callers record the
+/// withdrawal in `state` before calling this.
+///
+/// A direct debit is only reconciled with the runtime at the next CPI that
+/// passes the vault, so the state is saved (possibly growing it through a
+/// top-up CPI) before the lamports move, and callers make this their last
+/// step.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn transfer_from_vault<'a>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    vault_state: &AccountInfo<'a>,
+    vault_auth: &AccountInfo<'a>,
+    vault: &AccountInfo<'a>,
+    recipient: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    state: &mut Vault,
+    amount: u64,
+) -> ProgramResult {
+    let custody = state.custody()?;
+    if vault.owner != &custody.vault_owner(program_id) {
+        return Err(WbaVaultError::InvalidCustody.into());
+    }
+    save_vault_state(payer, vault_state, system_program, state)?;
+
+    match custody {
+        Custody::SystemOwned => invoke_signed(
+            &system_instruction::transfer(vault.key, recipient.key, amount),
+            &[vault.clone(), recipient.clone(), system_program.clone()],
+            &[&[b"vault", vault_auth.key.as_ref(), &[state.vault_bump]]],
+        ),
+        Custody::ProgramOwned => {
+            let remaining = vault
+                .lamports()
+                .checked_sub(amount)
+                .ok_or(ProgramError::InsufficientFunds)?;
+            let credited = recipient
+                .lamports()
+                .checked_add(amount)
+                .ok_or(ProgramError::ArithmeticOverflow)?;
+            **vault.lamports.borrow_mut() = remaining;
+            **recipient.lamports.borrow_mut() = credited;
+            Ok(())
+        }
+    }
+}
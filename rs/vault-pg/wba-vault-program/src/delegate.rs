@@ -0,0 +1,289 @@
+use bytemuck::{Pod, Zeroable};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    allowlist::assert_allowed_recipient,
+    assert_system_program, assert_vault_pdas,
+    checks::{assert_distinct, assert_no_aliases, assert_writable},
+    close_program_account, create_pda_account, load_vault_state,
+    lock::assert_unlocked,
+    multisig::assert_threshold,
+    payout::{pay_out_sol, pay_out_tokens},
+    pool::assert_not_pooled,
+    receipt::assert_no_receipts,
+    zero_copy::{self, ZeroCopy},
+    Vault, WbaVaultError,
+};
+
+/// A spending allowance at `["delegate", vaultState, delegate, mint]`. `mint`
+/// is `Pubkey::default()` for SOL. The delegate may pay out up to `allowance`
+/// to `recipients` without the owner, until `expires_at` (0 means never).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct Delegate {
+    pub vault_state: Pubkey,
+    pub delegate: Pubkey,
+    pub mint: Pubkey,
+    pub allowance: u64,
+    pub expires_at: i64,
+    recipients: [Pubkey; Delegate::MAX_RECIPIENTS],
+    pub bump: u8,
+    recipient_count: u8,
+    _padding: [u8; 6],
+}
+
+impl ZeroCopy for Delegate {
+    const DISCRIMINATOR: [u8; 8] = *b"wbadeleg";
+}
+
+impl Delegate {
+    pub const MAX_RECIPIENTS: usize = 4;
+
+    pub fn recipients(&self) -> &[Pubkey] {
+        zero_copy::list(&self.recipients, self.recipient_count)
+    }
+
+    pub fn set_recipients(&mut self, recipients: &[Pubkey]) -> ProgramResult {
+        zero_copy::set_list(&mut self.recipients, &mut self.recipient_count, recipients)
+    }
+
+    pub fn is_sol(&self) -> bool {
+        self.mint == Pubkey::default()
+    }
+
+    pub fn is_expired(&self, clock: &Clock) -> bool {
+        self.expires_at != 0 && clock.unix_timestamp >= self.expires_at
+    }
+}
+
+fn load_delegate(
+    program_id: &Pubkey,
+    vault_state: &AccountInfo,
+    delegate_account: &AccountInfo,
+) -> Result<Delegate, ProgramError> {
+    if delegate_account.owner != program_id {
+        return Err(WbaVaultError::InvalidDelegate.into());
+    }
+
+    let state = Delegate::read(&delegate_account.data.borrow())?;
+
+    if state.vault_state != *vault_state.key {
+        return Err(WbaVaultError::InvalidDelegate.into());
+    }
+
+    Ok(state)
+}
+
+fn save_delegate(delegate_account: &AccountInfo, state: &Delegate) -> ProgramResult {
+    state.write(&mut delegate_account.data.borrow_mut())
+}
+
+/// Creates or replaces the allowance of `delegate` for `mint`.
+pub(crate) fn set_delegate(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    delegate: Pubkey,
+    mint: Pubkey,
+    allowance: u64,
+    expires_at: i64,
+    recipients: Vec<Pubkey>,
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let delegate_account = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[owner, vault_state, delegate_account, system_program])?;
+    assert_writable(&[delegate_account])?;
+
+    assert_system_program(system_program)?;
+    let state = load_vault_state(program_id, owner, vault_state)?;
+    assert_threshold(&state, accounts)?;
+
+    if recipients.is_empty() || recipients.len() > Delegate::MAX_RECIPIENTS {
+        return Err(WbaVaultError::InvalidDelegate.into());
+    }
+
+    // delegate PDA = ["delegate", vaultState, delegate, mint]
+    let (expected_delegate, bump) = Pubkey::find_program_address(
+        &[
+            b"delegate",
+            vault_state.key.as_ref(),
+            delegate.as_ref(),
+            mint.as_ref(),
+        ],
+        program_id,
+    );
+    if delegate_account.key != &expected_delegate {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+
+    let mut state = Delegate {
+        vault_state: *vault_state.key,
+        delegate,
+        mint,
+        allowance,
+        expires_at,
+        bump,
+        ..Delegate::zeroed()
+    };
+    state.set_recipients(&recipients)?;
+
+    if delegate_account.owner != program_id {
+        create_pda_account(
+            owner,
+            delegate_account,
+            system_program,
+            Delegate::SPACE,
+            program_id,
+            &[
+                b"delegate",
+                vault_state.key.as_ref(),
+                delegate.as_ref(),
+                mint.as_ref(),
+                &[bump],
+            ],
+        )?;
+        state.init(&mut delegate_account.data.borrow_mut())?;
+    } else {
+        save_delegate(delegate_account, &state)?;
+    }
+
+    msg!("Set delegate successful");
+    Ok(())
+}
+
+/// Pays out of the vault on the delegate's signature alone, drawing down its
+/// allowance. Locks and withdraw limits still apply.
+pub(crate) fn delegate_withdraw(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let delegate = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let vault_auth = next_account_info(&mut accounts_iter)?;
+    let vault = next_account_info(&mut accounts_iter)?;
+    let delegate_account = next_account_info(&mut accounts_iter)?;
+    let recipient = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !delegate.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    // A delegate may pay itself when it is one of the recipients.
+    assert_no_aliases(
+        &[
+            vault_state,
+            vault_auth,
+            vault,
+            delegate_account,
+            system_program,
+        ],
+        &[delegate, recipient],
+    )?;
+    assert_writable(&[delegate_account])?;
+
+    assert_system_program(system_program)?;
+    if vault_state.owner != program_id {
+        return Err(WbaVaultError::InvalidVaultStateOwner.into());
+    }
+    let mut vault_data = Vault::unpack(&vault_state.data.borrow())?;
+    assert_unlocked(&vault_data)?;
+    assert_not_pooled(&vault_data)?;
+    assert_no_receipts(&vault_data)?;
+    assert_vault_pdas(program_id, vault_state, vault_auth, vault)?;
+
+    let mut state = load_delegate(program_id, vault_state, delegate_account)?;
+    if state.delegate != *delegate.key {
+        return Err(WbaVaultError::InvalidSigner.into());
+    }
+    if state.is_expired(&Clock::get()?) {
+        return Err(WbaVaultError::DelegateExpired.into());
+    }
+    if !state.recipients().contains(recipient.key) {
+        return Err(WbaVaultError::InvalidDelegate.into());
+    }
+    assert_allowed_recipient(
+        program_id,
+        vault_state,
+        &vault_data,
+        accounts,
+        recipient.key,
+    )?;
+
+    if amount > state.allowance {
+        msg!("Allowance of {} is below {}", state.allowance, amount);
+        return Err(WbaVaultError::AllowanceExceeded.into());
+    }
+    state.allowance -= amount;
+    save_delegate(delegate_account, &state)?;
+
+    if state.is_sol() {
+        pay_out_sol(
+            program_id,
+            delegate,
+            vault_state,
+            vault_auth,
+            vault,
+            recipient,
+            system_program,
+            &mut vault_data,
+            amount,
+        )?;
+    } else {
+        pay_out_tokens(
+            program_id,
+            &mut accounts_iter,
+            delegate,
+            vault_state,
+            vault_auth,
+            system_program,
+            &mut vault_data,
+            recipient,
+            &state.mint,
+            amount,
+            false,
+        )?;
+    }
+
+    msg!("Delegate withdraw successful");
+    Ok(())
+}
+
+pub(crate) fn revoke_delegate(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let delegate_account = next_account_info(&mut accounts_iter)?;
+
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[owner, vault_state, delegate_account])?;
+
+    let state = load_vault_state(program_id, owner, vault_state)?;
+    assert_threshold(&state, accounts)?;
+    load_delegate(program_id, vault_state, delegate_account)?;
+
+    close_program_account(delegate_account, owner)?;
+
+    msg!("Revoke delegate successful");
+    Ok(())
+}
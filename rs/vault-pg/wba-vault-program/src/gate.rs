@@ -0,0 +1,253 @@
+use bytemuck::{Pod, Zeroable};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    bpf_loader_upgradeable,
+    entrypoint::ProgramResult,
+    keccak, msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    assert_system_program,
+    checks::{assert_distinct, assert_writable},
+    create_pda_account,
+    zero_copy::{PodBool, ZeroCopy},
+    WbaVaultError,
+};
+
+/// Program-wide settings at `["config"]`.
+///
+/// While `gated` is set, `Initialize` only opens vaults for owners with a
+/// Merkle proof against `allowlist_root`; see `verify_allowlist_proof`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct Config {
+    pub admin: Pubkey,
+    pub gated: PodBool,
+    pub allowlist_root: [u8; 32],
+    pub bump: u8,
+}
+
+impl ZeroCopy for Config {
+    const DISCRIMINATOR: [u8; 8] = *b"wbaconfg";
+}
+
+/// Leaf for `owner`. Leaves and inner nodes hash under different prefixes so
+/// an inner node can never pass as a leaf.
+pub fn allowlist_leaf(owner: &Pubkey) -> [u8; 32] {
+    keccak::hashv(&[&[0], owner.as_ref()]).to_bytes()
+}
+
+/// Parent of two nodes. The pair is sorted first, so proofs need no
+/// left/right flags.
+pub fn allowlist_node(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let (low, high) = if a <= b { (a, b) } else { (b, a) };
+    keccak::hashv(&[&[1], low, high]).to_bytes()
+}
+
+pub fn verify_allowlist_proof(root: &[u8; 32], owner: &Pubkey, proof: &[[u8; 32]]) -> bool {
+    let computed = proof.iter().fold(allowlist_leaf(owner), |node, sibling| {
+        allowlist_node(&node, sibling)
+    });
+    &computed == root
+}
+
+/// Builds allowlist roots and proofs off-chain.
+#[cfg(feature = "client")]
+pub struct AllowlistTree {
+    /// Leaf level first; each level is sorted so the root does not depend on
+    /// the order owners were listed in.
+    levels: Vec<Vec<[u8; 32]>>,
+}
+
+#[cfg(feature = "client")]
+impl AllowlistTree {
+    pub fn new(owners: &[Pubkey]) -> Self {
+        let mut leaves: Vec<[u8; 32]> = owners.iter().map(allowlist_leaf).collect();
+        leaves.sort_unstable();
+        leaves.dedup();
+
+        let mut levels = vec![leaves];
+        while levels.last().unwrap().len() > 1 {
+            // An odd node out moves up unchanged.
+            let next = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| match pair {
+                    [a, b] => allowlist_node(a, b),
+                    [a] => *a,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+        Self { levels }
+    }
+
+    /// All zeroes for an empty list, which no proof can match.
+    pub fn root(&self) -> [u8; 32] {
+        self.levels
+            .last()
+            .and_then(|level| level.first())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Proof for `owner`, or `None` if it is not on the list.
+    pub fn proof(&self, owner: &Pubkey) -> Option<Vec<[u8; 32]>> {
+        let leaf = allowlist_leaf(owner);
+        let mut index = self.levels[0].binary_search(&leaf).ok()?;
+        let mut proof = Vec::new();
+        for level in &self.levels[..self.levels.len() - 1] {
+            if let Some(sibling) = level.get(index ^ 1) {
+                proof.push(*sibling);
+            }
+            index /= 2;
+        }
+        Some(proof)
+    }
+}
+
+fn config_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    // config PDA = ["config"]
+    Pubkey::find_program_address(&[b"config"], program_id)
+}
+
+/// Checks the owner of a new vault against the config. A config that was
+/// never created leaves the program ungated.
+pub(crate) fn assert_may_open_vault(
+    program_id: &Pubkey,
+    config: &AccountInfo,
+    owner: &Pubkey,
+    proof: &[[u8; 32]],
+) -> ProgramResult {
+    if config.key != &config_address(program_id).0 {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+    if config.owner != program_id {
+        return Ok(());
+    }
+
+    let data = config.data.borrow();
+    let config = Config::view(&data)?;
+    if config.gated.get() && !verify_allowlist_proof(&config.allowlist_root, owner, proof) {
+        msg!("{} is not on the vault allowlist", owner);
+        return Err(WbaVaultError::NotAllowlisted.into());
+    }
+    Ok(())
+}
+
+/// Reads the upgrade authority out of the program's `ProgramData` account:
+/// a bincode `UpgradeableLoaderState::ProgramData { slot, upgrade_authority }`.
+fn upgrade_authority(
+    program_id: &Pubkey,
+    program_data: &AccountInfo,
+) -> Result<Option<Pubkey>, ProgramError> {
+    let (expected, _) =
+        Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+    if program_data.key != &expected || program_data.owner != &bpf_loader_upgradeable::id() {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+
+    let data = program_data.data.borrow();
+    if data.len() < 45 || data[..4] != [3, 0, 0, 0] {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    match data[12] {
+        0 => Ok(None),
+        _ => Ok(Some(Pubkey::new_from_array(
+            data[13..45].try_into().unwrap(),
+        ))),
+    }
+}
+
+/// Creates the config. Only the program's upgrade authority may do this; it
+/// becomes the config admin.
+pub(crate) fn init_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    gated: bool,
+    allowlist_root: [u8; 32],
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let authority = next_account_info(&mut accounts_iter)?;
+    let config = next_account_info(&mut accounts_iter)?;
+    let program_data = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[authority, config, program_data, system_program])?;
+    assert_writable(&[authority, config])?;
+
+    assert_system_program(system_program)?;
+    if upgrade_authority(program_id, program_data)? != Some(*authority.key) {
+        return Err(WbaVaultError::InvalidSigner.into());
+    }
+
+    let (expected_config, bump) = config_address(program_id);
+    if config.key != &expected_config {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+    if config.owner == program_id {
+        return Err(WbaVaultError::InvalidConfig.into());
+    }
+
+    create_pda_account(
+        authority,
+        config,
+        system_program,
+        Config::SPACE,
+        program_id,
+        &[b"config", &[bump]],
+    )?;
+
+    Config {
+        admin: *authority.key,
+        gated: gated.into(),
+        allowlist_root,
+        bump,
+    }
+    .init(&mut config.data.borrow_mut())?;
+
+    msg!("Config created, gated: {}", gated);
+    Ok(())
+}
+
+/// Lets the admin switch gating and rotate the allowlist root, e.g. per cohort.
+pub(crate) fn update_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    gated: bool,
+    allowlist_root: [u8; 32],
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let admin = next_account_info(&mut accounts_iter)?;
+    let config = next_account_info(&mut accounts_iter)?;
+
+    if !admin.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[admin, config])?;
+    assert_writable(&[config])?;
+
+    if config.owner != program_id || config.key != &config_address(program_id).0 {
+        return Err(WbaVaultError::InvalidConfig.into());
+    }
+    let mut state = Config::read(&config.data.borrow())?;
+    if &state.admin != admin.key {
+        return Err(WbaVaultError::InvalidSigner.into());
+    }
+
+    state.gated = gated.into();
+    state.allowlist_root = allowlist_root;
+    state.write(&mut config.data.borrow_mut())?;
+
+    msg!("Config updated, gated: {}", gated);
+    Ok(())
+}
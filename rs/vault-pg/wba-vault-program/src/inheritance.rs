@@ -0,0 +1,158 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    assert_system_program, checks::assert_distinct, load_vault_state, multisig::assert_threshold,
+    save_vault_state, update_vault_state, Vault, WbaVaultError,
+};
+
+impl Vault {
+    /// Whether a dead-man switch is configured.
+    pub fn has_inheritance(&self) -> bool {
+        self.inactivity_secs > 0 && self.beneficiary != Pubkey::default()
+    }
+
+    /// Whether the beneficiary may take over the vault at `now`.
+    pub fn is_inheritable(&self, now: i64) -> bool {
+        self.has_inheritance() && now >= self.last_activity.saturating_add(self.inactivity_secs)
+    }
+}
+
+/// Resets the inactivity timer when `owner` signed for the vault. Called on
+/// every owner load; it only writes when the switch is configured and
+/// `vault_state` was passed writable, and never changes the account size.
+///
+/// The activity slot in `VaultStats` is stamped here too, and saved with
+/// whatever else the handler writes.
+pub(crate) fn record_activity(
+    owner: &AccountInfo,
+    vault_state: &AccountInfo,
+    state: &mut Vault,
+) -> ProgramResult {
+    if !owner.is_signer {
+        return Ok(());
+    }
+    let clock = Clock::get()?;
+    state.last_activity_slot = clock.slot;
+    if !vault_state.is_writable || !state.has_inheritance() {
+        return Ok(());
+    }
+
+    state.last_activity = clock.unix_timestamp;
+    update_vault_state(vault_state, |stored| {
+        stored.last_activity = clock.unix_timestamp;
+        Ok(())
+    })
+}
+
+/// Configures the dead-man switch. A default `beneficiary` or zero
+/// `inactivity_secs` turns it off.
+pub(crate) fn set_inheritance(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    beneficiary: Pubkey,
+    inactivity_secs: i64,
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[owner, vault_state, system_program])?;
+
+    assert_system_program(system_program)?;
+    let mut state = load_vault_state(program_id, owner, vault_state)?;
+    assert_threshold(&state, accounts)?;
+    if inactivity_secs < 0 {
+        return Err(WbaVaultError::InvalidInheritance.into());
+    }
+
+    state.beneficiary = beneficiary;
+    state.inactivity_secs = inactivity_secs;
+    state.last_activity = Clock::get()?.unix_timestamp;
+    save_vault_state(owner, vault_state, system_program, &state)?;
+
+    msg!("Set inheritance successful");
+    Ok(())
+}
+
+/// Proves the owner is still around. Any other owner-signed instruction that
+/// takes the vault state writable does the same.
+pub(crate) fn heartbeat(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[owner, vault_state])?;
+
+    // Loading the vault records the activity.
+    let state = load_vault_state(program_id, owner, vault_state)?;
+    if !state.has_inheritance() || !vault_state.is_writable {
+        return Err(WbaVaultError::InvalidInheritance.into());
+    }
+
+    msg!("Heartbeat recorded at {}", state.last_activity);
+    Ok(())
+}
+
+/// Hands the vault to the beneficiary once the owner has been inactive for
+/// `inactivity_secs`. The beneficiary then withdraws with the usual
+/// instructions, subject to any locks, limits and allowlist.
+pub(crate) fn claim_inheritance(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let beneficiary = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !beneficiary.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[beneficiary, vault_state, system_program])?;
+
+    assert_system_program(system_program)?;
+    if vault_state.owner != program_id {
+        return Err(WbaVaultError::InvalidVaultStateOwner.into());
+    }
+    let mut state = Vault::unpack(&vault_state.data.borrow())?;
+    if !state.has_inheritance() || state.beneficiary != *beneficiary.key {
+        return Err(WbaVaultError::InvalidInheritance.into());
+    }
+
+    let now = Clock::get()?.unix_timestamp;
+    if !state.is_inheritable(now) {
+        msg!(
+            "Inheritable at {}, now {}",
+            state.last_activity.saturating_add(state.inactivity_secs),
+            now
+        );
+        return Err(WbaVaultError::InheritanceNotClaimable.into());
+    }
+
+    state.owner = *beneficiary.key;
+    state.set_signers(&[])?;
+    state.threshold = 0;
+    state.set_recovery(None);
+    state.beneficiary = Pubkey::default();
+    state.inactivity_secs = 0;
+    state.last_activity = now;
+    save_vault_state(beneficiary, vault_state, system_program, &state)?;
+
+    msg!("Inheritance claimed: owner {}", state.owner);
+    Ok(())
+}
@@ -0,0 +1,336 @@
+use bytemuck::{Pod, Zeroable};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use spl_associated_token_account::get_associated_token_address;
+
+use crate::{
+    assert_system_program,
+    checks::{assert_distinct, assert_writable},
+    create_pda_account, load_vault_state,
+    multisig::assert_threshold,
+    save_vault_state,
+    stats::token_balance,
+    zero_copy::ZeroCopy,
+    Vault, WbaVaultError,
+};
+
+/// What `DepositSpl` and `DepositNft` have put into the vault for one mint,
+/// minus every token of that mint that has left it since, at
+/// `["ledger", vaultState, mint]`.
+///
+/// The ledger is bookkeeping only and never holds tokens back. Escrows that
+/// come back unspent, from a cancelled offer or a revoked vesting, are
+/// credited again. Tokens sent straight to the vault ATA, asks paid in by a
+/// filled offer, and pool assets, which the pool accounts for, are not
+/// attributed; `Reconcile` reports the difference and can sync it.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Pod, Zeroable)]
+pub struct Ledger {
+    pub vault_state: Pubkey,
+    pub mint: Pubkey,
+    pub balance: u64,
+    /// Zero until the first `DepositSpl`.
+    pub first_deposit_slot: u64,
+    pub last_deposit_slot: u64,
+    pub bump: u8,
+    _padding: [u8; 7],
+}
+
+impl ZeroCopy for Ledger {
+    const DISCRIMINATOR: [u8; 8] = *b"wbaledgr";
+}
+
+fn ledger_address(program_id: &Pubkey, vault_state: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    // ledger PDA = ["ledger", vaultState, mint]
+    Pubkey::find_program_address(
+        &[b"ledger", vault_state.as_ref(), mint.as_ref()],
+        program_id,
+    )
+}
+
+pub(crate) fn is_ledger(
+    program_id: &Pubkey,
+    account: &AccountInfo,
+    vault_state: &Pubkey,
+    mint: &Pubkey,
+) -> bool {
+    account.key == &ledger_address(program_id, vault_state, mint).0
+}
+
+/// Returns the ledger for `mint` if the caller passed it. It is looked up by
+/// address and conventionally goes last, after any optional accounts.
+fn find_ledger<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'b>],
+    vault_state: &Pubkey,
+    mint: &Pubkey,
+) -> Option<&'a AccountInfo<'b>> {
+    let (expected, _bump) = ledger_address(program_id, vault_state, mint);
+    accounts.iter().find(|account| account.key == &expected)
+}
+
+/// Like `find_ledger`, but once the vault has any ledger the one for `mint`
+/// must be passed, so tokens cannot leave without being debited. A PDA that
+/// was never created simply means that mint has no ledger.
+fn required_ledger<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'b>],
+    vault_state: &Pubkey,
+    vault: &Vault,
+    mint: &Pubkey,
+) -> Result<Option<&'a AccountInfo<'b>>, ProgramError> {
+    match find_ledger(program_id, accounts, vault_state, mint) {
+        Some(ledger) => Ok(Some(ledger)),
+        None if vault.ledger_count == 0 => Ok(None),
+        None => {
+            msg!(
+                "Missing ledger {}",
+                ledger_address(program_id, vault_state, mint).0
+            );
+            Err(WbaVaultError::MissingLedger.into())
+        }
+    }
+}
+
+/// Checks `ledger` is the PDA for `mint` and reads it. A ledger that was
+/// never created reads as empty; it is created the first time it is saved.
+fn load_ledger(
+    program_id: &Pubkey,
+    vault_state: &AccountInfo,
+    ledger: &AccountInfo,
+    mint: &Pubkey,
+) -> Result<Ledger, ProgramError> {
+    let (expected, bump) = ledger_address(program_id, vault_state.key, mint);
+    if ledger.key != &expected {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+
+    if ledger.owner != program_id {
+        return Ok(Ledger {
+            vault_state: *vault_state.key,
+            mint: *mint,
+            bump,
+            ..Ledger::default()
+        });
+    }
+
+    let state = Ledger::read(&ledger.data.borrow())?;
+    if &state.vault_state != vault_state.key || &state.mint != mint {
+        return Err(WbaVaultError::InvalidLedger.into());
+    }
+    Ok(state)
+}
+
+/// Writes `state` to `ledger`, creating it the first time and counting it in
+/// `vault`, which the caller saves.
+fn save_ledger<'a>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    ledger: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    vault: &mut Vault,
+    state: &Ledger,
+) -> ProgramResult {
+    if ledger.owner != program_id {
+        vault.ledger_count = vault
+            .ledger_count
+            .checked_add(1)
+            .ok_or(ProgramError::InvalidArgument)?;
+        create_pda_account(
+            payer,
+            ledger,
+            system_program,
+            Ledger::SPACE,
+            program_id,
+            &[
+                b"ledger",
+                state.vault_state.as_ref(),
+                state.mint.as_ref(),
+                &[state.bump],
+            ],
+        )?;
+        return state.init(&mut ledger.data.borrow_mut());
+    }
+
+    state.write(&mut ledger.data.borrow_mut())
+}
+
+/// Credits a deposit of `amount` to the mint's ledger, creating it on the
+/// first deposit. Without the ledger among `accounts` the deposit goes
+/// unrecorded until `Reconcile` syncs it.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn credit_ledger<'a>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo<'a>],
+    owner: &AccountInfo<'a>,
+    vault_state: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    vault: &mut Vault,
+    mint: &Pubkey,
+    amount: u64,
+) -> ProgramResult {
+    let Some(ledger) = find_ledger(program_id, accounts, vault_state.key, mint) else {
+        return Ok(());
+    };
+    assert_writable(&[ledger])?;
+    let mut state = load_ledger(program_id, vault_state, ledger, mint)?;
+
+    let slot = Clock::get()?.slot;
+    state.balance = state
+        .balance
+        .checked_add(amount)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    if state.first_deposit_slot == 0 {
+        state.first_deposit_slot = slot;
+    }
+    state.last_deposit_slot = slot;
+    save_ledger(program_id, owner, ledger, system_program, vault, &state)
+}
+
+/// Debits `amount` of `mint` leaving the vault from the mint's ledger, which
+/// is required once the vault has any. Tokens the ledger does not attribute,
+/// e.g. from before it existed, still leave; the ledger just bottoms out at
+/// zero.
+pub(crate) fn debit_ledger(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    vault_state: &AccountInfo,
+    vault: &Vault,
+    mint: &Pubkey,
+    amount: u64,
+) -> ProgramResult {
+    let Some(ledger) = required_ledger(program_id, accounts, vault_state.key, vault, mint)? else {
+        return Ok(());
+    };
+    if ledger.owner != program_id {
+        // Nothing was ever recorded, so there is nothing to debit.
+        return Ok(());
+    }
+    assert_writable(&[ledger])?;
+    let mut state = load_ledger(program_id, vault_state, ledger, mint)?;
+
+    if state.balance < amount {
+        msg!(
+            "Ledger holds {}, below {}; {} unattributed",
+            state.balance,
+            amount,
+            amount - state.balance
+        );
+    }
+    state.balance = state.balance.saturating_sub(amount);
+    state.write(&mut ledger.data.borrow_mut())
+}
+
+/// Credits back `amount` of `mint` that left through an escrow and returned
+/// unspent. Like `debit_ledger` it needs the ledger once the vault has any,
+/// and a mint without a ledger stays without one.
+pub(crate) fn restore_ledger(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    vault_state: &AccountInfo,
+    vault: &Vault,
+    mint: &Pubkey,
+    amount: u64,
+) -> ProgramResult {
+    let Some(ledger) = required_ledger(program_id, accounts, vault_state.key, vault, mint)? else {
+        return Ok(());
+    };
+    if ledger.owner != program_id {
+        return Ok(());
+    }
+    assert_writable(&[ledger])?;
+    let mut state = load_ledger(program_id, vault_state, ledger, mint)?;
+
+    state.balance = state
+        .balance
+        .checked_add(amount)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    state.write(&mut ledger.data.borrow_mut())
+}
+
+/// Logs how far the vault ATA has drifted from the ledger, e.g. through
+/// direct transfers, and with `sync` set moves the ledger to the ATA
+/// balance. Syncing rewrites the record, so it takes the multisig threshold;
+/// a report is the owner's alone.
+pub(crate) fn reconcile(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sync: bool,
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let vault_auth = next_account_info(&mut accounts_iter)?;
+    let vault_ata = next_account_info(&mut accounts_iter)?;
+    let mint = next_account_info(&mut accounts_iter)?;
+    let ledger = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[
+        owner,
+        vault_state,
+        vault_auth,
+        vault_ata,
+        mint,
+        ledger,
+        system_program,
+    ])?;
+    if sync {
+        // Creating the ledger counts it in the vault state.
+        assert_writable(&[owner, vault_state, ledger])?;
+    }
+
+    assert_system_program(system_program)?;
+    let mut vault = load_vault_state(program_id, owner, vault_state)?;
+    if sync {
+        assert_threshold(&vault, accounts)?;
+    }
+
+    let (expected_vault_auth, _auth_bump) =
+        Pubkey::find_program_address(&[b"auth", vault_state.key.as_ref()], program_id);
+    if vault_auth.key != &expected_vault_auth {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+    if vault_ata.key != &get_associated_token_address(vault_auth.key, mint.key) {
+        return Err(WbaVaultError::InvalidTokenAccount.into());
+    }
+
+    let mut state = load_ledger(program_id, vault_state, ledger, mint.key)?;
+    let held = token_balance(vault_ata);
+    msg!(
+        "Ledger {}, vault ATA {}, unattributed {}",
+        state.balance,
+        held,
+        i128::from(held) - i128::from(state.balance)
+    );
+
+    if sync && state.balance != held {
+        let created = ledger.owner != program_id;
+        state.balance = held;
+        save_ledger(
+            program_id,
+            owner,
+            ledger,
+            system_program,
+            &mut vault,
+            &state,
+        )?;
+        if created {
+            save_vault_state(owner, vault_state, system_program, &vault)?;
+        }
+        msg!("Ledger synced to {}", held);
+    }
+    Ok(())
+}
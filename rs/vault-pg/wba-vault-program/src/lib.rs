@@ -1,6 +1,7 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
+    clock::Clock,
     entrypoint::ProgramResult,
     msg,
     program::invoke,
@@ -12,22 +13,201 @@ use solana_program::{
     sysvar::Sysvar,
 };
 
+use bytemuck::{Pod, Zeroable};
 use spl_associated_token_account::get_associated_token_address;
 use spl_token::instruction as token_instruction;
 
+mod allowlist;
+mod checks;
+mod custody;
+mod delegate;
+mod gate;
+mod inheritance;
+mod ledger;
+mod limit;
+mod lock;
+mod multisig;
+mod payout;
+mod permit;
+mod pool;
+mod proposal;
+mod receipt;
+mod recovery;
+mod score;
+mod stake;
+mod stats;
+mod swap;
+mod vesting;
+mod zero_copy;
+
+pub use allowlist::{Allowlist, AllowlistEntry, PendingAllowlistConfig};
+pub use custody::Custody;
+pub use delegate::Delegate;
+#[cfg(feature = "client")]
+pub use gate::AllowlistTree;
+pub use gate::{allowlist_leaf, allowlist_node, verify_allowlist_proof, Config};
+pub use ledger::Ledger;
+pub use limit::{MintLimit, WithdrawLimit};
+pub use permit::Permit;
+pub use pool::Pool;
+pub use proposal::{Proposal, ProposalAction};
+pub use recovery::PendingRecovery;
+pub use score::NftStake;
+#[cfg(feature = "client")]
+pub use stats::read_vault_stats;
+pub use stats::VaultStats;
+pub use swap::Offer;
+pub use vesting::Vesting;
+pub use zero_copy::{PodBool, ZeroCopy};
+
 solana_program::declare_id!("26fuYGrUBSa5wjzeUNu42MaQQzraX4kfchtTM9NTUKbM");
 
-#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+/// Vault state, stored zero-copy behind `Vault::DISCRIMINATOR`. Lists have a
+/// fixed capacity and a count, and are read through accessors.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
 pub struct Vault {
     pub owner: Pubkey,
     pub auth_bump: u8,
     pub vault_bump: u8,
+    /// `score_points` capped at `u8::MAX`, kept for existing readers.
     pub score: u8,
+    stake_account_count: u8,
+    signer_count: u8,
+    /// Number of distinct `signers` required for withdrawals and admin changes.
+    pub threshold: u8,
+    /// Set once the `["allowlist", vaultState]` PDA exists; payouts then need it.
+    pub has_allowlist: PodBool,
+    guardian_count: u8,
+    /// Number of distinct `guardians` needed to start a recovery; 0 disables it.
+    pub guardian_threshold: u8,
+    has_recovery: PodBool,
+    /// Deposits mint `["receipt", vaultState, mint]` tokens 1:1 and
+    /// withdrawals burn them.
+    pub receipts: PodBool,
+    custody: u8,
+    _padding: [u8; 4],
+    /// Stake accounts created by `StakeFromVault` and still holding lamports.
+    stake_accounts: [Pubkey; Vault::MAX_STAKE_ACCOUNTS],
+    /// Withdrawals are rejected while `Clock::unix_timestamp` is below this.
+    pub locked_until: i64,
+    /// Withdrawals are rejected while `Clock::epoch` is below this.
+    pub locked_until_epoch: u64,
+    /// Cap on SOL leaving the vault per window.
+    pub sol_limit: WithdrawLimit,
+    /// Number of `["limit", vaultState, mint]` PDAs created for this vault.
+    pub mint_limit_count: u32,
+    /// Number of `["pool", vaultState, mint]` PDAs; once any exists the owner
+    /// can no longer move funds out.
+    pub pool_count: u32,
+    /// NFTs with an open `["nft_stake", vaultState, mint]` record.
+    pub staked_nfts: u32,
+    pub mints_held: u32,
+    /// Multisig members; only used when `threshold > 0`.
+    signers: [Pubkey; Vault::MAX_SIGNERS],
+    /// Keys that may jointly rotate `owner` if it is lost.
+    guardians: [Pubkey; Vault::MAX_GUARDIANS],
+    /// How long the owner has to cancel a recovery.
+    pub recovery_delay_secs: i64,
+    recovery: PendingRecovery,
+    /// Takes over the vault after `inactivity_secs` without owner activity.
+    pub beneficiary: Pubkey,
+    pub inactivity_secs: i64,
+    /// Last owner-signed instruction that touched the vault state.
+    pub last_activity: i64,
+    /// Nonce the next withdrawal permit must carry.
+    pub permit_nonce: u64,
+    /// One point per NFT per day held in the vault, as of `score_updated_at`.
+    pub score_points: u64,
+    /// Points already turned into reward tokens.
+    pub claimed_points: u64,
+    /// NFT-seconds accrued towards the next point.
+    pub score_remainder: u64,
+    pub score_updated_at: i64,
+    /// Lifetime counters; see `VaultStats`.
+    pub total_sol_deposited: u64,
+    pub total_sol_withdrawn: u64,
+    pub deposit_count: u64,
+    pub withdraw_count: u64,
+    pub last_activity_slot: u64,
+    /// Offers made from this vault and not yet filled or cancelled.
+    pub open_offers: u32,
+    /// Vestings created from this vault and not yet closed.
+    pub open_vestings: u32,
+    /// Number of `["ledger", vaultState, mint]` PDAs; once any exists every
+    /// token outflow must pass the ledger of its mint.
+    pub ledger_count: u32,
+    _tail_padding: [u8; 4],
 }
 
 impl Vault {
+    pub const MAX_STAKE_ACCOUNTS: usize = 8;
+    /// Size of the Borsh layout the program started with: `owner`, the two
+    /// bumps and `score`, which this struct begins with.
+    pub const LEGACY_LEN: usize = 32 + 1 + 1 + 1;
+
     pub fn space() -> usize {
-        32 + 1 + 1 + 1
+        Self::SPACE
+    }
+
+    /// Decodes vault state from account data in either layout. Borsh
+    /// accounts only hold the first `LEGACY_LEN` bytes; every later field
+    /// reads as zero until the account is migrated.
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        Self::read(data)
+    }
+
+    pub fn stake_accounts(&self) -> &[Pubkey] {
+        zero_copy::list(&self.stake_accounts, self.stake_account_count)
+    }
+
+    pub fn set_stake_accounts(&mut self, stake_accounts: &[Pubkey]) -> ProgramResult {
+        zero_copy::set_list(&mut self.stake_accounts, &mut self.stake_account_count, stake_accounts)
+    }
+
+    pub fn signers(&self) -> &[Pubkey] {
+        zero_copy::list(&self.signers, self.signer_count)
+    }
+
+    pub fn set_signers(&mut self, signers: &[Pubkey]) -> ProgramResult {
+        zero_copy::set_list(&mut self.signers, &mut self.signer_count, signers)
+    }
+
+    pub fn guardians(&self) -> &[Pubkey] {
+        zero_copy::list(&self.guardians, self.guardian_count)
+    }
+
+    pub fn set_guardians(&mut self, guardians: &[Pubkey]) -> ProgramResult {
+        zero_copy::set_list(&mut self.guardians, &mut self.guardian_count, guardians)
+    }
+
+    pub fn recovery(&self) -> Option<&PendingRecovery> {
+        self.has_recovery.get().then_some(&self.recovery)
+    }
+
+    pub fn set_recovery(&mut self, recovery: Option<PendingRecovery>) {
+        self.has_recovery = recovery.is_some().into();
+        self.recovery = recovery.unwrap_or_else(PendingRecovery::zeroed);
+    }
+
+    pub fn custody(&self) -> Result<Custody, ProgramError> {
+        Custody::try_from(self.custody)
+    }
+
+    pub fn set_custody(&mut self, custody: Custody) {
+        self.custody = custody as u8;
+    }
+}
+
+impl ZeroCopy for Vault {
+    const DISCRIMINATOR: [u8; 8] = *b"wbavault";
+
+    fn read_legacy(data: &[u8]) -> Result<Self, ProgramError> {
+        zero_copy::read_prefix(data, Self::LEGACY_LEN)
+    }
+
+    fn write_legacy(&self, data: &mut [u8]) -> ProgramResult {
+        zero_copy::write_prefix(self, data, Self::LEGACY_LEN)
     }
 }
 
@@ -41,9 +221,61 @@ pub enum WbaVaultInstruction {
     DepositNft,
     WithdrawNft,
     CloseAccount,
+    StakeFromVault { amount: u64, seed: u64 },
+    DeactivateStake,
+    WithdrawStake { amount: u64 },
+    OfferSwap { offer_amount: u64, ask_amount: u64, seed: u64 },
+    AcceptSwap,
+    CancelSwap,
+    CreateVesting { amount: u64, start_ts: i64, cliff_ts: i64, end_ts: i64, seed: u64 },
+    ClaimVested,
+    RevokeVesting,
+    SetLock { locked_until: i64, locked_until_epoch: u64 },
+    SetWithdrawLimit { max_amount: u64, window_secs: i64 },
+    SetMintWithdrawLimit { max_amount: u64, window_secs: i64 },
+    SetMultisig { signers: Vec<Pubkey>, threshold: u8 },
+    CreateProposal { action: ProposalAction, recipient: Pubkey, expires_at: i64, seed: u64 },
+    Approve,
+    Execute,
+    CancelProposal,
+    SetDelegate {
+        delegate: Pubkey,
+        mint: Pubkey,
+        allowance: u64,
+        expires_at: i64,
+        recipients: Vec<Pubkey>,
+    },
+    DelegateWithdraw { amount: u64 },
+    RevokeDelegate,
+    SetAllowlistConfig { enabled: bool, add_delay_secs: i64 },
+    AddAllowlistEntry { address: Pubkey },
+    RemoveAllowlistEntry { address: Pubkey },
+    SetGuardians { guardians: Vec<Pubkey>, threshold: u8, delay_secs: i64 },
+    InitiateRecovery { new_owner: Pubkey },
+    CancelRecovery,
+    CompleteRecovery,
+    SetInheritance { beneficiary: Pubkey, inactivity_secs: i64 },
+    Heartbeat,
+    ClaimInheritance,
+    WithdrawWithPermit { permit: Permit },
+    CreatePool { mint: Pubkey },
+    DepositToPool { amount: u64 },
+    RedeemShares { shares: u64 },
+    EnableReceipts,
+    CreateReceiptMint { mint: Pubkey },
+    Settle,
+    ClaimRewards,
+    InitConfig { gated: bool, allowlist_root: [u8; 32] },
+    UpdateConfig { gated: bool, allowlist_root: [u8; 32] },
+    InitializeWithProof { proof: Vec<[u8; 32]> },
+    InitializeWithCustody { custody: Custody, proof: Vec<[u8; 32]> },
+    Reconcile { sync: bool },
+    Resize { size: u64 },
 }
 
-#[derive(Debug)]
+/// Returned as `ProgramError::Custom(error as u32)`, so codes follow the
+/// declaration order; add new variants at the end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum WbaVaultError {
     InvalidSystemProgram,
     InvalidPda,
@@ -52,11 +284,53 @@ pub enum WbaVaultError {
     InvalidTokenProgram,
     InvalidTokenAccount,
     InvalidMetadataProgram,
+    InvalidStakeProgram,
+    InvalidStakeAccount,
+    TooManyStakeAccounts,
+    InvalidOffer,
+    InvalidVesting,
+    InvalidSchedule,
+    NothingToClaim,
+    VaultLocked,
+    WithdrawLimitExceeded,
+    MissingWithdrawLimit,
+    InvalidMultisig,
+    InvalidProposal,
+    ProposalExpired,
+    InsufficientApprovals,
+    InvalidDelegate,
+    DelegateExpired,
+    AllowanceExceeded,
+    InvalidAllowlist,
+    MissingAllowlist,
+    RecipientNotAllowed,
+    InvalidGuardians,
+    InvalidRecovery,
+    RecoveryNotReady,
+    InvalidInheritance,
+    InheritanceNotClaimable,
+    InvalidPermit,
+    PermitExpired,
+    DuplicateAccount,
+    AccountNotWritable,
+    InvalidPool,
+    VaultPooled,
+    MissingReceiptMint,
+    ReceiptsEnabled,
+    InvalidNftStake,
+    InvalidConfig,
+    NotAllowlisted,
+    InvalidCustody,
+    InvalidLedger,
+    InvalidVaultSize,
+    VaultNotEmpty,
+    MissingMasterEdition,
+    MissingLedger,
 }
 
 impl From<WbaVaultError> for ProgramError {
-    fn from(_: WbaVaultError) -> Self {
-        ProgramError::Custom(0)
+    fn from(e: WbaVaultError) -> Self {
+        ProgramError::Custom(e as u32)
     }
 }
 
@@ -72,7 +346,7 @@ pub fn process_instruction(
         .map_err(|_| ProgramError::InvalidInstructionData)?;
 
     match ix {
-        WbaVaultInstruction::Initialize => initialize(program_id, accounts),
+        WbaVaultInstruction::Initialize => initialize(program_id, accounts, Custody::SystemOwned, &[]),
         WbaVaultInstruction::Deposit { amount } => deposit(program_id, accounts, amount),
         WbaVaultInstruction::Withdraw { amount } => withdraw(program_id, accounts, amount),
         WbaVaultInstruction::DepositSpl { amount } => deposit_spl(program_id, accounts, amount),
@@ -80,6 +354,96 @@ pub fn process_instruction(
         WbaVaultInstruction::DepositNft => deposit_nft(program_id, accounts),
         WbaVaultInstruction::WithdrawNft => withdraw_nft(program_id, accounts),
         WbaVaultInstruction::CloseAccount => close_account(program_id, accounts),
+        WbaVaultInstruction::StakeFromVault { amount, seed } => {
+            stake::stake_from_vault(program_id, accounts, amount, seed)
+        }
+        WbaVaultInstruction::DeactivateStake => stake::deactivate_stake(program_id, accounts),
+        WbaVaultInstruction::WithdrawStake { amount } => stake::withdraw_stake(program_id, accounts, amount),
+        WbaVaultInstruction::OfferSwap { offer_amount, ask_amount, seed } => {
+            swap::offer_swap(program_id, accounts, offer_amount, ask_amount, seed)
+        }
+        WbaVaultInstruction::AcceptSwap => swap::accept_swap(program_id, accounts),
+        WbaVaultInstruction::CancelSwap => swap::cancel_swap(program_id, accounts),
+        WbaVaultInstruction::CreateVesting { amount, start_ts, cliff_ts, end_ts, seed } => {
+            vesting::create_vesting(program_id, accounts, amount, start_ts, cliff_ts, end_ts, seed)
+        }
+        WbaVaultInstruction::ClaimVested => vesting::claim_vested(program_id, accounts),
+        WbaVaultInstruction::RevokeVesting => vesting::revoke_vesting(program_id, accounts),
+        WbaVaultInstruction::SetLock { locked_until, locked_until_epoch } => {
+            lock::set_lock(program_id, accounts, locked_until, locked_until_epoch)
+        }
+        WbaVaultInstruction::SetWithdrawLimit { max_amount, window_secs } => {
+            limit::set_withdraw_limit(program_id, accounts, max_amount, window_secs)
+        }
+        WbaVaultInstruction::SetMintWithdrawLimit { max_amount, window_secs } => {
+            limit::set_mint_withdraw_limit(program_id, accounts, max_amount, window_secs)
+        }
+        WbaVaultInstruction::SetMultisig { signers, threshold } => {
+            multisig::set_multisig(program_id, accounts, signers, threshold)
+        }
+        WbaVaultInstruction::CreateProposal { action, recipient, expires_at, seed } => {
+            proposal::create_proposal(program_id, accounts, action, recipient, expires_at, seed)
+        }
+        WbaVaultInstruction::Approve => proposal::approve(program_id, accounts),
+        WbaVaultInstruction::Execute => proposal::execute(program_id, accounts),
+        WbaVaultInstruction::CancelProposal => proposal::cancel_proposal(program_id, accounts),
+        WbaVaultInstruction::SetDelegate { delegate, mint, allowance, expires_at, recipients } => {
+            delegate::set_delegate(
+                program_id, accounts, delegate, mint, allowance, expires_at, recipients,
+            )
+        }
+        WbaVaultInstruction::DelegateWithdraw { amount } => {
+            delegate::delegate_withdraw(program_id, accounts, amount)
+        }
+        WbaVaultInstruction::RevokeDelegate => delegate::revoke_delegate(program_id, accounts),
+        WbaVaultInstruction::SetAllowlistConfig { enabled, add_delay_secs } => {
+            allowlist::set_allowlist_config(program_id, accounts, enabled, add_delay_secs)
+        }
+        WbaVaultInstruction::AddAllowlistEntry { address } => {
+            allowlist::add_allowlist_entry(program_id, accounts, address)
+        }
+        WbaVaultInstruction::RemoveAllowlistEntry { address } => {
+            allowlist::remove_allowlist_entry(program_id, accounts, address)
+        }
+        WbaVaultInstruction::SetGuardians { guardians, threshold, delay_secs } => {
+            recovery::set_guardians(program_id, accounts, guardians, threshold, delay_secs)
+        }
+        WbaVaultInstruction::InitiateRecovery { new_owner } => {
+            recovery::initiate_recovery(program_id, accounts, new_owner)
+        }
+        WbaVaultInstruction::CancelRecovery => recovery::cancel_recovery(program_id, accounts),
+        WbaVaultInstruction::CompleteRecovery => recovery::complete_recovery(program_id, accounts),
+        WbaVaultInstruction::SetInheritance { beneficiary, inactivity_secs } => {
+            inheritance::set_inheritance(program_id, accounts, beneficiary, inactivity_secs)
+        }
+        WbaVaultInstruction::Heartbeat => inheritance::heartbeat(program_id, accounts),
+        WbaVaultInstruction::ClaimInheritance => inheritance::claim_inheritance(program_id, accounts),
+        WbaVaultInstruction::WithdrawWithPermit { permit } => {
+            permit::withdraw_with_permit(program_id, accounts, permit)
+        }
+        WbaVaultInstruction::CreatePool { mint } => pool::create_pool(program_id, accounts, mint),
+        WbaVaultInstruction::DepositToPool { amount } => pool::deposit_to_pool(program_id, accounts, amount),
+        WbaVaultInstruction::RedeemShares { shares } => pool::redeem_shares(program_id, accounts, shares),
+        WbaVaultInstruction::EnableReceipts => receipt::enable_receipts(program_id, accounts),
+        WbaVaultInstruction::CreateReceiptMint { mint } => {
+            receipt::create_receipt_mint(program_id, accounts, mint)
+        }
+        WbaVaultInstruction::Settle => score::settle(program_id, accounts),
+        WbaVaultInstruction::ClaimRewards => score::claim_rewards(program_id, accounts),
+        WbaVaultInstruction::InitConfig { gated, allowlist_root } => {
+            gate::init_config(program_id, accounts, gated, allowlist_root)
+        }
+        WbaVaultInstruction::UpdateConfig { gated, allowlist_root } => {
+            gate::update_config(program_id, accounts, gated, allowlist_root)
+        }
+        WbaVaultInstruction::InitializeWithProof { proof } => {
+            initialize(program_id, accounts, Custody::SystemOwned, &proof)
+        }
+        WbaVaultInstruction::InitializeWithCustody { custody, proof } => {
+            initialize(program_id, accounts, custody, &proof)
+        }
+        WbaVaultInstruction::Reconcile { sync } => ledger::reconcile(program_id, accounts, sync),
+        WbaVaultInstruction::Resize { size } => resize(program_id, accounts, size),
     }
 }
 
@@ -97,21 +461,161 @@ fn assert_token_program(token_program: &AccountInfo) -> ProgramResult {
     Ok(())
 }
 
+/// Checks the Token Metadata accounts passed with an NFT. The master edition
+/// must exist: Metaplex only creates one for a mint it has taken the mint
+/// authority of, so any mint without one could still be minted into a supply
+/// of two.
+fn assert_nft_accounts(
+    token_mint: &AccountInfo,
+    nft_metadata: &AccountInfo,
+    nft_master_edition: &AccountInfo,
+    metadata_program: &AccountInfo,
+) -> ProgramResult {
+    if metadata_program.key != &receipt::METADATA_PROGRAM_ID {
+        return Err(WbaVaultError::InvalidMetadataProgram.into());
+    }
+
+    let (expected_metadata, _bump) = Pubkey::find_program_address(
+        &[b"metadata", metadata_program.key.as_ref(), token_mint.key.as_ref()],
+        metadata_program.key,
+    );
+    if nft_metadata.key != &expected_metadata {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+
+    let (expected_edition, _bump) = Pubkey::find_program_address(
+        &[
+            b"metadata",
+            metadata_program.key.as_ref(),
+            token_mint.key.as_ref(),
+            b"edition",
+        ],
+        metadata_program.key,
+    );
+    if nft_master_edition.key != &expected_edition {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+
+    if nft_master_edition.owner != metadata_program.key || nft_master_edition.data_is_empty() {
+        msg!("{} has no master edition", token_mint.key);
+        return Err(WbaVaultError::MissingMasterEdition.into());
+    }
+    Ok(())
+}
+
 fn load_vault_state(program_id: &Pubkey, owner: &AccountInfo, vault_state: &AccountInfo) -> Result<Vault, ProgramError> {
     if vault_state.owner != program_id {
         return Err(WbaVaultError::InvalidVaultStateOwner.into());
     }
 
-    let state = Vault::try_from_slice(&vault_state.data.borrow())
-        .map_err(|_| ProgramError::InvalidAccountData)?;
+    let mut state = Vault::unpack(&vault_state.data.borrow())?;
 
-    if state.owner != *owner.key {
+    if !state.is_member(owner.key) {
         return Err(WbaVaultError::InvalidSigner.into());
     }
 
+    inheritance::record_activity(owner, vault_state, &mut state)?;
+
     Ok(state)
 }
 
+/// Writes `state` back to `vault_state` in the zero-copy layout, growing the
+/// account first if it was created with an older, smaller layout. The payer
+/// covers the extra rent. Borsh accounts are migrated here.
+fn save_vault_state<'a>(
+    payer: &AccountInfo<'a>,
+    vault_state: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    state: &Vault,
+) -> ProgramResult {
+    checks::assert_writable(&[vault_state])?;
+
+    if vault_state.data_len() < Vault::space() {
+        let rent = Rent::get()?;
+        let required = rent.minimum_balance(Vault::space());
+        let top_up = required.saturating_sub(vault_state.lamports());
+        if top_up > 0 {
+            invoke(
+                &system_instruction::transfer(payer.key, vault_state.key, top_up),
+                &[payer.clone(), vault_state.clone(), system_program.clone()],
+            )?;
+        }
+        vault_state.realloc(Vault::space(), true)?;
+    }
+
+    // Zeroes the tail so bytes left over from the Borsh layout are never read
+    // back as fields appended in a later version.
+    state.init(&mut vault_state.data.borrow_mut())
+}
+
+/// Writes `state` over `vault_state` for handlers without a payer to grow
+/// it, in whichever layout the account already has. Not yet migrated vault
+/// states that are too short are skipped instead of failing.
+fn write_vault_state(vault_state: &AccountInfo, state: &Vault) -> ProgramResult {
+    checks::assert_writable(&[vault_state])?;
+    match state.write(&mut vault_state.data.borrow_mut()) {
+        Err(ProgramError::AccountDataTooSmall) => Ok(()),
+        result => result,
+    }
+}
+
+/// Applies `update` to the vault state directly in account data, for
+/// handlers without a payer that only change a few fields of an account they
+/// did not load themselves. Not yet migrated accounts are decoded and written
+/// back in their own layout; see `write_vault_state` for what is skipped.
+fn update_vault_state(vault_state: &AccountInfo, update: impl FnOnce(&mut Vault) -> ProgramResult) -> ProgramResult {
+    checks::assert_writable(&[vault_state])?;
+    let mut data = vault_state.data.borrow_mut();
+    if let Ok(state) = Vault::load_mut(&mut data) {
+        return update(state);
+    }
+    let mut state = Vault::read(&data)?;
+    update(&mut state)?;
+    match state.write(&mut data) {
+        Err(ProgramError::AccountDataTooSmall) => Ok(()),
+        result => result,
+    }
+}
+
+/// Creates a rent-exempt account at a PDA of this program, paid by `payer`.
+fn create_pda_account<'a>(
+    payer: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    space: usize,
+    owner: &Pubkey,
+    seeds: &[&[u8]],
+) -> ProgramResult {
+    checks::assert_distinct(&[payer, account])?;
+    checks::assert_writable(&[payer, account])?;
+    let rent = Rent::get()?;
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            account.key,
+            rent.minimum_balance(space),
+            space as u64,
+            owner,
+        ),
+        &[payer.clone(), account.clone(), system_program.clone()],
+        &[seeds],
+    )
+}
+
+/// Drains a program-owned account into `destination` and zeroes its data.
+fn close_program_account<'a>(account: &AccountInfo<'a>, destination: &AccountInfo<'a>) -> ProgramResult {
+    checks::assert_distinct(&[account, destination])?;
+    checks::assert_writable(&[account, destination])?;
+    let lamports = account.lamports();
+    **account.lamports.borrow_mut() = 0;
+    **destination.lamports.borrow_mut() = destination
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(ProgramError::InvalidArgument)?;
+    account.data.borrow_mut().fill(0);
+    Ok(())
+}
+
 fn assert_vault_pdas(program_id: &Pubkey, vault_state: &AccountInfo, vault_auth: &AccountInfo, vault: &AccountInfo) -> ProgramResult {
     let (expected_vault_auth, _auth_bump) =
         Pubkey::find_program_address(&[b"auth", vault_state.key.as_ref()], program_id);
@@ -128,15 +632,17 @@ fn assert_vault_pdas(program_id: &Pubkey, vault_state: &AccountInfo, vault_auth:
     Ok(())
 }
 
-fn initialize(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+/// `proof` is only checked while the `["config"]` PDA gates new vaults.
+fn initialize(program_id: &Pubkey, accounts: &[AccountInfo], custody: Custody, proof: &[[u8; 32]]) -> ProgramResult {
     let mut accounts_iter = accounts.iter();
     let owner = next_account_info(&mut accounts_iter)?;
     let vault_state = next_account_info(&mut accounts_iter)?;
     let vault_auth = next_account_info(&mut accounts_iter)?;
     let vault = next_account_info(&mut accounts_iter)?;
     let system_program = next_account_info(&mut accounts_iter)?;
+    let config = next_account_info(&mut accounts_iter)?;
 
-    msg!("Initialize: system-owned vault mode");
+    msg!("Initialize: {:?} vault mode", custody);
     msg!("Initialize: vault PDA {} owner {} lamports {}", vault.key, vault.owner, vault.lamports());
 
     if !owner.is_signer {
@@ -147,7 +653,11 @@ fn initialize(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
         return Err(ProgramError::MissingRequiredSignature);
     }
 
+    checks::assert_distinct(&[owner, vault_state, vault_auth, vault, system_program, config])?;
+    checks::assert_writable(&[owner, vault_state, vault])?;
+
     assert_system_program(system_program)?;
+    gate::assert_may_open_vault(program_id, config, owner.key, proof)?;
 
     // vaultAuth PDA = ["auth", vaultState]
     let (expected_vault_auth, auth_bump) =
@@ -165,8 +675,15 @@ fn initialize(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
         return Err(WbaVaultError::InvalidPda.into());
     }
 
-    // Create vault_state account (program-owned) if needed.
-    if vault_state.owner != program_id {
+    // Create vault_state account (program-owned) if needed. One the program
+    // already owns is only taken over while it holds no state; otherwise
+    // whoever holds the vault_state keypair could reset a live vault.
+    if vault_state.owner == program_id {
+        if vault_state.data.borrow().iter().any(|byte| *byte != 0) {
+            msg!("Vault state is already initialized");
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+    } else {
         let rent = Rent::get()?;
         let space = Vault::space();
         let lamports = rent.minimum_balance(space);
@@ -183,22 +700,23 @@ fn initialize(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
         )?;
     }
 
-    // The vault PDA must be SYSTEM-owned so we can withdraw SOL via
-    // invoke_signed(system_instruction::transfer).
-    // If it already exists but is NOT system-owned, we cannot fix it in-place;
+    // The vault PDA must be owned as the custody mode says, since that decides
+    // how SOL is withdrawn (see custody::transfer_from_vault).
+    // If it already exists with another owner, we cannot fix it in-place;
     // you must initialize a fresh vaultState (which derives a fresh vault PDA).
-    if vault.lamports() > 0 && vault.owner != &solana_program::system_program::id() {
-        msg!("Vault PDA already exists but is not system-owned. Re-initialize with a new vaultState.");
+    let vault_owner = custody.vault_owner(program_id);
+    if vault.lamports() > 0 && vault.owner != &vault_owner {
+        msg!("Vault PDA already exists with another owner. Re-initialize with a new vaultState.");
         return Err(ProgramError::InvalidAccountOwner);
     }
 
-    // Create vault PDA account (system-owned, holds lamports)
+    // Create vault PDA account (holds lamports)
     if vault.lamports() == 0 && vault.data_is_empty() {
         let rent = Rent::get()?;
         let space = 0usize;
         let lamports = rent.minimum_balance(space);
 
-        msg!("Initialize: creating vault PDA as system-owned");
+        msg!("Initialize: creating vault PDA owned by {}", vault_owner);
 
         invoke_signed(
             &system_instruction::create_account(
@@ -206,7 +724,7 @@ fn initialize(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
                 vault.key,
                 lamports,
                 space as u64,
-                &solana_program::system_program::id(),
+                &vault_owner,
             ),
             &[owner.clone(), vault.clone(), system_program.clone()],
             &[&[b"vault", vault_auth.key.as_ref(), &[vault_bump]]],
@@ -215,16 +733,14 @@ fn initialize(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
         msg!("Initialize: vault PDA created; owner {} lamports {}", vault.owner, vault.lamports());
     }
 
-    let state = Vault {
-        owner: *owner.key,
-        auth_bump,
-        vault_bump,
-        score: 0,
-    };
+    let mut state = Vault::zeroed();
+    state.owner = *owner.key;
+    state.auth_bump = auth_bump;
+    state.vault_bump = vault_bump;
+    state.set_custody(custody);
+    state.last_activity_slot = Clock::get()?.slot;
 
-    state
-        .serialize(&mut &mut vault_state.data.borrow_mut()[..])
-        .map_err(|_| ProgramError::AccountDataTooSmall)?;
+    state.init(&mut vault_state.data.borrow_mut())?;
 
     msg!("Vault initialized");
     Ok(())
@@ -242,14 +758,29 @@ fn deposit(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> Progra
         return Err(ProgramError::MissingRequiredSignature);
     }
 
+    checks::assert_distinct(&[owner, vault_state, vault_auth, vault, system_program])?;
+    checks::assert_writable(&[owner, vault])?;
+
     assert_system_program(system_program)?;
-    let _state = load_vault_state(program_id, owner, vault_state)?;
+    let mut state = load_vault_state(program_id, owner, vault_state)?;
     assert_vault_pdas(program_id, vault_state, vault_auth, vault)?;
 
     invoke(
         &system_instruction::transfer(owner.key, vault.key, amount),
         &[owner.clone(), vault.clone(), system_program.clone()],
     )?;
+    state.record_sol_deposit(amount)?;
+    save_vault_state(owner, vault_state, system_program, &state)?;
+    receipt::mint_receipts(
+        program_id,
+        accounts,
+        vault_state,
+        vault_auth,
+        &state,
+        owner,
+        &Pubkey::default(),
+        amount,
+    )?;
 
     msg!("Deposit successful");
     Ok(())
@@ -267,41 +798,32 @@ fn withdraw(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> Progr
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    if system_program.key != &solana_program::system_program::id() {
-        return Err(WbaVaultError::InvalidSystemProgram.into());
-    }
-
-    if vault_state.owner != program_id {
-        return Err(WbaVaultError::InvalidVaultStateOwner.into());
-    }
+    checks::assert_distinct(&[owner, vault_state, vault_auth, vault, system_program])?;
+    checks::assert_writable(&[owner, vault])?;
 
-    let state = Vault::try_from_slice(&vault_state.data.borrow())
-        .map_err(|_| ProgramError::InvalidAccountData)?;
-
-    if state.owner != *owner.key {
-        return Err(WbaVaultError::InvalidSigner.into());
-    }
-
-    let (expected_vault_auth, _auth_bump) =
-        Pubkey::find_program_address(&[b"auth", vault_state.key.as_ref()], program_id);
-
-    if vault_auth.key != &expected_vault_auth {
-        return Err(WbaVaultError::InvalidPda.into());
-    }
-
-    let (expected_vault, _vault_bump) =
-        Pubkey::find_program_address(&[b"vault", vault_auth.key.as_ref()], program_id);
+    assert_system_program(system_program)?;
+    let mut state = load_vault_state(program_id, owner, vault_state)?;
+    multisig::assert_threshold(&state, accounts)?;
+    lock::assert_unlocked(&state)?;
+    pool::assert_not_pooled(&state)?;
+    allowlist::assert_allowed_recipient(program_id, vault_state, &state, accounts, owner.key)?;
 
-    if vault.key != &expected_vault {
-        return Err(WbaVaultError::InvalidPda.into());
-    }
+    assert_vault_pdas(program_id, vault_state, vault_auth, vault)?;
 
-    invoke_signed(
-        &system_instruction::transfer(vault.key, owner.key, amount),
-        &[vault.clone(), owner.clone(), system_program.clone()],
-        &[&[b"vault", vault_auth.key.as_ref(), &[state.vault_bump]]],
+    limit::consume_sol_limit(owner, vault_state, system_program, &mut state, amount)?;
+    receipt::burn_receipts(
+        program_id,
+        accounts,
+        vault_state,
+        &state,
+        owner,
+        &Pubkey::default(),
+        amount,
     )?;
 
+    state.record_sol_withdrawal(amount)?;
+    custody::transfer_from_vault(program_id, owner, vault_state, vault_auth, vault, owner, system_program, &mut state, amount)?;
+
     msg!("Withdraw successful");
     Ok(())
 }
@@ -322,9 +844,22 @@ fn deposit_spl(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> Pr
         return Err(ProgramError::MissingRequiredSignature);
     }
 
+    checks::assert_distinct(&[
+        owner,
+        owner_ata,
+        vault_state,
+        vault_auth,
+        vault_ata,
+        token_mint,
+        token_program,
+        _associated_token_program,
+        system_program,
+    ])?;
+    checks::assert_writable(&[owner_ata, vault_ata])?;
+
     assert_system_program(system_program)?;
     assert_token_program(token_program)?;
-    let _state = load_vault_state(program_id, owner, vault_state)?;
+    let mut state = load_vault_state(program_id, owner, vault_state)?;
 
     let (expected_vault_auth, _auth_bump) =
         Pubkey::find_program_address(&[b"auth", vault_state.key.as_ref()], program_id);
@@ -342,6 +877,7 @@ fn deposit_spl(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> Pr
         return Err(WbaVaultError::InvalidTokenAccount.into());
     }
 
+    state.record_token_deposit(stats::token_balance(vault_ata), amount)?;
     let ix = token_instruction::transfer(
         token_program.key,
         owner_ata.key,
@@ -360,6 +896,18 @@ fn deposit_spl(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> Pr
             token_program.clone(),
         ],
     )?;
+    receipt::mint_receipts(
+        program_id,
+        accounts,
+        vault_state,
+        vault_auth,
+        &state,
+        owner,
+        token_mint.key,
+        amount,
+    )?;
+    ledger::credit_ledger(program_id, accounts, owner, vault_state, system_program, &mut state, token_mint.key, amount)?;
+    save_vault_state(owner, vault_state, system_program, &state)?;
 
     msg!("Deposit SPL successful");
     Ok(())
@@ -376,14 +924,33 @@ fn withdraw_spl(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> P
     let token_program = next_account_info(&mut accounts_iter)?;
     let _associated_token_program = next_account_info(&mut accounts_iter)?;
     let system_program = next_account_info(&mut accounts_iter)?;
+    // Required once the vault has any per-mint withdraw limit.
+    let mint_limit = limit::next_mint_limit(program_id, &mut accounts_iter, vault_state.key, token_mint.key);
 
     if !owner.is_signer {
         return Err(ProgramError::MissingRequiredSignature);
     }
 
+    checks::assert_distinct(&[
+        owner,
+        owner_ata,
+        vault_state,
+        vault_auth,
+        vault_ata,
+        token_mint,
+        token_program,
+        _associated_token_program,
+        system_program,
+    ])?;
+    checks::assert_writable(&[owner_ata, vault_ata])?;
+
     assert_system_program(system_program)?;
     assert_token_program(token_program)?;
-    let state = load_vault_state(program_id, owner, vault_state)?;
+    let mut state = load_vault_state(program_id, owner, vault_state)?;
+    multisig::assert_threshold(&state, accounts)?;
+    lock::assert_unlocked(&state)?;
+    pool::assert_not_pooled(&state)?;
+    allowlist::assert_allowed_recipient(program_id, vault_state, &state, accounts, owner.key)?;
 
     let (expected_vault_auth, _auth_bump) =
         Pubkey::find_program_address(&[b"auth", vault_state.key.as_ref()], program_id);
@@ -401,6 +968,25 @@ fn withdraw_spl(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> P
         return Err(WbaVaultError::InvalidTokenAccount.into());
     }
 
+    limit::consume_mint_limit(
+        program_id,
+        vault_state,
+        &state,
+        token_mint.key,
+        mint_limit,
+        amount,
+    )?;
+    receipt::burn_receipts(
+        program_id,
+        accounts,
+        vault_state,
+        &state,
+        owner,
+        token_mint.key,
+        amount,
+    )?;
+    ledger::debit_ledger(program_id, accounts, vault_state, &state, token_mint.key, amount)?;
+
     let ix = token_instruction::transfer(
         token_program.key,
         vault_ata.key,
@@ -420,6 +1006,9 @@ fn withdraw_spl(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> P
         ],
         &[&[b"auth", vault_state.key.as_ref(), &[state.auth_bump]]],
     )?;
+    state.record_token_withdrawal(stats::token_balance(vault_ata), amount)?;
+    score::release_nft(program_id, accounts, owner, vault_state, token_mint.key, &mut state, amount)?;
+    save_vault_state(owner, vault_state, system_program, &state)?;
 
     msg!("Withdraw SPL successful");
     Ok(())
@@ -446,9 +1035,25 @@ fn deposit_nft(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
         return Err(ProgramError::MissingRequiredSignature);
     }
 
+    checks::assert_distinct(&[
+        owner,
+        owner_ata,
+        vault_state,
+        vault_auth,
+        vault_ata,
+        token_mint,
+        nft_metadata,
+        nft_master_edition,
+        metadata_program,
+        token_program,
+        _associated_token_program,
+        system_program,
+    ])?;
+    checks::assert_writable(&[owner_ata, vault_ata])?;
+
     assert_system_program(system_program)?;
     assert_token_program(token_program)?;
-    let _state = load_vault_state(program_id, owner, vault_state)?;
+    let mut state = load_vault_state(program_id, owner, vault_state)?;
 
     let (expected_vault_auth, _auth_bump) =
         Pubkey::find_program_address(&[b"auth", vault_state.key.as_ref()], program_id);
@@ -467,33 +1072,9 @@ fn deposit_nft(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
         return Err(WbaVaultError::InvalidTokenAccount.into());
     }
 
-    // Validate Metadata PDAs under the provided metadata program.
-    let (expected_metadata, _bump) = Pubkey::find_program_address(
-        &[b"metadata", metadata_program.key.as_ref(), token_mint.key.as_ref()],
-        metadata_program.key,
-    );
-    if nft_metadata.key != &expected_metadata {
-        return Err(WbaVaultError::InvalidPda.into());
-    }
-
-    let (expected_edition, _bump) = Pubkey::find_program_address(
-        &[
-            b"metadata",
-            metadata_program.key.as_ref(),
-            token_mint.key.as_ref(),
-            b"edition",
-        ],
-        metadata_program.key,
-    );
-    if nft_master_edition.key != &expected_edition {
-        return Err(WbaVaultError::InvalidPda.into());
-    }
-
-    // We only validate the metadata program is executable as a sanity check.
-    if !metadata_program.executable {
-        return Err(WbaVaultError::InvalidMetadataProgram.into());
-    }
+    assert_nft_accounts(token_mint, nft_metadata, nft_master_edition, metadata_program)?;
 
+    state.record_token_deposit(stats::token_balance(vault_ata), 1)?;
     let ix = token_instruction::transfer(
         token_program.key,
         owner_ata.key,
@@ -512,6 +1093,20 @@ fn deposit_nft(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
             token_program.clone(),
         ],
     )?;
+    receipt::mint_receipts(
+        program_id,
+        accounts,
+        vault_state,
+        vault_auth,
+        &state,
+        owner,
+        token_mint.key,
+        1,
+    )?;
+    ledger::credit_ledger(program_id, accounts, owner, vault_state, system_program, &mut state, token_mint.key, 1)?;
+
+    score::stake_nft(program_id, accounts, owner, vault_state, token_mint, system_program, &mut state)?;
+    save_vault_state(owner, vault_state, system_program, &state)?;
 
     msg!("Deposit NFT successful");
     Ok(())
@@ -532,14 +1127,36 @@ fn withdraw_nft(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult
     let token_program = next_account_info(&mut accounts_iter)?;
     let _associated_token_program = next_account_info(&mut accounts_iter)?;
     let system_program = next_account_info(&mut accounts_iter)?;
+    // Required once the vault has any per-mint withdraw limit.
+    let mint_limit = limit::next_mint_limit(program_id, &mut accounts_iter, vault_state.key, token_mint.key);
 
     if !owner.is_signer {
         return Err(ProgramError::MissingRequiredSignature);
     }
 
+    checks::assert_distinct(&[
+        owner,
+        owner_ata,
+        vault_state,
+        vault_auth,
+        vault_ata,
+        token_mint,
+        nft_metadata,
+        nft_master_edition,
+        metadata_program,
+        token_program,
+        _associated_token_program,
+        system_program,
+    ])?;
+    checks::assert_writable(&[owner_ata, vault_ata])?;
+
     assert_system_program(system_program)?;
     assert_token_program(token_program)?;
-    let state = load_vault_state(program_id, owner, vault_state)?;
+    let mut state = load_vault_state(program_id, owner, vault_state)?;
+    multisig::assert_threshold(&state, accounts)?;
+    lock::assert_unlocked(&state)?;
+    pool::assert_not_pooled(&state)?;
+    allowlist::assert_allowed_recipient(program_id, vault_state, &state, accounts, owner.key)?;
 
     let (expected_vault_auth, _auth_bump) =
         Pubkey::find_program_address(&[b"auth", vault_state.key.as_ref()], program_id);
@@ -557,30 +1174,27 @@ fn withdraw_nft(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult
         return Err(WbaVaultError::InvalidTokenAccount.into());
     }
 
-    let (expected_metadata, _bump) = Pubkey::find_program_address(
-        &[b"metadata", metadata_program.key.as_ref(), token_mint.key.as_ref()],
-        metadata_program.key,
-    );
-    if nft_metadata.key != &expected_metadata {
-        return Err(WbaVaultError::InvalidPda.into());
-    }
-
-    let (expected_edition, _bump) = Pubkey::find_program_address(
-        &[
-            b"metadata",
-            metadata_program.key.as_ref(),
-            token_mint.key.as_ref(),
-            b"edition",
-        ],
-        metadata_program.key,
-    );
-    if nft_master_edition.key != &expected_edition {
-        return Err(WbaVaultError::InvalidPda.into());
-    }
+    assert_nft_accounts(token_mint, nft_metadata, nft_master_edition, metadata_program)?;
+    score::assert_nft(token_mint)?;
 
-    if !metadata_program.executable {
-        return Err(WbaVaultError::InvalidMetadataProgram.into());
-    }
+    limit::consume_mint_limit(
+        program_id,
+        vault_state,
+        &state,
+        token_mint.key,
+        mint_limit,
+        1,
+    )?;
+    receipt::burn_receipts(
+        program_id,
+        accounts,
+        vault_state,
+        &state,
+        owner,
+        token_mint.key,
+        1,
+    )?;
+    ledger::debit_ledger(program_id, accounts, vault_state, &state, token_mint.key, 1)?;
 
     let ix = token_instruction::transfer(
         token_program.key,
@@ -601,6 +1215,10 @@ fn withdraw_nft(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult
         ],
         &[&[b"auth", vault_state.key.as_ref(), &[state.auth_bump]]],
     )?;
+    state.record_token_withdrawal(stats::token_balance(vault_ata), 1)?;
+
+    score::release_nft(program_id, accounts, owner, vault_state, token_mint.key, &mut state, 1)?;
+    save_vault_state(owner, vault_state, system_program, &state)?;
 
     msg!("Withdraw NFT successful");
     Ok(())
@@ -617,10 +1235,29 @@ fn close_account(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult
         return Err(ProgramError::MissingRequiredSignature);
     }
 
+    // The vault state is often closed back to its owner.
+    checks::assert_no_aliases(&[vault_state, system_program], &[owner, close_vault_state])?;
+    checks::assert_writable(&[close_vault_state, vault_state])?;
+
     assert_system_program(system_program)?;
 
     // Must be program-owned so we can mutate lamports/data.
-    let _state = load_vault_state(program_id, owner, vault_state)?;
+    let state = load_vault_state(program_id, owner, vault_state)?;
+    multisig::assert_threshold(&state, accounts)?;
+    lock::assert_unlocked(&state)?;
+    pool::assert_not_pooled(&state)?;
+    receipt::assert_no_receipts(&state)?;
+    // Stake accounts are only withdrawn, and NFT stake records only closed,
+    // through a loadable vault state.
+    if !state.stake_accounts().is_empty() || state.staked_nfts > 0 {
+        msg!("Vault still has stake accounts or staked NFTs");
+        return Err(WbaVaultError::VaultNotEmpty.into());
+    }
+    // Offers are paid into, and vestings closed back to, this vault.
+    if state.open_offers > 0 || state.open_vestings > 0 {
+        msg!("Vault still has open offers or vestings");
+        return Err(WbaVaultError::VaultNotEmpty.into());
+    }
 
     // Move lamports to the close destination (often the owner).
     let lamports = **vault_state.lamports.borrow();
@@ -639,3 +1276,53 @@ fn close_account(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult
     msg!("Vault state closed");
     Ok(())
 }
+
+/// Reallocs vault_state to `size` bytes so later fields fit without
+/// recreating the vault. Growing is paid by the owner up to the new
+/// rent-exempt minimum; shrinking refunds the excess. The runtime caps growth
+/// at `MAX_PERMITTED_DATA_INCREASE` per instruction.
+fn resize(program_id: &Pubkey, accounts: &[AccountInfo], size: u64) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    checks::assert_distinct(&[owner, vault_state, system_program])?;
+    checks::assert_writable(&[owner, vault_state])?;
+
+    assert_system_program(system_program)?;
+    let state = load_vault_state(program_id, owner, vault_state)?;
+    multisig::assert_threshold(&state, accounts)?;
+
+    // Never below what the current layout needs to be written back.
+    let size = usize::try_from(size).map_err(|_| WbaVaultError::InvalidVaultSize)?;
+    if size < Vault::space() {
+        return Err(WbaVaultError::InvalidVaultSize.into());
+    }
+
+    let required = Rent::get()?.minimum_balance(size);
+    let current = vault_state.lamports();
+    if current < required {
+        invoke(
+            &system_instruction::transfer(owner.key, vault_state.key, required - current),
+            &[owner.clone(), vault_state.clone(), system_program.clone()],
+        )?;
+    } else if size < vault_state.data_len() {
+        **vault_state.lamports.borrow_mut() = required;
+        **owner.lamports.borrow_mut() = owner
+            .lamports()
+            .checked_add(current - required)
+            .ok_or(ProgramError::InvalidArgument)?;
+    }
+    vault_state.realloc(size, true)?;
+
+    // Rewrites the state and zeroes everything after it, new bytes included.
+    save_vault_state(owner, vault_state, system_program, &state)?;
+
+    msg!("Vault state resized to {} bytes", size);
+    Ok(())
+}
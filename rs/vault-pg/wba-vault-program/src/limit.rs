@@ -0,0 +1,350 @@
+use std::slice::Iter;
+
+use bytemuck::{Pod, Zeroable};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    allowlist::is_allowlist,
+    assert_system_program,
+    checks::{assert_distinct, assert_writable},
+    create_pda_account,
+    ledger::is_ledger,
+    load_vault_state,
+    multisig::{assert_threshold, next_optional_account},
+    save_vault_state,
+    score::is_nft_stake,
+    zero_copy::{PodBool, ZeroCopy},
+    Vault, WbaVaultError,
+};
+
+/// Spend cap over a window. `window_secs == 0` means the window is the
+/// current epoch; otherwise a window opens on the first withdrawal after the
+/// previous one ended and lasts `window_secs` seconds.
+///
+/// Loosening a limit never applies immediately: it is parked in `pending` and
+/// takes effect when the current window ends, so a stolen owner key cannot lift
+/// the cap and drain the vault in one go.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Pod, Zeroable)]
+pub struct WithdrawLimit {
+    /// Maximum amount per window; 0 means unlimited.
+    pub max_amount: u64,
+    pub window_secs: i64,
+    /// Unix timestamp, or epoch when `window_secs == 0`.
+    pub window_start: i64,
+    pub spent: u64,
+    has_pending: PodBool,
+    _padding: [u8; 7],
+    pending_max_amount: u64,
+    pending_window_secs: i64,
+}
+
+impl WithdrawLimit {
+    /// `(max_amount, window_secs)` to switch to when the window ends.
+    pub fn pending(&self) -> Option<(u64, i64)> {
+        self.has_pending
+            .get()
+            .then_some((self.pending_max_amount, self.pending_window_secs))
+    }
+
+    pub fn set_pending(&mut self, pending: Option<(u64, i64)>) {
+        let (max_amount, window_secs) = pending.unwrap_or_default();
+        self.has_pending = pending.is_some().into();
+        self.pending_max_amount = max_amount;
+        self.pending_window_secs = window_secs;
+    }
+
+    fn window_key(window_secs: i64, clock: &Clock) -> i64 {
+        if window_secs == 0 {
+            clock.epoch as i64
+        } else {
+            clock.unix_timestamp
+        }
+    }
+
+    fn window_ended(&self, clock: &Clock) -> bool {
+        if self.window_secs == 0 {
+            clock.epoch as i64 != self.window_start
+        } else {
+            clock.unix_timestamp >= self.window_start.saturating_add(self.window_secs)
+        }
+    }
+
+    /// Starts a new window if the current one is over, applying any pending
+    /// change first.
+    fn roll(&mut self, clock: &Clock) {
+        if !self.window_ended(clock) {
+            return;
+        }
+        if let Some((max_amount, window_secs)) = self.pending() {
+            self.set_pending(None);
+            self.max_amount = max_amount;
+            self.window_secs = window_secs;
+        }
+        self.window_start = Self::window_key(self.window_secs, clock);
+        self.spent = 0;
+    }
+
+    /// Records `amount` against the current window, failing once the cap
+    /// would be exceeded.
+    pub fn consume(&mut self, amount: u64, clock: &Clock) -> ProgramResult {
+        self.roll(clock);
+        if self.max_amount == 0 {
+            return Ok(());
+        }
+        let spent = self
+            .spent
+            .checked_add(amount)
+            .ok_or(ProgramError::InvalidArgument)?;
+        if spent > self.max_amount {
+            msg!(
+                "Withdraw limit exceeded: {} of {} already spent this window",
+                self.spent,
+                self.max_amount
+            );
+            return Err(WbaVaultError::WithdrawLimitExceeded.into());
+        }
+        self.spent = spent;
+        Ok(())
+    }
+
+    /// Applies a new cap. Tightening (a lower cap on the same window, or any
+    /// cap where there was none) is immediate; everything else waits for the
+    /// current window to end.
+    pub fn update(&mut self, max_amount: u64, window_secs: i64, clock: &Clock) {
+        self.roll(clock);
+        if self.max_amount == 0 {
+            if max_amount != 0 {
+                self.max_amount = max_amount;
+                self.window_secs = window_secs;
+                self.window_start = Self::window_key(window_secs, clock);
+                self.spent = 0;
+            }
+            self.set_pending(None);
+            return;
+        }
+
+        let tightens =
+            max_amount != 0 && max_amount <= self.max_amount && window_secs == self.window_secs;
+        if tightens {
+            self.max_amount = max_amount;
+            self.set_pending(None);
+        } else {
+            self.set_pending(Some((max_amount, window_secs)));
+        }
+    }
+}
+
+/// Per-mint cap stored at `["limit", vaultState, mint]`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct MintLimit {
+    pub vault_state: Pubkey,
+    pub mint: Pubkey,
+    pub limit: WithdrawLimit,
+    pub bump: u8,
+    _padding: [u8; 7],
+}
+
+impl ZeroCopy for MintLimit {
+    const DISCRIMINATOR: [u8; 8] = *b"wbalimit";
+}
+
+/// Charges a SOL outflow against the vault's SOL limit, persisting the new
+/// usage only when a limit is configured.
+pub(crate) fn consume_sol_limit<'a>(
+    payer: &AccountInfo<'a>,
+    vault_state: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    state: &mut Vault,
+    amount: u64,
+) -> ProgramResult {
+    if state.sol_limit == WithdrawLimit::default() {
+        return Ok(());
+    }
+    state.sol_limit.consume(amount, &Clock::get()?)?;
+    save_vault_state(payer, vault_state, system_program, state)
+}
+
+/// Reads the optional mint limit slot of a token outflow; see
+/// `filter_mint_limit`.
+pub(crate) fn next_mint_limit<'a, 'b>(
+    program_id: &Pubkey,
+    accounts_iter: &mut Iter<'a, AccountInfo<'b>>,
+    vault_state: &Pubkey,
+    mint: &Pubkey,
+) -> Option<&'a AccountInfo<'b>> {
+    let account = next_optional_account(accounts_iter);
+    filter_mint_limit(program_id, account, vault_state, mint)
+}
+
+/// Drops the account read from a mint limit slot if it is really the ledger,
+/// NFT stake record or allowlist. Those are found by address and go last, so
+/// without a limit any of them may sit in the slot instead.
+pub(crate) fn filter_mint_limit<'a, 'b>(
+    program_id: &Pubkey,
+    account: Option<&'a AccountInfo<'b>>,
+    vault_state: &Pubkey,
+    mint: &Pubkey,
+) -> Option<&'a AccountInfo<'b>> {
+    account.filter(|account| {
+        !is_ledger(program_id, account, vault_state, mint)
+            && !is_nft_stake(program_id, account, vault_state, mint)
+            && !is_allowlist(program_id, account, vault_state)
+    })
+}
+
+/// Charges a token outflow against the `["limit", vaultState, mint]` PDA.
+///
+/// Once any mint limit exists the PDA must be passed for every token
+/// withdrawal; an uninitialized PDA simply means that mint has no limit.
+pub(crate) fn consume_mint_limit(
+    program_id: &Pubkey,
+    vault_state: &AccountInfo,
+    state: &Vault,
+    mint: &Pubkey,
+    limit_account: Option<&AccountInfo>,
+    amount: u64,
+) -> ProgramResult {
+    let limit_account = match limit_account {
+        Some(limit_account) => limit_account,
+        None if state.mint_limit_count == 0 => return Ok(()),
+        None => return Err(WbaVaultError::MissingWithdrawLimit.into()),
+    };
+
+    let (expected_limit, _bump) = Pubkey::find_program_address(
+        &[b"limit", vault_state.key.as_ref(), mint.as_ref()],
+        program_id,
+    );
+    if limit_account.key != &expected_limit {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+
+    if limit_account.owner != program_id {
+        return Ok(());
+    }
+
+    let mut mint_limit = MintLimit::read(&limit_account.data.borrow())?;
+    mint_limit.limit.consume(amount, &Clock::get()?)?;
+    mint_limit.write(&mut limit_account.data.borrow_mut())
+}
+
+pub(crate) fn set_withdraw_limit(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    max_amount: u64,
+    window_secs: i64,
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[owner, vault_state, system_program])?;
+
+    if window_secs < 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    assert_system_program(system_program)?;
+    let mut state = load_vault_state(program_id, owner, vault_state)?;
+    assert_threshold(&state, accounts)?;
+
+    state
+        .sol_limit
+        .update(max_amount, window_secs, &Clock::get()?);
+    save_vault_state(owner, vault_state, system_program, &state)?;
+
+    msg!("Set withdraw limit successful");
+    Ok(())
+}
+
+pub(crate) fn set_mint_withdraw_limit(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    max_amount: u64,
+    window_secs: i64,
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let mint = next_account_info(&mut accounts_iter)?;
+    let limit_account = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[owner, vault_state, mint, limit_account, system_program])?;
+    assert_writable(&[limit_account])?;
+
+    if window_secs < 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    assert_system_program(system_program)?;
+    let mut state = load_vault_state(program_id, owner, vault_state)?;
+    assert_threshold(&state, accounts)?;
+
+    // limit PDA = ["limit", vaultState, mint]
+    let (expected_limit, bump) = Pubkey::find_program_address(
+        &[b"limit", vault_state.key.as_ref(), mint.key.as_ref()],
+        program_id,
+    );
+    if limit_account.key != &expected_limit {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+
+    let clock = Clock::get()?;
+    let mut mint_limit = if limit_account.owner == program_id {
+        MintLimit::read(&limit_account.data.borrow())?
+    } else {
+        create_pda_account(
+            owner,
+            limit_account,
+            system_program,
+            MintLimit::SPACE,
+            program_id,
+            &[
+                b"limit",
+                vault_state.key.as_ref(),
+                mint.key.as_ref(),
+                &[bump],
+            ],
+        )?;
+
+        state.mint_limit_count = state
+            .mint_limit_count
+            .checked_add(1)
+            .ok_or(ProgramError::InvalidArgument)?;
+        save_vault_state(owner, vault_state, system_program, &state)?;
+
+        let mint_limit = MintLimit {
+            vault_state: *vault_state.key,
+            mint: *mint.key,
+            bump,
+            ..MintLimit::zeroed()
+        };
+        mint_limit.init(&mut limit_account.data.borrow_mut())?;
+        mint_limit
+    };
+
+    mint_limit.limit.update(max_amount, window_secs, &clock);
+    mint_limit.write(&mut limit_account.data.borrow_mut())?;
+
+    msg!("Set mint withdraw limit successful");
+    Ok(())
+}
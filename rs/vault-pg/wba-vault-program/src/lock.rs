@@ -0,0 +1,71 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    assert_system_program, checks::assert_distinct, load_vault_state, multisig::assert_threshold,
+    save_vault_state, Vault, WbaVaultError,
+};
+
+impl Vault {
+    /// A vault is locked until both its timestamp and its epoch have passed.
+    pub fn is_locked(&self, clock: &Clock) -> bool {
+        clock.unix_timestamp < self.locked_until || clock.epoch < self.locked_until_epoch
+    }
+}
+
+/// Rejects any instruction that moves funds out of a time-locked vault.
+pub(crate) fn assert_unlocked(state: &Vault) -> ProgramResult {
+    if state.is_locked(&Clock::get()?) {
+        msg!(
+            "Vault is locked until {} (epoch {})",
+            state.locked_until,
+            state.locked_until_epoch
+        );
+        return Err(WbaVaultError::VaultLocked.into());
+    }
+    Ok(())
+}
+
+pub(crate) fn set_lock(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    locked_until: i64,
+    locked_until_epoch: u64,
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[owner, vault_state, system_program])?;
+
+    assert_system_program(system_program)?;
+    let mut state = load_vault_state(program_id, owner, vault_state)?;
+    assert_threshold(&state, accounts)?;
+
+    // Extending is always fine; shortening either bound is only allowed once
+    // the current lock has fully expired.
+    let shortens =
+        locked_until < state.locked_until || locked_until_epoch < state.locked_until_epoch;
+    if shortens && state.is_locked(&Clock::get()?) {
+        return Err(WbaVaultError::VaultLocked.into());
+    }
+
+    state.locked_until = locked_until;
+    state.locked_until_epoch = locked_until_epoch;
+    save_vault_state(owner, vault_state, system_program, &state)?;
+
+    msg!("Set lock successful");
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use std::slice::Iter;
+
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    assert_system_program, checks::assert_distinct, load_vault_state, save_vault_state, Vault,
+    WbaVaultError,
+};
+
+impl Vault {
+    pub const MAX_SIGNERS: usize = 10;
+
+    /// A vault is in multisig mode once it has a non-zero threshold; `owner`
+    /// is then only the key that created it.
+    pub fn is_multisig(&self) -> bool {
+        self.threshold > 0
+    }
+
+    /// Whether `key` may act for the vault on its own (e.g. deposit).
+    pub fn is_member(&self, key: &Pubkey) -> bool {
+        if self.is_multisig() {
+            self.signers().contains(key)
+        } else {
+            self.owner == *key
+        }
+    }
+}
+
+/// Requires `threshold` distinct listed signers among `accounts`. A no-op for
+/// single-owner vaults, where the handler has already checked the owner.
+///
+/// Co-signers may appear anywhere in the instruction, but are normally
+/// appended after all other accounts.
+pub(crate) fn assert_threshold(state: &Vault, accounts: &[AccountInfo]) -> ProgramResult {
+    if !state.is_multisig() {
+        return Ok(());
+    }
+
+    let signed = count_signers(state.signers(), accounts);
+    if signed < state.threshold as usize {
+        msg!(
+            "Multisig: {} of {} required signers",
+            signed,
+            state.threshold
+        );
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    Ok(())
+}
+
+/// Number of distinct `keys` that signed among `accounts`.
+pub(crate) fn count_signers(keys: &[Pubkey], accounts: &[AccountInfo]) -> usize {
+    let mut signed: Vec<&Pubkey> = Vec::with_capacity(keys.len());
+    for account in accounts {
+        if account.is_signer && keys.contains(account.key) && !signed.contains(&account.key) {
+            signed.push(account.key);
+        }
+    }
+    signed.len()
+}
+
+/// A key set with a threshold is valid when it is empty with a zero threshold,
+/// or holds at most `max` distinct keys and at least `threshold` of them.
+pub(crate) fn is_valid_key_set(keys: &[Pubkey], threshold: u8, max: usize) -> bool {
+    if threshold == 0 {
+        return keys.is_empty();
+    }
+    if keys.len() > max || threshold as usize > keys.len() {
+        return false;
+    }
+    keys.iter()
+        .enumerate()
+        .all(|(i, key)| !keys[..i].contains(key))
+}
+
+/// Returns the next account if it is one of the handler's optional trailing
+/// accounts. Those are PDAs and token accounts that never sign, so a signer in
+/// that position is a multisig co-signer and is left in place.
+pub(crate) fn next_optional_account<'a, 'b>(
+    accounts_iter: &mut Iter<'a, AccountInfo<'b>>,
+) -> Option<&'a AccountInfo<'b>> {
+    match accounts_iter.clone().next() {
+        Some(account) if !account.is_signer => accounts_iter.next(),
+        _ => None,
+    }
+}
+
+/// Replaces the vault's signer set. `threshold == 0` with no signers returns
+/// the vault to single-owner mode under the first account.
+pub(crate) fn set_multisig(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    signers: Vec<Pubkey>,
+    threshold: u8,
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[owner, vault_state, system_program])?;
+
+    assert_system_program(system_program)?;
+    let mut state = load_vault_state(program_id, owner, vault_state)?;
+    assert_threshold(&state, accounts)?;
+    if !is_valid_key_set(&signers, threshold, Vault::MAX_SIGNERS) {
+        return Err(WbaVaultError::InvalidMultisig.into());
+    }
+
+    if threshold == 0 {
+        state.owner = *owner.key;
+    }
+    state.set_signers(&signers)?;
+    state.threshold = threshold;
+    save_vault_state(owner, vault_state, system_program, &state)?;
+
+    msg!("Set multisig successful");
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use std::slice::Iter;
+
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program::invoke_signed,
+    pubkey::Pubkey,
+};
+
+use spl_associated_token_account::get_associated_token_address;
+use spl_token::instruction as token_instruction;
+
+use crate::{
+    assert_token_program,
+    checks::{assert_distinct, assert_writable},
+    custody::transfer_from_vault,
+    ledger::debit_ledger,
+    limit::{consume_mint_limit, consume_sol_limit, next_mint_limit},
+    save_vault_state,
+    score::{assert_nft, release_nft},
+    stats::token_balance,
+    Vault, WbaVaultError,
+};
+
+/// Pays `amount` lamports from the vault PDA to `recipient`, charging the
+/// vault's SOL limit. Shared by flows that pay someone other than the owner.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn pay_out_sol<'a>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    vault_state: &AccountInfo<'a>,
+    vault_auth: &AccountInfo<'a>,
+    vault: &AccountInfo<'a>,
+    recipient: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    vault_data: &mut Vault,
+    amount: u64,
+) -> ProgramResult {
+    assert_distinct(&[vault, recipient])?;
+    assert_writable(&[vault, recipient])?;
+    consume_sol_limit(payer, vault_state, system_program, vault_data, amount)?;
+    vault_data.record_sol_withdrawal(amount)?;
+
+    transfer_from_vault(
+        program_id,
+        payer,
+        vault_state,
+        vault_auth,
+        vault,
+        recipient,
+        system_program,
+        vault_data,
+        amount,
+    )
+}
+
+/// Pays `amount` of `mint` from the vault ATA to the recipient's ATA, reading
+/// `vault_ata, recipient_ata, mint, token_program` and the optional mint limit
+/// PDA from `accounts_iter`, then records the withdrawal and saves
+/// `vault_data`. With `nft` set the mint must have no decimals and a supply of
+/// one. A staked NFT's record is closed into `vault_state`, since the owner who
+/// paid for it need not be among the accounts.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn pay_out_tokens<'a, 'b>(
+    program_id: &Pubkey,
+    accounts_iter: &mut Iter<'a, AccountInfo<'b>>,
+    payer: &AccountInfo<'b>,
+    vault_state: &AccountInfo<'b>,
+    vault_auth: &AccountInfo<'b>,
+    system_program: &AccountInfo<'b>,
+    vault_data: &mut Vault,
+    recipient: &AccountInfo<'b>,
+    mint: &Pubkey,
+    amount: u64,
+    nft: bool,
+) -> ProgramResult {
+    let accounts = accounts_iter.as_slice();
+    let vault_ata = next_account_info(accounts_iter)?;
+    let recipient_ata = next_account_info(accounts_iter)?;
+    let token_mint = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let mint_limit = next_mint_limit(program_id, accounts_iter, vault_state.key, mint);
+
+    assert_distinct(&[vault_ata, recipient_ata, token_mint, token_program])?;
+    assert_writable(&[vault_ata, recipient_ata])?;
+    assert_token_program(token_program)?;
+    if token_mint.key != mint {
+        return Err(WbaVaultError::InvalidTokenAccount.into());
+    }
+
+    if nft {
+        assert_nft(token_mint)?;
+    }
+
+    let expected_vault_ata = get_associated_token_address(vault_auth.key, mint);
+    let expected_recipient_ata = get_associated_token_address(recipient.key, mint);
+    if vault_ata.key != &expected_vault_ata || recipient_ata.key != &expected_recipient_ata {
+        return Err(WbaVaultError::InvalidTokenAccount.into());
+    }
+
+    consume_mint_limit(
+        program_id,
+        vault_state,
+        vault_data,
+        mint,
+        mint_limit,
+        amount,
+    )?;
+    debit_ledger(program_id, accounts, vault_state, vault_data, mint, amount)?;
+
+    let ix = token_instruction::transfer(
+        token_program.key,
+        vault_ata.key,
+        recipient_ata.key,
+        vault_auth.key,
+        &[],
+        amount,
+    )?;
+
+    invoke_signed(
+        &ix,
+        &[
+            vault_ata.clone(),
+            recipient_ata.clone(),
+            vault_auth.clone(),
+            token_program.clone(),
+        ],
+        &[&[b"auth", vault_state.key.as_ref(), &[vault_data.auth_bump]]],
+    )?;
+
+    vault_data.record_token_withdrawal(token_balance(vault_ata), amount)?;
+    release_nft(
+        program_id,
+        accounts,
+        vault_state,
+        vault_state,
+        mint,
+        vault_data,
+        amount,
+    )?;
+    save_vault_state(payer, vault_state, system_program, vault_data)
+}
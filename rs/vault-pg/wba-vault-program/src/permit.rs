@@ -0,0 +1,205 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    ed25519_program,
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::{
+        instructions::{load_current_index_checked, load_instruction_at_checked},
+        Sysvar,
+    },
+};
+
+use crate::{
+    allowlist::assert_allowed_recipient,
+    assert_system_program, assert_vault_pdas,
+    checks::assert_no_aliases,
+    lock::assert_unlocked,
+    payout::{pay_out_sol, pay_out_tokens},
+    pool::assert_not_pooled,
+    receipt::assert_no_receipts,
+    save_vault_state, Vault, WbaVaultError,
+};
+
+/// An off-chain withdrawal authorization. The owner signs the Borsh encoding
+/// of this struct with Ed25519; `mint` is `Pubkey::default()` for SOL.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct Permit {
+    pub vault_state: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub recipient: Pubkey,
+    /// Must equal `Vault::permit_nonce`, which is bumped on every use.
+    pub nonce: u64,
+    pub expires_at: i64,
+}
+
+impl Permit {
+    /// The bytes the owner signs.
+    pub fn message(&self) -> Vec<u8> {
+        borsh::to_vec(self).unwrap()
+    }
+}
+
+/// Size of one signature offsets entry in Ed25519 program instruction data.
+const ED25519_OFFSETS_LEN: usize = 14;
+/// Offsets start after the signature count and a padding byte.
+const ED25519_OFFSETS_START: usize = 2;
+
+fn read_u16(data: &[u8], at: usize) -> Result<usize, ProgramError> {
+    data.get(at..at + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]) as usize)
+        .ok_or_else(|| WbaVaultError::InvalidPermit.into())
+}
+
+/// Checks that the instruction right before this one is an Ed25519 program
+/// instruction verifying one signature by `signer` over `message`. The runtime
+/// has already checked the signature itself; this only ties it to our data.
+fn assert_ed25519_signature(
+    instructions: &AccountInfo,
+    signer: &Pubkey,
+    message: &[u8],
+) -> ProgramResult {
+    if instructions.key != &solana_program::sysvar::instructions::id() {
+        return Err(WbaVaultError::InvalidPermit.into());
+    }
+
+    let current = load_current_index_checked(instructions)? as usize;
+    if current == 0 {
+        return Err(WbaVaultError::InvalidPermit.into());
+    }
+    let ix = load_instruction_at_checked(current - 1, instructions)?;
+    if ix.program_id != ed25519_program::id() {
+        return Err(WbaVaultError::InvalidPermit.into());
+    }
+
+    let data = &ix.data;
+    if data.len() < ED25519_OFFSETS_START + ED25519_OFFSETS_LEN || data[0] != 1 {
+        return Err(WbaVaultError::InvalidPermit.into());
+    }
+
+    // Every offset must point into the Ed25519 instruction itself, not into
+    // another instruction the attacker controls.
+    let offsets = ED25519_OFFSETS_START;
+    let signature_ix = read_u16(data, offsets + 2)?;
+    let public_key_offset = read_u16(data, offsets + 4)?;
+    let public_key_ix = read_u16(data, offsets + 6)?;
+    let message_offset = read_u16(data, offsets + 8)?;
+    let message_size = read_u16(data, offsets + 10)?;
+    let message_ix = read_u16(data, offsets + 12)?;
+    let this_ix = u16::MAX as usize;
+    if signature_ix != this_ix || public_key_ix != this_ix || message_ix != this_ix {
+        return Err(WbaVaultError::InvalidPermit.into());
+    }
+
+    let signed_key = data.get(public_key_offset..public_key_offset + 32);
+    let signed_message = data.get(message_offset..message_offset + message_size);
+    if signed_key != Some(signer.as_ref()) || signed_message != Some(message) {
+        return Err(WbaVaultError::InvalidPermit.into());
+    }
+    Ok(())
+}
+
+/// Pays out a withdrawal the owner signed off-chain. Anyone may submit it and
+/// pays the fees; locks, limits and the allowlist still apply.
+pub(crate) fn withdraw_with_permit(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    permit: Permit,
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let relayer = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let vault_auth = next_account_info(&mut accounts_iter)?;
+    let vault = next_account_info(&mut accounts_iter)?;
+    let recipient = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+    let instructions = next_account_info(&mut accounts_iter)?;
+
+    if !relayer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    // The relayer may also be the recipient.
+    assert_no_aliases(
+        &[vault_state, vault_auth, vault, system_program, instructions],
+        &[relayer, recipient],
+    )?;
+
+    assert_system_program(system_program)?;
+    if vault_state.owner != program_id {
+        return Err(WbaVaultError::InvalidVaultStateOwner.into());
+    }
+    let mut state = Vault::unpack(&vault_state.data.borrow())?;
+    assert_vault_pdas(program_id, vault_state, vault_auth, vault)?;
+
+    // A single key cannot speak for a multisig.
+    if state.is_multisig()
+        || permit.vault_state != *vault_state.key
+        || permit.recipient != *recipient.key
+    {
+        return Err(WbaVaultError::InvalidPermit.into());
+    }
+    assert_ed25519_signature(instructions, &state.owner, &permit.message())?;
+
+    let now = Clock::get()?.unix_timestamp;
+    if now >= permit.expires_at {
+        return Err(WbaVaultError::PermitExpired.into());
+    }
+    if permit.nonce != state.permit_nonce {
+        msg!(
+            "Permit nonce {}, expected {}",
+            permit.nonce,
+            state.permit_nonce
+        );
+        return Err(WbaVaultError::InvalidPermit.into());
+    }
+
+    assert_unlocked(&state)?;
+
+    assert_not_pooled(&state)?;
+
+    assert_no_receipts(&state)?;
+    assert_allowed_recipient(program_id, vault_state, &state, accounts, recipient.key)?;
+
+    // The owner signed, so this counts as activity for the dead-man switch.
+    state.permit_nonce += 1;
+    if state.has_inheritance() {
+        state.last_activity = now;
+    }
+    save_vault_state(relayer, vault_state, system_program, &state)?;
+
+    if permit.mint == Pubkey::default() {
+        pay_out_sol(
+            program_id,
+            relayer,
+            vault_state,
+            vault_auth,
+            vault,
+            recipient,
+            system_program,
+            &mut state,
+            permit.amount,
+        )?;
+    } else {
+        pay_out_tokens(
+            program_id,
+            &mut accounts_iter,
+            relayer,
+            vault_state,
+            vault_auth,
+            system_program,
+            &mut state,
+            recipient,
+            &permit.mint,
+            permit.amount,
+            false,
+        )?;
+    }
+
+    msg!("Withdraw with permit successful: nonce {}", permit.nonce);
+    Ok(())
+}
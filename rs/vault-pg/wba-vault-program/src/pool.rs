@@ -0,0 +1,518 @@
+use bytemuck::{Pod, Zeroable};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    system_instruction,
+};
+
+use spl_associated_token_account::get_associated_token_address;
+use spl_token::instruction as token_instruction;
+
+use crate::{
+    assert_system_program, assert_token_program, assert_vault_pdas,
+    checks::{assert_distinct, assert_writable},
+    create_pda_account,
+    custody::transfer_from_vault,
+    load_vault_state,
+    multisig::assert_threshold,
+    save_vault_state,
+    score::release_nft,
+    stats::token_balance,
+    zero_copy::ZeroCopy,
+    Vault, WbaVaultError,
+};
+
+/// A pooled balance at `["pool", vaultState, mint]`, where `mint` is
+/// `Pubkey::default()` for SOL. Depositors receive shares of
+/// `["shares", pool]`, minted by `vault_auth`, and burn them to redeem a
+/// pro-rata part of `total_assets`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct Pool {
+    pub vault_state: Pubkey,
+    pub mint: Pubkey,
+    pub share_mint: Pubkey,
+    /// Assets deposited and not yet redeemed. Direct transfers into the vault
+    /// are not counted, so they cannot move the share price.
+    pub total_assets: u64,
+    pub total_shares: u64,
+    pub bump: u8,
+    pub share_mint_bump: u8,
+    _padding: [u8; 6],
+}
+
+impl ZeroCopy for Pool {
+    const DISCRIMINATOR: [u8; 8] = *b"wbapool_";
+}
+
+impl Pool {
+    /// Shares and assets the price is computed as if the pool already held.
+    /// The offset makes a first deposit of one unit worth `VIRTUAL_SHARES`
+    /// shares, so an attacker cannot inflate the price of a near-empty pool
+    /// to round later depositors down to nothing.
+    pub const VIRTUAL_SHARES: u128 = 1_000;
+    pub const VIRTUAL_ASSETS: u128 = 1;
+
+    pub fn is_sol(&self) -> bool {
+        self.mint == Pubkey::default()
+    }
+
+    /// Shares minted for depositing `assets`, rounded down.
+    pub fn shares_for(&self, assets: u64) -> Option<u64> {
+        let shares = (assets as u128)
+            .checked_mul(self.total_shares as u128 + Self::VIRTUAL_SHARES)?
+            / (self.total_assets as u128 + Self::VIRTUAL_ASSETS);
+        u64::try_from(shares).ok()
+    }
+
+    /// Assets paid out for burning `shares`, rounded down.
+    pub fn assets_for(&self, shares: u64) -> Option<u64> {
+        let assets = (shares as u128)
+            .checked_mul(self.total_assets as u128 + Self::VIRTUAL_ASSETS)?
+            / (self.total_shares as u128 + Self::VIRTUAL_SHARES);
+        u64::try_from(assets).ok()
+    }
+}
+
+impl Vault {
+    /// Once a pool exists the vault's balances belong to its shareholders.
+    pub fn is_pooled(&self) -> bool {
+        self.pool_count > 0
+    }
+}
+
+/// Rejects owner-side instructions that move funds out of a pooled vault.
+pub(crate) fn assert_not_pooled(state: &Vault) -> ProgramResult {
+    if state.is_pooled() {
+        msg!("Vault funds belong to its pools");
+        return Err(WbaVaultError::VaultPooled.into());
+    }
+    Ok(())
+}
+
+fn load_pool(
+    program_id: &Pubkey,
+    vault_state: &AccountInfo,
+    pool: &AccountInfo,
+    share_mint: &AccountInfo,
+) -> Result<Pool, ProgramError> {
+    if pool.owner != program_id {
+        return Err(WbaVaultError::InvalidPool.into());
+    }
+
+    let state = Pool::read(&pool.data.borrow())?;
+
+    if state.vault_state != *vault_state.key || state.share_mint != *share_mint.key {
+        return Err(WbaVaultError::InvalidPool.into());
+    }
+
+    Ok(state)
+}
+
+fn save_pool(pool: &AccountInfo, state: &Pool) -> ProgramResult {
+    state.write(&mut pool.data.borrow_mut())
+}
+
+/// Opens a pool for `mint`. From then on the owner can no longer withdraw
+/// from the vault, so anything already in it stays with the vault for good.
+pub(crate) fn create_pool(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    mint: Pubkey,
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let vault_auth = next_account_info(&mut accounts_iter)?;
+    let pool = next_account_info(&mut accounts_iter)?;
+    let share_mint = next_account_info(&mut accounts_iter)?;
+    let token_program = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[
+        owner,
+        vault_state,
+        vault_auth,
+        pool,
+        share_mint,
+        token_program,
+        system_program,
+    ])?;
+    assert_writable(&[pool, share_mint])?;
+
+    assert_system_program(system_program)?;
+    assert_token_program(token_program)?;
+    let mut state = load_vault_state(program_id, owner, vault_state)?;
+    assert_threshold(&state, accounts)?;
+
+    let (expected_vault_auth, _auth_bump) =
+        Pubkey::find_program_address(&[b"auth", vault_state.key.as_ref()], program_id);
+    if vault_auth.key != &expected_vault_auth {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+
+    // Shares mirror the decimals of what they represent.
+    let decimals = if mint == Pubkey::default() {
+        9
+    } else {
+        let asset_mint = next_account_info(&mut accounts_iter)?;
+        assert_distinct(&[pool, share_mint, asset_mint])?;
+        if asset_mint.key != &mint || asset_mint.owner != token_program.key {
+            return Err(WbaVaultError::InvalidTokenAccount.into());
+        }
+        spl_token::state::Mint::unpack(&asset_mint.data.borrow())?.decimals
+    };
+
+    // pool PDA = ["pool", vaultState, mint]
+    let (expected_pool, bump) = Pubkey::find_program_address(
+        &[b"pool", vault_state.key.as_ref(), mint.as_ref()],
+        program_id,
+    );
+    if pool.key != &expected_pool {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+
+    // share mint PDA = ["shares", pool]
+    let (expected_share_mint, share_mint_bump) =
+        Pubkey::find_program_address(&[b"shares", pool.key.as_ref()], program_id);
+    if share_mint.key != &expected_share_mint {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+
+    create_pda_account(
+        owner,
+        pool,
+        system_program,
+        Pool::SPACE,
+        program_id,
+        &[b"pool", vault_state.key.as_ref(), mint.as_ref(), &[bump]],
+    )?;
+
+    create_pda_account(
+        owner,
+        share_mint,
+        system_program,
+        spl_token::state::Mint::LEN,
+        token_program.key,
+        &[b"shares", pool.key.as_ref(), &[share_mint_bump]],
+    )?;
+
+    invoke(
+        &token_instruction::initialize_mint2(
+            token_program.key,
+            share_mint.key,
+            vault_auth.key,
+            None,
+            decimals,
+        )?,
+        &[share_mint.clone(), token_program.clone()],
+    )?;
+
+    Pool {
+        vault_state: *vault_state.key,
+        mint,
+        share_mint: *share_mint.key,
+        bump,
+        share_mint_bump,
+        ..Pool::zeroed()
+    }
+    .init(&mut pool.data.borrow_mut())?;
+
+    state.pool_count = state
+        .pool_count
+        .checked_add(1)
+        .ok_or(ProgramError::InvalidArgument)?;
+    save_vault_state(owner, vault_state, system_program, &state)?;
+
+    msg!("Create pool successful");
+    Ok(())
+}
+
+/// Deposits `amount` into a pool and mints shares to the depositor at the
+/// current price. Anyone may deposit.
+pub(crate) fn deposit_to_pool(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let depositor = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let vault_auth = next_account_info(&mut accounts_iter)?;
+    let vault = next_account_info(&mut accounts_iter)?;
+    let pool = next_account_info(&mut accounts_iter)?;
+    let share_mint = next_account_info(&mut accounts_iter)?;
+    let depositor_shares = next_account_info(&mut accounts_iter)?;
+    let token_program = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !depositor.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[
+        depositor,
+        vault_state,
+        vault_auth,
+        vault,
+        pool,
+        share_mint,
+        depositor_shares,
+        token_program,
+        system_program,
+    ])?;
+    assert_writable(&[
+        depositor,
+        vault_state,
+        vault,
+        pool,
+        share_mint,
+        depositor_shares,
+    ])?;
+
+    assert_system_program(system_program)?;
+    assert_token_program(token_program)?;
+    if vault_state.owner != program_id {
+        return Err(WbaVaultError::InvalidVaultStateOwner.into());
+    }
+    let mut vault_data = Vault::unpack(&vault_state.data.borrow())?;
+    assert_vault_pdas(program_id, vault_state, vault_auth, vault)?;
+    let mut state = load_pool(program_id, vault_state, pool, share_mint)?;
+
+    let shares = state
+        .shares_for(amount)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    if shares == 0 {
+        msg!("Deposit of {} is worth no shares", amount);
+        return Err(WbaVaultError::InvalidPool.into());
+    }
+
+    if state.is_sol() {
+        vault_data.record_sol_deposit(amount)?;
+        invoke(
+            &system_instruction::transfer(depositor.key, vault.key, amount),
+            &[depositor.clone(), vault.clone(), system_program.clone()],
+        )?;
+    } else {
+        let depositor_ata = next_account_info(&mut accounts_iter)?;
+        let vault_ata = next_account_info(&mut accounts_iter)?;
+        assert_distinct(&[
+            depositor,
+            vault_state,
+            vault_auth,
+            vault,
+            pool,
+            share_mint,
+            depositor_shares,
+            token_program,
+            system_program,
+            depositor_ata,
+            vault_ata,
+        ])?;
+        assert_writable(&[depositor_ata, vault_ata])?;
+        if vault_ata.key != &get_associated_token_address(vault_auth.key, &state.mint) {
+            return Err(WbaVaultError::InvalidTokenAccount.into());
+        }
+
+        vault_data.record_token_deposit(token_balance(vault_ata), amount)?;
+        invoke(
+            &token_instruction::transfer(
+                token_program.key,
+                depositor_ata.key,
+                vault_ata.key,
+                depositor.key,
+                &[],
+                amount,
+            )?,
+            &[
+                depositor_ata.clone(),
+                vault_ata.clone(),
+                depositor.clone(),
+                token_program.clone(),
+            ],
+        )?;
+    }
+
+    invoke_signed(
+        &token_instruction::mint_to(
+            token_program.key,
+            share_mint.key,
+            depositor_shares.key,
+            vault_auth.key,
+            &[],
+            shares,
+        )?,
+        &[
+            share_mint.clone(),
+            depositor_shares.clone(),
+            vault_auth.clone(),
+            token_program.clone(),
+        ],
+        &[&[b"auth", vault_state.key.as_ref(), &[vault_data.auth_bump]]],
+    )?;
+
+    state.total_assets = state
+        .total_assets
+        .checked_add(amount)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    state.total_shares = state
+        .total_shares
+        .checked_add(shares)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    save_pool(pool, &state)?;
+    save_vault_state(depositor, vault_state, system_program, &vault_data)?;
+
+    msg!("Deposited {} for {} shares", amount, shares);
+    Ok(())
+}
+
+/// Burns `shares` from the holder and pays out their pro-rata part of the pool.
+pub(crate) fn redeem_shares(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    shares: u64,
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let holder = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let vault_auth = next_account_info(&mut accounts_iter)?;
+    let vault = next_account_info(&mut accounts_iter)?;
+    let pool = next_account_info(&mut accounts_iter)?;
+    let share_mint = next_account_info(&mut accounts_iter)?;
+    let holder_shares = next_account_info(&mut accounts_iter)?;
+    let token_program = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !holder.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[
+        holder,
+        vault_state,
+        vault_auth,
+        vault,
+        pool,
+        share_mint,
+        holder_shares,
+        token_program,
+        system_program,
+    ])?;
+    assert_writable(&[holder, vault_state, vault, pool, share_mint, holder_shares])?;
+
+    assert_system_program(system_program)?;
+    assert_token_program(token_program)?;
+    if vault_state.owner != program_id {
+        return Err(WbaVaultError::InvalidVaultStateOwner.into());
+    }
+    let mut vault_data = Vault::unpack(&vault_state.data.borrow())?;
+    assert_vault_pdas(program_id, vault_state, vault_auth, vault)?;
+    let mut state = load_pool(program_id, vault_state, pool, share_mint)?;
+
+    let amount = state
+        .assets_for(shares)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    if amount == 0 {
+        msg!("{} shares redeem for nothing", shares);
+        return Err(WbaVaultError::InvalidPool.into());
+    }
+
+    invoke(
+        &token_instruction::burn(
+            token_program.key,
+            holder_shares.key,
+            share_mint.key,
+            holder.key,
+            &[],
+            shares,
+        )?,
+        &[
+            holder_shares.clone(),
+            share_mint.clone(),
+            holder.clone(),
+            token_program.clone(),
+        ],
+    )?;
+
+    // Burning succeeded, so `shares <= total_shares` and, rounding down,
+    // `amount <= total_assets`.
+    state.total_shares -= shares;
+    state.total_assets -= amount;
+    save_pool(pool, &state)?;
+
+    if state.is_sol() {
+        vault_data.record_sol_withdrawal(amount)?;
+        transfer_from_vault(
+            program_id,
+            holder,
+            vault_state,
+            vault_auth,
+            vault,
+            holder,
+            system_program,
+            &mut vault_data,
+            amount,
+        )?;
+    } else {
+        let vault_ata = next_account_info(&mut accounts_iter)?;
+        let holder_ata = next_account_info(&mut accounts_iter)?;
+        assert_distinct(&[
+            holder,
+            vault_state,
+            vault_auth,
+            vault,
+            pool,
+            share_mint,
+            holder_shares,
+            token_program,
+            system_program,
+            vault_ata,
+            holder_ata,
+        ])?;
+        assert_writable(&[vault_ata, holder_ata])?;
+        if vault_ata.key != &get_associated_token_address(vault_auth.key, &state.mint) {
+            return Err(WbaVaultError::InvalidTokenAccount.into());
+        }
+
+        invoke_signed(
+            &token_instruction::transfer(
+                token_program.key,
+                vault_ata.key,
+                holder_ata.key,
+                vault_auth.key,
+                &[],
+                amount,
+            )?,
+            &[
+                vault_ata.clone(),
+                holder_ata.clone(),
+                vault_auth.clone(),
+                token_program.clone(),
+            ],
+            &[&[b"auth", vault_state.key.as_ref(), &[vault_data.auth_bump]]],
+        )?;
+        vault_data.record_token_withdrawal(token_balance(vault_ata), amount)?;
+        release_nft(
+            program_id,
+            accounts,
+            vault_state,
+            vault_state,
+            &state.mint,
+            &mut vault_data,
+            amount,
+        )?;
+        save_vault_state(holder, vault_state, system_program, &vault_data)?;
+    }
+
+    msg!("Redeemed {} shares for {}", shares, amount);
+    Ok(())
+}
@@ -0,0 +1,372 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::{Pod, Zeroable};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    allowlist::assert_allowed_recipient,
+    assert_system_program, assert_vault_pdas,
+    checks::{assert_distinct, assert_no_aliases, assert_writable},
+    close_program_account, create_pda_account, load_vault_state,
+    lock::assert_unlocked,
+    payout::{pay_out_sol, pay_out_tokens},
+    pool::assert_not_pooled,
+    receipt::assert_no_receipts,
+    zero_copy::{self, ZeroCopy},
+    Vault, WbaVaultError,
+};
+
+/// What a proposal does once enough members have approved it.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub enum ProposalAction {
+    WithdrawSol { amount: u64 },
+    WithdrawSpl { mint: Pubkey, amount: u64 },
+    WithdrawNft { mint: Pubkey },
+}
+
+/// A pending withdrawal at `["proposal", vaultState, seed]`. Approvals are
+/// collected over any number of transactions; the proposer approves on
+/// creation.
+///
+/// The action is stored flattened as `action_kind` (the `ProposalAction`
+/// variant index), `action_mint` and `action_amount`; read it through
+/// `action()`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct Proposal {
+    pub vault_state: Pubkey,
+    pub proposer: Pubkey,
+    pub recipient: Pubkey,
+    action_mint: Pubkey,
+    action_amount: u64,
+    pub expires_at: i64,
+    approvals: [Pubkey; Vault::MAX_SIGNERS],
+    pub bump: u8,
+    action_kind: u8,
+    approval_count: u8,
+    _padding: [u8; 5],
+}
+
+impl ZeroCopy for Proposal {
+    const DISCRIMINATOR: [u8; 8] = *b"wbapropo";
+}
+
+impl Proposal {
+    pub fn action(&self) -> Result<ProposalAction, ProgramError> {
+        let (mint, amount) = (self.action_mint, self.action_amount);
+        match self.action_kind {
+            0 => Ok(ProposalAction::WithdrawSol { amount }),
+            1 => Ok(ProposalAction::WithdrawSpl { mint, amount }),
+            2 => Ok(ProposalAction::WithdrawNft { mint }),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    pub fn set_action(&mut self, action: &ProposalAction) {
+        let (kind, mint, amount) = match *action {
+            ProposalAction::WithdrawSol { amount } => (0, Pubkey::default(), amount),
+            ProposalAction::WithdrawSpl { mint, amount } => (1, mint, amount),
+            ProposalAction::WithdrawNft { mint } => (2, mint, 0),
+        };
+        self.action_kind = kind;
+        self.action_mint = mint;
+        self.action_amount = amount;
+    }
+
+    /// Every key that approved, including any that have since left.
+    pub fn approvals(&self) -> &[Pubkey] {
+        zero_copy::list(&self.approvals, self.approval_count)
+    }
+
+    pub fn set_approvals(&mut self, approvals: &[Pubkey]) -> ProgramResult {
+        zero_copy::set_list(&mut self.approvals, &mut self.approval_count, approvals)
+    }
+
+    /// Approvals from keys that are still members. Members removed after
+    /// approving no longer count.
+    pub fn approval_count(&self, vault: &Vault) -> usize {
+        self.approvals()
+            .iter()
+            .filter(|key| vault.is_member(key))
+            .count()
+    }
+}
+
+impl Vault {
+    /// Approvals a proposal needs; a single-owner vault needs its owner.
+    pub fn required_approvals(&self) -> usize {
+        (self.threshold as usize).max(1)
+    }
+}
+
+fn load_proposal(
+    program_id: &Pubkey,
+    vault_state: &AccountInfo,
+    proposal: &AccountInfo,
+) -> Result<Proposal, ProgramError> {
+    if proposal.owner != program_id {
+        return Err(WbaVaultError::InvalidProposal.into());
+    }
+
+    let state = Proposal::read(&proposal.data.borrow())?;
+
+    if state.vault_state != *vault_state.key {
+        return Err(WbaVaultError::InvalidProposal.into());
+    }
+
+    Ok(state)
+}
+
+fn save_proposal(proposal: &AccountInfo, state: &Proposal) -> ProgramResult {
+    state.write(&mut proposal.data.borrow_mut())
+}
+
+fn assert_not_expired(state: &Proposal) -> ProgramResult {
+    if Clock::get()?.unix_timestamp >= state.expires_at {
+        return Err(WbaVaultError::ProposalExpired.into());
+    }
+    Ok(())
+}
+
+pub(crate) fn create_proposal(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    action: ProposalAction,
+    recipient: Pubkey,
+    expires_at: i64,
+    seed: u64,
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let proposer = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let proposal = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !proposer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[proposer, vault_state, proposal, system_program])?;
+
+    assert_system_program(system_program)?;
+    let _vault = load_vault_state(program_id, proposer, vault_state)?;
+
+    let amount = match action {
+        ProposalAction::WithdrawSol { amount } | ProposalAction::WithdrawSpl { amount, .. } => {
+            amount
+        }
+        ProposalAction::WithdrawNft { .. } => 1,
+    };
+    if amount == 0 || expires_at <= Clock::get()?.unix_timestamp {
+        return Err(WbaVaultError::InvalidProposal.into());
+    }
+
+    // proposal PDA = ["proposal", vaultState, seed]
+    let seed_bytes = seed.to_le_bytes();
+    let (expected_proposal, bump) = Pubkey::find_program_address(
+        &[b"proposal", vault_state.key.as_ref(), &seed_bytes],
+        program_id,
+    );
+    if proposal.key != &expected_proposal {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+
+    create_pda_account(
+        proposer,
+        proposal,
+        system_program,
+        Proposal::SPACE,
+        program_id,
+        &[b"proposal", vault_state.key.as_ref(), &seed_bytes, &[bump]],
+    )?;
+
+    let mut state = Proposal {
+        vault_state: *vault_state.key,
+        proposer: *proposer.key,
+        recipient,
+        expires_at,
+        bump,
+        ..Proposal::zeroed()
+    };
+    state.set_action(&action);
+    state.set_approvals(&[*proposer.key])?;
+    state.init(&mut proposal.data.borrow_mut())?;
+
+    msg!("Create proposal successful");
+    Ok(())
+}
+
+pub(crate) fn approve(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let member = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let proposal = next_account_info(&mut accounts_iter)?;
+
+    if !member.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[member, vault_state, proposal])?;
+    assert_writable(&[proposal])?;
+
+    let vault = load_vault_state(program_id, member, vault_state)?;
+    let mut state = load_proposal(program_id, vault_state, proposal)?;
+    assert_not_expired(&state)?;
+
+    if state.approvals().contains(member.key) {
+        return Err(WbaVaultError::InvalidProposal.into());
+    }
+    // Drop approvals from former members so the list stays bounded.
+    let mut approvals = state.approvals().to_vec();
+    approvals.retain(|key| vault.is_member(key));
+    approvals.push(*member.key);
+    state.set_approvals(&approvals)?;
+    save_proposal(proposal, &state)?;
+
+    msg!(
+        "Approve successful: {} of {}",
+        state.approval_count(&vault),
+        vault.required_approvals()
+    );
+    Ok(())
+}
+
+/// Runs an approved proposal. Anyone may execute once the threshold is met;
+/// the proposal is closed and its rent returned to the proposer.
+pub(crate) fn execute(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let executor = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let vault_auth = next_account_info(&mut accounts_iter)?;
+    let vault = next_account_info(&mut accounts_iter)?;
+    let proposal = next_account_info(&mut accounts_iter)?;
+    let proposer = next_account_info(&mut accounts_iter)?;
+    let recipient = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !executor.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    // Proposer, executor and recipient are often the same member.
+    assert_no_aliases(
+        &[vault_state, vault_auth, vault, proposal, system_program],
+        &[executor, proposer, recipient],
+    )?;
+    assert_writable(&[vault_state, proposal, proposer])?;
+
+    assert_system_program(system_program)?;
+    if vault_state.owner != program_id {
+        return Err(WbaVaultError::InvalidVaultStateOwner.into());
+    }
+    let mut vault_data = Vault::unpack(&vault_state.data.borrow())?;
+    assert_unlocked(&vault_data)?;
+    assert_not_pooled(&vault_data)?;
+    assert_no_receipts(&vault_data)?;
+    assert_vault_pdas(program_id, vault_state, vault_auth, vault)?;
+
+    let state = load_proposal(program_id, vault_state, proposal)?;
+    assert_not_expired(&state)?;
+    if proposer.key != &state.proposer || recipient.key != &state.recipient {
+        return Err(WbaVaultError::InvalidProposal.into());
+    }
+    if state.approval_count(&vault_data) < vault_data.required_approvals() {
+        return Err(WbaVaultError::InsufficientApprovals.into());
+    }
+    assert_allowed_recipient(
+        program_id,
+        vault_state,
+        &vault_data,
+        accounts,
+        recipient.key,
+    )?;
+
+    match state.action()? {
+        ProposalAction::WithdrawSol { amount } => {
+            pay_out_sol(
+                program_id,
+                executor,
+                vault_state,
+                vault_auth,
+                vault,
+                recipient,
+                system_program,
+                &mut vault_data,
+                amount,
+            )?;
+        }
+        ProposalAction::WithdrawSpl { mint, amount } => {
+            pay_out_tokens(
+                program_id,
+                &mut accounts_iter,
+                executor,
+                vault_state,
+                vault_auth,
+                system_program,
+                &mut vault_data,
+                recipient,
+                &mint,
+                amount,
+                false,
+            )?;
+        }
+        ProposalAction::WithdrawNft { mint } => {
+            pay_out_tokens(
+                program_id,
+                &mut accounts_iter,
+                executor,
+                vault_state,
+                vault_auth,
+                system_program,
+                &mut vault_data,
+                recipient,
+                &mint,
+                1,
+                true,
+            )?;
+        }
+    }
+
+    close_program_account(proposal, proposer)?;
+
+    msg!("Execute successful");
+    Ok(())
+}
+
+/// Closes a proposal. The proposer may cancel at any time; once expired,
+/// anyone may close it to return the rent to the proposer.
+pub(crate) fn cancel_proposal(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let signer = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let proposal = next_account_info(&mut accounts_iter)?;
+    let proposer = next_account_info(&mut accounts_iter)?;
+
+    if !signer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_no_aliases(&[vault_state, proposal], &[signer, proposer])?;
+    assert_writable(&[proposal, proposer])?;
+
+    let state = load_proposal(program_id, vault_state, proposal)?;
+    if proposer.key != &state.proposer {
+        return Err(WbaVaultError::InvalidProposal.into());
+    }
+    let expired = Clock::get()?.unix_timestamp >= state.expires_at;
+    if signer.key != &state.proposer && !expired {
+        return Err(WbaVaultError::InvalidSigner.into());
+    }
+
+    close_program_account(proposal, proposer)?;
+
+    msg!("Cancel proposal successful");
+    Ok(())
+}
@@ -0,0 +1,365 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey,
+    pubkey::Pubkey,
+    rent::Rent,
+    sysvar::Sysvar,
+};
+
+use spl_associated_token_account::get_associated_token_address;
+use spl_token::instruction as token_instruction;
+
+use crate::{
+    assert_system_program, assert_token_program, assert_vault_pdas,
+    checks::{assert_distinct, assert_writable},
+    create_pda_account, load_vault_state,
+    multisig::assert_threshold,
+    save_vault_state, Vault, WbaVaultError,
+};
+
+/// The Metaplex Token Metadata program. Pinned rather than passed in, since
+/// `vault_auth` signs the CPI.
+pub(crate) const METADATA_PROGRAM_ID: Pubkey =
+    pubkey!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+
+const CREATE_METADATA_ACCOUNT_V3: u8 = 33;
+const MAX_NAME_LENGTH: usize = 32;
+const MAX_SYMBOL_LENGTH: usize = 10;
+const MAX_URI_LENGTH: usize = 200;
+
+/// receipt mint PDA = ["receipt", vaultState, mint], with `Pubkey::default()`
+/// standing in for SOL.
+fn receipt_mint_address(program_id: &Pubkey, vault_state: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"receipt", vault_state.as_ref(), mint.as_ref()],
+        program_id,
+    )
+}
+
+fn metadata_address(mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"metadata", METADATA_PROGRAM_ID.as_ref(), mint.as_ref()],
+        &METADATA_PROGRAM_ID,
+    )
+    .0
+}
+
+/// Rejects owner-side instructions that would move funds out without burning
+/// receipts, which would leave receipts in circulation unbacked.
+pub(crate) fn assert_no_receipts(state: &Vault) -> ProgramResult {
+    if state.receipts.get() {
+        msg!("Vault has receipts enabled; use Withdraw or WithdrawSpl");
+        return Err(WbaVaultError::ReceiptsEnabled.into());
+    }
+    Ok(())
+}
+
+fn find_account<'a, 'b>(
+    accounts: &'a [AccountInfo<'b>],
+    key: &Pubkey,
+) -> Result<&'a AccountInfo<'b>, ProgramError> {
+    accounts
+        .iter()
+        .find(|account| account.key == key)
+        .ok_or_else(|| {
+            msg!("Missing receipt account {}", key);
+            WbaVaultError::MissingReceiptMint.into()
+        })
+}
+
+/// Mints `amount` receipts for `mint` to the owner's receipt ATA when the
+/// vault has receipts enabled. The receipt mint, that ATA and the token
+/// program are looked up among `accounts` by address.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn mint_receipts<'a>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo<'a>],
+    vault_state: &AccountInfo<'a>,
+    vault_auth: &AccountInfo<'a>,
+    state: &Vault,
+    owner: &AccountInfo<'a>,
+    mint: &Pubkey,
+    amount: u64,
+) -> ProgramResult {
+    if !state.receipts.get() {
+        return Ok(());
+    }
+
+    let (receipt_mint_key, _bump) = receipt_mint_address(program_id, vault_state.key, mint);
+    let receipt_mint = find_account(accounts, &receipt_mint_key)?;
+    let owner_receipts = find_account(
+        accounts,
+        &get_associated_token_address(owner.key, &receipt_mint_key),
+    )?;
+    let token_program = find_account(accounts, &spl_token::id())?;
+    assert_writable(&[receipt_mint, owner_receipts])?;
+
+    invoke_signed(
+        &token_instruction::mint_to(
+            token_program.key,
+            receipt_mint.key,
+            owner_receipts.key,
+            vault_auth.key,
+            &[],
+            amount,
+        )?,
+        &[
+            receipt_mint.clone(),
+            owner_receipts.clone(),
+            vault_auth.clone(),
+            token_program.clone(),
+        ],
+        &[&[b"auth", vault_state.key.as_ref(), &[state.auth_bump]]],
+    )
+}
+
+/// Burns `amount` receipts for `mint` from the owner's receipt ATA when the
+/// vault has receipts enabled; accounts are found as in `mint_receipts`.
+pub(crate) fn burn_receipts<'a>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo<'a>],
+    vault_state: &AccountInfo<'a>,
+    state: &Vault,
+    owner: &AccountInfo<'a>,
+    mint: &Pubkey,
+    amount: u64,
+) -> ProgramResult {
+    if !state.receipts.get() {
+        return Ok(());
+    }
+
+    let (receipt_mint_key, _bump) = receipt_mint_address(program_id, vault_state.key, mint);
+    let receipt_mint = find_account(accounts, &receipt_mint_key)?;
+    let owner_receipts = find_account(
+        accounts,
+        &get_associated_token_address(owner.key, &receipt_mint_key),
+    )?;
+    let token_program = find_account(accounts, &spl_token::id())?;
+    assert_writable(&[receipt_mint, owner_receipts])?;
+
+    invoke(
+        &token_instruction::burn(
+            token_program.key,
+            owner_receipts.key,
+            receipt_mint.key,
+            owner.key,
+            &[],
+            amount,
+        )?,
+        &[
+            owner_receipts.clone(),
+            receipt_mint.clone(),
+            owner.clone(),
+            token_program.clone(),
+        ],
+    )
+}
+
+/// Turns receipts on for good: from now on deposits mint them and withdrawals
+/// burn them. Only allowed on an empty vault, since anything already inside
+/// has no receipts and could never be withdrawn once they are required.
+pub(crate) fn enable_receipts(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let vault_auth = next_account_info(&mut accounts_iter)?;
+    let vault = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[owner, vault_state, vault_auth, vault, system_program])?;
+
+    assert_system_program(system_program)?;
+    let mut state = load_vault_state(program_id, owner, vault_state)?;
+    assert_threshold(&state, accounts)?;
+    assert_vault_pdas(program_id, vault_state, vault_auth, vault)?;
+
+    // The vault PDA keeps its rent-exempt reserve even when empty.
+    let reserve = Rent::get()?.minimum_balance(vault.data_len());
+    if vault.lamports() > reserve
+        || state.mints_held > 0
+        || state.staked_nfts > 0
+        || !state.stake_accounts().is_empty()
+    {
+        msg!("Receipts can only be enabled on an empty vault");
+        return Err(WbaVaultError::VaultNotEmpty.into());
+    }
+
+    state.receipts = true.into();
+    save_vault_state(owner, vault_state, system_program, &state)?;
+
+    msg!("Enable receipts successful");
+    Ok(())
+}
+
+/// Name, symbol and URI of the underlying mint's Metaplex metadata, if it has
+/// any. The fields are stored null-padded.
+fn read_metadata(metadata: &AccountInfo) -> Option<(String, String, String)> {
+    if metadata.owner != &METADATA_PROGRAM_ID {
+        return None;
+    }
+    let data = metadata.data.borrow();
+    let (_key, _update_authority, _mint, name, symbol, uri) =
+        <(u8, Pubkey, Pubkey, String, String, String)>::deserialize(&mut &data[..]).ok()?;
+    let trim = |s: String| s.trim_end_matches('\0').to_string();
+    Some((trim(name), trim(symbol), trim(uri)))
+}
+
+fn truncate(mut s: String, max: usize) -> String {
+    while s.len() > max {
+        s.pop();
+    }
+    s
+}
+
+/// Creates the receipt mint for `mint` with `vault_auth` as mint authority,
+/// and its Metaplex metadata named after the underlying asset.
+pub(crate) fn create_receipt_mint(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    mint: Pubkey,
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let vault_auth = next_account_info(&mut accounts_iter)?;
+    let receipt_mint = next_account_info(&mut accounts_iter)?;
+    let receipt_metadata = next_account_info(&mut accounts_iter)?;
+    let metadata_program = next_account_info(&mut accounts_iter)?;
+    let token_program = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[
+        owner,
+        vault_state,
+        vault_auth,
+        receipt_mint,
+        receipt_metadata,
+        metadata_program,
+        token_program,
+        system_program,
+    ])?;
+    assert_writable(&[owner, receipt_mint, receipt_metadata])?;
+
+    assert_system_program(system_program)?;
+    assert_token_program(token_program)?;
+    if metadata_program.key != &METADATA_PROGRAM_ID {
+        return Err(WbaVaultError::InvalidMetadataProgram.into());
+    }
+    let state = load_vault_state(program_id, owner, vault_state)?;
+    assert_threshold(&state, accounts)?;
+
+    let (expected_vault_auth, _auth_bump) =
+        Pubkey::find_program_address(&[b"auth", vault_state.key.as_ref()], program_id);
+    if vault_auth.key != &expected_vault_auth {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+
+    let (expected_receipt_mint, bump) = receipt_mint_address(program_id, vault_state.key, &mint);
+    if receipt_mint.key != &expected_receipt_mint {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+    if receipt_metadata.key != &metadata_address(receipt_mint.key) {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+
+    // Receipts are 1:1, so they share the underlying decimals, and take their
+    // name from the underlying metadata when there is some.
+    let (decimals, name, symbol, uri) = if mint == Pubkey::default() {
+        (9, "SOL".to_string(), "SOL".to_string(), String::new())
+    } else {
+        let underlying_mint = next_account_info(&mut accounts_iter)?;
+        let underlying_metadata = next_account_info(&mut accounts_iter)?;
+        assert_distinct(&[
+            receipt_mint,
+            receipt_metadata,
+            underlying_mint,
+            underlying_metadata,
+        ])?;
+        if underlying_mint.key != &mint || underlying_mint.owner != token_program.key {
+            return Err(WbaVaultError::InvalidTokenAccount.into());
+        }
+        if underlying_metadata.key != &metadata_address(&mint) {
+            return Err(WbaVaultError::InvalidPda.into());
+        }
+        let decimals = spl_token::state::Mint::unpack(&underlying_mint.data.borrow())?.decimals;
+        let (name, symbol, uri) = read_metadata(underlying_metadata).unwrap_or_else(|| {
+            let short: String = mint.to_string().chars().take(4).collect();
+            (short.clone(), short, String::new())
+        });
+        (decimals, name, symbol, uri)
+    };
+
+    create_pda_account(
+        owner,
+        receipt_mint,
+        system_program,
+        spl_token::state::Mint::LEN,
+        token_program.key,
+        &[b"receipt", vault_state.key.as_ref(), mint.as_ref(), &[bump]],
+    )?;
+
+    invoke(
+        &token_instruction::initialize_mint2(
+            token_program.key,
+            receipt_mint.key,
+            vault_auth.key,
+            None,
+            decimals,
+        )?,
+        &[receipt_mint.clone(), token_program.clone()],
+    )?;
+
+    let name = truncate(format!("{} Vault Receipt", name), MAX_NAME_LENGTH);
+    let symbol = truncate(format!("r{}", symbol), MAX_SYMBOL_LENGTH);
+    let uri = truncate(uri, MAX_URI_LENGTH);
+
+    // CreateMetadataAccountV3 { data: DataV2, is_mutable, collection_details }
+    let mut data = vec![CREATE_METADATA_ACCOUNT_V3];
+    (name, symbol, uri, 0u16)
+        .serialize(&mut data)
+        .map_err(|_| ProgramError::InvalidArgument)?;
+    // No creators, collection or uses; mutable; no collection details.
+    data.extend_from_slice(&[0, 0, 0, 1, 0]);
+
+    invoke_signed(
+        &Instruction {
+            program_id: METADATA_PROGRAM_ID,
+            accounts: vec![
+                AccountMeta::new(*receipt_metadata.key, false),
+                AccountMeta::new_readonly(*receipt_mint.key, false),
+                AccountMeta::new_readonly(*vault_auth.key, true),
+                AccountMeta::new(*owner.key, true),
+                AccountMeta::new_readonly(*vault_auth.key, true),
+                AccountMeta::new_readonly(*system_program.key, false),
+            ],
+            data,
+        },
+        &[
+            receipt_metadata.clone(),
+            receipt_mint.clone(),
+            vault_auth.clone(),
+            owner.clone(),
+            system_program.clone(),
+            metadata_program.clone(),
+        ],
+        &[&[b"auth", vault_state.key.as_ref(), &[state.auth_bump]]],
+    )?;
+
+    msg!("Create receipt mint successful");
+    Ok(())
+}
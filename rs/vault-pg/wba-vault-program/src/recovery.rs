@@ -0,0 +1,206 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::{Pod, Zeroable};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    assert_system_program,
+    checks::assert_distinct,
+    load_vault_state,
+    multisig::{assert_threshold, count_signers, is_valid_key_set},
+    save_vault_state, Vault, WbaVaultError,
+};
+
+/// Owner rotation started by the guardians, completable at `executable_at`.
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct PendingRecovery {
+    pub new_owner: Pubkey,
+    pub executable_at: i64,
+}
+
+impl PendingRecovery {
+    pub const LEN: usize = 32 + 8;
+}
+
+impl Vault {
+    pub const MAX_GUARDIANS: usize = 5;
+}
+
+fn load_recoverable_vault(
+    program_id: &Pubkey,
+    vault_state: &AccountInfo,
+) -> Result<Vault, ProgramError> {
+    if vault_state.owner != program_id {
+        return Err(WbaVaultError::InvalidVaultStateOwner.into());
+    }
+    Vault::unpack(&vault_state.data.borrow())
+}
+
+/// Replaces the guardian set. Any recovery in flight is dropped, since it was
+/// started by the old guardians.
+pub(crate) fn set_guardians(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    guardians: Vec<Pubkey>,
+    threshold: u8,
+    delay_secs: i64,
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[owner, vault_state, system_program])?;
+
+    assert_system_program(system_program)?;
+    let mut state = load_vault_state(program_id, owner, vault_state)?;
+    assert_threshold(&state, accounts)?;
+
+    // Without a delay the owner would have no window to cancel.
+    if !is_valid_key_set(&guardians, threshold, Vault::MAX_GUARDIANS)
+        || delay_secs < 0
+        || (threshold > 0 && delay_secs == 0)
+    {
+        return Err(WbaVaultError::InvalidGuardians.into());
+    }
+
+    state.set_guardians(&guardians)?;
+    state.guardian_threshold = threshold;
+    state.recovery_delay_secs = delay_secs;
+    state.set_recovery(None);
+    save_vault_state(owner, vault_state, system_program, &state)?;
+
+    msg!("Set guardians successful");
+    Ok(())
+}
+
+/// Starts rotating the vault to `new_owner`. Needs `guardian_threshold`
+/// distinct guardian signatures; a new request replaces a pending one and
+/// restarts the delay.
+pub(crate) fn initiate_recovery(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    new_owner: Pubkey,
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let guardian = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !guardian.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[guardian, vault_state, system_program])?;
+
+    assert_system_program(system_program)?;
+    let mut state = load_recoverable_vault(program_id, vault_state)?;
+    if state.guardian_threshold == 0 || new_owner == Pubkey::default() {
+        return Err(WbaVaultError::InvalidRecovery.into());
+    }
+    if !state.guardians().contains(guardian.key) {
+        return Err(WbaVaultError::InvalidSigner.into());
+    }
+
+    let signed = count_signers(state.guardians(), accounts);
+    if signed < state.guardian_threshold as usize {
+        msg!(
+            "Recovery: {} of {} required guardians",
+            signed,
+            state.guardian_threshold
+        );
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let executable_at = Clock::get()?
+        .unix_timestamp
+        .saturating_add(state.recovery_delay_secs);
+    state.set_recovery(Some(PendingRecovery {
+        new_owner,
+        executable_at,
+    }));
+    save_vault_state(guardian, vault_state, system_program, &state)?;
+
+    msg!(
+        "Initiate recovery successful: executable at {}",
+        executable_at
+    );
+    Ok(())
+}
+
+/// Lets the current owner (or multisig) abort a recovery within the delay.
+pub(crate) fn cancel_recovery(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[owner, vault_state, system_program])?;
+
+    assert_system_program(system_program)?;
+    let mut state = load_vault_state(program_id, owner, vault_state)?;
+    assert_threshold(&state, accounts)?;
+    if state.recovery().is_none() {
+        return Err(WbaVaultError::InvalidRecovery.into());
+    }
+
+    state.set_recovery(None);
+    save_vault_state(owner, vault_state, system_program, &state)?;
+
+    msg!("Cancel recovery successful");
+    Ok(())
+}
+
+/// Hands the vault to the recovered key once the delay has passed. Anyone may
+/// crank it. Multisig mode is switched off, as the lost key may be a member.
+pub(crate) fn complete_recovery(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let payer = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !payer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[payer, vault_state, system_program])?;
+
+    assert_system_program(system_program)?;
+    let mut state = load_recoverable_vault(program_id, vault_state)?;
+    let recovery = *state.recovery().ok_or(WbaVaultError::InvalidRecovery)?;
+    state.set_recovery(None);
+
+    let now = Clock::get()?.unix_timestamp;
+    if now < recovery.executable_at {
+        msg!(
+            "Recovery executable at {}, now {}",
+            recovery.executable_at,
+            now
+        );
+        return Err(WbaVaultError::RecoveryNotReady.into());
+    }
+
+    state.owner = recovery.new_owner;
+    state.set_signers(&[])?;
+    state.threshold = 0;
+    save_vault_state(payer, vault_state, system_program, &state)?;
+
+    msg!("Recovery complete: owner {}", state.owner);
+    Ok(())
+}
@@ -0,0 +1,338 @@
+use bytemuck::{Pod, Zeroable};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use spl_associated_token_account::{
+    get_associated_token_address, instruction::create_associated_token_account_idempotent,
+};
+use spl_token::instruction as token_instruction;
+
+use crate::{
+    assert_system_program, assert_token_program,
+    checks::{assert_distinct, assert_writable},
+    close_program_account, create_pda_account, load_vault_state,
+    multisig::assert_threshold,
+    save_vault_state,
+    zero_copy::ZeroCopy,
+    Vault, WbaVaultError,
+};
+
+/// Records when an NFT went into the vault, at `["nft_stake", vaultState, mint]`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct NftStake {
+    pub vault_state: Pubkey,
+    pub mint: Pubkey,
+    pub staked_at: i64,
+    pub bump: u8,
+    _padding: [u8; 7],
+}
+
+impl ZeroCopy for NftStake {
+    const DISCRIMINATOR: [u8; 8] = *b"wbanftst";
+}
+
+impl Vault {
+    pub const SECONDS_PER_POINT: u64 = 86_400;
+
+    /// Accrues one point per staked NFT per day up to `now`. Partial days are
+    /// carried in `score_remainder`, so settling often loses nothing.
+    pub fn settle_score(&mut self, now: i64) {
+        let elapsed = now.saturating_sub(self.score_updated_at).max(0) as u128;
+        let nft_secs = self.score_remainder as u128 + elapsed * self.staked_nfts as u128;
+        let points = (nft_secs / Self::SECONDS_PER_POINT as u128).min(u64::MAX as u128) as u64;
+
+        self.score_points = self.score_points.saturating_add(points);
+        self.score_remainder = (nft_secs % Self::SECONDS_PER_POINT as u128) as u64;
+        self.score_updated_at = now;
+        self.score = self.score_points.min(u8::MAX as u64) as u8;
+    }
+}
+
+/// Reward tokens minted per point by `ClaimRewards`.
+pub const REWARD_DECIMALS: u8 = 6;
+pub const REWARD_PER_POINT: u64 = 10u64.pow(REWARD_DECIMALS as u32);
+
+fn stake_address(program_id: &Pubkey, vault_state: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    // nft stake PDA = ["nft_stake", vaultState, mint]
+    Pubkey::find_program_address(
+        &[b"nft_stake", vault_state.as_ref(), mint.as_ref()],
+        program_id,
+    )
+}
+
+pub(crate) fn is_nft_stake(
+    program_id: &Pubkey,
+    account: &AccountInfo,
+    vault_state: &Pubkey,
+    mint: &Pubkey,
+) -> bool {
+    account.key == &stake_address(program_id, vault_state, mint).0
+}
+
+/// Returns the stake record for `mint` if the caller passed it. It is looked
+/// up by address and conventionally goes last, after any optional accounts.
+fn find_stake<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'b>],
+    vault_state: &Pubkey,
+    mint: &Pubkey,
+) -> (Option<&'a AccountInfo<'b>>, u8) {
+    let (expected, bump) = stake_address(program_id, vault_state, mint);
+    let stake = accounts.iter().find(|account| account.key == &expected);
+    (stake, bump)
+}
+
+/// Rejects mints that are not zero-decimal with a supply of one.
+pub(crate) fn assert_nft(token_mint: &AccountInfo) -> ProgramResult {
+    let mint_state = spl_token::state::Mint::unpack(&token_mint.data.borrow())?;
+    if mint_state.decimals != 0 || mint_state.supply != 1 {
+        return Err(WbaVaultError::InvalidTokenAccount.into());
+    }
+    Ok(())
+}
+
+/// Opens the stake record for an NFT that `deposit_nft` just took in. Only
+/// zero-decimal, single-supply mints count. Without the record among
+/// `accounts` the NFT is held unstaked, as before staking existed.
+pub(crate) fn stake_nft<'a>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo<'a>],
+    owner: &AccountInfo<'a>,
+    vault_state: &AccountInfo<'a>,
+    token_mint: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    state: &mut Vault,
+) -> ProgramResult {
+    assert_nft(token_mint)?;
+
+    let (stake, bump) = find_stake(program_id, accounts, vault_state.key, token_mint.key);
+    let Some(stake) = stake else {
+        msg!("No NFT stake account; {} is held unstaked", token_mint.key);
+        return Ok(());
+    };
+    assert_writable(&[stake])?;
+    if stake.owner == program_id {
+        return Err(WbaVaultError::InvalidNftStake.into());
+    }
+
+    create_pda_account(
+        owner,
+        stake,
+        system_program,
+        NftStake::SPACE,
+        program_id,
+        &[
+            b"nft_stake",
+            vault_state.key.as_ref(),
+            token_mint.key.as_ref(),
+            &[bump],
+        ],
+    )?;
+
+    let now = Clock::get()?.unix_timestamp;
+    NftStake {
+        vault_state: *vault_state.key,
+        mint: *token_mint.key,
+        staked_at: now,
+        bump,
+        _padding: [0; 7],
+    }
+    .init(&mut stake.data.borrow_mut())?;
+
+    state.settle_score(now);
+    state.staked_nfts = state
+        .staked_nfts
+        .checked_add(1)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    Ok(())
+}
+
+/// Settles the score and closes the stake record, into `rent_receiver`, of
+/// `mint` tokens about to leave the vault by any route.
+///
+/// While the vault has staked NFTs every token outflow must pass the record's
+/// PDA, initialized or not, since only the account shows whether `mint` is
+/// staked. Vaults with none staked, such as those whose NFTs all predate
+/// staking, need not pass it.
+pub(crate) fn release_nft<'a>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo<'a>],
+    rent_receiver: &AccountInfo<'a>,
+    vault_state: &AccountInfo<'a>,
+    mint: &Pubkey,
+    state: &mut Vault,
+    amount: u64,
+) -> ProgramResult {
+    if amount == 0 || state.staked_nfts == 0 {
+        return Ok(());
+    }
+
+    let (stake, _bump) = find_stake(program_id, accounts, vault_state.key, mint);
+    let Some(stake) = stake else {
+        msg!(
+            "Missing NFT stake account {}",
+            stake_address(program_id, vault_state.key, mint).0
+        );
+        return Err(WbaVaultError::InvalidNftStake.into());
+    };
+    if stake.owner != program_id {
+        return Ok(());
+    }
+
+    state.settle_score(Clock::get()?.unix_timestamp);
+    state.staked_nfts = state.staked_nfts.saturating_sub(1);
+    close_program_account(stake, rent_receiver)
+}
+
+/// Brings `score` up to date. Anyone may pay for this.
+pub(crate) fn settle(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let payer = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !payer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[payer, vault_state, system_program])?;
+
+    assert_system_program(system_program)?;
+    if vault_state.owner != program_id {
+        return Err(WbaVaultError::InvalidVaultStateOwner.into());
+    }
+    let mut state = Vault::unpack(&vault_state.data.borrow())?;
+
+    state.settle_score(Clock::get()?.unix_timestamp);
+    save_vault_state(payer, vault_state, system_program, &state)?;
+
+    msg!("Score settled at {} points", state.score_points);
+    Ok(())
+}
+
+/// Mints `REWARD_PER_POINT` tokens of the program-wide `["rewards"]` mint for
+/// every point not yet claimed into the owner's ATA, creating the mint and
+/// the ATA on first use.
+pub(crate) fn claim_rewards(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let reward_mint = next_account_info(&mut accounts_iter)?;
+    let owner_rewards = next_account_info(&mut accounts_iter)?;
+    let token_program = next_account_info(&mut accounts_iter)?;
+    let associated_token_program = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[
+        owner,
+        vault_state,
+        reward_mint,
+        owner_rewards,
+        token_program,
+        associated_token_program,
+        system_program,
+    ])?;
+    assert_writable(&[owner, reward_mint, owner_rewards])?;
+
+    assert_system_program(system_program)?;
+    assert_token_program(token_program)?;
+    let mut state = load_vault_state(program_id, owner, vault_state)?;
+    assert_threshold(&state, accounts)?;
+
+    // reward mint PDA = ["rewards"], its own mint authority
+    let (expected_reward_mint, bump) = Pubkey::find_program_address(&[b"rewards"], program_id);
+    if reward_mint.key != &expected_reward_mint {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+
+    if reward_mint.owner != token_program.key {
+        create_pda_account(
+            owner,
+            reward_mint,
+            system_program,
+            spl_token::state::Mint::LEN,
+            token_program.key,
+            &[b"rewards", &[bump]],
+        )?;
+        invoke(
+            &token_instruction::initialize_mint2(
+                token_program.key,
+                reward_mint.key,
+                reward_mint.key,
+                None,
+                REWARD_DECIMALS,
+            )?,
+            &[reward_mint.clone(), token_program.clone()],
+        )?;
+    }
+
+    if owner_rewards.key != &get_associated_token_address(owner.key, reward_mint.key) {
+        return Err(WbaVaultError::InvalidTokenAccount.into());
+    }
+    if associated_token_program.key != &spl_associated_token_account::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    invoke(
+        &create_associated_token_account_idempotent(
+            owner.key,
+            owner.key,
+            reward_mint.key,
+            token_program.key,
+        ),
+        &[
+            owner.clone(),
+            owner_rewards.clone(),
+            owner.clone(),
+            reward_mint.clone(),
+            system_program.clone(),
+            token_program.clone(),
+            associated_token_program.clone(),
+        ],
+    )?;
+
+    state.settle_score(Clock::get()?.unix_timestamp);
+    let points = state.score_points - state.claimed_points;
+    if points == 0 {
+        return Err(WbaVaultError::NothingToClaim.into());
+    }
+    let amount = points
+        .checked_mul(REWARD_PER_POINT)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+
+    state.claimed_points = state.score_points;
+    save_vault_state(owner, vault_state, system_program, &state)?;
+
+    invoke_signed(
+        &token_instruction::mint_to(
+            token_program.key,
+            reward_mint.key,
+            owner_rewards.key,
+            reward_mint.key,
+            &[],
+            amount,
+        )?,
+        &[
+            reward_mint.clone(),
+            owner_rewards.clone(),
+            token_program.clone(),
+        ],
+        &[&[b"rewards", &[bump]]],
+    )?;
+
+    msg!("Claimed {} points", points);
+    Ok(())
+}
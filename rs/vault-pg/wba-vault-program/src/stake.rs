@@ -0,0 +1,280 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    stake::{
+        self,
+        state::{Authorized, Lockup, StakeStateV2},
+    },
+    system_instruction,
+};
+
+use crate::{
+    assert_system_program, assert_vault_pdas,
+    checks::{assert_distinct, assert_writable},
+    custody::Custody,
+    load_vault_state,
+    multisig::assert_threshold,
+    pool::assert_not_pooled,
+    save_vault_state, Vault, WbaVaultError,
+};
+
+fn assert_stake_program(stake_program: &AccountInfo) -> ProgramResult {
+    if stake_program.key != &stake::program::id() {
+        return Err(WbaVaultError::InvalidStakeProgram.into());
+    }
+    Ok(())
+}
+
+fn assert_vault_stake_account(state: &Vault, stake_account: &AccountInfo) -> ProgramResult {
+    if !state.stake_accounts().contains(stake_account.key) {
+        return Err(WbaVaultError::InvalidStakeAccount.into());
+    }
+    Ok(())
+}
+
+pub(crate) fn stake_from_vault(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+    seed: u64,
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let vault_auth = next_account_info(&mut accounts_iter)?;
+    let vault = next_account_info(&mut accounts_iter)?;
+    let stake_account = next_account_info(&mut accounts_iter)?;
+    let vote_account = next_account_info(&mut accounts_iter)?;
+    let rent_sysvar = next_account_info(&mut accounts_iter)?;
+    let clock_sysvar = next_account_info(&mut accounts_iter)?;
+    let stake_history_sysvar = next_account_info(&mut accounts_iter)?;
+    let stake_config = next_account_info(&mut accounts_iter)?;
+    let stake_program = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[
+        owner,
+        vault_state,
+        vault_auth,
+        vault,
+        stake_account,
+        vote_account,
+        rent_sysvar,
+        clock_sysvar,
+        stake_history_sysvar,
+        stake_config,
+        stake_program,
+        system_program,
+    ])?;
+    assert_writable(&[owner, vault, stake_account])?;
+
+    assert_system_program(system_program)?;
+    assert_stake_program(stake_program)?;
+    let mut state = load_vault_state(program_id, owner, vault_state)?;
+    assert_threshold(&state, accounts)?;
+    assert_not_pooled(&state)?;
+    assert_vault_pdas(program_id, vault_state, vault_auth, vault)?;
+
+    // The stake account must be funded before the stake program CPIs, and a
+    // direct debit from a program-owned vault cannot be followed by a CPI
+    // that leaves the vault out. Such vaults withdraw first and stake from
+    // the owner's wallet.
+    if state.custody()? != Custody::SystemOwned {
+        return Err(WbaVaultError::InvalidCustody.into());
+    }
+
+    // stake PDA = ["stake", vaultState, seed]
+    let seed_bytes = seed.to_le_bytes();
+    let (expected_stake, stake_bump) = Pubkey::find_program_address(
+        &[b"stake", vault_state.key.as_ref(), &seed_bytes],
+        program_id,
+    );
+    if stake_account.key != &expected_stake {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+
+    if state.stake_accounts().len() >= Vault::MAX_STAKE_ACCOUNTS {
+        return Err(WbaVaultError::TooManyStakeAccounts.into());
+    }
+
+    // Funded straight from the vault PDA, so the vault signs as the payer and
+    // the stake PDA signs for its own creation.
+    invoke_signed(
+        &system_instruction::create_account(
+            vault.key,
+            stake_account.key,
+            amount,
+            StakeStateV2::size_of() as u64,
+            &stake::program::id(),
+        ),
+        &[vault.clone(), stake_account.clone(), system_program.clone()],
+        &[
+            &[b"vault", vault_auth.key.as_ref(), &[state.vault_bump]],
+            &[
+                b"stake",
+                vault_state.key.as_ref(),
+                &seed_bytes,
+                &[stake_bump],
+            ],
+        ],
+    )?;
+
+    // vault_auth is both staker and withdrawer, so only this program can move
+    // the stake afterwards.
+    invoke(
+        &stake::instruction::initialize(
+            stake_account.key,
+            &Authorized {
+                staker: *vault_auth.key,
+                withdrawer: *vault_auth.key,
+            },
+            &Lockup::default(),
+        ),
+        &[stake_account.clone(), rent_sysvar.clone()],
+    )?;
+
+    invoke_signed(
+        &stake::instruction::delegate_stake(stake_account.key, vault_auth.key, vote_account.key),
+        &[
+            stake_account.clone(),
+            vote_account.clone(),
+            clock_sysvar.clone(),
+            stake_history_sysvar.clone(),
+            stake_config.clone(),
+            vault_auth.clone(),
+        ],
+        &[&[b"auth", vault_state.key.as_ref(), &[state.auth_bump]]],
+    )?;
+
+    let mut stake_accounts = state.stake_accounts().to_vec();
+    stake_accounts.push(*stake_account.key);
+    state.set_stake_accounts(&stake_accounts)?;
+    state.record_sol_withdrawal(amount)?;
+    save_vault_state(owner, vault_state, system_program, &state)?;
+
+    msg!("Stake from vault successful");
+    Ok(())
+}
+
+pub(crate) fn deactivate_stake(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let vault_auth = next_account_info(&mut accounts_iter)?;
+    let stake_account = next_account_info(&mut accounts_iter)?;
+    let clock_sysvar = next_account_info(&mut accounts_iter)?;
+    let stake_program = next_account_info(&mut accounts_iter)?;
+
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[
+        owner,
+        vault_state,
+        vault_auth,
+        stake_account,
+        clock_sysvar,
+        stake_program,
+    ])?;
+    assert_writable(&[stake_account])?;
+
+    assert_stake_program(stake_program)?;
+    let state = load_vault_state(program_id, owner, vault_state)?;
+    assert_threshold(&state, accounts)?;
+
+    let (expected_vault_auth, _auth_bump) =
+        Pubkey::find_program_address(&[b"auth", vault_state.key.as_ref()], program_id);
+    if vault_auth.key != &expected_vault_auth {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+
+    assert_vault_stake_account(&state, stake_account)?;
+
+    invoke_signed(
+        &stake::instruction::deactivate_stake(stake_account.key, vault_auth.key),
+        &[
+            stake_account.clone(),
+            clock_sysvar.clone(),
+            vault_auth.clone(),
+        ],
+        &[&[b"auth", vault_state.key.as_ref(), &[state.auth_bump]]],
+    )?;
+
+    msg!("Deactivate stake successful");
+    Ok(())
+}
+
+pub(crate) fn withdraw_stake(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let vault_auth = next_account_info(&mut accounts_iter)?;
+    let vault = next_account_info(&mut accounts_iter)?;
+    let stake_account = next_account_info(&mut accounts_iter)?;
+    let clock_sysvar = next_account_info(&mut accounts_iter)?;
+    let stake_history_sysvar = next_account_info(&mut accounts_iter)?;
+    let stake_program = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[
+        owner,
+        vault_state,
+        vault_auth,
+        vault,
+        stake_account,
+        clock_sysvar,
+        stake_history_sysvar,
+        stake_program,
+        system_program,
+    ])?;
+    assert_writable(&[vault, stake_account])?;
+
+    assert_system_program(system_program)?;
+    assert_stake_program(stake_program)?;
+    let mut state = load_vault_state(program_id, owner, vault_state)?;
+    assert_threshold(&state, accounts)?;
+    assert_vault_pdas(program_id, vault_state, vault_auth, vault)?;
+    assert_vault_stake_account(&state, stake_account)?;
+
+    // Lamports always return to the vault PDA; use `Withdraw` to move them on.
+    invoke_signed(
+        &stake::instruction::withdraw(stake_account.key, vault_auth.key, vault.key, amount, None),
+        &[
+            stake_account.clone(),
+            vault.clone(),
+            clock_sysvar.clone(),
+            stake_history_sysvar.clone(),
+            vault_auth.clone(),
+        ],
+        &[&[b"auth", vault_state.key.as_ref(), &[state.auth_bump]]],
+    )?;
+
+    // A fully drained stake account is gone once the transaction ends.
+    if stake_account.lamports() == 0 {
+        let mut stake_accounts = state.stake_accounts().to_vec();
+        stake_accounts.retain(|key| key != stake_account.key);
+        state.set_stake_accounts(&stake_accounts)?;
+    }
+    state.record_sol_deposit(amount)?;
+    save_vault_state(owner, vault_state, system_program, &state)?;
+
+    msg!("Withdraw stake successful");
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use solana_program::{
+    account_info::AccountInfo, entrypoint::ProgramResult, program_error::ProgramError,
+    program_pack::Pack,
+};
+
+use crate::{write_vault_state, Vault};
+
+/// Lifetime counters kept in the vault state. Vaults created before these
+/// existed start counting from zero at their first instruction afterwards.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VaultStats {
+    pub total_sol_deposited: u64,
+    pub total_sol_withdrawn: u64,
+    /// SOL, SPL and NFT deposits alike.
+    pub deposit_count: u64,
+    pub withdraw_count: u64,
+    /// Slot of the last instruction an owner signed for the vault.
+    pub last_activity_slot: u64,
+    /// Vault token accounts the program saw go from empty to holding a
+    /// balance, and has not seen emptied since.
+    pub mints_held: u32,
+}
+
+impl Vault {
+    pub fn stats(&self) -> VaultStats {
+        VaultStats {
+            total_sol_deposited: self.total_sol_deposited,
+            total_sol_withdrawn: self.total_sol_withdrawn,
+            deposit_count: self.deposit_count,
+            withdraw_count: self.withdraw_count,
+            last_activity_slot: self.last_activity_slot,
+            mints_held: self.mints_held,
+        }
+    }
+
+    pub(crate) fn record_sol_deposit(&mut self, amount: u64) -> ProgramResult {
+        self.total_sol_deposited = checked_add(self.total_sol_deposited, amount)?;
+        self.deposit_count = checked_add(self.deposit_count, 1)?;
+        Ok(())
+    }
+
+    pub(crate) fn record_sol_withdrawal(&mut self, amount: u64) -> ProgramResult {
+        self.total_sol_withdrawn = checked_add(self.total_sol_withdrawn, amount)?;
+        self.withdraw_count = checked_add(self.withdraw_count, 1)?;
+        Ok(())
+    }
+
+    /// Counts a deposit of `amount` into a vault token account that held
+    /// `balance_before`.
+    pub(crate) fn record_token_deposit(
+        &mut self,
+        balance_before: u64,
+        amount: u64,
+    ) -> ProgramResult {
+        self.deposit_count = checked_add(self.deposit_count, 1)?;
+        self.track_token_deposit(balance_before, amount)
+    }
+
+    /// Counts a withdrawal of `amount` from a vault token account that is
+    /// left with `balance_after`.
+    pub(crate) fn record_token_withdrawal(
+        &mut self,
+        balance_after: u64,
+        amount: u64,
+    ) -> ProgramResult {
+        self.withdraw_count = checked_add(self.withdraw_count, 1)?;
+        self.track_token_withdrawal(balance_after, amount)
+    }
+
+    /// Keeps `mints_held` in step with tokens arriving in a vault token
+    /// account, whoever moved them.
+    fn track_token_deposit(&mut self, balance_before: u64, amount: u64) -> ProgramResult {
+        if balance_before == 0 && amount > 0 {
+            self.mints_held = self
+                .mints_held
+                .checked_add(1)
+                .ok_or(ProgramError::ArithmeticOverflow)?;
+        }
+        Ok(())
+    }
+
+    /// Keeps `mints_held` in step with tokens leaving a vault token account,
+    /// whoever moved them.
+    fn track_token_withdrawal(&mut self, balance_after: u64, amount: u64) -> ProgramResult {
+        if balance_after == 0 && amount > 0 {
+            // Only a mint that arrived outside the program (a plain transfer,
+            // or before these counters existed) was never counted in; emptying
+            // it must not block the withdrawal.
+            self.mints_held = self.mints_held.saturating_sub(1);
+        }
+        Ok(())
+    }
+}
+
+fn checked_add(counter: u64, amount: u64) -> Result<u64, ProgramError> {
+    counter
+        .checked_add(amount)
+        .ok_or(ProgramError::ArithmeticOverflow)
+}
+
+/// Balance of a token account, or zero if it is not initialized yet.
+pub(crate) fn token_balance(account: &AccountInfo) -> u64 {
+    spl_token::state::Account::unpack(&account.data.borrow())
+        .map(|account| account.amount)
+        .unwrap_or(0)
+}
+
+/// Writes `state` back for handlers without a payer to grow the account;
+/// see `write_vault_state`.
+pub(crate) fn save_in_place(vault_state: &AccountInfo, state: &Vault) -> ProgramResult {
+    write_vault_state(vault_state, state)
+}
+
+/// Reads the counters out of raw vault state account data.
+#[cfg(feature = "client")]
+pub fn read_vault_stats(data: &[u8]) -> Result<VaultStats, ProgramError> {
+    Vault::unpack(data).map(|vault| vault.stats())
+}
@@ -0,0 +1,598 @@
+use bytemuck::{Pod, Zeroable};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+};
+
+use spl_associated_token_account::get_associated_token_address;
+use spl_token::instruction as token_instruction;
+
+use crate::{
+    allowlist::{assert_allowed_recipient, is_allowlist},
+    assert_system_program, assert_token_program,
+    checks::{assert_distinct, assert_writable},
+    close_program_account, create_pda_account,
+    ledger::{debit_ledger, restore_ledger},
+    limit::{consume_mint_limit, filter_mint_limit, next_mint_limit},
+    load_vault_state,
+    lock::assert_unlocked,
+    multisig::{assert_threshold, next_optional_account},
+    pool::assert_not_pooled,
+    receipt::assert_no_receipts,
+    save_vault_state,
+    score::release_nft,
+    stats::{save_in_place, token_balance},
+    update_vault_state,
+    zero_copy::ZeroCopy,
+    Vault, WbaVaultError,
+};
+
+/// An open OTC offer: `offer_amount` of `offer_mint` sits in the escrow token
+/// account until someone pays `ask_amount` of `ask_mint` into the maker vault.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct Offer {
+    pub vault_state: Pubkey,
+    pub maker: Pubkey,
+    pub offer_mint: Pubkey,
+    pub ask_mint: Pubkey,
+    pub offer_amount: u64,
+    pub ask_amount: u64,
+    pub bump: u8,
+    pub escrow_bump: u8,
+    _padding: [u8; 6],
+}
+
+impl ZeroCopy for Offer {
+    const DISCRIMINATOR: [u8; 8] = *b"wbaoffer";
+}
+
+fn assert_vault_auth(
+    program_id: &Pubkey,
+    vault_state: &AccountInfo,
+    vault_auth: &AccountInfo,
+) -> ProgramResult {
+    let (expected_vault_auth, _auth_bump) =
+        Pubkey::find_program_address(&[b"auth", vault_state.key.as_ref()], program_id);
+    if vault_auth.key != &expected_vault_auth {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+    Ok(())
+}
+
+/// Loads an offer and checks it belongs to `vault_state` and is escrowed in
+/// `escrow`.
+fn load_offer(
+    program_id: &Pubkey,
+    vault_state: &AccountInfo,
+    offer: &AccountInfo,
+    escrow: &AccountInfo,
+) -> Result<Offer, ProgramError> {
+    if offer.owner != program_id {
+        return Err(WbaVaultError::InvalidOffer.into());
+    }
+
+    let state = Offer::read(&offer.data.borrow())?;
+
+    if state.vault_state != *vault_state.key {
+        return Err(WbaVaultError::InvalidOffer.into());
+    }
+
+    let expected_escrow = Pubkey::create_program_address(
+        &[b"escrow", offer.key.as_ref(), &[state.escrow_bump]],
+        program_id,
+    )
+    .map_err(|_| WbaVaultError::InvalidPda)?;
+    if escrow.key != &expected_escrow {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+
+    Ok(state)
+}
+
+/// Moves the escrowed tokens to `destination`, then closes both the
+/// escrow token account and the offer, returning their rent to `rent_receiver`.
+#[allow(clippy::too_many_arguments)]
+fn release_escrow<'a>(
+    vault_state: &AccountInfo<'a>,
+    vault_auth: &AccountInfo<'a>,
+    offer: &AccountInfo<'a>,
+    escrow: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    rent_receiver: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    state: &Offer,
+    auth_bump: u8,
+) -> ProgramResult {
+    let auth_seeds: &[&[u8]] = &[b"auth", vault_state.key.as_ref(), &[auth_bump]];
+
+    invoke_signed(
+        &token_instruction::transfer(
+            token_program.key,
+            escrow.key,
+            destination.key,
+            vault_auth.key,
+            &[],
+            state.offer_amount,
+        )?,
+        &[
+            escrow.clone(),
+            destination.clone(),
+            vault_auth.clone(),
+            token_program.clone(),
+        ],
+        &[auth_seeds],
+    )?;
+
+    invoke_signed(
+        &token_instruction::close_account(
+            token_program.key,
+            escrow.key,
+            rent_receiver.key,
+            vault_auth.key,
+            &[],
+        )?,
+        &[
+            escrow.clone(),
+            rent_receiver.clone(),
+            vault_auth.clone(),
+            token_program.clone(),
+        ],
+        &[auth_seeds],
+    )?;
+
+    close_program_account(offer, rent_receiver)
+}
+
+pub(crate) fn offer_swap(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    offer_amount: u64,
+    ask_amount: u64,
+    seed: u64,
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let vault_auth = next_account_info(&mut accounts_iter)?;
+    let vault_ata = next_account_info(&mut accounts_iter)?;
+    let offer = next_account_info(&mut accounts_iter)?;
+    let escrow = next_account_info(&mut accounts_iter)?;
+    let offer_mint = next_account_info(&mut accounts_iter)?;
+    let ask_mint = next_account_info(&mut accounts_iter)?;
+    let token_program = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+    // Required once the vault has any per-mint withdraw limit.
+    let mint_limit = next_mint_limit(
+        program_id,
+        &mut accounts_iter,
+        vault_state.key,
+        offer_mint.key,
+    );
+
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[
+        owner,
+        vault_state,
+        vault_auth,
+        vault_ata,
+        offer,
+        escrow,
+        offer_mint,
+        ask_mint,
+        token_program,
+        system_program,
+    ])?;
+    assert_writable(&[vault_ata, offer, escrow])?;
+
+    assert_system_program(system_program)?;
+    assert_token_program(token_program)?;
+    let mut vault: Vault = load_vault_state(program_id, owner, vault_state)?;
+    assert_threshold(&vault, accounts)?;
+    assert_unlocked(&vault)?;
+    assert_not_pooled(&vault)?;
+    assert_no_receipts(&vault)?;
+    assert_vault_auth(program_id, vault_state, vault_auth)?;
+
+    let expected_vault_ata = get_associated_token_address(vault_auth.key, offer_mint.key);
+    if vault_ata.key != &expected_vault_ata {
+        return Err(WbaVaultError::InvalidTokenAccount.into());
+    }
+
+    if offer_amount == 0 || ask_amount == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    consume_mint_limit(
+        program_id,
+        vault_state,
+        &vault,
+        offer_mint.key,
+        mint_limit,
+        offer_amount,
+    )?;
+    debit_ledger(
+        program_id,
+        accounts,
+        vault_state,
+        &vault,
+        offer_mint.key,
+        offer_amount,
+    )?;
+
+    // offer PDA = ["offer", vaultState, seed]
+    let seed_bytes = seed.to_le_bytes();
+    let (expected_offer, bump) = Pubkey::find_program_address(
+        &[b"offer", vault_state.key.as_ref(), &seed_bytes],
+        program_id,
+    );
+    if offer.key != &expected_offer {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+
+    // escrow PDA = ["escrow", offer]; a token account whose authority is
+    // vault_auth, so the escrowed tokens can only move with the vault's PDA
+    // signature.
+    let (expected_escrow, escrow_bump) =
+        Pubkey::find_program_address(&[b"escrow", offer.key.as_ref()], program_id);
+    if escrow.key != &expected_escrow {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+
+    create_pda_account(
+        owner,
+        offer,
+        system_program,
+        Offer::SPACE,
+        program_id,
+        &[b"offer", vault_state.key.as_ref(), &seed_bytes, &[bump]],
+    )?;
+
+    create_pda_account(
+        owner,
+        escrow,
+        system_program,
+        spl_token::state::Account::LEN,
+        token_program.key,
+        &[b"escrow", offer.key.as_ref(), &[escrow_bump]],
+    )?;
+
+    invoke(
+        &token_instruction::initialize_account3(
+            token_program.key,
+            escrow.key,
+            offer_mint.key,
+            vault_auth.key,
+        )?,
+        &[escrow.clone(), offer_mint.clone(), token_program.clone()],
+    )?;
+
+    invoke_signed(
+        &token_instruction::transfer(
+            token_program.key,
+            vault_ata.key,
+            escrow.key,
+            vault_auth.key,
+            &[],
+            offer_amount,
+        )?,
+        &[
+            vault_ata.clone(),
+            escrow.clone(),
+            vault_auth.clone(),
+            token_program.clone(),
+        ],
+        &[&[b"auth", vault_state.key.as_ref(), &[vault.auth_bump]]],
+    )?;
+    vault.record_token_withdrawal(token_balance(vault_ata), offer_amount)?;
+    release_nft(
+        program_id,
+        accounts,
+        owner,
+        vault_state,
+        offer_mint.key,
+        &mut vault,
+        offer_amount,
+    )?;
+    vault.open_offers = vault
+        .open_offers
+        .checked_add(1)
+        .ok_or(ProgramError::InvalidArgument)?;
+    save_vault_state(owner, vault_state, system_program, &vault)?;
+
+    let state = Offer {
+        vault_state: *vault_state.key,
+        maker: *owner.key,
+        offer_mint: *offer_mint.key,
+        ask_mint: *ask_mint.key,
+        offer_amount,
+        ask_amount,
+        bump,
+        escrow_bump,
+        ..Offer::zeroed()
+    };
+    state.init(&mut offer.data.borrow_mut())?;
+
+    msg!("Offer swap successful");
+    Ok(())
+}
+
+pub(crate) fn accept_swap(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let taker = next_account_info(&mut accounts_iter)?;
+    let taker_source = next_account_info(&mut accounts_iter)?;
+    let taker_destination = next_account_info(&mut accounts_iter)?;
+    let maker = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let vault_auth = next_account_info(&mut accounts_iter)?;
+    let vault_ask_ata = next_account_info(&mut accounts_iter)?;
+    let offer = next_account_info(&mut accounts_iter)?;
+    let escrow = next_account_info(&mut accounts_iter)?;
+    let token_program = next_account_info(&mut accounts_iter)?;
+    // Present only when the taker fills the offer from their own vault. The
+    // maker's allowlist goes last, so a wallet fill may pass it here instead.
+    let mut next_taker_account = || {
+        next_optional_account(&mut accounts_iter)
+            .filter(|account| !is_allowlist(program_id, account, vault_state.key))
+    };
+    let taker_vault_state = next_taker_account();
+    let taker_vault_auth = next_taker_account();
+    let taker_mint_limit = next_taker_account();
+
+    if !taker.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[
+        taker,
+        taker_source,
+        taker_destination,
+        maker,
+        vault_state,
+        vault_auth,
+        vault_ask_ata,
+        offer,
+        escrow,
+        token_program,
+    ])?;
+    assert_writable(&[
+        taker_source,
+        taker_destination,
+        maker,
+        vault_ask_ata,
+        offer,
+        escrow,
+    ])?;
+
+    assert_token_program(token_program)?;
+    assert_vault_auth(program_id, vault_state, vault_auth)?;
+    let state = load_offer(program_id, vault_state, offer, escrow)?;
+
+    if maker.key != &state.maker {
+        return Err(WbaVaultError::InvalidOffer.into());
+    }
+
+    let expected_vault_ask_ata = get_associated_token_address(vault_auth.key, &state.ask_mint);
+    if vault_ask_ata.key != &expected_vault_ask_ata {
+        return Err(WbaVaultError::InvalidTokenAccount.into());
+    }
+
+    // The ask is paid to the maker's vault, which cannot be closed while the
+    // offer is open; it must be writable so `mints_held` sees the ask arrive.
+    if vault_state.owner != program_id {
+        return Err(WbaVaultError::InvalidVaultStateOwner.into());
+    }
+    assert_writable(&[vault_state])?;
+    let maker_vault = Vault::unpack(&vault_state.data.borrow())?;
+    let ask_balance = token_balance(vault_ask_ata);
+    // The escrow leaves the maker's vault for whoever owns the destination.
+    if maker_vault.has_allowlist.get() {
+        let destination = spl_token::state::Account::unpack(&taker_destination.data.borrow())?;
+        assert_allowed_recipient(
+            program_id,
+            vault_state,
+            &maker_vault,
+            accounts,
+            &destination.owner,
+        )?;
+    }
+    let pay = token_instruction::transfer(
+        token_program.key,
+        taker_source.key,
+        vault_ask_ata.key,
+        match taker_vault_auth {
+            Some(taker_vault_auth) => taker_vault_auth.key,
+            None => taker.key,
+        },
+        &[],
+        state.ask_amount,
+    )?;
+
+    match (taker_vault_state, taker_vault_auth) {
+        (Some(taker_vault_state), Some(taker_vault_auth)) => {
+            // A vault cannot fill its own offer.
+            assert_distinct(&[vault_state, vault_auth, taker_vault_state, taker_vault_auth])?;
+            assert_writable(&[taker_vault_state])?;
+            let mut taker_vault = load_vault_state(program_id, taker, taker_vault_state)?;
+            assert_threshold(&taker_vault, accounts)?;
+            assert_unlocked(&taker_vault)?;
+            assert_not_pooled(&taker_vault)?;
+            assert_no_receipts(&taker_vault)?;
+            assert_vault_auth(program_id, taker_vault_state, taker_vault_auth)?;
+            // The payment leaves the taker's vault for the maker's.
+            assert_allowed_recipient(
+                program_id,
+                taker_vault_state,
+                &taker_vault,
+                accounts,
+                vault_auth.key,
+            )?;
+            let taker_mint_limit = filter_mint_limit(
+                program_id,
+                taker_mint_limit,
+                taker_vault_state.key,
+                &state.ask_mint,
+            );
+            consume_mint_limit(
+                program_id,
+                taker_vault_state,
+                &taker_vault,
+                &state.ask_mint,
+                taker_mint_limit,
+                state.ask_amount,
+            )?;
+            debit_ledger(
+                program_id,
+                accounts,
+                taker_vault_state,
+                &taker_vault,
+                &state.ask_mint,
+                state.ask_amount,
+            )?;
+
+            // Both legs must stay inside the taker's vault.
+            let expected_source =
+                get_associated_token_address(taker_vault_auth.key, &state.ask_mint);
+            let expected_destination =
+                get_associated_token_address(taker_vault_auth.key, &state.offer_mint);
+            if taker_source.key != &expected_source
+                || taker_destination.key != &expected_destination
+            {
+                return Err(WbaVaultError::InvalidTokenAccount.into());
+            }
+
+            invoke_signed(
+                &pay,
+                &[
+                    taker_source.clone(),
+                    vault_ask_ata.clone(),
+                    taker_vault_auth.clone(),
+                    token_program.clone(),
+                ],
+                &[&[
+                    b"auth",
+                    taker_vault_state.key.as_ref(),
+                    &[taker_vault.auth_bump],
+                ]],
+            )?;
+
+            taker_vault.record_token_withdrawal(token_balance(taker_source), state.ask_amount)?;
+            taker_vault
+                .record_token_deposit(token_balance(taker_destination), state.offer_amount)?;
+            release_nft(
+                program_id,
+                accounts,
+                taker_vault_state,
+                taker_vault_state,
+                &state.ask_mint,
+                &mut taker_vault,
+                state.ask_amount,
+            )?;
+            save_in_place(taker_vault_state, &taker_vault)?;
+        }
+        (None, None) => {
+            invoke(
+                &pay,
+                &[
+                    taker_source.clone(),
+                    vault_ask_ata.clone(),
+                    taker.clone(),
+                    token_program.clone(),
+                ],
+            )?;
+        }
+        _ => return Err(ProgramError::NotEnoughAccountKeys),
+    }
+
+    let (_, auth_bump) =
+        Pubkey::find_program_address(&[b"auth", vault_state.key.as_ref()], program_id);
+    release_escrow(
+        vault_state,
+        vault_auth,
+        offer,
+        escrow,
+        taker_destination,
+        maker,
+        token_program,
+        &state,
+        auth_bump,
+    )?;
+    update_vault_state(vault_state, |maker_vault| {
+        maker_vault.open_offers = maker_vault.open_offers.saturating_sub(1);
+        maker_vault.record_token_deposit(ask_balance, state.ask_amount)
+    })?;
+
+    msg!("Accept swap successful");
+    Ok(())
+}
+
+pub(crate) fn cancel_swap(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let vault_auth = next_account_info(&mut accounts_iter)?;
+    let vault_ata = next_account_info(&mut accounts_iter)?;
+    let offer = next_account_info(&mut accounts_iter)?;
+    let escrow = next_account_info(&mut accounts_iter)?;
+    let token_program = next_account_info(&mut accounts_iter)?;
+
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[
+        owner,
+        vault_state,
+        vault_auth,
+        vault_ata,
+        offer,
+        escrow,
+        token_program,
+    ])?;
+    assert_writable(&[owner, vault_state, vault_ata, offer, escrow])?;
+
+    assert_token_program(token_program)?;
+    let mut vault = load_vault_state(program_id, owner, vault_state)?;
+    assert_threshold(&vault, accounts)?;
+    assert_vault_auth(program_id, vault_state, vault_auth)?;
+    let state = load_offer(program_id, vault_state, offer, escrow)?;
+
+    let expected_vault_ata = get_associated_token_address(vault_auth.key, &state.offer_mint);
+    if vault_ata.key != &expected_vault_ata {
+        return Err(WbaVaultError::InvalidTokenAccount.into());
+    }
+
+    restore_ledger(
+        program_id,
+        accounts,
+        vault_state,
+        &vault,
+        &state.offer_mint,
+        state.offer_amount,
+    )?;
+    vault.record_token_deposit(token_balance(vault_ata), state.offer_amount)?;
+    vault.open_offers = vault.open_offers.saturating_sub(1);
+    release_escrow(
+        vault_state,
+        vault_auth,
+        offer,
+        escrow,
+        vault_ata,
+        owner,
+        token_program,
+        &state,
+        vault.auth_bump,
+    )?;
+    save_in_place(vault_state, &vault)?;
+
+    msg!("Cancel swap successful");
+    Ok(())
+}
@@ -0,0 +1,620 @@
+use std::slice::Iter;
+
+use bytemuck::{Pod, Zeroable};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use spl_associated_token_account::get_associated_token_address;
+use spl_token::instruction as token_instruction;
+
+use crate::{
+    allowlist::assert_allowed_recipient,
+    assert_system_program, assert_token_program, assert_vault_pdas,
+    checks::{assert_distinct, assert_no_aliases, assert_writable},
+    close_program_account, create_pda_account,
+    custody::transfer_from_vault,
+    ledger::{debit_ledger, restore_ledger},
+    limit::{consume_mint_limit, consume_sol_limit, next_mint_limit},
+    load_vault_state,
+    lock::assert_unlocked,
+    multisig::{assert_threshold, next_optional_account},
+    pool::assert_not_pooled,
+    receipt::assert_no_receipts,
+    save_vault_state,
+    score::release_nft,
+    stats::{save_in_place, token_balance},
+    update_vault_state,
+    zero_copy::ZeroCopy,
+    Vault, WbaVaultError,
+};
+
+/// A linear vesting schedule funded from a vault. `mint` is
+/// `Pubkey::default()` for SOL, in which case the lamports sit in the vesting
+/// account itself; otherwise the tokens sit in the `["escrow", vesting]` token
+/// account owned by `vault_auth`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct Vesting {
+    pub vault_state: Pubkey,
+    pub beneficiary: Pubkey,
+    pub mint: Pubkey,
+    pub total_amount: u64,
+    pub claimed_amount: u64,
+    pub start_ts: i64,
+    pub cliff_ts: i64,
+    pub end_ts: i64,
+    pub bump: u8,
+    pub escrow_bump: u8,
+    _padding: [u8; 6],
+}
+
+impl ZeroCopy for Vesting {
+    const DISCRIMINATOR: [u8; 8] = *b"wbavestg";
+}
+
+impl Vesting {
+    pub fn is_sol(&self) -> bool {
+        self.mint == Pubkey::default()
+    }
+
+    /// Amount unlocked at `now`: nothing before the cliff, everything from
+    /// `end_ts`, and linear from `start_ts` in between.
+    pub fn vested_amount(&self, now: i64) -> u64 {
+        if now < self.cliff_ts {
+            return 0;
+        }
+        if now >= self.end_ts {
+            return self.total_amount;
+        }
+        let elapsed = (now - self.start_ts) as u128;
+        let duration = (self.end_ts - self.start_ts) as u128;
+        (self.total_amount as u128 * elapsed / duration) as u64
+    }
+}
+
+fn load_vesting(
+    program_id: &Pubkey,
+    vault_state: &AccountInfo,
+    vesting: &AccountInfo,
+) -> Result<Vesting, ProgramError> {
+    if vesting.owner != program_id {
+        return Err(WbaVaultError::InvalidVesting.into());
+    }
+
+    let state = Vesting::read(&vesting.data.borrow())?;
+
+    if state.vault_state != *vault_state.key {
+        return Err(WbaVaultError::InvalidVesting.into());
+    }
+
+    Ok(state)
+}
+
+fn save_vesting(vesting: &AccountInfo, state: &Vesting) -> ProgramResult {
+    state.write(&mut vesting.data.borrow_mut())
+}
+
+fn assert_escrow(
+    program_id: &Pubkey,
+    vesting: &AccountInfo,
+    escrow: &AccountInfo,
+    state: &Vesting,
+) -> ProgramResult {
+    let expected_escrow = Pubkey::create_program_address(
+        &[b"escrow", vesting.key.as_ref(), &[state.escrow_bump]],
+        program_id,
+    )
+    .map_err(|_| WbaVaultError::InvalidPda)?;
+    if escrow.key != &expected_escrow {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+    Ok(())
+}
+
+/// Reads the optional creator account of a claim. Only the final claim uses
+/// it, to return the rent to the owner of the vault the vesting came from.
+fn next_creator<'a, 'b>(
+    program_id: &Pubkey,
+    accounts_iter: &mut Iter<'a, AccountInfo<'b>>,
+    vault_state: &AccountInfo,
+    fully_claimed: bool,
+) -> Result<Option<&'a AccountInfo<'b>>, ProgramError> {
+    let creator = match next_optional_account(accounts_iter) {
+        Some(creator) if fully_claimed => creator,
+        _ => return Ok(None),
+    };
+    if vault_state.owner != program_id {
+        return Err(WbaVaultError::InvalidVaultStateOwner.into());
+    }
+    // Closing the vesting takes it off the vault's open count.
+    assert_writable(&[vault_state])?;
+    if creator.key != &Vault::unpack(&vault_state.data.borrow())?.owner {
+        return Err(WbaVaultError::InvalidVesting.into());
+    }
+    Ok(Some(creator))
+}
+
+/// Moves `amount` lamports out of a program-owned vesting account.
+fn debit_vesting(vesting: &AccountInfo, destination: &AccountInfo, amount: u64) -> ProgramResult {
+    **vesting.lamports.borrow_mut() = vesting
+        .lamports()
+        .checked_sub(amount)
+        .ok_or(ProgramError::InsufficientFunds)?;
+    **destination.lamports.borrow_mut() = destination
+        .lamports()
+        .checked_add(amount)
+        .ok_or(ProgramError::InvalidArgument)?;
+    Ok(())
+}
+
+pub(crate) fn create_vesting(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+    start_ts: i64,
+    cliff_ts: i64,
+    end_ts: i64,
+    seed: u64,
+) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let vault_auth = next_account_info(&mut accounts_iter)?;
+    let vault = next_account_info(&mut accounts_iter)?;
+    let vesting = next_account_info(&mut accounts_iter)?;
+    let beneficiary = next_account_info(&mut accounts_iter)?;
+    let system_program = next_account_info(&mut accounts_iter)?;
+    // SPL schedules additionally pass: vault_ata, escrow, mint, token_program.
+    let spl_accounts = next_optional_account(&mut accounts_iter);
+
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    // Vesting to oneself is a plain self-imposed lock.
+    assert_no_aliases(
+        &[vault_state, vault_auth, vault, vesting, system_program],
+        &[owner, beneficiary],
+    )?;
+    assert_writable(&[owner, vesting])?;
+
+    assert_system_program(system_program)?;
+    let mut vault_data = load_vault_state(program_id, owner, vault_state)?;
+    assert_threshold(&vault_data, accounts)?;
+    assert_unlocked(&vault_data)?;
+    assert_not_pooled(&vault_data)?;
+    assert_no_receipts(&vault_data)?;
+    assert_allowed_recipient(
+        program_id,
+        vault_state,
+        &vault_data,
+        accounts,
+        beneficiary.key,
+    )?;
+    assert_vault_pdas(program_id, vault_state, vault_auth, vault)?;
+
+    if amount == 0 || start_ts > cliff_ts || cliff_ts > end_ts || start_ts >= end_ts {
+        return Err(WbaVaultError::InvalidSchedule.into());
+    }
+
+    // vesting PDA = ["vesting", vaultState, seed]
+    let seed_bytes = seed.to_le_bytes();
+    let (expected_vesting, bump) = Pubkey::find_program_address(
+        &[b"vesting", vault_state.key.as_ref(), &seed_bytes],
+        program_id,
+    );
+    if vesting.key != &expected_vesting {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+
+    vault_data.open_vestings = vault_data
+        .open_vestings
+        .checked_add(1)
+        .ok_or(ProgramError::InvalidArgument)?;
+    create_pda_account(
+        owner,
+        vesting,
+        system_program,
+        Vesting::SPACE,
+        program_id,
+        &[b"vesting", vault_state.key.as_ref(), &seed_bytes, &[bump]],
+    )?;
+
+    let mut state = Vesting {
+        vault_state: *vault_state.key,
+        beneficiary: *beneficiary.key,
+        mint: Pubkey::default(),
+        total_amount: amount,
+        claimed_amount: 0,
+        start_ts,
+        cliff_ts,
+        end_ts,
+        bump,
+        escrow_bump: 0,
+        _padding: [0; 6],
+    };
+
+    match spl_accounts {
+        None => {
+            consume_sol_limit(owner, vault_state, system_program, &mut vault_data, amount)?;
+            vault_data.record_sol_withdrawal(amount)?;
+
+            transfer_from_vault(
+                program_id,
+                owner,
+                vault_state,
+                vault_auth,
+                vault,
+                vesting,
+                system_program,
+                &mut vault_data,
+                amount,
+            )?;
+        }
+        Some(vault_ata) => {
+            let escrow = next_account_info(&mut accounts_iter)?;
+            let mint = next_account_info(&mut accounts_iter)?;
+            let token_program = next_account_info(&mut accounts_iter)?;
+            let mint_limit =
+                next_mint_limit(program_id, &mut accounts_iter, vault_state.key, mint.key);
+
+            assert_no_aliases(
+                &[
+                    vault_state,
+                    vault_auth,
+                    vesting,
+                    vault_ata,
+                    escrow,
+                    mint,
+                    token_program,
+                ],
+                &[owner, beneficiary],
+            )?;
+            assert_writable(&[vault_ata, escrow])?;
+            assert_token_program(token_program)?;
+
+            let expected_vault_ata = get_associated_token_address(vault_auth.key, mint.key);
+            if vault_ata.key != &expected_vault_ata {
+                return Err(WbaVaultError::InvalidTokenAccount.into());
+            }
+
+            consume_mint_limit(
+                program_id,
+                vault_state,
+                &vault_data,
+                mint.key,
+                mint_limit,
+                amount,
+            )?;
+            debit_ledger(
+                program_id,
+                accounts,
+                vault_state,
+                &vault_data,
+                mint.key,
+                amount,
+            )?;
+
+            let (expected_escrow, escrow_bump) =
+                Pubkey::find_program_address(&[b"escrow", vesting.key.as_ref()], program_id);
+            if escrow.key != &expected_escrow {
+                return Err(WbaVaultError::InvalidPda.into());
+            }
+
+            create_pda_account(
+                owner,
+                escrow,
+                system_program,
+                spl_token::state::Account::LEN,
+                token_program.key,
+                &[b"escrow", vesting.key.as_ref(), &[escrow_bump]],
+            )?;
+
+            invoke(
+                &token_instruction::initialize_account3(
+                    token_program.key,
+                    escrow.key,
+                    mint.key,
+                    vault_auth.key,
+                )?,
+                &[escrow.clone(), mint.clone(), token_program.clone()],
+            )?;
+
+            invoke_signed(
+                &token_instruction::transfer(
+                    token_program.key,
+                    vault_ata.key,
+                    escrow.key,
+                    vault_auth.key,
+                    &[],
+                    amount,
+                )?,
+                &[
+                    vault_ata.clone(),
+                    escrow.clone(),
+                    vault_auth.clone(),
+                    token_program.clone(),
+                ],
+                &[&[b"auth", vault_state.key.as_ref(), &[vault_data.auth_bump]]],
+            )?;
+            vault_data.record_token_withdrawal(token_balance(vault_ata), amount)?;
+            release_nft(
+                program_id,
+                accounts,
+                owner,
+                vault_state,
+                mint.key,
+                &mut vault_data,
+                amount,
+            )?;
+            save_vault_state(owner, vault_state, system_program, &vault_data)?;
+
+            state.mint = *mint.key;
+            state.escrow_bump = escrow_bump;
+        }
+    }
+
+    state.init(&mut vesting.data.borrow_mut())?;
+
+    msg!("Create vesting successful");
+    Ok(())
+}
+
+/// The vault owner may be passed last; the final claim then closes the
+/// vesting (and its escrow) and returns the rent to them.
+pub(crate) fn claim_vested(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let beneficiary = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let vault_auth = next_account_info(&mut accounts_iter)?;
+    let vesting = next_account_info(&mut accounts_iter)?;
+
+    if !beneficiary.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[beneficiary, vault_state, vault_auth, vesting])?;
+    assert_writable(&[beneficiary, vesting])?;
+
+    let mut state = load_vesting(program_id, vault_state, vesting)?;
+    if state.beneficiary != *beneficiary.key {
+        return Err(WbaVaultError::InvalidSigner.into());
+    }
+
+    let (expected_vault_auth, auth_bump) =
+        Pubkey::find_program_address(&[b"auth", vault_state.key.as_ref()], program_id);
+    if vault_auth.key != &expected_vault_auth {
+        return Err(WbaVaultError::InvalidPda.into());
+    }
+
+    let now = Clock::get()?.unix_timestamp;
+    let claimable = state
+        .vested_amount(now)
+        .saturating_sub(state.claimed_amount);
+    if claimable == 0 {
+        return Err(WbaVaultError::NothingToClaim.into());
+    }
+    state.claimed_amount = state
+        .claimed_amount
+        .checked_add(claimable)
+        .ok_or(ProgramError::InvalidArgument)?;
+    let fully_claimed = state.claimed_amount == state.total_amount;
+
+    let creator = if state.is_sol() {
+        debit_vesting(vesting, beneficiary, claimable)?;
+        next_creator(program_id, &mut accounts_iter, vault_state, fully_claimed)?
+    } else {
+        let escrow = next_account_info(&mut accounts_iter)?;
+        let beneficiary_ata = next_account_info(&mut accounts_iter)?;
+        let token_program = next_account_info(&mut accounts_iter)?;
+
+        assert_distinct(&[
+            beneficiary,
+            vault_state,
+            vault_auth,
+            vesting,
+            escrow,
+            beneficiary_ata,
+            token_program,
+        ])?;
+        assert_writable(&[escrow, beneficiary_ata])?;
+        assert_token_program(token_program)?;
+        assert_escrow(program_id, vesting, escrow, &state)?;
+
+        let expected_beneficiary_ata = get_associated_token_address(beneficiary.key, &state.mint);
+        if beneficiary_ata.key != &expected_beneficiary_ata {
+            return Err(WbaVaultError::InvalidTokenAccount.into());
+        }
+
+        invoke_signed(
+            &token_instruction::transfer(
+                token_program.key,
+                escrow.key,
+                beneficiary_ata.key,
+                vault_auth.key,
+                &[],
+                claimable,
+            )?,
+            &[
+                escrow.clone(),
+                beneficiary_ata.clone(),
+                vault_auth.clone(),
+                token_program.clone(),
+            ],
+            &[&[b"auth", vault_state.key.as_ref(), &[auth_bump]]],
+        )?;
+
+        let creator = next_creator(program_id, &mut accounts_iter, vault_state, fully_claimed)?;
+        if let Some(creator) = creator {
+            invoke_signed(
+                &token_instruction::close_account(
+                    token_program.key,
+                    escrow.key,
+                    creator.key,
+                    vault_auth.key,
+                    &[],
+                )?,
+                &[
+                    escrow.clone(),
+                    creator.clone(),
+                    vault_auth.clone(),
+                    token_program.clone(),
+                ],
+                &[&[b"auth", vault_state.key.as_ref(), &[auth_bump]]],
+            )?;
+        }
+        creator
+    };
+
+    if let Some(creator) = creator {
+        close_program_account(vesting, creator)?;
+        update_vault_state(vault_state, |vault| {
+            vault.open_vestings = vault.open_vestings.saturating_sub(1);
+            Ok(())
+        })?;
+        msg!("Claim vested successful; vesting closed");
+        return Ok(());
+    }
+
+    save_vesting(vesting, &state)?;
+
+    msg!("Claim vested successful");
+    Ok(())
+}
+
+pub(crate) fn revoke_vesting(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let mut accounts_iter = accounts.iter();
+    let owner = next_account_info(&mut accounts_iter)?;
+    let vault_state = next_account_info(&mut accounts_iter)?;
+    let vault_auth = next_account_info(&mut accounts_iter)?;
+    let vault = next_account_info(&mut accounts_iter)?;
+    let vesting = next_account_info(&mut accounts_iter)?;
+
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    assert_distinct(&[owner, vault_state, vault_auth, vault, vesting])?;
+    assert_writable(&[vault_state, vault, vesting])?;
+
+    let mut vault_data = load_vault_state(program_id, owner, vault_state)?;
+    assert_threshold(&vault_data, accounts)?;
+    assert_vault_pdas(program_id, vault_state, vault_auth, vault)?;
+    let mut state = load_vesting(program_id, vault_state, vesting)?;
+
+    // Whatever has vested stays claimable by the beneficiary; only the
+    // unvested remainder goes back into the vault.
+    let now = Clock::get()?.unix_timestamp;
+    let vested = state.vested_amount(now);
+    let unvested = state.total_amount - vested;
+    state.total_amount = vested;
+    state.cliff_ts = state.cliff_ts.min(now);
+    state.end_ts = state.end_ts.min(now);
+    state.start_ts = state.start_ts.min(state.end_ts);
+    let fully_claimed = state.claimed_amount == state.total_amount;
+
+    if state.is_sol() {
+        if unvested > 0 {
+            vault_data.record_sol_deposit(unvested)?;
+        }
+        debit_vesting(vesting, vault, unvested)?;
+    } else {
+        let vault_ata = next_account_info(&mut accounts_iter)?;
+        let escrow = next_account_info(&mut accounts_iter)?;
+        let token_program = next_account_info(&mut accounts_iter)?;
+
+        assert_distinct(&[
+            owner,
+            vault_state,
+            vault_auth,
+            vault,
+            vesting,
+            vault_ata,
+            escrow,
+            token_program,
+        ])?;
+        assert_writable(&[vault_ata, escrow])?;
+        assert_token_program(token_program)?;
+        assert_escrow(program_id, vesting, escrow, &state)?;
+
+        let expected_vault_ata = get_associated_token_address(vault_auth.key, &state.mint);
+        if vault_ata.key != &expected_vault_ata {
+            return Err(WbaVaultError::InvalidTokenAccount.into());
+        }
+
+        let auth_seeds: &[&[u8]] = &[b"auth", vault_state.key.as_ref(), &[vault_data.auth_bump]];
+
+        if unvested > 0 {
+            restore_ledger(
+                program_id,
+                accounts,
+                vault_state,
+                &vault_data,
+                &state.mint,
+                unvested,
+            )?;
+            vault_data.record_token_deposit(token_balance(vault_ata), unvested)?;
+            invoke_signed(
+                &token_instruction::transfer(
+                    token_program.key,
+                    escrow.key,
+                    vault_ata.key,
+                    vault_auth.key,
+                    &[],
+                    unvested,
+                )?,
+                &[
+                    escrow.clone(),
+                    vault_ata.clone(),
+                    vault_auth.clone(),
+                    token_program.clone(),
+                ],
+                &[auth_seeds],
+            )?;
+        }
+
+        if fully_claimed {
+            invoke_signed(
+                &token_instruction::close_account(
+                    token_program.key,
+                    escrow.key,
+                    owner.key,
+                    vault_auth.key,
+                    &[],
+                )?,
+                &[
+                    escrow.clone(),
+                    owner.clone(),
+                    vault_auth.clone(),
+                    token_program.clone(),
+                ],
+                &[auth_seeds],
+            )?;
+        }
+    }
+
+    if fully_claimed {
+        vault_data.open_vestings = vault_data.open_vestings.saturating_sub(1);
+    }
+    save_in_place(vault_state, &vault_data)?;
+
+    if fully_claimed {
+        close_program_account(vesting, owner)?;
+        msg!("Revoke vesting successful; vesting closed");
+        return Ok(());
+    }
+
+    save_vesting(vesting, &state)?;
+
+    msg!("Revoke vesting successful");
+    Ok(())
+}
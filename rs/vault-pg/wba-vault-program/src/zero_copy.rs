@@ -0,0 +1,162 @@
+//! Fixed-layout account state, read and written in place.
+//!
+//! State types are `#[repr(C)]` with any padding spelled out as `_padding`
+//! fields, so `bytemuck` can view account data as the struct without decoding
+//! it. An account holds the type's 8-byte `DISCRIMINATOR` followed by the
+//! struct. Vault states written before this layout hold Borsh and start with
+//! the owner instead; they stay readable, and writable in their own layout,
+//! until a handler with a payer migrates them. Every other account without
+//! the discriminator is invalid.
+
+use std::{borrow::Cow, mem::size_of};
+
+use bytemuck::{Pod, Zeroable};
+use solana_program::{entrypoint::ProgramResult, program_error::ProgramError};
+
+pub const DISCRIMINATOR_LEN: usize = 8;
+
+/// A `bool` that is `Pod`. Any non-zero byte reads as `true`.
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
+pub struct PodBool(u8);
+
+impl PodBool {
+    pub fn get(self) -> bool {
+        self.0 != 0
+    }
+}
+
+impl From<bool> for PodBool {
+    fn from(value: bool) -> Self {
+        Self(value as u8)
+    }
+}
+
+impl PartialEq for PodBool {
+    fn eq(&self, other: &Self) -> bool {
+        self.get() == other.get()
+    }
+}
+
+impl Eq for PodBool {}
+
+pub trait ZeroCopy: Pod {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN];
+    /// Account size for the current layout.
+    const SPACE: usize = DISCRIMINATOR_LEN + size_of::<Self>();
+
+    /// Decodes an account written before the zero-copy layout. Only the
+    /// vault state has one.
+    fn read_legacy(_data: &[u8]) -> Result<Self, ProgramError> {
+        Err(ProgramError::InvalidAccountData)
+    }
+
+    /// Encodes `self` in the pre-zero-copy layout.
+    fn write_legacy(&self, _data: &mut [u8]) -> ProgramResult {
+        Err(ProgramError::InvalidAccountData)
+    }
+
+    /// Whether `data` holds the zero-copy layout. A legacy account would
+    /// need a pubkey starting with the discriminator to pass.
+    fn is_zero_copy(data: &[u8]) -> bool {
+        data.get(..DISCRIMINATOR_LEN) == Some(&Self::DISCRIMINATOR[..])
+    }
+
+    /// Borrows the state straight out of account data. Fails for legacy or
+    /// short accounts, which have to go through `read`.
+    fn load(data: &[u8]) -> Result<&Self, ProgramError> {
+        if !Self::is_zero_copy(data) {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let bytes = data
+            .get(DISCRIMINATOR_LEN..Self::SPACE)
+            .ok_or(ProgramError::InvalidAccountData)?;
+        bytemuck::try_from_bytes(bytes).map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    /// Mutable `load`, for updates made straight in account data.
+    fn load_mut(data: &mut [u8]) -> Result<&mut Self, ProgramError> {
+        if !Self::is_zero_copy(data) {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let bytes = data
+            .get_mut(DISCRIMINATOR_LEN..Self::SPACE)
+            .ok_or(ProgramError::InvalidAccountData)?;
+        bytemuck::try_from_bytes_mut(bytes).map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    /// Borrows the state in place when possible and decodes it otherwise,
+    /// for callers that only read.
+    fn view(data: &[u8]) -> Result<Cow<'_, Self>, ProgramError> {
+        match Self::load(data) {
+            Ok(state) => Ok(Cow::Borrowed(state)),
+            Err(_) => Self::read(data).map(Cow::Owned),
+        }
+    }
+
+    /// Copies the state out of either layout. Zero-copy accounts written
+    /// before fields were appended read the missing bytes as zeroes.
+    fn read(data: &[u8]) -> Result<Self, ProgramError> {
+        if !Self::is_zero_copy(data) {
+            return Self::read_legacy(data);
+        }
+        let mut state = Self::zeroed();
+        let bytes = &data[DISCRIMINATOR_LEN..data.len().min(Self::SPACE)];
+        bytemuck::bytes_of_mut(&mut state)[..bytes.len()].copy_from_slice(bytes);
+        Ok(state)
+    }
+
+    /// Writes `self` over `data` in the layout it already has.
+    fn write(&self, data: &mut [u8]) -> ProgramResult {
+        if !Self::is_zero_copy(data) {
+            return self.write_legacy(data);
+        }
+        data.get_mut(DISCRIMINATOR_LEN..Self::SPACE)
+            .ok_or(ProgramError::AccountDataTooSmall)?
+            .copy_from_slice(bytemuck::bytes_of(self));
+        Ok(())
+    }
+
+    /// Writes `self` in the zero-copy layout and zeroes the rest of `data`.
+    fn init(&self, data: &mut [u8]) -> ProgramResult {
+        if data.len() < Self::SPACE {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        data[..DISCRIMINATOR_LEN].copy_from_slice(&Self::DISCRIMINATOR);
+        data[DISCRIMINATOR_LEN..Self::SPACE].copy_from_slice(bytemuck::bytes_of(self));
+        data[Self::SPACE..].fill(0);
+        Ok(())
+    }
+}
+
+/// `read_legacy` for types whose Borsh encoding is their first `len` bytes,
+/// i.e. every field is fixed-size and all padding comes last.
+pub(crate) fn read_prefix<T: Pod>(data: &[u8], len: usize) -> Result<T, ProgramError> {
+    let bytes = data.get(..len).ok_or(ProgramError::InvalidAccountData)?;
+    let mut state = T::zeroed();
+    bytemuck::bytes_of_mut(&mut state)[..len].copy_from_slice(bytes);
+    Ok(state)
+}
+
+pub(crate) fn write_prefix<T: Pod>(state: &T, data: &mut [u8], len: usize) -> ProgramResult {
+    data.get_mut(..len)
+        .ok_or(ProgramError::AccountDataTooSmall)?
+        .copy_from_slice(&bytemuck::bytes_of(state)[..len]);
+    Ok(())
+}
+
+/// The first `len` entries of a fixed-capacity list.
+pub(crate) fn list<T>(items: &[T], len: u8) -> &[T] {
+    &items[..(len as usize).min(items.len())]
+}
+
+/// Replaces a fixed-capacity list, failing if `values` does not fit.
+pub(crate) fn set_list<T: Pod>(items: &mut [T], len: &mut u8, values: &[T]) -> ProgramResult {
+    if values.len() > items.len() {
+        return Err(ProgramError::InvalidArgument);
+    }
+    items.fill(T::zeroed());
+    items[..values.len()].copy_from_slice(values);
+    *len = values.len() as u8;
+    Ok(())
+}
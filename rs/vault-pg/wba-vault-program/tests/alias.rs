@@ -0,0 +1,1056 @@
+//! Pairwise aliasing regression suite: every instruction is replayed with
+//! each pair of its accounts collapsed into one, and each variant must be
+//! rejected. Only role pairs a handler deliberately allows to coincide (e.g.
+//! the proposer executing their own proposal) are exempt; those are covered
+//! by the feature tests.
+
+mod common;
+
+use common::*;
+use solana_program::{
+    bpf_loader_upgradeable,
+    clock::Clock,
+    ed25519_program,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey::Pubkey,
+    stake, system_instruction, system_program, sysvar,
+    vote::{
+        instruction as vote_instruction,
+        state::{VoteInit, VoteState},
+    },
+};
+use solana_program_test::{BanksClientError, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use spl_associated_token_account::get_associated_token_address;
+use wba_vault_program::{
+    id, AllowlistTree, Permit, ProposalAction, Vault, WbaVaultError, WbaVaultInstruction,
+};
+
+const NOW: i64 = 10_000;
+
+fn pda(seeds: &[&[u8]]) -> Pubkey {
+    Pubkey::find_program_address(seeds, &id()).0
+}
+
+/// The keypairs among `keypairs` that `instructions` need signatures from.
+fn required_signers<'a>(
+    instructions: &[Instruction],
+    keypairs: &[&'a Keypair],
+) -> Vec<&'a Keypair> {
+    keypairs
+        .iter()
+        .copied()
+        .filter(|keypair| {
+            instructions.iter().any(|ix| {
+                ix.accounts
+                    .iter()
+                    .any(|meta| meta.is_signer && meta.pubkey == keypair.pubkey())
+            })
+        })
+        .collect()
+}
+
+/// Sends without waiting for a fresh blockhash; every variant is a distinct
+/// message, so none of them is deduplicated.
+async fn try_send(
+    context: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    keypairs: &[&Keypair],
+) -> Result<(), BanksClientError> {
+    let mut signers = vec![&context.payer];
+    signers.extend(required_signers(instructions, keypairs));
+    let tx = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&context.payer.pubkey()),
+        &signers,
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await
+}
+
+/// Collapses every pair of `target`'s accounts and requires each variant to
+/// fail with `DuplicateAccount`, then runs `target` as given and requires it
+/// to succeed, so the failures are down to the aliasing. `prefix` goes before
+/// `target` in the same transaction; `shared` lists the index pairs that may
+/// alias.
+async fn assert_aliases_rejected(
+    context: &mut ProgramTestContext,
+    prefix: &[Instruction],
+    target: Instruction,
+    keypairs: &[&Keypair],
+    shared: &[(usize, usize)],
+) {
+    assert_aliases_rejected_with(context, prefix, target, keypairs, shared, &[]).await;
+}
+
+/// Like `assert_aliases_rejected`, for targets with trailing accounts that are
+/// checked by address rather than for distinctness: overwriting the index in
+/// `checked` fails with its error instead, since the account is then missing.
+async fn assert_aliases_rejected_with(
+    context: &mut ProgramTestContext,
+    prefix: &[Instruction],
+    target: Instruction,
+    keypairs: &[&Keypair],
+    shared: &[(usize, usize)],
+    checked: &[(usize, WbaVaultError)],
+) {
+    let metas = target.accounts.clone();
+    for i in 0..metas.len() {
+        for j in i + 1..metas.len() {
+            if metas[i].pubkey == metas[j].pubkey || shared.contains(&(i, j)) {
+                continue;
+            }
+            // Keep the signer's key so the variant can still be signed.
+            let (from, to) = if metas[j].is_signer && !metas[i].is_signer {
+                (j, i)
+            } else {
+                (i, j)
+            };
+            let mut aliased = target.clone();
+            aliased.accounts[to] = AccountMeta {
+                pubkey: metas[from].pubkey,
+                is_signer: metas[i].is_signer || metas[j].is_signer,
+                is_writable: metas[i].is_writable || metas[j].is_writable,
+            };
+            aliased.accounts[from].is_signer = aliased.accounts[to].is_signer;
+            aliased.accounts[from].is_writable = aliased.accounts[to].is_writable;
+
+            let mut instructions = prefix.to_vec();
+            instructions.push(aliased);
+            let error = checked
+                .iter()
+                .find(|(index, _)| *index == to)
+                .map_or(WbaVaultError::DuplicateAccount, |(_, error)| *error);
+            let result = try_send(context, &instructions, keypairs)
+                .await
+                .map_err(|error| error.unwrap());
+            assert_eq!(
+                result,
+                Err(TransactionError::InstructionError(
+                    prefix.len() as u8,
+                    InstructionError::Custom(error as u32),
+                )),
+                "aliasing accounts {} and {}",
+                i,
+                j
+            );
+        }
+    }
+
+    let mut instructions = prefix.to_vec();
+    instructions.push(target);
+    let signers = required_signers(&instructions, keypairs);
+    process(context, &instructions, &signers).await.unwrap();
+}
+
+async fn set_time(context: &mut ProgramTestContext, unix_timestamp: i64) {
+    let mut clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    clock.unix_timestamp = unix_timestamp;
+    context.set_sysvar(&clock);
+}
+
+fn owner_ix(keys: &VaultKeys, data: WbaVaultInstruction) -> Instruction {
+    ix(
+        data,
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+fn spl_ix(keys: &VaultKeys, mint: &Pubkey, data: WbaVaultInstruction) -> Instruction {
+    let owner_ata = get_associated_token_address(&keys.owner.pubkey(), mint);
+    let vault_ata = get_associated_token_address(&keys.vault_auth, mint);
+    let mut ix = withdraw_spl_ix(keys, &owner_ata, &vault_ata, mint, 0);
+    ix.data = borsh::to_vec(&data).unwrap();
+    // The ledger is found by address, so any account in its place just
+    // leaves it out.
+    ix.accounts.pop();
+    ix
+}
+
+#[tokio::test]
+async fn core_instructions_reject_aliases() {
+    let mut context = program_test().start_with_context().await;
+    let keys = VaultKeys::new();
+    let fund = fund_ix(&context, &keys.owner.pubkey());
+    process(&mut context, &[fund], &[]).await.unwrap();
+    let signers = [&keys.owner, &keys.vault_state];
+
+    assert_aliases_rejected(&mut context, &[], initialize_ix(&keys), &signers, &[]).await;
+    assert_aliases_rejected(
+        &mut context,
+        &[],
+        deposit_ix(&keys, 1_000_000),
+        &signers,
+        &[],
+    )
+    .await;
+    assert_aliases_rejected(&mut context, &[], withdraw_ix(&keys, 1_000), &signers, &[]).await;
+    let resize = ix(
+        WbaVaultInstruction::Resize {
+            size: Vault::space() as u64,
+        },
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    assert_aliases_rejected(&mut context, &[], resize, &signers, &[]).await;
+
+    let payer = context.payer.pubkey();
+    let mint = create_mint(&mut context, &payer, 6).await;
+    let owner_ata = create_ata(&mut context, &keys.owner.pubkey(), &mint).await;
+    create_ata(&mut context, &keys.vault_auth, &mint).await;
+    mint_to(&mut context, &mint, &owner_ata, 100).await;
+    let deposit_spl = spl_ix(&keys, &mint, WbaVaultInstruction::DepositSpl { amount: 50 });
+    assert_aliases_rejected(&mut context, &[], deposit_spl, &signers, &[]).await;
+    let withdraw_spl = spl_ix(
+        &keys,
+        &mint,
+        WbaVaultInstruction::WithdrawSpl { amount: 20 },
+    );
+    assert_aliases_rejected(&mut context, &[], withdraw_spl, &signers, &[]).await;
+    let reconcile = reconcile_ix(&keys, &mint, true);
+    assert_aliases_rejected(&mut context, &[], reconcile, &signers, &[]).await;
+
+    let nft = create_mint(&mut context, &payer, 0).await;
+    seed_master_edition(&mut context, &nft);
+    let owner_nft_ata = create_ata(&mut context, &keys.owner.pubkey(), &nft).await;
+    create_ata(&mut context, &keys.vault_auth, &nft).await;
+    mint_to(&mut context, &nft, &owner_nft_ata, 1).await;
+    // Like the ledger, the stake record is found by address; the NFT is then
+    // held unstaked.
+    let unstaked_nft_ix = |data| {
+        let mut ix = nft_ix(&keys, &nft, data);
+        ix.accounts.pop();
+        ix
+    };
+    let mut deposit_nft = unstaked_nft_ix(WbaVaultInstruction::DepositNft);
+    deposit_nft.accounts.pop();
+    assert_aliases_rejected(&mut context, &[], deposit_nft, &signers, &[]).await;
+    let withdraw_nft = unstaked_nft_ix(WbaVaultInstruction::WithdrawNft);
+    // A signer in the optional mint limit slot just leaves the limit out; any
+    // other account there is checked as the limit PDA. The reconcile above
+    // created a ledger, so one for the NFT must be passed too. The metadata
+    // program cannot be loaded writable in either slot at all.
+    let checked = [
+        (12, WbaVaultError::InvalidPda),
+        (13, WbaVaultError::MissingLedger),
+    ];
+    assert_aliases_rejected_with(
+        &mut context,
+        &[],
+        withdraw_nft,
+        &signers,
+        &[(0, 12), (8, 12), (8, 13)],
+        &checked,
+    )
+    .await;
+
+    // Closing back to the owner is the common case.
+    let close = ix(
+        WbaVaultInstruction::CloseAccount,
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(Keypair::new().pubkey(), false),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    assert_aliases_rejected(&mut context, &[], close, &signers, &[(0, 1)]).await;
+}
+
+#[tokio::test]
+async fn admin_instructions_reject_aliases() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let payer = context.payer.pubkey();
+    let mint = create_mint(&mut context, &payer, 6).await;
+    let vault_ata = create_ata(&mut context, &keys.vault_auth, &mint).await;
+    mint_to(&mut context, &mint, &vault_ata, 100).await;
+    let signers = [&keys.owner];
+    let state = keys.vault_state.pubkey();
+
+    let cases = [
+        owner_ix(
+            &keys,
+            WbaVaultInstruction::SetLock {
+                locked_until: 0,
+                locked_until_epoch: 0,
+            },
+        ),
+        owner_ix(
+            &keys,
+            WbaVaultInstruction::SetWithdrawLimit {
+                max_amount: u64::MAX,
+                window_secs: 60,
+            },
+        ),
+        owner_ix(
+            &keys,
+            WbaVaultInstruction::SetGuardians {
+                guardians: vec![Keypair::new().pubkey()],
+                threshold: 1,
+                delay_secs: 60,
+            },
+        ),
+        owner_ix(
+            &keys,
+            WbaVaultInstruction::SetInheritance {
+                beneficiary: Keypair::new().pubkey(),
+                inactivity_secs: 60,
+            },
+        ),
+        ix(
+            WbaVaultInstruction::Heartbeat,
+            vec![
+                AccountMeta::new_readonly(keys.owner.pubkey(), true),
+                AccountMeta::new(state, false),
+            ],
+        ),
+        ix(
+            WbaVaultInstruction::SetMintWithdrawLimit {
+                max_amount: u64::MAX,
+                window_secs: 60,
+            },
+            vec![
+                AccountMeta::new(keys.owner.pubkey(), true),
+                AccountMeta::new(state, false),
+                AccountMeta::new_readonly(mint, false),
+                AccountMeta::new(pda(&[b"limit", state.as_ref(), mint.as_ref()]), false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+        ),
+    ];
+    for case in cases {
+        assert_aliases_rejected(&mut context, &[], case, &signers, &[]).await;
+    }
+
+    // Delegates: set, spend to a recipient, revoke.
+    let bot = Keypair::new();
+    let recipient = Keypair::new().pubkey();
+    let fund_bot = fund_ix(&context, &bot.pubkey());
+    let fund_recipient = fund_ix(&context, &recipient);
+    process(
+        &mut context,
+        &[fund_bot, fund_recipient, deposit_ix(&keys, 1_000_000)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    let sol = Pubkey::default();
+    let delegate = pda(&[
+        b"delegate",
+        state.as_ref(),
+        bot.pubkey().as_ref(),
+        sol.as_ref(),
+    ]);
+    let set_delegate = ix(
+        WbaVaultInstruction::SetDelegate {
+            delegate: bot.pubkey(),
+            mint: sol,
+            allowance: 1_000,
+            expires_at: 0,
+            recipients: vec![recipient],
+        },
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new_readonly(state, false),
+            AccountMeta::new(delegate, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    assert_aliases_rejected(&mut context, &[], set_delegate, &signers, &[]).await;
+    let delegate_withdraw = ix(
+        WbaVaultInstruction::DelegateWithdraw { amount: 100 },
+        vec![
+            AccountMeta::new(bot.pubkey(), true),
+            AccountMeta::new(state, false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(keys.vault, false),
+            AccountMeta::new(delegate, false),
+            AccountMeta::new(recipient, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    assert_aliases_rejected(&mut context, &[], delegate_withdraw, &[&bot], &[(0, 5)]).await;
+    let revoke_delegate = ix(
+        WbaVaultInstruction::RevokeDelegate,
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new_readonly(state, false),
+            AccountMeta::new(delegate, false),
+        ],
+    );
+    assert_aliases_rejected(&mut context, &[], revoke_delegate, &signers, &[]).await;
+
+    let allowlist = pda(&[b"allowlist", state.as_ref()]);
+    let allowlist_ix = |data| {
+        ix(
+            data,
+            vec![
+                AccountMeta::new(keys.owner.pubkey(), true),
+                AccountMeta::new(state, false),
+                AccountMeta::new(allowlist, false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+        )
+    };
+    let entry = Keypair::new().pubkey();
+    for case in [
+        WbaVaultInstruction::SetAllowlistConfig {
+            enabled: false,
+            add_delay_secs: 0,
+        },
+        WbaVaultInstruction::AddAllowlistEntry { address: entry },
+        WbaVaultInstruction::RemoveAllowlistEntry { address: entry },
+    ] {
+        assert_aliases_rejected(&mut context, &[], allowlist_ix(case), &signers, &[]).await;
+    }
+
+    let set_multisig = owner_ix(
+        &keys,
+        WbaVaultInstruction::SetMultisig {
+            signers: vec![keys.owner.pubkey()],
+            threshold: 1,
+        },
+    );
+    assert_aliases_rejected(&mut context, &[], set_multisig, &signers, &[]).await;
+    // Receipts need a vault with nothing in it yet.
+    let empty = setup_vault(&mut context).await;
+    let mut enable_receipts = owner_ix(&empty, WbaVaultInstruction::EnableReceipts);
+    enable_receipts.accounts.splice(
+        2..2,
+        [
+            AccountMeta::new_readonly(empty.vault_auth, false),
+            AccountMeta::new_readonly(empty.vault, false),
+        ],
+    );
+    assert_aliases_rejected(&mut context, &[], enable_receipts, &[&empty.owner], &[]).await;
+
+    let receipt_mint = pda(&[
+        b"receipt",
+        empty.vault_state.pubkey().as_ref(),
+        Pubkey::default().as_ref(),
+    ]);
+    let create_receipt_mint = ix(
+        WbaVaultInstruction::CreateReceiptMint {
+            mint: Pubkey::default(),
+        },
+        vec![
+            AccountMeta::new(empty.owner.pubkey(), true),
+            AccountMeta::new_readonly(empty.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(empty.vault_auth, false),
+            AccountMeta::new(receipt_mint, false),
+            AccountMeta::new(metadata_address(&receipt_mint), false),
+            AccountMeta::new_readonly(METADATA_PROGRAM_ID, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    assert_aliases_rejected(&mut context, &[], create_receipt_mint, &[&empty.owner], &[]).await;
+}
+
+#[tokio::test]
+async fn recovery_and_inheritance_reject_aliases() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let guardian = Keypair::new();
+    let heir = Keypair::new();
+    let fund_guardian = fund_ix(&context, &guardian.pubkey());
+    let fund_heir = fund_ix(&context, &heir.pubkey());
+    set_time(&mut context, NOW).await;
+    process(
+        &mut context,
+        &[
+            fund_guardian,
+            fund_heir,
+            owner_ix(
+                &keys,
+                WbaVaultInstruction::SetGuardians {
+                    guardians: vec![guardian.pubkey()],
+                    threshold: 1,
+                    delay_secs: 60,
+                },
+            ),
+        ],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    let signed_by_key = |signer: &Pubkey, data| {
+        let mut ix = owner_ix(&keys, data);
+        ix.accounts[0].pubkey = *signer;
+        ix
+    };
+    let new_owner = heir.pubkey();
+    let initiate = || {
+        signed_by_key(
+            &guardian.pubkey(),
+            WbaVaultInstruction::InitiateRecovery { new_owner },
+        )
+    };
+    assert_aliases_rejected(&mut context, &[], initiate(), &[&guardian], &[]).await;
+    let cancel = owner_ix(&keys, WbaVaultInstruction::CancelRecovery);
+    assert_aliases_rejected(&mut context, &[], cancel, &[&keys.owner], &[]).await;
+
+    process(&mut context, &[initiate()], &[&guardian])
+        .await
+        .unwrap();
+    set_time(&mut context, NOW + 60).await;
+    let complete = signed_by_key(&guardian.pubkey(), WbaVaultInstruction::CompleteRecovery);
+    assert_aliases_rejected(&mut context, &[], complete, &[&guardian], &[]).await;
+
+    // The recovered key names a beneficiary, then goes quiet.
+    let beneficiary = Keypair::new();
+    let fund = fund_ix(&context, &beneficiary.pubkey());
+    let set_inheritance = signed_by_key(
+        &heir.pubkey(),
+        WbaVaultInstruction::SetInheritance {
+            beneficiary: beneficiary.pubkey(),
+            inactivity_secs: 60,
+        },
+    );
+    process(&mut context, &[fund, set_inheritance], &[&heir])
+        .await
+        .unwrap();
+    set_time(&mut context, NOW + 120).await;
+    let claim = signed_by_key(&beneficiary.pubkey(), WbaVaultInstruction::ClaimInheritance);
+    assert_aliases_rejected(&mut context, &[], claim, &[&beneficiary], &[]).await;
+}
+
+#[tokio::test]
+async fn proposals_reject_aliases() {
+    let mut context = program_test().start_with_context().await;
+    let (keys, [a, b, c]) = setup_multisig(&mut context).await;
+    let state = keys.vault_state.pubkey();
+    let recipient = Keypair::new().pubkey();
+    let fund = fund_ix(&context, &recipient);
+    process(&mut context, &[fund], &[]).await.unwrap();
+    set_time(&mut context, NOW).await;
+
+    let proposal = |seed: u64| pda(&[b"proposal", state.as_ref(), &seed.to_le_bytes()]);
+    let create = |seed: u64| {
+        ix(
+            WbaVaultInstruction::CreateProposal {
+                action: ProposalAction::WithdrawSol { amount: 1_000 },
+                recipient,
+                expires_at: NOW + 3_600,
+                seed,
+            },
+            vec![
+                AccountMeta::new(a.pubkey(), true),
+                AccountMeta::new_readonly(state, false),
+                AccountMeta::new(proposal(seed), false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+        )
+    };
+    assert_aliases_rejected(&mut context, &[], create(0), &[&a], &[]).await;
+
+    let approve = ix(
+        WbaVaultInstruction::Approve,
+        vec![
+            AccountMeta::new_readonly(b.pubkey(), true),
+            AccountMeta::new_readonly(state, false),
+            AccountMeta::new(proposal(0), false),
+        ],
+    );
+    assert_aliases_rejected(&mut context, &[], approve, &[&b], &[]).await;
+
+    // Executor, proposer and recipient are free to coincide.
+    let execute = ix(
+        WbaVaultInstruction::Execute,
+        vec![
+            AccountMeta::new(c.pubkey(), true),
+            AccountMeta::new(state, false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(keys.vault, false),
+            AccountMeta::new(proposal(0), false),
+            AccountMeta::new(a.pubkey(), false),
+            AccountMeta::new(recipient, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    assert_aliases_rejected(&mut context, &[], execute, &[&c], &[(0, 5), (0, 6), (5, 6)]).await;
+
+    process(&mut context, &[create(1)], &[&a]).await.unwrap();
+    let cancel = ix(
+        WbaVaultInstruction::CancelProposal,
+        vec![
+            AccountMeta::new_readonly(b.pubkey(), true),
+            AccountMeta::new_readonly(state, false),
+            AccountMeta::new(proposal(1), false),
+            AccountMeta::new(a.pubkey(), false),
+        ],
+    );
+    // Only the proposer may cancel before expiry.
+    set_time(&mut context, NOW + 3_600).await;
+    assert_aliases_rejected(&mut context, &[], cancel, &[&b], &[(0, 3)]).await;
+}
+
+#[tokio::test]
+async fn swaps_and_vesting_reject_aliases() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let state = keys.vault_state.pubkey();
+    let payer = context.payer.pubkey();
+    let offer_mint = create_mint(&mut context, &payer, 0).await;
+    let ask_mint = create_mint(&mut context, &payer, 0).await;
+    let vault_offer_ata = create_ata(&mut context, &keys.vault_auth, &offer_mint).await;
+    let vault_ask_ata = create_ata(&mut context, &keys.vault_auth, &ask_mint).await;
+    mint_to(&mut context, &offer_mint, &vault_offer_ata, 100).await;
+
+    let offer = |seed: u64| pda(&[b"offer", state.as_ref(), &seed.to_le_bytes()]);
+    let escrow = |seed: u64| pda(&[b"escrow", offer(seed).as_ref()]);
+    let offer_swap = |seed: u64| {
+        ix(
+            WbaVaultInstruction::OfferSwap {
+                offer_amount: 10,
+                ask_amount: 4,
+                seed,
+            },
+            vec![
+                AccountMeta::new(keys.owner.pubkey(), true),
+                AccountMeta::new(state, false),
+                AccountMeta::new_readonly(keys.vault_auth, false),
+                AccountMeta::new(vault_offer_ata, false),
+                AccountMeta::new(offer(seed), false),
+                AccountMeta::new(escrow(seed), false),
+                AccountMeta::new_readonly(offer_mint, false),
+                AccountMeta::new_readonly(ask_mint, false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+        )
+    };
+    assert_aliases_rejected(&mut context, &[], offer_swap(0), &[&keys.owner], &[]).await;
+
+    let taker = Keypair::new();
+    let fund = fund_ix(&context, &taker.pubkey());
+    process(&mut context, &[fund], &[]).await.unwrap();
+    let taker_source = create_ata(&mut context, &taker.pubkey(), &ask_mint).await;
+    let taker_destination = create_ata(&mut context, &taker.pubkey(), &offer_mint).await;
+    mint_to(&mut context, &ask_mint, &taker_source, 4).await;
+    let accept = ix(
+        WbaVaultInstruction::AcceptSwap,
+        vec![
+            AccountMeta::new_readonly(taker.pubkey(), true),
+            AccountMeta::new(taker_source, false),
+            AccountMeta::new(taker_destination, false),
+            AccountMeta::new(keys.owner.pubkey(), false),
+            AccountMeta::new(state, false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(vault_ask_ata, false),
+            AccountMeta::new(offer(0), false),
+            AccountMeta::new(escrow(0), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+    );
+    assert_aliases_rejected(&mut context, &[], accept, &[&taker], &[]).await;
+
+    process(&mut context, &[offer_swap(1)], &[&keys.owner])
+        .await
+        .unwrap();
+    let cancel = ix(
+        WbaVaultInstruction::CancelSwap,
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(state, false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(vault_offer_ata, false),
+            AccountMeta::new(offer(1), false),
+            AccountMeta::new(escrow(1), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+    );
+    assert_aliases_rejected(&mut context, &[], cancel, &[&keys.owner], &[]).await;
+
+    // Vesting to oneself is allowed, so owner and beneficiary may coincide.
+    let beneficiary = Keypair::new();
+    let fund = fund_ix(&context, &beneficiary.pubkey());
+    process(
+        &mut context,
+        &[fund, deposit_ix(&keys, 1_000_000)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    set_time(&mut context, NOW).await;
+    let vesting = pda(&[b"vesting", state.as_ref(), &0u64.to_le_bytes()]);
+    let create = ix(
+        WbaVaultInstruction::CreateVesting {
+            amount: 1_000,
+            start_ts: NOW - 100,
+            cliff_ts: NOW - 100,
+            end_ts: NOW + 100,
+            seed: 0,
+        },
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(state, false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(keys.vault, false),
+            AccountMeta::new(vesting, false),
+            AccountMeta::new_readonly(beneficiary.pubkey(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    assert_aliases_rejected(&mut context, &[], create, &[&keys.owner], &[(0, 5)]).await;
+
+    let claim = ix(
+        WbaVaultInstruction::ClaimVested,
+        vec![
+            AccountMeta::new(beneficiary.pubkey(), true),
+            AccountMeta::new_readonly(state, false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(vesting, false),
+        ],
+    );
+    assert_aliases_rejected(&mut context, &[], claim, &[&beneficiary], &[]).await;
+
+    let revoke = ix(
+        WbaVaultInstruction::RevokeVesting,
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(state, false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(keys.vault, false),
+            AccountMeta::new(vesting, false),
+        ],
+    );
+    assert_aliases_rejected(&mut context, &[], revoke, &[&keys.owner], &[]).await;
+}
+
+// The stake program still expects the (deprecated) config account.
+#[allow(deprecated)]
+#[tokio::test]
+async fn stake_instructions_reject_aliases() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let state = keys.vault_state.pubkey();
+    process(
+        &mut context,
+        &[deposit_ix(&keys, 10_000_000_000)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    let validator = Keypair::new();
+    let vote = Keypair::new();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let payer = context.payer.pubkey();
+    let mut instructions = vec![system_instruction::create_account(
+        &payer,
+        &validator.pubkey(),
+        rent.minimum_balance(0),
+        0,
+        &system_program::id(),
+    )];
+    instructions.extend(vote_instruction::create_account_with_config(
+        &payer,
+        &vote.pubkey(),
+        &VoteInit {
+            node_pubkey: validator.pubkey(),
+            authorized_voter: validator.pubkey(),
+            authorized_withdrawer: validator.pubkey(),
+            commission: 0,
+        },
+        rent.minimum_balance(VoteState::size_of()),
+        vote_instruction::CreateVoteAccountConfig {
+            space: VoteState::size_of() as u64,
+            ..Default::default()
+        },
+    ));
+    process(&mut context, &instructions, &[&validator, &vote])
+        .await
+        .unwrap();
+
+    let stake_account = pda(&[b"stake", state.as_ref(), &0u64.to_le_bytes()]);
+    let stake_from_vault = ix(
+        WbaVaultInstruction::StakeFromVault {
+            amount: 5_000_000_000,
+            seed: 0,
+        },
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(state, false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(keys.vault, false),
+            AccountMeta::new(stake_account, false),
+            AccountMeta::new_readonly(vote.pubkey(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(sysvar::stake_history::id(), false),
+            AccountMeta::new_readonly(stake::config::id(), false),
+            AccountMeta::new_readonly(stake::program::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    assert_aliases_rejected(&mut context, &[], stake_from_vault, &[&keys.owner], &[]).await;
+
+    let deactivate = ix(
+        WbaVaultInstruction::DeactivateStake,
+        vec![
+            AccountMeta::new_readonly(keys.owner.pubkey(), true),
+            AccountMeta::new_readonly(state, false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(stake_account, false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(stake::program::id(), false),
+        ],
+    );
+    assert_aliases_rejected(&mut context, &[], deactivate, &[&keys.owner], &[]).await;
+
+    // Deactivated in the epoch it was delegated, so it is withdrawable now.
+    let withdraw = ix(
+        WbaVaultInstruction::WithdrawStake { amount: 1_000 },
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(state, false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(keys.vault, false),
+            AccountMeta::new(stake_account, false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(sysvar::stake_history::id(), false),
+            AccountMeta::new_readonly(stake::program::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    assert_aliases_rejected(&mut context, &[], withdraw, &[&keys.owner], &[]).await;
+}
+
+#[tokio::test]
+async fn permit_rejects_aliases() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let recipient = Keypair::new().pubkey();
+    let relayer = Keypair::new();
+    let fund_recipient = fund_ix(&context, &recipient);
+    let fund_relayer = fund_ix(&context, &relayer.pubkey());
+    set_time(&mut context, NOW).await;
+    process(
+        &mut context,
+        &[fund_recipient, fund_relayer, deposit_ix(&keys, 1_000_000)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    let permit = Permit {
+        vault_state: keys.vault_state.pubkey(),
+        mint: Pubkey::default(),
+        amount: 1_000,
+        recipient,
+        nonce: 0,
+        expires_at: NOW + 100,
+    };
+    let message = permit.message();
+    let mut data = vec![1u8, 0];
+    for value in [
+        48u16,
+        u16::MAX,
+        16,
+        u16::MAX,
+        112,
+        message.len() as u16,
+        u16::MAX,
+    ] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(keys.owner.pubkey().as_ref());
+    data.extend_from_slice(keys.owner.sign_message(&message).as_ref());
+    data.extend_from_slice(&message);
+    let ed25519 = Instruction {
+        program_id: ed25519_program::id(),
+        accounts: vec![],
+        data,
+    };
+
+    let withdraw = ix(
+        WbaVaultInstruction::WithdrawWithPermit { permit },
+        vec![
+            AccountMeta::new(relayer.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(keys.vault, false),
+            AccountMeta::new(recipient, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::instructions::id(), false),
+        ],
+    );
+    assert_aliases_rejected(&mut context, &[ed25519], withdraw, &[&relayer], &[(0, 4)]).await;
+}
+
+#[tokio::test]
+async fn pool_instructions_reject_aliases() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let state = keys.vault_state.pubkey();
+    let sol = Pubkey::default();
+    let pool = pda(&[b"pool", state.as_ref(), sol.as_ref()]);
+    let share_mint = pda(&[b"shares", pool.as_ref()]);
+    let create = ix(
+        WbaVaultInstruction::CreatePool { mint: sol },
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(state, false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(pool, false),
+            AccountMeta::new(share_mint, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    assert_aliases_rejected(&mut context, &[], create, &[&keys.owner], &[]).await;
+
+    let user = Keypair::new();
+    let fund = fund_ix(&context, &user.pubkey());
+    process(&mut context, &[fund], &[]).await.unwrap();
+    let user_shares = create_ata(&mut context, &user.pubkey(), &share_mint).await;
+    let pool_ix = |data| {
+        ix(
+            data,
+            vec![
+                AccountMeta::new(user.pubkey(), true),
+                AccountMeta::new(state, false),
+                AccountMeta::new_readonly(keys.vault_auth, false),
+                AccountMeta::new(keys.vault, false),
+                AccountMeta::new(pool, false),
+                AccountMeta::new(share_mint, false),
+                AccountMeta::new(user_shares, false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+        )
+    };
+    for data in [
+        WbaVaultInstruction::DepositToPool { amount: 1_000 },
+        WbaVaultInstruction::RedeemShares { shares: 1_000 },
+    ] {
+        assert_aliases_rejected(&mut context, &[], pool_ix(data), &[&user], &[]).await;
+    }
+}
+
+#[tokio::test]
+async fn score_instructions_reject_aliases() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let signers = [&keys.owner];
+    let payer = context.payer.pubkey();
+    set_time(&mut context, NOW).await;
+
+    let nft = create_mint(&mut context, &payer, 0).await;
+    seed_master_edition(&mut context, &nft);
+    let owner_nft_ata = create_ata(&mut context, &keys.owner.pubkey(), &nft).await;
+    create_ata(&mut context, &keys.vault_auth, &nft).await;
+    mint_to(&mut context, &nft, &owner_nft_ata, 1).await;
+    let deposit_nft = nft_ix(&keys, &nft, WbaVaultInstruction::DepositNft);
+    process(&mut context, &[deposit_nft], &signers)
+        .await
+        .unwrap();
+    set_time(&mut context, NOW + 2 * 86_400).await;
+
+    let settle = ix(
+        WbaVaultInstruction::Settle,
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    assert_aliases_rejected(&mut context, &[], settle, &signers, &[]).await;
+
+    let reward_mint = pda(&[b"rewards"]);
+    let claim_rewards = ix(
+        WbaVaultInstruction::ClaimRewards,
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new(reward_mint, false),
+            AccountMeta::new(
+                get_associated_token_address(&keys.owner.pubkey(), &reward_mint),
+                false,
+            ),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    assert_aliases_rejected(&mut context, &[], claim_rewards, &signers, &[]).await;
+}
+
+#[tokio::test]
+async fn config_instructions_reject_aliases() {
+    let admin = Keypair::new();
+    let program_data =
+        Pubkey::find_program_address(&[id().as_ref()], &bpf_loader_upgradeable::id()).0;
+    let mut data = vec![3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+    data.extend(admin.pubkey().to_bytes());
+    let mut program_test = program_test();
+    program_test.add_account(
+        program_data,
+        Account {
+            lamports: 1_000_000_000,
+            data,
+            owner: bpf_loader_upgradeable::id(),
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+    program_test.add_account(
+        admin.pubkey(),
+        Account::new(OWNER_LAMPORTS, 0, &system_program::id()),
+    );
+    let mut context = program_test.start_with_context().await;
+    let signers = [&admin];
+
+    let init_config = ix(
+        WbaVaultInstruction::InitConfig {
+            gated: false,
+            allowlist_root: [0; 32],
+        },
+        vec![
+            AccountMeta::new(admin.pubkey(), true),
+            AccountMeta::new(config_address(), false),
+            AccountMeta::new_readonly(program_data, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    assert_aliases_rejected(&mut context, &[], init_config, &signers, &[]).await;
+
+    // Gate new vaults on a cohort holding `keys.owner`.
+    let keys = VaultKeys::new();
+    let tree = AllowlistTree::new(&[keys.owner.pubkey(), Keypair::new().pubkey()]);
+    let update_config = ix(
+        WbaVaultInstruction::UpdateConfig {
+            gated: true,
+            allowlist_root: tree.root(),
+        },
+        vec![
+            AccountMeta::new_readonly(admin.pubkey(), true),
+            AccountMeta::new(config_address(), false),
+        ],
+    );
+    assert_aliases_rejected(&mut context, &[], update_config, &signers, &[]).await;
+
+    let fund = fund_ix(&context, &keys.owner.pubkey());
+    process(&mut context, &[fund], &[]).await.unwrap();
+    let mut initialize = initialize_ix(&keys);
+    initialize.data = borsh::to_vec(&WbaVaultInstruction::InitializeWithProof {
+        proof: tree.proof(&keys.owner.pubkey()).unwrap(),
+    })
+    .unwrap();
+    let signers = [&keys.owner, &keys.vault_state];
+    assert_aliases_rejected(&mut context, &[], initialize, &signers, &[]).await;
+}
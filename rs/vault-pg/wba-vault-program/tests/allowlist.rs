@@ -0,0 +1,218 @@
+mod common;
+
+use common::*;
+use solana_program::{
+    clock::Clock,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::Signer;
+use wba_vault_program::{id, WbaVaultInstruction};
+
+const NOW: i64 = 10_000;
+const DELAY: i64 = 100;
+
+fn allowlist_pda(keys: &VaultKeys) -> Pubkey {
+    Pubkey::find_program_address(&[b"allowlist", keys.vault_state.pubkey().as_ref()], &id()).0
+}
+
+fn admin_ix(keys: &VaultKeys, data: WbaVaultInstruction) -> Instruction {
+    ix(
+        data,
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new(allowlist_pda(keys), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+fn set_config_ix(keys: &VaultKeys, enabled: bool, add_delay_secs: i64) -> Instruction {
+    admin_ix(
+        keys,
+        WbaVaultInstruction::SetAllowlistConfig {
+            enabled,
+            add_delay_secs,
+        },
+    )
+}
+
+fn add_entry_ix(keys: &VaultKeys, address: Pubkey) -> Instruction {
+    admin_ix(keys, WbaVaultInstruction::AddAllowlistEntry { address })
+}
+
+fn remove_entry_ix(keys: &VaultKeys, address: Pubkey) -> Instruction {
+    admin_ix(keys, WbaVaultInstruction::RemoveAllowlistEntry { address })
+}
+
+/// Appends the allowlist PDA, which payouts need once it exists.
+fn with_allowlist(mut ix: Instruction, keys: &VaultKeys) -> Instruction {
+    ix.accounts
+        .push(AccountMeta::new_readonly(allowlist_pda(keys), false));
+    ix
+}
+
+async fn set_time(context: &mut ProgramTestContext, unix_timestamp: i64) {
+    let mut clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    clock.unix_timestamp = unix_timestamp;
+    context.set_sysvar(&clock);
+}
+
+#[tokio::test]
+async fn entries_activate_after_delay() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    set_time(&mut context, NOW).await;
+    process(
+        &mut context,
+        &[
+            deposit_ix(&keys, 1_000_000),
+            set_config_ix(&keys, true, DELAY),
+            add_entry_ix(&keys, keys.owner.pubkey()),
+        ],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    assert!(vault_state(&mut context, &keys).await.has_allowlist.get());
+
+    // The allowlist account is required, and the entry is not active yet.
+    assert!(
+        process(&mut context, &[withdraw_ix(&keys, 1)], &[&keys.owner])
+            .await
+            .is_err()
+    );
+    set_time(&mut context, NOW + DELAY - 1).await;
+    assert!(process(
+        &mut context,
+        &[with_allowlist(withdraw_ix(&keys, 1), &keys)],
+        &[&keys.owner],
+    )
+    .await
+    .is_err());
+
+    set_time(&mut context, NOW + DELAY).await;
+    process(
+        &mut context,
+        &[with_allowlist(withdraw_ix(&keys, 1), &keys)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    // Removal is immediate.
+    process(
+        &mut context,
+        &[remove_entry_ix(&keys, keys.owner.pubkey())],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    assert!(process(
+        &mut context,
+        &[with_allowlist(withdraw_ix(&keys, 2), &keys)],
+        &[&keys.owner],
+    )
+    .await
+    .is_err());
+}
+
+#[tokio::test]
+async fn disabling_waits_for_delay() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    set_time(&mut context, NOW).await;
+    process(
+        &mut context,
+        &[
+            deposit_ix(&keys, 1_000_000),
+            set_config_ix(&keys, true, DELAY),
+        ],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    process(
+        &mut context,
+        &[set_config_ix(&keys, false, 0)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    assert!(process(
+        &mut context,
+        &[with_allowlist(withdraw_ix(&keys, 1), &keys)],
+        &[&keys.owner],
+    )
+    .await
+    .is_err());
+
+    set_time(&mut context, NOW + DELAY).await;
+    process(
+        &mut context,
+        &[with_allowlist(withdraw_ix(&keys, 1), &keys)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn token_withdrawals_check_the_wallet() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let payer = context.payer.pubkey();
+    let mint = create_mint(&mut context, &payer, 0).await;
+    let vault_ata = create_ata(&mut context, &keys.vault_auth, &mint).await;
+    let owner_ata = create_ata(&mut context, &keys.owner.pubkey(), &mint).await;
+    mint_to(&mut context, &mint, &vault_ata, 100).await;
+    process(
+        &mut context,
+        &[reconcile_ix(&keys, &mint, true)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    set_time(&mut context, NOW).await;
+    process(
+        &mut context,
+        &[set_config_ix(&keys, true, 0)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    // The optional mint limit PDA goes before the allowlist; an uninitialized
+    // one just means no limit.
+    let limit = Pubkey::find_program_address(
+        &[b"limit", keys.vault_state.pubkey().as_ref(), mint.as_ref()],
+        &id(),
+    )
+    .0;
+    let withdraw = || {
+        let mut ix = withdraw_spl_ix(&keys, &owner_ata, &vault_ata, &mint, 10);
+        ix.accounts
+            .insert(MINT_LIMIT_INDEX, AccountMeta::new(limit, false));
+        with_allowlist(ix, &keys)
+    };
+
+    assert!(process(&mut context, &[withdraw()], &[&keys.owner])
+        .await
+        .is_err());
+
+    process(
+        &mut context,
+        &[add_entry_ix(&keys, keys.owner.pubkey())],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    process(&mut context, &[withdraw()], &[&keys.owner])
+        .await
+        .unwrap();
+    assert_eq!(token_balance(&mut context, &owner_ata).await, 10);
+}
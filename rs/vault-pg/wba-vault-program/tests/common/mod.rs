@@ -0,0 +1,535 @@
+#![allow(dead_code)]
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    program::invoke_signed,
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction, system_program,
+    sysvar::Sysvar,
+};
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::{Account, AccountSharedData},
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use spl_associated_token_account::get_associated_token_address;
+use wba_vault_program::{
+    id, process_instruction, Custody, Ledger, WbaVaultError, WbaVaultInstruction, ZeroCopy,
+};
+
+pub const OWNER_LAMPORTS: u64 = 100_000_000_000;
+
+/// The vault program, with a Token Metadata stand-in at its real address.
+pub fn program_test() -> ProgramTest {
+    let mut program_test =
+        ProgramTest::new("wba_vault_program", id(), processor!(process_instruction));
+    program_test.add_program(
+        "token_metadata",
+        METADATA_PROGRAM_ID,
+        processor!(fake_token_metadata),
+    );
+    program_test
+}
+
+pub const METADATA_PROGRAM_ID: Pubkey = pubkey!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+
+/// The Token Metadata metadata and master edition PDAs of `mint`.
+pub fn metadata_pdas(mint: &Pubkey) -> (Pubkey, Pubkey) {
+    let seeds = [
+        b"metadata".as_ref(),
+        METADATA_PROGRAM_ID.as_ref(),
+        mint.as_ref(),
+    ];
+    let metadata = Pubkey::find_program_address(&seeds, &METADATA_PROGRAM_ID).0;
+    let edition = Pubkey::find_program_address(
+        &[seeds[0], seeds[1], seeds[2], b"edition"],
+        &METADATA_PROGRAM_ID,
+    )
+    .0;
+    (metadata, edition)
+}
+
+/// Puts metadata and master edition accounts in place for `mint`, as if it
+/// had been minted through Token Metadata.
+pub fn seed_master_edition(context: &mut ProgramTestContext, mint: &Pubkey) {
+    let (metadata, edition) = metadata_pdas(mint);
+    for (address, key) in [(metadata, 4u8), (edition, 6u8)] {
+        let mut data = vec![key];
+        data.extend_from_slice(mint.as_ref());
+        let account = Account {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner: METADATA_PROGRAM_ID,
+            executable: false,
+            rent_epoch: 0,
+        };
+        context.set_account(&address, &AccountSharedData::from(account));
+    }
+}
+
+pub type MetadataRecord = (u8, Pubkey, Pubkey, String, String, String);
+
+/// Stands in for Token Metadata's `CreateMetadataAccountV3`: checks the
+/// authorities signed and stores the fields in the same leading layout as a
+/// real metadata account.
+pub fn fake_token_metadata(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    let [metadata, mint, mint_authority, payer, update_authority, system_program] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+    if data[0] != 33 || !mint_authority.is_signer || !update_authority.is_signer {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let (name, symbol, uri, _fee) = <(String, String, String, u16)>::deserialize(&mut &data[1..])?;
+    if data[1..].len() != borsh::to_vec(&(&name, &symbol, &uri, 0u16)).unwrap().len() + 5 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let record: MetadataRecord = (4, *update_authority.key, *mint.key, name, symbol, uri);
+    let record = borsh::to_vec(&record).unwrap();
+    let (_, bump) = Pubkey::find_program_address(
+        &[b"metadata", program_id.as_ref(), mint.key.as_ref()],
+        program_id,
+    );
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            metadata.key,
+            Rent::get()?.minimum_balance(record.len()),
+            record.len() as u64,
+            program_id,
+        ),
+        &[payer.clone(), metadata.clone(), system_program.clone()],
+        &[&[b"metadata", program_id.as_ref(), mint.key.as_ref(), &[bump]]],
+    )?;
+    metadata.data.borrow_mut().copy_from_slice(&record);
+    Ok(())
+}
+
+pub fn metadata_address(mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"metadata", METADATA_PROGRAM_ID.as_ref(), mint.as_ref()],
+        &METADATA_PROGRAM_ID,
+    )
+    .0
+}
+
+/// Keys of one initialized vault: the owner keypair, the `vault_state`
+/// keypair and both PDAs derived from it.
+pub struct VaultKeys {
+    pub owner: Keypair,
+    pub vault_state: Keypair,
+    pub vault_auth: Pubkey,
+    pub vault: Pubkey,
+}
+
+impl VaultKeys {
+    pub fn new() -> Self {
+        Self::from_keypairs(Keypair::new(), Keypair::new())
+    }
+
+    pub fn from_keypairs(owner: Keypair, vault_state: Keypair) -> Self {
+        let (vault_auth, _) =
+            Pubkey::find_program_address(&[b"auth", vault_state.pubkey().as_ref()], &id());
+        let (vault, _) = Pubkey::find_program_address(&[b"vault", vault_auth.as_ref()], &id());
+        Self {
+            owner,
+            vault_state,
+            vault_auth,
+            vault,
+        }
+    }
+}
+
+pub fn ix(data: WbaVaultInstruction, accounts: Vec<AccountMeta>) -> Instruction {
+    Instruction::new_with_borsh(id(), &data, accounts)
+}
+
+pub fn initialize_ix(keys: &VaultKeys) -> Instruction {
+    ix(
+        WbaVaultInstruction::Initialize,
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), true),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(keys.vault, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(config_address(), false),
+        ],
+    )
+}
+
+pub fn config_address() -> Pubkey {
+    Pubkey::find_program_address(&[b"config"], &id()).0
+}
+
+fn sol_accounts(keys: &VaultKeys) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(keys.owner.pubkey(), true),
+        AccountMeta::new(keys.vault_state.pubkey(), false),
+        AccountMeta::new_readonly(keys.vault_auth, false),
+        AccountMeta::new(keys.vault, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ]
+}
+
+pub fn deposit_ix(keys: &VaultKeys, amount: u64) -> Instruction {
+    ix(WbaVaultInstruction::Deposit { amount }, sol_accounts(keys))
+}
+
+pub fn withdraw_ix(keys: &VaultKeys, amount: u64) -> Instruction {
+    ix(WbaVaultInstruction::Withdraw { amount }, sol_accounts(keys))
+}
+
+pub fn withdraw_spl_ix(
+    keys: &VaultKeys,
+    owner_ata: &Pubkey,
+    vault_ata: &Pubkey,
+    mint: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    ix(
+        WbaVaultInstruction::WithdrawSpl { amount },
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(*owner_ata, false),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(*vault_ata, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            // Found by address, so it stays last; the optional mint limit
+            // PDA goes in before it, at `MINT_LIMIT_INDEX`.
+            AccountMeta::new(ledger_address(keys, mint), false),
+        ],
+    )
+}
+
+/// Where `withdraw_spl_ix` takes the optional mint limit PDA.
+pub const MINT_LIMIT_INDEX: usize = 9;
+
+/// `CloseAccount`, returning the rent to the owner.
+pub fn close_account_ix(keys: &VaultKeys) -> Instruction {
+    ix(
+        WbaVaultInstruction::CloseAccount,
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(keys.owner.pubkey(), false),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn ledger_address(keys: &VaultKeys, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"ledger", keys.vault_state.pubkey().as_ref(), mint.as_ref()],
+        &id(),
+    )
+    .0
+}
+
+pub async fn ledger_balance(
+    context: &mut ProgramTestContext,
+    keys: &VaultKeys,
+    mint: &Pubkey,
+) -> u64 {
+    let account = context
+        .banks_client
+        .get_account(ledger_address(keys, mint))
+        .await
+        .unwrap()
+        .unwrap();
+    Ledger::read(&account.data).unwrap().balance
+}
+
+/// `Reconcile` for `mint`; with `sync` set the ledger takes on whatever the
+/// vault ATA holds, e.g. after tokens were minted straight into it.
+pub fn reconcile_ix(keys: &VaultKeys, mint: &Pubkey, sync: bool) -> Instruction {
+    ix(
+        WbaVaultInstruction::Reconcile { sync },
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta {
+                pubkey: keys.vault_state.pubkey(),
+                is_signer: false,
+                is_writable: sync,
+            },
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new_readonly(get_associated_token_address(&keys.vault_auth, mint), false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new(ledger_address(keys, mint), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+/// Sends `instructions` paid by the context payer and signed by `signers`.
+pub async fn process(
+    context: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<(), BanksClientError> {
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+    let tx = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+    context.banks_client.process_transaction(tx).await
+}
+
+/// Requires `result` to have failed with `error`, raised by the instruction
+/// at `index`.
+pub fn assert_vault_error(result: Result<(), BanksClientError>, index: u8, error: WbaVaultError) {
+    assert_eq!(
+        result.expect_err("transaction succeeded").unwrap(),
+        TransactionError::InstructionError(index, InstructionError::Custom(error as u32)),
+    );
+}
+
+pub fn fund_ix(context: &ProgramTestContext, to: &Pubkey) -> Instruction {
+    system_instruction::transfer(&context.payer.pubkey(), to, OWNER_LAMPORTS)
+}
+
+/// Funds a fresh owner and initializes its vault.
+pub async fn setup_vault(context: &mut ProgramTestContext) -> VaultKeys {
+    setup_vault_with_custody(context, Custody::SystemOwned).await
+}
+
+pub async fn setup_vault_with_custody(
+    context: &mut ProgramTestContext,
+    custody: Custody,
+) -> VaultKeys {
+    let keys = VaultKeys::new();
+    let fund = fund_ix(context, &keys.owner.pubkey());
+    let mut initialize = initialize_ix(&keys);
+    if custody != Custody::SystemOwned {
+        initialize.data = borsh::to_vec(&WbaVaultInstruction::InitializeWithCustody {
+            custody,
+            proof: Vec::new(),
+        })
+        .unwrap();
+    }
+    process(
+        context,
+        &[fund, initialize],
+        &[&keys.owner, &keys.vault_state],
+    )
+    .await
+    .unwrap();
+    keys
+}
+
+pub async fn lamports(context: &mut ProgramTestContext, address: &Pubkey) -> u64 {
+    context.banks_client.get_balance(*address).await.unwrap()
+}
+
+pub async fn vault_state(
+    context: &mut ProgramTestContext,
+    keys: &VaultKeys,
+) -> wba_vault_program::Vault {
+    let account = context
+        .banks_client
+        .get_account(keys.vault_state.pubkey())
+        .await
+        .unwrap()
+        .unwrap();
+    wba_vault_program::Vault::unpack(&account.data).unwrap()
+}
+
+pub async fn create_mint(
+    context: &mut ProgramTestContext,
+    authority: &Pubkey,
+    decimals: u8,
+) -> Pubkey {
+    create_mint_at(context, &Keypair::new(), authority, decimals).await
+}
+
+/// `create_mint` at the address of `mint`.
+pub async fn create_mint_at(
+    context: &mut ProgramTestContext,
+    mint: &Keypair,
+    authority: &Pubkey,
+    decimals: u8,
+) -> Pubkey {
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let instructions = [
+        system_instruction::create_account(
+            &context.payer.pubkey(),
+            &mint.pubkey(),
+            rent.minimum_balance(spl_token::state::Mint::LEN),
+            spl_token::state::Mint::LEN as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_mint2(
+            &spl_token::id(),
+            &mint.pubkey(),
+            authority,
+            None,
+            decimals,
+        )
+        .unwrap(),
+    ];
+    process(context, &instructions, &[mint]).await.unwrap();
+    mint.pubkey()
+}
+
+/// Creates the ATA of `wallet` for `mint`; `wallet` may be a PDA.
+pub async fn create_ata(
+    context: &mut ProgramTestContext,
+    wallet: &Pubkey,
+    mint: &Pubkey,
+) -> Pubkey {
+    let ix = spl_associated_token_account::instruction::create_associated_token_account(
+        &context.payer.pubkey(),
+        wallet,
+        mint,
+        &spl_token::id(),
+    );
+    process(context, &[ix], &[]).await.unwrap();
+    spl_associated_token_account::get_associated_token_address(wallet, mint)
+}
+
+/// Mints with the context payer as mint authority.
+pub async fn mint_to(context: &mut ProgramTestContext, mint: &Pubkey, to: &Pubkey, amount: u64) {
+    let ix = spl_token::instruction::mint_to(
+        &spl_token::id(),
+        mint,
+        to,
+        &context.payer.pubkey(),
+        &[],
+        amount,
+    )
+    .unwrap();
+    process(context, &[ix], &[]).await.unwrap();
+}
+
+pub async fn token_balance(context: &mut ProgramTestContext, account: &Pubkey) -> u64 {
+    let account = context
+        .banks_client
+        .get_account(*account)
+        .await
+        .unwrap()
+        .unwrap();
+    spl_token::state::Account::unpack(&account.data)
+        .unwrap()
+        .amount
+}
+
+pub async fn account_exists(context: &mut ProgramTestContext, address: &Pubkey) -> bool {
+    context
+        .banks_client
+        .get_account(*address)
+        .await
+        .unwrap()
+        .is_some()
+}
+
+pub fn set_multisig_ix(keys: &VaultKeys, signers: Vec<Pubkey>, threshold: u8) -> Instruction {
+    ix(
+        WbaVaultInstruction::SetMultisig { signers, threshold },
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+/// Re-targets an owner instruction at `member` and appends `cosigners`.
+pub fn signed_by(mut ix: Instruction, member: &Keypair, cosigners: &[&Keypair]) -> Instruction {
+    ix.accounts[0] = AccountMeta::new(member.pubkey(), true);
+    for cosigner in cosigners {
+        ix.accounts
+            .push(AccountMeta::new_readonly(cosigner.pubkey(), true));
+    }
+    ix
+}
+
+/// Creates a funded vault and turns it into a 2-of-3 multisig.
+pub async fn setup_multisig(context: &mut ProgramTestContext) -> (VaultKeys, [Keypair; 3]) {
+    let keys = setup_vault(context).await;
+    let members = [Keypair::new(), Keypair::new(), Keypair::new()];
+    let mut instructions: Vec<Instruction> = members
+        .iter()
+        .map(|member| fund_ix(context, &member.pubkey()))
+        .collect();
+    instructions.push(deposit_ix(&keys, 1_000_000));
+    instructions.push(set_multisig_ix(
+        &keys,
+        members.iter().map(|member| member.pubkey()).collect(),
+        2,
+    ));
+    process(context, &instructions, &[&keys.owner])
+        .await
+        .unwrap();
+    (keys, members)
+}
+
+pub fn nft_stake_address(keys: &VaultKeys, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"nft_stake",
+            keys.vault_state.pubkey().as_ref(),
+            mint.as_ref(),
+        ],
+        &id(),
+    )
+    .0
+}
+
+/// `DepositNft`/`WithdrawNft` for `mint`, with the NFT stake PDA appended.
+pub fn nft_ix(keys: &VaultKeys, mint: &Pubkey, data: WbaVaultInstruction) -> Instruction {
+    let withdraw = matches!(data, WbaVaultInstruction::WithdrawNft);
+    let (metadata, edition) = metadata_pdas(mint);
+    let mut ix = ix(
+        data,
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(
+                get_associated_token_address(&keys.owner.pubkey(), mint),
+                false,
+            ),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(get_associated_token_address(&keys.vault_auth, mint), false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(metadata, false),
+            AccountMeta::new_readonly(edition, false),
+            AccountMeta::new_readonly(METADATA_PROGRAM_ID, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    let state = keys.vault_state.pubkey();
+    if withdraw {
+        // Positional: the optional mint limit PDA comes first.
+        let limit =
+            Pubkey::find_program_address(&[b"limit", state.as_ref(), mint.as_ref()], &id()).0;
+        ix.accounts.push(AccountMeta::new(limit, false));
+    }
+    // Both found by address; the stake record goes last.
+    ix.accounts
+        .push(AccountMeta::new(ledger_address(keys, mint), false));
+    ix.accounts
+        .push(AccountMeta::new(nft_stake_address(keys, mint), false));
+    ix
+}
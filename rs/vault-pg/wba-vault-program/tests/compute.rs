@@ -0,0 +1,1183 @@
+//! Compute units per instruction, checked against `compute_budgets.txt`.
+//!
+//! Every instruction is measured once, alone in its transaction, on a few
+//! vaults that the cases build up in order. The run fails when an
+//! instruction has no budget for the current mode or uses more than its
+//! budget plus `TOLERANCE_PERCENT`; the report table is printed either way
+//! (pass `--nocapture` to see it on success).
+//!
+//! Budgets are kept per mode. Under plain `cargo test` the program runs as a
+//! native builtin and only its CPIs are metered, so `native` budgets track
+//! CPI counts. Under `cargo test-sbf` the compiled program is loaded and
+//! `sbf` budgets cover everything, `find_program_address` included. Run with
+//! `UPDATE_COMPUTE_BUDGETS=1` to rewrite the current mode's budgets.
+
+mod common;
+
+use std::{collections::BTreeMap, env, fmt::Write, fs, path::PathBuf, time::Duration};
+
+use common::*;
+use solana_program::{
+    bpf_loader_upgradeable,
+    clock::Clock,
+    ed25519_program,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    stake, system_instruction, system_program, sysvar,
+    vote::{
+        instruction as vote_instruction,
+        state::{VoteInit, VoteState},
+    },
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::{
+    account::Account,
+    signature::{Keypair, Signer},
+    signer::keypair::keypair_from_seed,
+    transaction::{Transaction, TransactionError},
+};
+use spl_associated_token_account::get_associated_token_address;
+use wba_vault_program::{
+    id, AllowlistTree, Custody, Permit, ProposalAction, Vault, WbaVaultInstruction,
+};
+
+const TOLERANCE_PERCENT: u64 = 5;
+/// The default per-instruction limit, which batched flows must fit under.
+const DEFAULT_LIMIT: u64 = 200_000;
+const NOW: i64 = 10_000;
+const DAY: i64 = 86_400;
+
+fn budgets_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/compute_budgets.txt")
+}
+
+/// Same switch `ProgramTest` uses to prefer the compiled program.
+fn mode() -> &'static str {
+    if env::var("SBF_OUT_DIR").is_ok() || env::var("BPF_OUT_DIR").is_ok() {
+        "sbf"
+    } else {
+        "native"
+    }
+}
+
+/// `mode instruction units` lines; `#` starts a comment.
+fn read_budgets() -> BTreeMap<(String, String), u64> {
+    let text = fs::read_to_string(budgets_path()).unwrap_or_default();
+    text.lines()
+        .map(|line| line.split('#').next().unwrap().trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            let [mode, name, units] = fields[..] else {
+                panic!("malformed budget line: {line}");
+            };
+            ((mode.to_string(), name.to_string()), units.parse().unwrap())
+        })
+        .collect()
+}
+
+fn write_budgets(budgets: &BTreeMap<(String, String), u64>) {
+    let mut text = String::from(
+        "# Compute-unit budgets checked by tests/compute.rs, as `mode instruction units`.\n\
+         # `native` rows come from `cargo test`, `sbf` rows from `cargo test-sbf`.\n\
+         # Regenerate the current mode with UPDATE_COMPUTE_BUDGETS=1.\n",
+    );
+    for ((mode, name), units) in budgets {
+        writeln!(text, "{mode:<7} {name:<21} {units}").unwrap();
+    }
+    fs::write(budgets_path(), text).unwrap();
+}
+
+/// Runs transactions against one test validator and records what each
+/// measured instruction used.
+struct Meter {
+    context: ProgramTestContext,
+    measured: Vec<(&'static str, u64)>,
+    next_seed: u8,
+}
+
+impl Meter {
+    /// A fresh keypair, the same on every run: PDA and ATA derivations cost
+    /// more for some addresses than for others.
+    fn keypair(&mut self) -> Keypair {
+        self.next_seed += 1;
+        keypair(self.next_seed)
+    }
+
+    fn vault_keys(&mut self) -> VaultKeys {
+        let owner = self.keypair();
+        VaultKeys::from_keypairs(owner, self.keypair())
+    }
+
+    /// Funds a fresh owner and initializes its vault.
+    async fn open_vault(&mut self) -> VaultKeys {
+        let keys = self.vault_keys();
+        let fund = fund_ix(&self.context, &keys.owner.pubkey());
+        let initialize = initialize_ix(&keys);
+        self.run(&[fund, initialize], &[&keys.owner, &keys.vault_state])
+            .await;
+        keys
+    }
+
+    async fn create_mint(&mut self) -> Pubkey {
+        let mint = self.keypair();
+        let payer = self.context.payer.pubkey();
+        create_mint_at(&mut self.context, &mint, &payer, 0).await
+    }
+
+    /// Records the units `ix` uses, alone in its transaction.
+    async fn case(&mut self, name: &'static str, ix: Instruction, signers: &[&Keypair]) {
+        self.case_after(name, &[], ix, signers).await;
+    }
+
+    /// Like `case`, after `prefix` in the same transaction. Only `ix` is
+    /// metered; `prefix` is meant for precompiles, which use no units.
+    async fn case_after(
+        &mut self,
+        name: &'static str,
+        prefix: &[Instruction],
+        ix: Instruction,
+        signers: &[&Keypair],
+    ) {
+        let context = &mut self.context;
+        let blockhash = context.get_new_latest_blockhash().await.unwrap();
+        let mut all_signers = vec![&context.payer];
+        all_signers.extend_from_slice(signers);
+        let mut instructions = prefix.to_vec();
+        instructions.push(ix);
+        let tx = Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&context.payer.pubkey()),
+            &all_signers,
+            blockhash,
+        );
+        // This runs straight on the working bank, where the banks server may
+        // still be resending the previous transaction; nothing was executed
+        // when its accounts were in use, so just try again.
+        let outcome = loop {
+            let outcome = context
+                .banks_client
+                .process_transaction_with_metadata(tx.clone())
+                .await
+                .unwrap();
+            if outcome.result != Err(TransactionError::AccountInUse) {
+                break outcome;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        outcome.result.unwrap();
+        let units = outcome.metadata.unwrap().compute_units_consumed;
+        self.measured.push((name, units));
+    }
+
+    /// Runs setup that is not measured.
+    async fn run(&mut self, instructions: &[Instruction], signers: &[&Keypair]) {
+        process(&mut self.context, instructions, signers)
+            .await
+            .unwrap();
+    }
+
+    async fn set_time(&mut self, unix_timestamp: i64) {
+        let context = &mut self.context;
+        let mut clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+        clock.unix_timestamp = unix_timestamp;
+        context.set_sysvar(&clock);
+    }
+}
+
+fn keypair(seed: u8) -> Keypair {
+    keypair_from_seed(&[seed; 32]).unwrap()
+}
+
+fn pda(seeds: &[&[u8]]) -> Pubkey {
+    Pubkey::find_program_address(seeds, &id()).0
+}
+
+fn owner_ix(keys: &VaultKeys, data: WbaVaultInstruction, extra: &[AccountMeta]) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(keys.owner.pubkey(), true),
+        AccountMeta::new(keys.vault_state.pubkey(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    accounts.splice(2..2, extra.iter().cloned());
+    ix(data, accounts)
+}
+
+/// `owner_ix` signed by `signer` in the owner's place.
+fn signed_ix(keys: &VaultKeys, signer: &Keypair, data: WbaVaultInstruction) -> Instruction {
+    let mut ix = owner_ix(keys, data, &[]);
+    ix.accounts[0].pubkey = signer.pubkey();
+    ix
+}
+
+/// Starts the program with `admin` as its upgrade authority, so the config
+/// instructions can run.
+async fn start(admin: &Keypair) -> ProgramTestContext {
+    let program_data =
+        Pubkey::find_program_address(&[id().as_ref()], &bpf_loader_upgradeable::id()).0;
+    let mut data = vec![3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+    data.extend(admin.pubkey().to_bytes());
+    let mut program_test = program_test();
+    program_test.add_account(
+        program_data,
+        Account {
+            lamports: 1_000_000_000,
+            data,
+            owner: bpf_loader_upgradeable::id(),
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+    program_test.add_account(
+        admin.pubkey(),
+        Account::new(OWNER_LAMPORTS, 0, &system_program::id()),
+    );
+    program_test.start_with_context().await
+}
+
+/// Token moves, NFT staking and rewards, limits and the owner settings on a
+/// single vault, closed at the end.
+async fn core_cases(meter: &mut Meter) {
+    let keys = meter.vault_keys();
+    let owner = &keys.owner;
+    let fund = fund_ix(&meter.context, &owner.pubkey());
+    meter.run(&[fund], &[]).await;
+
+    let mint = meter.create_mint().await;
+    let nft = meter.create_mint().await;
+    let context = &mut meter.context;
+    let owner_ata = create_ata(context, &owner.pubkey(), &mint).await;
+    let vault_ata = create_ata(context, &keys.vault_auth, &mint).await;
+    mint_to(context, &mint, &owner_ata, 1_000).await;
+    seed_master_edition(context, &nft);
+    let owner_nft_ata = create_ata(context, &owner.pubkey(), &nft).await;
+    create_ata(context, &keys.vault_auth, &nft).await;
+    mint_to(context, &nft, &owner_nft_ata, 1).await;
+
+    let spl = |data| {
+        let mut ix = withdraw_spl_ix(&keys, &owner_ata, &vault_ata, &mint, 0);
+        ix.data = borsh::to_vec(&data).unwrap();
+        ix
+    };
+
+    meter
+        .case(
+            "Initialize",
+            initialize_ix(&keys),
+            &[owner, &keys.vault_state],
+        )
+        .await;
+    meter
+        .case("Deposit", deposit_ix(&keys, 1_000_000), &[owner])
+        .await;
+    meter
+        .case("Withdraw", withdraw_ix(&keys, 500_000), &[owner])
+        .await;
+    meter
+        .case(
+            "DepositSpl",
+            spl(WbaVaultInstruction::DepositSpl { amount: 400 }),
+            &[owner],
+        )
+        .await;
+    meter
+        .case(
+            "WithdrawSpl",
+            spl(WbaVaultInstruction::WithdrawSpl { amount: 100 }),
+            &[owner],
+        )
+        .await;
+    meter
+        .case("Reconcile", reconcile_ix(&keys, &mint, true), &[owner])
+        .await;
+
+    // A day staked earns a point to claim rewards for.
+    meter.set_time(NOW).await;
+    meter
+        .case(
+            "DepositNft",
+            nft_ix(&keys, &nft, WbaVaultInstruction::DepositNft),
+            &[owner],
+        )
+        .await;
+    meter.set_time(NOW + DAY).await;
+    meter
+        .case(
+            "Settle",
+            owner_ix(&keys, WbaVaultInstruction::Settle, &[]),
+            &[owner],
+        )
+        .await;
+    let reward_mint = pda(&[b"rewards"]);
+    let claim_rewards = ix(
+        WbaVaultInstruction::ClaimRewards,
+        vec![
+            AccountMeta::new(owner.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new(reward_mint, false),
+            AccountMeta::new(
+                get_associated_token_address(&owner.pubkey(), &reward_mint),
+                false,
+            ),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    meter.case("ClaimRewards", claim_rewards, &[owner]).await;
+    meter
+        .case(
+            "WithdrawNft",
+            nft_ix(&keys, &nft, WbaVaultInstruction::WithdrawNft),
+            &[owner],
+        )
+        .await;
+
+    meter
+        .case(
+            "SetLock",
+            owner_ix(
+                &keys,
+                WbaVaultInstruction::SetLock {
+                    locked_until: 0,
+                    locked_until_epoch: 0,
+                },
+                &[],
+            ),
+            &[owner],
+        )
+        .await;
+    meter
+        .case(
+            "SetWithdrawLimit",
+            owner_ix(
+                &keys,
+                WbaVaultInstruction::SetWithdrawLimit {
+                    max_amount: 1_000_000,
+                    window_secs: 3_600,
+                },
+                &[],
+            ),
+            &[owner],
+        )
+        .await;
+    let limit = pda(&[b"limit", keys.vault_state.pubkey().as_ref(), mint.as_ref()]);
+    meter
+        .case(
+            "SetMintWithdrawLimit",
+            owner_ix(
+                &keys,
+                WbaVaultInstruction::SetMintWithdrawLimit {
+                    max_amount: 1_000,
+                    window_secs: 3_600,
+                },
+                &[
+                    AccountMeta::new_readonly(mint, false),
+                    AccountMeta::new(limit, false),
+                ],
+            ),
+            &[owner],
+        )
+        .await;
+    meter
+        .case(
+            "SetInheritance",
+            owner_ix(
+                &keys,
+                WbaVaultInstruction::SetInheritance {
+                    beneficiary: Pubkey::new_unique(),
+                    inactivity_secs: 86_400,
+                },
+                &[],
+            ),
+            &[owner],
+        )
+        .await;
+    let heartbeat = ix(
+        WbaVaultInstruction::Heartbeat,
+        vec![
+            AccountMeta::new_readonly(owner.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+        ],
+    );
+    meter.case("Heartbeat", heartbeat, &[owner]).await;
+    meter
+        .case(
+            "SetGuardians",
+            owner_ix(
+                &keys,
+                WbaVaultInstruction::SetGuardians {
+                    guardians: vec![Pubkey::new_unique()],
+                    threshold: 1,
+                    delay_secs: 60,
+                },
+                &[],
+            ),
+            &[owner],
+        )
+        .await;
+    meter
+        .case(
+            "SetMultisig",
+            set_multisig_ix(&keys, vec![owner.pubkey()], 1),
+            &[owner],
+        )
+        .await;
+    meter
+        .case(
+            "Resize",
+            owner_ix(
+                &keys,
+                WbaVaultInstruction::Resize {
+                    size: Vault::space() as u64 + 1_024,
+                },
+                &[],
+            ),
+            &[owner],
+        )
+        .await;
+    meter
+        .case("CloseAccount", close_account_ix(&keys), &[owner])
+        .await;
+}
+
+/// Delegates, the recipient allowlist and permits.
+async fn spending_cases(meter: &mut Meter) {
+    let keys = meter.open_vault().await;
+    let owner = &keys.owner;
+    let state = keys.vault_state.pubkey();
+    let bot = meter.keypair();
+    let recipient = meter.keypair().pubkey();
+    let fund_bot = fund_ix(&meter.context, &bot.pubkey());
+    let fund_recipient = fund_ix(&meter.context, &recipient);
+    meter
+        .run(
+            &[fund_bot, fund_recipient, deposit_ix(&keys, 1_000_000)],
+            &[owner],
+        )
+        .await;
+
+    let sol = Pubkey::default();
+    let delegate = pda(&[
+        b"delegate",
+        state.as_ref(),
+        bot.pubkey().as_ref(),
+        sol.as_ref(),
+    ]);
+    let set_delegate = ix(
+        WbaVaultInstruction::SetDelegate {
+            delegate: bot.pubkey(),
+            mint: sol,
+            allowance: 1_000,
+            expires_at: 0,
+            recipients: vec![recipient],
+        },
+        vec![
+            AccountMeta::new(owner.pubkey(), true),
+            AccountMeta::new_readonly(state, false),
+            AccountMeta::new(delegate, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    meter.case("SetDelegate", set_delegate, &[owner]).await;
+    let delegate_withdraw = ix(
+        WbaVaultInstruction::DelegateWithdraw { amount: 100 },
+        vec![
+            AccountMeta::new(bot.pubkey(), true),
+            AccountMeta::new(state, false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(keys.vault, false),
+            AccountMeta::new(delegate, false),
+            AccountMeta::new(recipient, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    meter
+        .case("DelegateWithdraw", delegate_withdraw, &[&bot])
+        .await;
+    let revoke_delegate = ix(
+        WbaVaultInstruction::RevokeDelegate,
+        vec![
+            AccountMeta::new(owner.pubkey(), true),
+            AccountMeta::new_readonly(state, false),
+            AccountMeta::new(delegate, false),
+        ],
+    );
+    meter
+        .case("RevokeDelegate", revoke_delegate, &[owner])
+        .await;
+
+    meter.set_time(NOW).await;
+    let permit = Permit {
+        vault_state: state,
+        mint: sol,
+        amount: 1_000,
+        recipient,
+        nonce: 0,
+        expires_at: NOW + 100,
+    };
+    let message = permit.message();
+    let mut data = vec![1u8, 0];
+    for value in [
+        48u16,
+        u16::MAX,
+        16,
+        u16::MAX,
+        112,
+        message.len() as u16,
+        u16::MAX,
+    ] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(owner.pubkey().as_ref());
+    data.extend_from_slice(owner.sign_message(&message).as_ref());
+    data.extend_from_slice(&message);
+    let ed25519 = Instruction {
+        program_id: ed25519_program::id(),
+        accounts: vec![],
+        data,
+    };
+    let withdraw = ix(
+        WbaVaultInstruction::WithdrawWithPermit { permit },
+        vec![
+            AccountMeta::new(bot.pubkey(), true),
+            AccountMeta::new(state, false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(keys.vault, false),
+            AccountMeta::new(recipient, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::instructions::id(), false),
+        ],
+    );
+    meter
+        .case_after("WithdrawWithPermit", &[ed25519], withdraw, &[&bot])
+        .await;
+
+    let allowlist = pda(&[b"allowlist", state.as_ref()]);
+    let allowlist_ix = |data| owner_ix(&keys, data, &[AccountMeta::new(allowlist, false)]);
+    meter
+        .case(
+            "SetAllowlistConfig",
+            allowlist_ix(WbaVaultInstruction::SetAllowlistConfig {
+                enabled: false,
+                add_delay_secs: 0,
+            }),
+            &[owner],
+        )
+        .await;
+    meter
+        .case(
+            "AddAllowlistEntry",
+            allowlist_ix(WbaVaultInstruction::AddAllowlistEntry { address: recipient }),
+            &[owner],
+        )
+        .await;
+    meter
+        .case(
+            "RemoveAllowlistEntry",
+            allowlist_ix(WbaVaultInstruction::RemoveAllowlistEntry { address: recipient }),
+            &[owner],
+        )
+        .await;
+}
+
+/// Swaps and vesting out of one vault.
+async fn swap_and_vesting_cases(meter: &mut Meter) {
+    let keys = meter.open_vault().await;
+    let owner = &keys.owner;
+    let state = keys.vault_state.pubkey();
+    let offer_mint = meter.create_mint().await;
+    let ask_mint = meter.create_mint().await;
+    let taker = meter.keypair();
+    let context = &mut meter.context;
+    let vault_offer_ata = create_ata(context, &keys.vault_auth, &offer_mint).await;
+    let vault_ask_ata = create_ata(context, &keys.vault_auth, &ask_mint).await;
+    mint_to(context, &offer_mint, &vault_offer_ata, 100).await;
+    let fund = fund_ix(context, &taker.pubkey());
+    process(context, &[fund], &[]).await.unwrap();
+    let taker_source = create_ata(context, &taker.pubkey(), &ask_mint).await;
+    let taker_destination = create_ata(context, &taker.pubkey(), &offer_mint).await;
+    mint_to(context, &ask_mint, &taker_source, 4).await;
+
+    let offer = |seed: u64| pda(&[b"offer", state.as_ref(), &seed.to_le_bytes()]);
+    let escrow = |seed: u64| pda(&[b"escrow", offer(seed).as_ref()]);
+    let offer_swap = |seed: u64| {
+        ix(
+            WbaVaultInstruction::OfferSwap {
+                offer_amount: 10,
+                ask_amount: 4,
+                seed,
+            },
+            vec![
+                AccountMeta::new(owner.pubkey(), true),
+                AccountMeta::new(state, false),
+                AccountMeta::new_readonly(keys.vault_auth, false),
+                AccountMeta::new(vault_offer_ata, false),
+                AccountMeta::new(offer(seed), false),
+                AccountMeta::new(escrow(seed), false),
+                AccountMeta::new_readonly(offer_mint, false),
+                AccountMeta::new_readonly(ask_mint, false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+        )
+    };
+    meter.case("OfferSwap", offer_swap(0), &[owner]).await;
+    let accept = ix(
+        WbaVaultInstruction::AcceptSwap,
+        vec![
+            AccountMeta::new_readonly(taker.pubkey(), true),
+            AccountMeta::new(taker_source, false),
+            AccountMeta::new(taker_destination, false),
+            AccountMeta::new(owner.pubkey(), false),
+            AccountMeta::new(state, false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(vault_ask_ata, false),
+            AccountMeta::new(offer(0), false),
+            AccountMeta::new(escrow(0), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+    );
+    meter.case("AcceptSwap", accept, &[&taker]).await;
+    meter.run(&[offer_swap(1)], &[owner]).await;
+    let cancel = ix(
+        WbaVaultInstruction::CancelSwap,
+        vec![
+            AccountMeta::new(owner.pubkey(), true),
+            AccountMeta::new(state, false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(vault_offer_ata, false),
+            AccountMeta::new(offer(1), false),
+            AccountMeta::new(escrow(1), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+    );
+    meter.case("CancelSwap", cancel, &[owner]).await;
+
+    let beneficiary = meter.keypair();
+    let fund = fund_ix(&meter.context, &beneficiary.pubkey());
+    meter
+        .run(&[fund, deposit_ix(&keys, 1_000_000)], &[owner])
+        .await;
+    meter.set_time(NOW).await;
+    let vesting = pda(&[b"vesting", state.as_ref(), &0u64.to_le_bytes()]);
+    let create = ix(
+        WbaVaultInstruction::CreateVesting {
+            amount: 1_000,
+            start_ts: NOW - 100,
+            cliff_ts: NOW - 100,
+            end_ts: NOW + 100,
+            seed: 0,
+        },
+        vec![
+            AccountMeta::new(owner.pubkey(), true),
+            AccountMeta::new(state, false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(keys.vault, false),
+            AccountMeta::new(vesting, false),
+            AccountMeta::new_readonly(beneficiary.pubkey(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    meter.case("CreateVesting", create, &[owner]).await;
+    let claim = ix(
+        WbaVaultInstruction::ClaimVested,
+        vec![
+            AccountMeta::new(beneficiary.pubkey(), true),
+            AccountMeta::new_readonly(state, false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(vesting, false),
+        ],
+    );
+    meter.case("ClaimVested", claim, &[&beneficiary]).await;
+    let revoke = ix(
+        WbaVaultInstruction::RevokeVesting,
+        vec![
+            AccountMeta::new(owner.pubkey(), true),
+            AccountMeta::new(state, false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(keys.vault, false),
+            AccountMeta::new(vesting, false),
+        ],
+    );
+    meter.case("RevokeVesting", revoke, &[owner]).await;
+}
+
+// The stake program still expects the (deprecated) config account.
+#[allow(deprecated)]
+async fn stake_cases(meter: &mut Meter) {
+    let keys = meter.open_vault().await;
+    let owner = &keys.owner;
+    let state = keys.vault_state.pubkey();
+    meter
+        .run(&[deposit_ix(&keys, 10_000_000_000)], &[owner])
+        .await;
+
+    let validator = meter.keypair();
+    let vote = meter.keypair();
+    let context = &mut meter.context;
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let payer = context.payer.pubkey();
+    let mut instructions = vec![system_instruction::create_account(
+        &payer,
+        &validator.pubkey(),
+        rent.minimum_balance(0),
+        0,
+        &system_program::id(),
+    )];
+    instructions.extend(vote_instruction::create_account_with_config(
+        &payer,
+        &vote.pubkey(),
+        &VoteInit {
+            node_pubkey: validator.pubkey(),
+            authorized_voter: validator.pubkey(),
+            authorized_withdrawer: validator.pubkey(),
+            commission: 0,
+        },
+        rent.minimum_balance(VoteState::size_of()),
+        vote_instruction::CreateVoteAccountConfig {
+            space: VoteState::size_of() as u64,
+            ..Default::default()
+        },
+    ));
+    meter.run(&instructions, &[&validator, &vote]).await;
+
+    let stake_account = pda(&[b"stake", state.as_ref(), &0u64.to_le_bytes()]);
+    let stake_from_vault = ix(
+        WbaVaultInstruction::StakeFromVault {
+            amount: 5_000_000_000,
+            seed: 0,
+        },
+        vec![
+            AccountMeta::new(owner.pubkey(), true),
+            AccountMeta::new(state, false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(keys.vault, false),
+            AccountMeta::new(stake_account, false),
+            AccountMeta::new_readonly(vote.pubkey(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(sysvar::stake_history::id(), false),
+            AccountMeta::new_readonly(stake::config::id(), false),
+            AccountMeta::new_readonly(stake::program::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    meter
+        .case("StakeFromVault", stake_from_vault, &[owner])
+        .await;
+    let deactivate = ix(
+        WbaVaultInstruction::DeactivateStake,
+        vec![
+            AccountMeta::new_readonly(owner.pubkey(), true),
+            AccountMeta::new_readonly(state, false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(stake_account, false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(stake::program::id(), false),
+        ],
+    );
+    meter.case("DeactivateStake", deactivate, &[owner]).await;
+
+    // Deactivated in the epoch it was delegated, so all of it comes back.
+    let amount = lamports(&mut meter.context, &stake_account).await;
+    let withdraw = ix(
+        WbaVaultInstruction::WithdrawStake { amount },
+        vec![
+            AccountMeta::new(owner.pubkey(), true),
+            AccountMeta::new(state, false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(keys.vault, false),
+            AccountMeta::new(stake_account, false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(sysvar::stake_history::id(), false),
+            AccountMeta::new_readonly(stake::program::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    meter.case("WithdrawStake", withdraw, &[owner]).await;
+}
+
+/// A proposal executed by a 2-of-3 multisig, and one cancelled.
+async fn proposal_cases(meter: &mut Meter) {
+    let keys = meter.open_vault().await;
+    let state = keys.vault_state.pubkey();
+    let [a, b, c] = [meter.keypair(), meter.keypair(), meter.keypair()];
+    let recipient = meter.keypair().pubkey();
+    let mut instructions: Vec<_> = [a.pubkey(), b.pubkey(), c.pubkey(), recipient]
+        .iter()
+        .map(|address| fund_ix(&meter.context, address))
+        .collect();
+    instructions.push(deposit_ix(&keys, 1_000_000));
+    instructions.push(set_multisig_ix(
+        &keys,
+        vec![a.pubkey(), b.pubkey(), c.pubkey()],
+        2,
+    ));
+    meter.run(&instructions, &[&keys.owner]).await;
+    meter.set_time(NOW).await;
+
+    let proposal = |seed: u64| pda(&[b"proposal", state.as_ref(), &seed.to_le_bytes()]);
+    let create = |seed: u64| {
+        ix(
+            WbaVaultInstruction::CreateProposal {
+                action: ProposalAction::WithdrawSol { amount: 1_000 },
+                recipient,
+                expires_at: NOW + 3_600,
+                seed,
+            },
+            vec![
+                AccountMeta::new(a.pubkey(), true),
+                AccountMeta::new_readonly(state, false),
+                AccountMeta::new(proposal(seed), false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+        )
+    };
+    meter.case("CreateProposal", create(0), &[&a]).await;
+    let approve = ix(
+        WbaVaultInstruction::Approve,
+        vec![
+            AccountMeta::new_readonly(b.pubkey(), true),
+            AccountMeta::new_readonly(state, false),
+            AccountMeta::new(proposal(0), false),
+        ],
+    );
+    meter.case("Approve", approve, &[&b]).await;
+    let execute = ix(
+        WbaVaultInstruction::Execute,
+        vec![
+            AccountMeta::new(c.pubkey(), true),
+            AccountMeta::new(state, false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(keys.vault, false),
+            AccountMeta::new(proposal(0), false),
+            AccountMeta::new(a.pubkey(), false),
+            AccountMeta::new(recipient, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    meter.case("Execute", execute, &[&c]).await;
+
+    meter.run(&[create(1)], &[&a]).await;
+    let cancel = ix(
+        WbaVaultInstruction::CancelProposal,
+        vec![
+            AccountMeta::new_readonly(a.pubkey(), true),
+            AccountMeta::new_readonly(state, false),
+            AccountMeta::new(proposal(1), false),
+            AccountMeta::new(a.pubkey(), false),
+        ],
+    );
+    meter.case("CancelProposal", cancel, &[&a]).await;
+}
+
+/// Recovery by a guardian, then inheritance by the recovered key's heir.
+async fn recovery_cases(meter: &mut Meter) {
+    let keys = meter.open_vault().await;
+    let guardian = meter.keypair();
+    let new_owner = meter.keypair();
+    let beneficiary = meter.keypair();
+    let funds: Vec<_> = [&guardian, &new_owner, &beneficiary]
+        .iter()
+        .map(|keypair| fund_ix(&meter.context, &keypair.pubkey()))
+        .collect();
+    meter.run(&funds, &[]).await;
+    meter.set_time(NOW).await;
+    let set_guardians = owner_ix(
+        &keys,
+        WbaVaultInstruction::SetGuardians {
+            guardians: vec![guardian.pubkey()],
+            threshold: 1,
+            delay_secs: 60,
+        },
+        &[],
+    );
+    meter.run(&[set_guardians], &[&keys.owner]).await;
+
+    let initiate = || {
+        signed_ix(
+            &keys,
+            &guardian,
+            WbaVaultInstruction::InitiateRecovery {
+                new_owner: new_owner.pubkey(),
+            },
+        )
+    };
+    meter
+        .case("InitiateRecovery", initiate(), &[&guardian])
+        .await;
+    meter
+        .case(
+            "CancelRecovery",
+            owner_ix(&keys, WbaVaultInstruction::CancelRecovery, &[]),
+            &[&keys.owner],
+        )
+        .await;
+    meter.run(&[initiate()], &[&guardian]).await;
+    meter.set_time(NOW + 60).await;
+    meter
+        .case(
+            "CompleteRecovery",
+            signed_ix(&keys, &guardian, WbaVaultInstruction::CompleteRecovery),
+            &[&guardian],
+        )
+        .await;
+
+    let set_inheritance = signed_ix(
+        &keys,
+        &new_owner,
+        WbaVaultInstruction::SetInheritance {
+            beneficiary: beneficiary.pubkey(),
+            inactivity_secs: 60,
+        },
+    );
+    meter.run(&[set_inheritance], &[&new_owner]).await;
+    meter.set_time(NOW + 120).await;
+    meter
+        .case(
+            "ClaimInheritance",
+            signed_ix(&keys, &beneficiary, WbaVaultInstruction::ClaimInheritance),
+            &[&beneficiary],
+        )
+        .await;
+}
+
+/// A SOL pool, and receipts on an empty vault.
+async fn pool_and_receipt_cases(meter: &mut Meter) {
+    let keys = meter.open_vault().await;
+    let state = keys.vault_state.pubkey();
+    let sol = Pubkey::default();
+    let pool = pda(&[b"pool", state.as_ref(), sol.as_ref()]);
+    let share_mint = pda(&[b"shares", pool.as_ref()]);
+    let create = ix(
+        WbaVaultInstruction::CreatePool { mint: sol },
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(state, false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(pool, false),
+            AccountMeta::new(share_mint, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    meter.case("CreatePool", create, &[&keys.owner]).await;
+
+    let user = meter.keypair();
+    let fund = fund_ix(&meter.context, &user.pubkey());
+    meter.run(&[fund], &[]).await;
+    let user_shares = create_ata(&mut meter.context, &user.pubkey(), &share_mint).await;
+    let pool_ix = |data| {
+        ix(
+            data,
+            vec![
+                AccountMeta::new(user.pubkey(), true),
+                AccountMeta::new(state, false),
+                AccountMeta::new_readonly(keys.vault_auth, false),
+                AccountMeta::new(keys.vault, false),
+                AccountMeta::new(pool, false),
+                AccountMeta::new(share_mint, false),
+                AccountMeta::new(user_shares, false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+        )
+    };
+    meter
+        .case(
+            "DepositToPool",
+            pool_ix(WbaVaultInstruction::DepositToPool { amount: 1_000 }),
+            &[&user],
+        )
+        .await;
+    meter
+        .case(
+            "RedeemShares",
+            pool_ix(WbaVaultInstruction::RedeemShares { shares: 1_000 }),
+            &[&user],
+        )
+        .await;
+
+    let empty = meter.open_vault().await;
+    let enable_receipts = owner_ix(
+        &empty,
+        WbaVaultInstruction::EnableReceipts,
+        &[
+            AccountMeta::new_readonly(empty.vault_auth, false),
+            AccountMeta::new_readonly(empty.vault, false),
+        ],
+    );
+    meter
+        .case("EnableReceipts", enable_receipts, &[&empty.owner])
+        .await;
+    let receipt_mint = pda(&[
+        b"receipt",
+        empty.vault_state.pubkey().as_ref(),
+        sol.as_ref(),
+    ]);
+    let create_receipt_mint = ix(
+        WbaVaultInstruction::CreateReceiptMint { mint: sol },
+        vec![
+            AccountMeta::new(empty.owner.pubkey(), true),
+            AccountMeta::new_readonly(empty.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(empty.vault_auth, false),
+            AccountMeta::new(receipt_mint, false),
+            AccountMeta::new(metadata_address(&receipt_mint), false),
+            AccountMeta::new_readonly(METADATA_PROGRAM_ID, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    meter
+        .case("CreateReceiptMint", create_receipt_mint, &[&empty.owner])
+        .await;
+}
+
+/// The program config, then gated vaults opened with allowlist proofs.
+async fn config_cases(meter: &mut Meter, admin: &Keypair) {
+    let program_data =
+        Pubkey::find_program_address(&[id().as_ref()], &bpf_loader_upgradeable::id()).0;
+    let init_config = ix(
+        WbaVaultInstruction::InitConfig {
+            gated: false,
+            allowlist_root: [0; 32],
+        },
+        vec![
+            AccountMeta::new(admin.pubkey(), true),
+            AccountMeta::new(config_address(), false),
+            AccountMeta::new_readonly(program_data, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    meter.case("InitConfig", init_config, &[admin]).await;
+
+    let proved = meter.vault_keys();
+    let custodied = meter.vault_keys();
+    let tree = AllowlistTree::new(&[
+        proved.owner.pubkey(),
+        custodied.owner.pubkey(),
+        Pubkey::new_unique(),
+    ]);
+    let update_config = ix(
+        WbaVaultInstruction::UpdateConfig {
+            gated: true,
+            allowlist_root: tree.root(),
+        },
+        vec![
+            AccountMeta::new_readonly(admin.pubkey(), true),
+            AccountMeta::new(config_address(), false),
+        ],
+    );
+    meter.case("UpdateConfig", update_config, &[admin]).await;
+
+    let funds = [
+        fund_ix(&meter.context, &proved.owner.pubkey()),
+        fund_ix(&meter.context, &custodied.owner.pubkey()),
+    ];
+    meter.run(&funds, &[]).await;
+    let mut initialize = initialize_ix(&proved);
+    initialize.data = borsh::to_vec(&WbaVaultInstruction::InitializeWithProof {
+        proof: tree.proof(&proved.owner.pubkey()).unwrap(),
+    })
+    .unwrap();
+    meter
+        .case(
+            "InitializeWithProof",
+            initialize,
+            &[&proved.owner, &proved.vault_state],
+        )
+        .await;
+    let mut initialize = initialize_ix(&custodied);
+    initialize.data = borsh::to_vec(&WbaVaultInstruction::InitializeWithCustody {
+        custody: Custody::ProgramOwned,
+        proof: tree.proof(&custodied.owner.pubkey()).unwrap(),
+    })
+    .unwrap();
+    meter
+        .case(
+            "InitializeWithCustody",
+            initialize,
+            &[&custodied.owner, &custodied.vault_state],
+        )
+        .await;
+}
+
+/// Runs every case and returns `(instruction, units)`, one per instruction.
+async fn run_cases() -> Vec<(&'static str, u64)> {
+    let admin = keypair(0);
+    let mut meter = Meter {
+        context: start(&admin).await,
+        measured: Vec::new(),
+        next_seed: 0,
+    };
+    core_cases(&mut meter).await;
+    spending_cases(&mut meter).await;
+    swap_and_vesting_cases(&mut meter).await;
+    stake_cases(&mut meter).await;
+    proposal_cases(&mut meter).await;
+    recovery_cases(&mut meter).await;
+    pool_and_receipt_cases(&mut meter).await;
+    config_cases(&mut meter, &admin).await;
+    meter.measured
+}
+
+#[tokio::test]
+async fn instructions_stay_within_their_compute_budgets() {
+    let mode = mode();
+    let measured = run_cases().await;
+    let mut budgets = read_budgets();
+
+    if env::var("UPDATE_COMPUTE_BUDGETS").is_ok() {
+        budgets.retain(|(budget_mode, _), _| budget_mode != mode);
+        for (name, units) in &measured {
+            budgets.insert((mode.to_string(), name.to_string()), *units);
+        }
+        write_budgets(&budgets);
+    }
+
+    let mut report = format!(
+        "\nCompute units ({mode}, tolerance {TOLERANCE_PERCENT}%)\n\n\
+         | instruction | budget | measured | change | headroom | status |\n\
+         |---|---:|---:|---:|---:|---|\n"
+    );
+    let mut over = Vec::new();
+    let mut missing = Vec::new();
+    for (name, units) in &measured {
+        let headroom = DEFAULT_LIMIT.saturating_sub(*units);
+        let Some(&budget) = budgets.get(&(mode.to_string(), name.to_string())) else {
+            missing.push(*name);
+            writeln!(
+                report,
+                "| {name} | - | {units} | - | {headroom} | no budget |"
+            )
+            .unwrap();
+            continue;
+        };
+        let slack = budget * TOLERANCE_PERCENT / 100;
+        let status = if *units > budget + slack {
+            over.push(*name);
+            "OVER"
+        } else if *units + slack < budget {
+            "under, lower the budget"
+        } else {
+            "ok"
+        };
+        let change = *units as i64 - budget as i64;
+        writeln!(
+            report,
+            "| {name} | {budget} | {units} | {change:+} | {headroom} | {status} |"
+        )
+        .unwrap();
+    }
+    println!("{report}");
+
+    assert!(
+        missing.is_empty(),
+        "no {mode} budget for {missing:?}; record one with UPDATE_COMPUTE_BUDGETS=1"
+    );
+    assert!(
+        over.is_empty(),
+        "over budget: {over:?}; if intended, rerun with UPDATE_COMPUTE_BUDGETS=1"
+    );
+}
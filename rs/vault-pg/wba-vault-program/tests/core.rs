@@ -0,0 +1,418 @@
+//! Happy paths and the common ways a caller gets each core instruction
+//! wrong: the wrong signer, a wrong PDA, a wrong token program, a wrong ATA
+//! and a `vault_state` this program does not own. Mints and Token Metadata
+//! accounts are seeded locally, so nothing here needs a network.
+
+mod common;
+
+use common::*;
+use solana_program::{
+    instruction::{Instruction, InstructionError},
+    pubkey::Pubkey,
+    system_instruction::SystemError,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::{
+    account::{Account, AccountSharedData},
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+use spl_associated_token_account::get_associated_token_address;
+use spl_token::error::TokenError;
+use wba_vault_program::{id, Vault, WbaVaultError, WbaVaultInstruction};
+
+/// Returns `ix` with account `index` swapped for `key`, keeping its flags.
+fn with_account(mut ix: Instruction, index: usize, key: Pubkey) -> Instruction {
+    ix.accounts[index].pubkey = key;
+    ix
+}
+
+fn vault_error(error: WbaVaultError) -> InstructionError {
+    InstructionError::Custom(error as u32)
+}
+
+async fn assert_rejected(
+    context: &mut ProgramTestContext,
+    ix: Instruction,
+    signers: &[&Keypair],
+    error: InstructionError,
+) {
+    let result = process(context, &[ix], signers).await;
+    assert_eq!(
+        result.expect_err("instruction succeeded").unwrap(),
+        TransactionError::InstructionError(0, error),
+    );
+}
+
+/// Re-signs `ix` as `stranger`, a funded wallet that is not the owner.
+async fn assert_stranger_rejected(context: &mut ProgramTestContext, ix: Instruction) {
+    let stranger = Keypair::new();
+    let fund = fund_ix(context, &stranger.pubkey());
+    process(context, &[fund], &[]).await.unwrap();
+    let ix = with_account(ix, 0, stranger.pubkey());
+    let error = vault_error(WbaVaultError::InvalidSigner);
+    assert_rejected(context, ix, &[&stranger], error).await;
+}
+
+/// Sends `ix` while `keys.vault_state` is owned by another program, then
+/// puts the account back.
+async fn assert_foreign_state_rejected(
+    context: &mut ProgramTestContext,
+    keys: &VaultKeys,
+    ix: Instruction,
+    signers: &[&Keypair],
+) {
+    let address = keys.vault_state.pubkey();
+    let original = context
+        .banks_client
+        .get_account(address)
+        .await
+        .unwrap()
+        .unwrap();
+    let mut foreign = original.clone();
+    foreign.owner = Pubkey::new_unique();
+    context.set_account(&address, &AccountSharedData::from(foreign));
+    let error = vault_error(WbaVaultError::InvalidVaultStateOwner);
+    assert_rejected(context, ix, signers, error).await;
+    context.set_account(&address, &AccountSharedData::from(original));
+}
+
+struct Tokens {
+    mint: Pubkey,
+    owner_ata: Pubkey,
+    vault_ata: Pubkey,
+}
+
+/// A mint with `amount` in the owner's ATA and an empty vault ATA.
+async fn setup_tokens(
+    context: &mut ProgramTestContext,
+    keys: &VaultKeys,
+    decimals: u8,
+    amount: u64,
+) -> Tokens {
+    let payer = context.payer.pubkey();
+    let mint = create_mint(context, &payer, decimals).await;
+    let owner_ata = create_ata(context, &keys.owner.pubkey(), &mint).await;
+    let vault_ata = create_ata(context, &keys.vault_auth, &mint).await;
+    mint_to(context, &mint, &owner_ata, amount).await;
+    Tokens {
+        mint,
+        owner_ata,
+        vault_ata,
+    }
+}
+
+fn spl_ix(keys: &VaultKeys, tokens: &Tokens, data: WbaVaultInstruction) -> Instruction {
+    let mut ix = withdraw_spl_ix(keys, &tokens.owner_ata, &tokens.vault_ata, &tokens.mint, 0);
+    ix.data = borsh::to_vec(&data).unwrap();
+    ix
+}
+
+/// An NFT in the owner's wallet with its metadata and master edition
+/// accounts in place.
+async fn setup_nft(context: &mut ProgramTestContext, keys: &VaultKeys) -> Tokens {
+    let nft = setup_tokens(context, keys, 0, 1).await;
+    seed_master_edition(context, &nft.mint);
+    nft
+}
+
+#[tokio::test]
+async fn initialize() {
+    let mut context = program_test().start_with_context().await;
+    let keys = VaultKeys::new();
+    let fund = fund_ix(&context, &keys.owner.pubkey());
+    process(&mut context, &[fund], &[]).await.unwrap();
+    let signers = [&keys.owner, &keys.vault_state];
+
+    // The new vault_state must sign for its own creation.
+    let mut unsigned = initialize_ix(&keys);
+    unsigned.accounts[1].is_signer = false;
+    let error = InstructionError::MissingRequiredSignature;
+    assert_rejected(&mut context, unsigned, &[&keys.owner], error).await;
+
+    for index in [2, 3, 5] {
+        let wrong_pda = with_account(initialize_ix(&keys), index, Pubkey::new_unique());
+        let error = vault_error(WbaVaultError::InvalidPda);
+        assert_rejected(&mut context, wrong_pda, &signers, error).await;
+    }
+
+    process(&mut context, &[initialize_ix(&keys)], &signers)
+        .await
+        .unwrap();
+    let state = vault_state(&mut context, &keys).await;
+    assert_eq!(state.owner, keys.owner.pubkey());
+    let account = context
+        .banks_client
+        .get_account(keys.vault_state.pubkey())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.owner, id());
+    assert_eq!(account.data.len(), Vault::space());
+
+    // Whoever holds the vault_state keypair cannot reset a live vault.
+    let stranger = Keypair::new();
+    let fund = fund_ix(&context, &stranger.pubkey());
+    process(&mut context, &[fund], &[]).await.unwrap();
+    for owner in [&keys.owner, &stranger] {
+        let reinitialize = with_account(initialize_ix(&keys), 0, owner.pubkey());
+        let error = InstructionError::AccountAlreadyInitialized;
+        assert_rejected(
+            &mut context,
+            reinitialize,
+            &[owner, &keys.vault_state],
+            error,
+        )
+        .await;
+    }
+    assert_eq!(
+        vault_state(&mut context, &keys).await.owner,
+        keys.owner.pubkey()
+    );
+
+    // An account another program already owns cannot become a vault: the
+    // System Program refuses to create it.
+    let taken = VaultKeys::new();
+    let fund = fund_ix(&context, &taken.owner.pubkey());
+    process(&mut context, &[fund], &[]).await.unwrap();
+    context.set_account(
+        &taken.vault_state.pubkey(),
+        &AccountSharedData::from(Account::new(1_000_000_000, 64, &Pubkey::new_unique())),
+    );
+    assert_rejected(
+        &mut context,
+        initialize_ix(&taken),
+        &[&taken.owner, &taken.vault_state],
+        InstructionError::Custom(SystemError::AccountAlreadyInUse as u32),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn deposit() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let signers = [&keys.owner];
+
+    assert_stranger_rejected(&mut context, deposit_ix(&keys, 1_000)).await;
+    for index in [2, 3] {
+        let wrong_pda = with_account(deposit_ix(&keys, 1_000), index, Pubkey::new_unique());
+        let error = vault_error(WbaVaultError::InvalidPda);
+        assert_rejected(&mut context, wrong_pda, &signers, error).await;
+    }
+    assert_foreign_state_rejected(&mut context, &keys, deposit_ix(&keys, 1_000), &signers).await;
+
+    let before = lamports(&mut context, &keys.vault).await;
+    process(&mut context, &[deposit_ix(&keys, 1_000)], &signers)
+        .await
+        .unwrap();
+    assert_eq!(lamports(&mut context, &keys.vault).await, before + 1_000);
+}
+
+#[tokio::test]
+async fn withdraw() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let signers = [&keys.owner];
+    process(&mut context, &[deposit_ix(&keys, 1_000_000)], &signers)
+        .await
+        .unwrap();
+
+    assert_stranger_rejected(&mut context, withdraw_ix(&keys, 1_000)).await;
+    for index in [2, 3] {
+        let wrong_pda = with_account(withdraw_ix(&keys, 1_000), index, Pubkey::new_unique());
+        let error = vault_error(WbaVaultError::InvalidPda);
+        assert_rejected(&mut context, wrong_pda, &signers, error).await;
+    }
+    assert_foreign_state_rejected(&mut context, &keys, withdraw_ix(&keys, 1_000), &signers).await;
+    // More than the vault holds, refused by the System Program transfer.
+    let held = lamports(&mut context, &keys.vault).await;
+    let error = InstructionError::Custom(SystemError::ResultWithNegativeLamports as u32);
+    assert_rejected(&mut context, withdraw_ix(&keys, held + 1), &signers, error).await;
+
+    let before = lamports(&mut context, &keys.owner.pubkey()).await;
+    process(&mut context, &[withdraw_ix(&keys, 1_000_000)], &signers)
+        .await
+        .unwrap();
+    assert_eq!(
+        lamports(&mut context, &keys.owner.pubkey()).await,
+        before + 1_000_000
+    );
+}
+
+/// The SPL and NFT handlers take the same leading accounts; `token_program`
+/// is the only one that moves.
+async fn assert_token_mistakes_rejected(
+    context: &mut ProgramTestContext,
+    keys: &VaultKeys,
+    tokens: &Tokens,
+    ix: Instruction,
+    token_program_index: usize,
+) {
+    let signers = [&keys.owner];
+    assert_stranger_rejected(context, ix.clone()).await;
+
+    let wrong_pda = with_account(ix.clone(), 3, Pubkey::new_unique());
+    let error = vault_error(WbaVaultError::InvalidPda);
+    assert_rejected(context, wrong_pda, &signers, error).await;
+
+    let wrong_program = with_account(ix.clone(), token_program_index, Pubkey::new_unique());
+    let error = vault_error(WbaVaultError::InvalidTokenProgram);
+    assert_rejected(context, wrong_program, &signers, error).await;
+
+    // Real token accounts for the mint, but not the ones the handler derives.
+    let payer = context.payer.pubkey();
+    let other_ata = create_ata(context, &payer, &tokens.mint).await;
+    for index in [1, 4] {
+        let wrong_ata = with_account(ix.clone(), index, other_ata);
+        let error = vault_error(WbaVaultError::InvalidTokenAccount);
+        assert_rejected(context, wrong_ata, &signers, error).await;
+    }
+    let other_mint = create_mint(context, &payer, 0).await;
+    let vault_ata = get_associated_token_address(&keys.vault_auth, &other_mint);
+    let wrong_ata = with_account(ix.clone(), 4, vault_ata);
+    let error = vault_error(WbaVaultError::InvalidTokenAccount);
+    assert_rejected(context, wrong_ata, &signers, error).await;
+
+    assert_foreign_state_rejected(context, keys, ix, &signers).await;
+}
+
+#[tokio::test]
+async fn deposit_spl() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let tokens = setup_tokens(&mut context, &keys, 6, 1_000).await;
+    let deposit = spl_ix(
+        &keys,
+        &tokens,
+        WbaVaultInstruction::DepositSpl { amount: 400 },
+    );
+
+    assert_token_mistakes_rejected(&mut context, &keys, &tokens, deposit.clone(), 6).await;
+    assert_eq!(token_balance(&mut context, &tokens.vault_ata).await, 0);
+
+    process(&mut context, &[deposit], &[&keys.owner])
+        .await
+        .unwrap();
+    assert_eq!(token_balance(&mut context, &tokens.owner_ata).await, 600);
+    assert_eq!(token_balance(&mut context, &tokens.vault_ata).await, 400);
+}
+
+#[tokio::test]
+async fn withdraw_spl() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let tokens = setup_tokens(&mut context, &keys, 6, 1_000).await;
+    let deposit = spl_ix(
+        &keys,
+        &tokens,
+        WbaVaultInstruction::DepositSpl { amount: 400 },
+    );
+    process(&mut context, &[deposit], &[&keys.owner])
+        .await
+        .unwrap();
+    let withdraw = spl_ix(
+        &keys,
+        &tokens,
+        WbaVaultInstruction::WithdrawSpl { amount: 150 },
+    );
+
+    assert_token_mistakes_rejected(&mut context, &keys, &tokens, withdraw.clone(), 6).await;
+    let too_much = spl_ix(
+        &keys,
+        &tokens,
+        WbaVaultInstruction::WithdrawSpl { amount: 401 },
+    );
+    let error = InstructionError::Custom(TokenError::InsufficientFunds as u32);
+    assert_rejected(&mut context, too_much, &[&keys.owner], error).await;
+
+    process(&mut context, &[withdraw], &[&keys.owner])
+        .await
+        .unwrap();
+    assert_eq!(token_balance(&mut context, &tokens.owner_ata).await, 750);
+    assert_eq!(token_balance(&mut context, &tokens.vault_ata).await, 250);
+}
+
+#[tokio::test]
+async fn deposit_nft() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let nft = setup_nft(&mut context, &keys).await;
+    let deposit = nft_ix(&keys, &nft.mint, WbaVaultInstruction::DepositNft);
+
+    assert_token_mistakes_rejected(&mut context, &keys, &nft, deposit.clone(), 9).await;
+    let (_, edition) = metadata_pdas(&nft.mint);
+    let cases = [
+        (6, edition, WbaVaultError::DuplicateAccount),
+        (7, Pubkey::new_unique(), WbaVaultError::InvalidPda),
+        (8, id(), WbaVaultError::InvalidMetadataProgram),
+    ];
+    for (index, key, error) in cases {
+        let wrong_metadata = with_account(deposit.clone(), index, key);
+        assert_rejected(
+            &mut context,
+            wrong_metadata,
+            &[&keys.owner],
+            vault_error(error),
+        )
+        .await;
+    }
+
+    // A mint Token Metadata never made a master edition for is not an NFT.
+    let plain = setup_tokens(&mut context, &keys, 0, 1).await;
+    let no_edition = nft_ix(&keys, &plain.mint, WbaVaultInstruction::DepositNft);
+    let error = vault_error(WbaVaultError::MissingMasterEdition);
+    assert_rejected(&mut context, no_edition, &[&keys.owner], error).await;
+
+    process(&mut context, &[deposit], &[&keys.owner])
+        .await
+        .unwrap();
+    assert_eq!(token_balance(&mut context, &nft.owner_ata).await, 0);
+    assert_eq!(token_balance(&mut context, &nft.vault_ata).await, 1);
+}
+
+#[tokio::test]
+async fn withdraw_nft() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let nft = setup_nft(&mut context, &keys).await;
+    let deposit = nft_ix(&keys, &nft.mint, WbaVaultInstruction::DepositNft);
+    process(&mut context, &[deposit], &[&keys.owner])
+        .await
+        .unwrap();
+    let withdraw = nft_ix(&keys, &nft.mint, WbaVaultInstruction::WithdrawNft);
+
+    assert_token_mistakes_rejected(&mut context, &keys, &nft, withdraw.clone(), 9).await;
+    let wrong_metadata = with_account(withdraw.clone(), 6, Pubkey::new_unique());
+    let error = vault_error(WbaVaultError::InvalidPda);
+    assert_rejected(&mut context, wrong_metadata, &[&keys.owner], error).await;
+
+    process(&mut context, &[withdraw], &[&keys.owner])
+        .await
+        .unwrap();
+    assert_eq!(token_balance(&mut context, &nft.owner_ata).await, 1);
+    assert_eq!(token_balance(&mut context, &nft.vault_ata).await, 0);
+}
+
+#[tokio::test]
+async fn close_account() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let signers = [&keys.owner];
+    let close = close_account_ix(&keys);
+
+    assert_stranger_rejected(&mut context, close.clone()).await;
+    let wrong_program = with_account(close.clone(), 3, Pubkey::new_unique());
+    let error = vault_error(WbaVaultError::InvalidSystemProgram);
+    assert_rejected(&mut context, wrong_program, &signers, error).await;
+    assert_foreign_state_rejected(&mut context, &keys, close.clone(), &signers).await;
+
+    let rent = lamports(&mut context, &keys.vault_state.pubkey()).await;
+    let before = lamports(&mut context, &keys.owner.pubkey()).await;
+    process(&mut context, &[close], &signers).await.unwrap();
+    assert_eq!(
+        lamports(&mut context, &keys.owner.pubkey()).await,
+        before + rent
+    );
+    assert!(!account_exists(&mut context, &keys.vault_state.pubkey()).await);
+}
@@ -0,0 +1,123 @@
+mod common;
+
+use common::*;
+use solana_program::{pubkey::Pubkey, system_program};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::{Keypair, Signer};
+use wba_vault_program::{id, Custody};
+
+async fn vault_owner(context: &mut ProgramTestContext, keys: &VaultKeys) -> Pubkey {
+    context
+        .banks_client
+        .get_account(keys.vault)
+        .await
+        .unwrap()
+        .unwrap()
+        .owner
+}
+
+#[tokio::test]
+async fn vaults_are_system_owned_by_default() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    assert_eq!(
+        vault_state(&mut context, &keys).await.custody().unwrap(),
+        Custody::SystemOwned
+    );
+    assert_eq!(vault_owner(&mut context, &keys).await, system_program::id());
+
+    process(
+        &mut context,
+        &[deposit_ix(&keys, 1_000_000)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    let before = lamports(&mut context, &keys.owner.pubkey()).await;
+    process(&mut context, &[withdraw_ix(&keys, 400_000)], &[&keys.owner])
+        .await
+        .unwrap();
+    assert_eq!(
+        lamports(&mut context, &keys.owner.pubkey()).await,
+        before + 400_000
+    );
+}
+
+#[tokio::test]
+async fn program_owned_vaults_withdraw_by_debit() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault_with_custody(&mut context, Custody::ProgramOwned).await;
+    assert_eq!(
+        vault_state(&mut context, &keys).await.custody().unwrap(),
+        Custody::ProgramOwned
+    );
+    assert_eq!(vault_owner(&mut context, &keys).await, id());
+
+    process(
+        &mut context,
+        &[deposit_ix(&keys, 1_000_000)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    let vault_before = lamports(&mut context, &keys.vault).await;
+    let before = lamports(&mut context, &keys.owner.pubkey()).await;
+    process(
+        &mut context,
+        &[withdraw_ix(&keys, 1_000_000)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        lamports(&mut context, &keys.owner.pubkey()).await,
+        before + 1_000_000
+    );
+    assert_eq!(
+        lamports(&mut context, &keys.vault).await,
+        vault_before - 1_000_000
+    );
+
+    // Only the rent reserve is left, and it stays.
+    let result = process(&mut context, &[withdraw_ix(&keys, 1)], &[&keys.owner]).await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn custody_cannot_be_switched_on_an_existing_vault_pda() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+
+    // Re-initializing the same vault_state as program-owned would leave
+    // the system-owned PDA's lamports under the wrong withdrawal path.
+    let mut initialize = initialize_ix(&keys);
+    initialize.data = borsh::to_vec(
+        &wba_vault_program::WbaVaultInstruction::InitializeWithCustody {
+            custody: Custody::ProgramOwned,
+            proof: Vec::new(),
+        },
+    )
+    .unwrap();
+    let result = process(
+        &mut context,
+        &[initialize],
+        &[&keys.owner, &keys.vault_state],
+    )
+    .await;
+    assert!(result.is_err());
+
+    // A stranger's signature does not move a program-owned vault's SOL either.
+    let keys = setup_vault_with_custody(&mut context, Custody::ProgramOwned).await;
+    process(
+        &mut context,
+        &[deposit_ix(&keys, 1_000_000)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    let thief = Keypair::new();
+    let mut stolen = withdraw_ix(&keys, 1_000_000);
+    stolen.accounts[0].pubkey = thief.pubkey();
+    let result = process(&mut context, &[stolen], &[&thief]).await;
+    assert!(result.is_err());
+}
@@ -0,0 +1,227 @@
+mod common;
+
+use common::*;
+use solana_program::{
+    clock::Clock,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::{Keypair, Signer};
+use spl_associated_token_account::get_associated_token_address;
+use wba_vault_program::{id, WbaVaultInstruction};
+
+const NOW: i64 = 10_000;
+
+fn delegate_pda(keys: &VaultKeys, delegate: &Pubkey, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"delegate",
+            keys.vault_state.pubkey().as_ref(),
+            delegate.as_ref(),
+            mint.as_ref(),
+        ],
+        &id(),
+    )
+    .0
+}
+
+fn set_delegate_ix(
+    keys: &VaultKeys,
+    delegate: &Pubkey,
+    mint: Pubkey,
+    allowance: u64,
+    expires_at: i64,
+    recipients: Vec<Pubkey>,
+) -> Instruction {
+    ix(
+        WbaVaultInstruction::SetDelegate {
+            delegate: *delegate,
+            mint,
+            allowance,
+            expires_at,
+            recipients,
+        },
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new_readonly(keys.vault_state.pubkey(), false),
+            AccountMeta::new(delegate_pda(keys, delegate, &mint), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+fn delegate_withdraw_ix(
+    keys: &VaultKeys,
+    delegate: &Keypair,
+    mint: Pubkey,
+    recipient: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(delegate.pubkey(), true),
+        AccountMeta::new(keys.vault_state.pubkey(), false),
+        AccountMeta::new_readonly(keys.vault_auth, false),
+        AccountMeta::new(keys.vault, false),
+        AccountMeta::new(delegate_pda(keys, &delegate.pubkey(), &mint), false),
+        AccountMeta::new(*recipient, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    if mint != Pubkey::default() {
+        accounts.extend([
+            AccountMeta::new(get_associated_token_address(&keys.vault_auth, &mint), false),
+            AccountMeta::new(get_associated_token_address(recipient, &mint), false),
+            AccountMeta::new_readonly(mint, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ]);
+    }
+    ix(WbaVaultInstruction::DelegateWithdraw { amount }, accounts)
+}
+
+fn revoke_delegate_ix(keys: &VaultKeys, delegate: &Pubkey, mint: Pubkey) -> Instruction {
+    ix(
+        WbaVaultInstruction::RevokeDelegate,
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new_readonly(keys.vault_state.pubkey(), false),
+            AccountMeta::new(delegate_pda(keys, delegate, &mint), false),
+        ],
+    )
+}
+
+async fn set_time(context: &mut ProgramTestContext, unix_timestamp: i64) {
+    let mut clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    clock.unix_timestamp = unix_timestamp;
+    context.set_sysvar(&clock);
+}
+
+#[tokio::test]
+async fn sol_allowance_is_drawn_down_and_revocable() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let bot = Keypair::new();
+    let recipient = Keypair::new().pubkey();
+    let sol = Pubkey::default();
+    let fund_bot = fund_ix(&context, &bot.pubkey());
+    let fund_recipient = fund_ix(&context, &recipient);
+    process(
+        &mut context,
+        &[
+            fund_bot,
+            fund_recipient,
+            deposit_ix(&keys, 1_000_000),
+            set_delegate_ix(&keys, &bot.pubkey(), sol, 1_000, 0, vec![recipient]),
+        ],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    let before = lamports(&mut context, &recipient).await;
+    process(
+        &mut context,
+        &[delegate_withdraw_ix(&keys, &bot, sol, &recipient, 600)],
+        &[&bot],
+    )
+    .await
+    .unwrap();
+    assert_eq!(lamports(&mut context, &recipient).await, before + 600);
+
+    // Over the remaining allowance, or to an unlisted recipient.
+    assert!(process(
+        &mut context,
+        &[delegate_withdraw_ix(&keys, &bot, sol, &recipient, 500)],
+        &[&bot],
+    )
+    .await
+    .is_err());
+    assert!(process(
+        &mut context,
+        &[delegate_withdraw_ix(&keys, &bot, sol, &bot.pubkey(), 100)],
+        &[&bot],
+    )
+    .await
+    .is_err());
+
+    process(
+        &mut context,
+        &[delegate_withdraw_ix(&keys, &bot, sol, &recipient, 100)],
+        &[&bot],
+    )
+    .await
+    .unwrap();
+    let stats = vault_state(&mut context, &keys).await.stats();
+    assert_eq!(stats.total_sol_withdrawn, 700);
+    assert_eq!(stats.withdraw_count, 2);
+
+    process(
+        &mut context,
+        &[revoke_delegate_ix(&keys, &bot.pubkey(), sol)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    assert!(process(
+        &mut context,
+        &[delegate_withdraw_ix(&keys, &bot, sol, &recipient, 1)],
+        &[&bot],
+    )
+    .await
+    .is_err());
+}
+
+#[tokio::test]
+async fn token_allowance_expires() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let bot = Keypair::new();
+    let recipient = Keypair::new().pubkey();
+    let payer = context.payer.pubkey();
+    let mint = create_mint(&mut context, &payer, 6).await;
+    let vault_ata = create_ata(&mut context, &keys.vault_auth, &mint).await;
+    let recipient_ata = create_ata(&mut context, &recipient, &mint).await;
+    mint_to(&mut context, &mint, &vault_ata, 1_000).await;
+    set_time(&mut context, NOW).await;
+
+    process(
+        &mut context,
+        &[set_delegate_ix(
+            &keys,
+            &bot.pubkey(),
+            mint,
+            500,
+            NOW + 100,
+            vec![recipient],
+        )],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    // Another key cannot use the bot's allowance.
+    let intruder = Keypair::new();
+    let mut stolen = delegate_withdraw_ix(&keys, &bot, mint, &recipient, 10);
+    stolen.accounts[0] = AccountMeta::new(intruder.pubkey(), true);
+    assert!(process(&mut context, &[stolen], &[&intruder])
+        .await
+        .is_err());
+
+    process(
+        &mut context,
+        &[delegate_withdraw_ix(&keys, &bot, mint, &recipient, 200)],
+        &[&bot],
+    )
+    .await
+    .unwrap();
+    assert_eq!(token_balance(&mut context, &recipient_ata).await, 200);
+
+    set_time(&mut context, NOW + 100).await;
+    assert!(process(
+        &mut context,
+        &[delegate_withdraw_ix(&keys, &bot, mint, &recipient, 1)],
+        &[&bot],
+    )
+    .await
+    .is_err());
+}
@@ -0,0 +1,191 @@
+mod common;
+
+use common::*;
+use solana_program::{
+    bpf_loader_upgradeable,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::{
+    account::Account,
+    signature::{Keypair, Signer},
+};
+use wba_vault_program::{id, verify_allowlist_proof, AllowlistTree, WbaVaultInstruction};
+
+fn program_data_address() -> Pubkey {
+    Pubkey::find_program_address(&[id().as_ref()], &bpf_loader_upgradeable::id()).0
+}
+
+/// Starts the program with a `ProgramData` account naming `authority` as
+/// its upgrade authority, as a real upgradeable deploy would.
+async fn start(authority: &Keypair) -> ProgramTestContext {
+    let mut program_test = program_test();
+    let mut data = vec![3, 0, 0, 0];
+    data.extend(0u64.to_le_bytes());
+    data.push(1);
+    data.extend(authority.pubkey().to_bytes());
+    program_test.add_account(
+        program_data_address(),
+        Account {
+            lamports: 1_000_000_000,
+            data,
+            owner: bpf_loader_upgradeable::id(),
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+    program_test.add_account(
+        authority.pubkey(),
+        Account::new(OWNER_LAMPORTS, 0, &system_program::id()),
+    );
+    program_test.start_with_context().await
+}
+
+fn init_config_ix(authority: &Pubkey, gated: bool, allowlist_root: [u8; 32]) -> Instruction {
+    ix(
+        WbaVaultInstruction::InitConfig {
+            gated,
+            allowlist_root,
+        },
+        vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(config_address(), false),
+            AccountMeta::new_readonly(program_data_address(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+fn update_config_ix(admin: &Pubkey, gated: bool, allowlist_root: [u8; 32]) -> Instruction {
+    ix(
+        WbaVaultInstruction::UpdateConfig {
+            gated,
+            allowlist_root,
+        },
+        vec![
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new(config_address(), false),
+        ],
+    )
+}
+
+/// Funds `keys.owner` and opens its vault, with a proof when one is given.
+async fn open_vault(
+    context: &mut ProgramTestContext,
+    keys: &VaultKeys,
+    proof: Option<Vec<[u8; 32]>>,
+) -> bool {
+    let mut initialize = initialize_ix(keys);
+    if let Some(proof) = proof {
+        initialize.data =
+            borsh::to_vec(&WbaVaultInstruction::InitializeWithProof { proof }).unwrap();
+    }
+    let fund = fund_ix(context, &keys.owner.pubkey());
+    process(
+        context,
+        &[fund, initialize],
+        &[&keys.owner, &keys.vault_state],
+    )
+    .await
+    .is_ok()
+}
+
+#[tokio::test]
+async fn gated_initialize_needs_an_allowlist_proof() {
+    let admin = Keypair::new();
+    let mut context = start(&admin).await;
+    let alice = VaultKeys::new();
+    let bob = VaultKeys::new();
+    let cohort = [
+        alice.owner.pubkey(),
+        Keypair::new().pubkey(),
+        Keypair::new().pubkey(),
+    ];
+    let tree = AllowlistTree::new(&cohort);
+    process(
+        &mut context,
+        &[init_config_ix(&admin.pubkey(), true, tree.root())],
+        &[&admin],
+    )
+    .await
+    .unwrap();
+
+    assert!(!open_vault(&mut context, &alice, None).await);
+    assert!(!open_vault(&mut context, &bob, tree.proof(&alice.owner.pubkey())).await);
+    assert!(open_vault(&mut context, &alice, tree.proof(&alice.owner.pubkey())).await);
+
+    // The next cohort gets a new root.
+    let tree = AllowlistTree::new(&[bob.owner.pubkey()]);
+    process(
+        &mut context,
+        &[update_config_ix(&admin.pubkey(), true, tree.root())],
+        &[&admin],
+    )
+    .await
+    .unwrap();
+    assert!(open_vault(&mut context, &bob, tree.proof(&bob.owner.pubkey())).await);
+
+    process(
+        &mut context,
+        &[update_config_ix(&admin.pubkey(), false, tree.root())],
+        &[&admin],
+    )
+    .await
+    .unwrap();
+    assert!(open_vault(&mut context, &VaultKeys::new(), None).await);
+}
+
+#[tokio::test]
+async fn only_the_upgrade_authority_configures_the_gate() {
+    let admin = Keypair::new();
+    let mut context = start(&admin).await;
+
+    // Vaults open freely until a config exists.
+    assert!(open_vault(&mut context, &VaultKeys::new(), None).await);
+
+    let intruder = Keypair::new();
+    let fund = fund_ix(&context, &intruder.pubkey());
+    process(&mut context, &[fund], &[]).await.unwrap();
+    let result = process(
+        &mut context,
+        &[init_config_ix(&intruder.pubkey(), true, [0; 32])],
+        &[&intruder],
+    )
+    .await;
+    assert!(result.is_err());
+
+    process(
+        &mut context,
+        &[init_config_ix(&admin.pubkey(), false, [0; 32])],
+        &[&admin],
+    )
+    .await
+    .unwrap();
+    let result = process(
+        &mut context,
+        &[update_config_ix(&intruder.pubkey(), true, [0; 32])],
+        &[&intruder],
+    )
+    .await;
+    assert!(result.is_err());
+    assert!(open_vault(&mut context, &VaultKeys::new(), None).await);
+}
+
+#[test]
+fn allowlist_tree_proves_every_member_only() {
+    for size in 1..=9 {
+        let owners: Vec<Pubkey> = (0..size).map(|_| Keypair::new().pubkey()).collect();
+        let tree = AllowlistTree::new(&owners);
+        for owner in &owners {
+            let proof = tree.proof(owner).unwrap();
+            assert!(verify_allowlist_proof(&tree.root(), owner, &proof));
+        }
+
+        let outsider = Keypair::new().pubkey();
+        assert!(tree.proof(&outsider).is_none());
+        let borrowed = tree.proof(&owners[0]).unwrap();
+        assert!(!verify_allowlist_proof(&tree.root(), &outsider, &borrowed));
+    }
+}
@@ -0,0 +1,190 @@
+mod common;
+
+use common::*;
+use solana_program::{
+    clock::Clock,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::{Keypair, Signer};
+use wba_vault_program::WbaVaultInstruction;
+
+const NOW: i64 = 10_000;
+const PERIOD: i64 = 86_400;
+
+fn set_inheritance_ix(keys: &VaultKeys, beneficiary: Pubkey, inactivity_secs: i64) -> Instruction {
+    ix(
+        WbaVaultInstruction::SetInheritance {
+            beneficiary,
+            inactivity_secs,
+        },
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+fn heartbeat_ix(keys: &VaultKeys) -> Instruction {
+    ix(
+        WbaVaultInstruction::Heartbeat,
+        vec![
+            AccountMeta::new_readonly(keys.owner.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+        ],
+    )
+}
+
+fn claim_ix(keys: &VaultKeys, beneficiary: &Pubkey) -> Instruction {
+    ix(
+        WbaVaultInstruction::ClaimInheritance,
+        vec![
+            AccountMeta::new(*beneficiary, true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+async fn set_time(context: &mut ProgramTestContext, unix_timestamp: i64) {
+    let mut clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    clock.unix_timestamp = unix_timestamp;
+    context.set_sysvar(&clock);
+}
+
+/// Funded vault whose funded heir inherits after `PERIOD` of inactivity.
+async fn setup_inheritance(context: &mut ProgramTestContext) -> (VaultKeys, Keypair) {
+    let keys = setup_vault(context).await;
+    let heir = Keypair::new();
+    let fund = fund_ix(context, &heir.pubkey());
+    set_time(context, NOW).await;
+    process(
+        context,
+        &[
+            fund,
+            deposit_ix(&keys, 1_000_000),
+            set_inheritance_ix(&keys, heir.pubkey(), PERIOD),
+        ],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    (keys, heir)
+}
+
+#[tokio::test]
+async fn beneficiary_takes_over_after_inactivity() {
+    let mut context = program_test().start_with_context().await;
+    let (keys, heir) = setup_inheritance(&mut context).await;
+
+    set_time(&mut context, NOW + PERIOD - 1).await;
+    assert!(
+        process(&mut context, &[claim_ix(&keys, &heir.pubkey())], &[&heir])
+            .await
+            .is_err()
+    );
+
+    // Only the beneficiary can claim.
+    set_time(&mut context, NOW + PERIOD).await;
+    let stranger = Keypair::new();
+    assert!(process(
+        &mut context,
+        &[claim_ix(&keys, &stranger.pubkey())],
+        &[&stranger],
+    )
+    .await
+    .is_err());
+
+    process(&mut context, &[claim_ix(&keys, &heir.pubkey())], &[&heir])
+        .await
+        .unwrap();
+    let state = vault_state(&mut context, &keys).await;
+    assert_eq!(state.owner, heir.pubkey());
+    assert!(!state.has_inheritance());
+
+    let before = lamports(&mut context, &heir.pubkey()).await;
+    process(
+        &mut context,
+        &[signed_by(withdraw_ix(&keys, 1_000_000), &heir, &[])],
+        &[&heir],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        lamports(&mut context, &heir.pubkey()).await,
+        before + 1_000_000
+    );
+    assert!(
+        process(&mut context, &[withdraw_ix(&keys, 1)], &[&keys.owner])
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn owner_activity_resets_timer() {
+    let mut context = program_test().start_with_context().await;
+    let (keys, heir) = setup_inheritance(&mut context).await;
+
+    // A plain withdrawal counts as activity...
+    set_time(&mut context, NOW + PERIOD - 10).await;
+    process(&mut context, &[withdraw_ix(&keys, 1)], &[&keys.owner])
+        .await
+        .unwrap();
+    assert_eq!(
+        vault_state(&mut context, &keys).await.last_activity,
+        NOW + PERIOD - 10
+    );
+
+    // ...and so does a heartbeat.
+    set_time(&mut context, NOW + 2 * PERIOD - 20).await;
+    process(&mut context, &[heartbeat_ix(&keys)], &[&keys.owner])
+        .await
+        .unwrap();
+
+    set_time(&mut context, NOW + 2 * PERIOD).await;
+    assert!(
+        process(&mut context, &[claim_ix(&keys, &heir.pubkey())], &[&heir])
+            .await
+            .is_err()
+    );
+
+    set_time(&mut context, NOW + 3 * PERIOD - 20).await;
+    process(&mut context, &[claim_ix(&keys, &heir.pubkey())], &[&heir])
+        .await
+        .unwrap();
+    assert_eq!(vault_state(&mut context, &keys).await.owner, heir.pubkey());
+}
+
+#[tokio::test]
+async fn disabled_switch_cannot_be_claimed() {
+    let mut context = program_test().start_with_context().await;
+    let (keys, heir) = setup_inheritance(&mut context).await;
+
+    process(
+        &mut context,
+        &[set_inheritance_ix(&keys, heir.pubkey(), 0)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    set_time(&mut context, NOW + 10 * PERIOD).await;
+    assert!(
+        process(&mut context, &[claim_ix(&keys, &heir.pubkey())], &[&heir])
+            .await
+            .is_err()
+    );
+    assert!(
+        process(&mut context, &[heartbeat_ix(&keys)], &[&keys.owner])
+            .await
+            .is_err()
+    );
+    assert_eq!(
+        vault_state(&mut context, &keys).await.owner,
+        keys.owner.pubkey()
+    );
+}
@@ -0,0 +1,273 @@
+mod common;
+
+use common::*;
+use solana_program::{
+    instruction::{Instruction, InstructionError},
+    pubkey::Pubkey,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::{signature::Signer, transaction::TransactionError};
+use wba_vault_program::{Ledger, WbaVaultError, WbaVaultInstruction, ZeroCopy};
+
+struct Tokens {
+    mint: Pubkey,
+    owner_ata: Pubkey,
+    vault_ata: Pubkey,
+}
+
+async fn setup_tokens(context: &mut ProgramTestContext, keys: &VaultKeys) -> Tokens {
+    let payer = context.payer.pubkey();
+    let mint = create_mint(context, &payer, 0).await;
+    let owner_ata = create_ata(context, &keys.owner.pubkey(), &mint).await;
+    let vault_ata = create_ata(context, &keys.vault_auth, &mint).await;
+    mint_to(context, &mint, &owner_ata, 1_000).await;
+    Tokens {
+        mint,
+        owner_ata,
+        vault_ata,
+    }
+}
+
+fn spl_ix(keys: &VaultKeys, tokens: &Tokens, data: WbaVaultInstruction) -> Instruction {
+    let mut ix = withdraw_spl_ix(keys, &tokens.owner_ata, &tokens.vault_ata, &tokens.mint, 0);
+    ix.data = borsh::to_vec(&data).unwrap();
+    ix
+}
+
+async fn ledger(
+    context: &mut ProgramTestContext,
+    keys: &VaultKeys,
+    mint: &Pubkey,
+) -> Option<Ledger> {
+    let account = context
+        .banks_client
+        .get_account(ledger_address(keys, mint))
+        .await
+        .unwrap()?;
+    Some(Ledger::read(&account.data).unwrap())
+}
+
+#[tokio::test]
+async fn deposits_and_withdrawals_move_the_ledger() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let tokens = setup_tokens(&mut context, &keys).await;
+    assert!(ledger(&mut context, &keys, &tokens.mint).await.is_none());
+
+    process(
+        &mut context,
+        &[spl_ix(
+            &keys,
+            &tokens,
+            WbaVaultInstruction::DepositSpl { amount: 300 },
+        )],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    let first = ledger(&mut context, &keys, &tokens.mint).await.unwrap();
+    assert_eq!(first.vault_state, keys.vault_state.pubkey());
+    assert_eq!(first.balance, 300);
+    assert_ne!(first.first_deposit_slot, 0);
+    assert_eq!(first.last_deposit_slot, first.first_deposit_slot);
+
+    context.warp_to_slot(100).unwrap();
+    process(
+        &mut context,
+        &[
+            spl_ix(
+                &keys,
+                &tokens,
+                WbaVaultInstruction::DepositSpl { amount: 200 },
+            ),
+            spl_ix(
+                &keys,
+                &tokens,
+                WbaVaultInstruction::WithdrawSpl { amount: 450 },
+            ),
+        ],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    let state = ledger(&mut context, &keys, &tokens.mint).await.unwrap();
+    assert_eq!(state.balance, 50);
+    assert_eq!(state.first_deposit_slot, first.first_deposit_slot);
+    assert!(state.last_deposit_slot >= 100);
+    assert_eq!(token_balance(&mut context, &tokens.vault_ata).await, 50);
+}
+
+#[tokio::test]
+async fn unattributed_tokens_still_withdraw() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let tokens = setup_tokens(&mut context, &keys).await;
+    process(
+        &mut context,
+        &[spl_ix(
+            &keys,
+            &tokens,
+            WbaVaultInstruction::DepositSpl { amount: 100 },
+        )],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    mint_to(&mut context, &tokens.mint, &tokens.vault_ata, 40).await;
+
+    // Reporting leaves the ledger alone; syncing attributes the difference.
+    process(
+        &mut context,
+        &[reconcile_ix(&keys, &tokens.mint, false)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    assert_eq!(ledger_balance(&mut context, &keys, &tokens.mint).await, 100);
+    process(
+        &mut context,
+        &[reconcile_ix(&keys, &tokens.mint, true)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    assert_eq!(ledger_balance(&mut context, &keys, &tokens.mint).await, 140);
+
+    // Without a sync the ATA holds more than the ledger attributes, and the
+    // ledger bottoms out at zero rather than holding the rest back.
+    mint_to(&mut context, &tokens.mint, &tokens.vault_ata, 60).await;
+    process(
+        &mut context,
+        &[spl_ix(
+            &keys,
+            &tokens,
+            WbaVaultInstruction::WithdrawSpl { amount: 200 },
+        )],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    assert_eq!(ledger_balance(&mut context, &keys, &tokens.mint).await, 0);
+    assert_eq!(token_balance(&mut context, &tokens.vault_ata).await, 0);
+}
+
+#[tokio::test]
+async fn the_ledger_account_is_optional() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let tokens = setup_tokens(&mut context, &keys).await;
+    // The layout from before ledgers, as older clients still send it.
+    let without_ledger = |data| {
+        let mut ix = spl_ix(&keys, &tokens, data);
+        ix.accounts.pop();
+        ix
+    };
+
+    process(
+        &mut context,
+        &[
+            without_ledger(WbaVaultInstruction::DepositSpl { amount: 300 }),
+            without_ledger(WbaVaultInstruction::WithdrawSpl { amount: 100 }),
+        ],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    assert!(ledger(&mut context, &keys, &tokens.mint).await.is_none());
+    assert_eq!(token_balance(&mut context, &tokens.vault_ata).await, 200);
+
+    // Tokens from before the ledger existed withdraw through it too.
+    process(
+        &mut context,
+        &[spl_ix(
+            &keys,
+            &tokens,
+            WbaVaultInstruction::WithdrawSpl { amount: 200 },
+        )],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    assert_eq!(token_balance(&mut context, &tokens.vault_ata).await, 0);
+}
+
+#[tokio::test]
+async fn syncing_a_multisig_vault_needs_the_threshold() {
+    let mut context = program_test().start_with_context().await;
+    let (keys, [a, b, _c]) = setup_multisig(&mut context).await;
+    let tokens = setup_tokens(&mut context, &keys).await;
+    mint_to(&mut context, &tokens.mint, &tokens.vault_ata, 40).await;
+
+    // One member may report, but not rewrite the ledger.
+    let report = signed_by(reconcile_ix(&keys, &tokens.mint, false), &a, &[]);
+    process(&mut context, &[report], &[&a]).await.unwrap();
+    let sync = signed_by(reconcile_ix(&keys, &tokens.mint, true), &a, &[]);
+    let result = process(&mut context, &[sync], &[&a]).await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::MissingRequiredSignature),
+    );
+    assert!(ledger(&mut context, &keys, &tokens.mint).await.is_none());
+
+    let sync = signed_by(reconcile_ix(&keys, &tokens.mint, true), &a, &[&b]);
+    process(&mut context, &[sync], &[&a, &b]).await.unwrap();
+    assert_eq!(ledger_balance(&mut context, &keys, &tokens.mint).await, 40);
+}
+
+#[tokio::test]
+async fn only_the_owner_reconciles() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let tokens = setup_tokens(&mut context, &keys).await;
+    mint_to(&mut context, &tokens.mint, &tokens.vault_ata, 40).await;
+
+    let stranger = VaultKeys::new();
+    let fund = fund_ix(&context, &stranger.owner.pubkey());
+    process(&mut context, &[fund], &[]).await.unwrap();
+    let mut reconcile = reconcile_ix(&keys, &tokens.mint, true);
+    reconcile.accounts[0].pubkey = stranger.owner.pubkey();
+    let result = process(&mut context, &[reconcile], &[&stranger.owner]).await;
+    assert!(result.is_err());
+    assert!(ledger(&mut context, &keys, &tokens.mint).await.is_none());
+
+    // A ledger for another mint cannot stand in.
+    let payer = context.payer.pubkey();
+    let other_mint = create_mint(&mut context, &payer, 0).await;
+    let mut reconcile = reconcile_ix(&keys, &tokens.mint, true);
+    reconcile.accounts[5].pubkey = ledger_address(&keys, &other_mint);
+    let result = process(&mut context, &[reconcile], &[&keys.owner]).await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn nfts_cannot_leave_past_the_ledger() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let payer = context.payer.pubkey();
+    let nft = create_mint(&mut context, &payer, 0).await;
+    seed_master_edition(&mut context, &nft);
+    let owner_ata = create_ata(&mut context, &keys.owner.pubkey(), &nft).await;
+    create_ata(&mut context, &keys.vault_auth, &nft).await;
+    mint_to(&mut context, &nft, &owner_ata, 1).await;
+
+    let deposit = nft_ix(&keys, &nft, WbaVaultInstruction::DepositNft);
+    process(&mut context, &[deposit], &[&keys.owner])
+        .await
+        .unwrap();
+    assert_eq!(ledger_balance(&mut context, &keys, &nft).await, 1);
+
+    // The ledger sits just before the stake record.
+    let mut without_ledger = nft_ix(&keys, &nft, WbaVaultInstruction::WithdrawNft);
+    without_ledger
+        .accounts
+        .remove(without_ledger.accounts.len() - 2);
+    let result = process(&mut context, &[without_ledger], &[&keys.owner]).await;
+    assert_vault_error(result, 0, WbaVaultError::MissingLedger);
+
+    let withdraw = nft_ix(&keys, &nft, WbaVaultInstruction::WithdrawNft);
+    process(&mut context, &[withdraw], &[&keys.owner])
+        .await
+        .unwrap();
+    assert_eq!(ledger_balance(&mut context, &keys, &nft).await, 0);
+    assert_eq!(token_balance(&mut context, &owner_ata).await, 1);
+}
@@ -0,0 +1,275 @@
+mod common;
+
+use common::*;
+use solana_program::{
+    clock::Clock,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::Signer;
+use wba_vault_program::{id, WbaVaultInstruction};
+
+const NOW: i64 = 10_000;
+const WINDOW: i64 = 3_600;
+
+fn set_withdraw_limit_ix(keys: &VaultKeys, max_amount: u64, window_secs: i64) -> Instruction {
+    ix(
+        WbaVaultInstruction::SetWithdrawLimit {
+            max_amount,
+            window_secs,
+        },
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+fn limit_pda(keys: &VaultKeys, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"limit", keys.vault_state.pubkey().as_ref(), mint.as_ref()],
+        &id(),
+    )
+    .0
+}
+
+fn set_mint_withdraw_limit_ix(
+    keys: &VaultKeys,
+    mint: &Pubkey,
+    max_amount: u64,
+    window_secs: i64,
+) -> Instruction {
+    ix(
+        WbaVaultInstruction::SetMintWithdrawLimit {
+            max_amount,
+            window_secs,
+        },
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new(limit_pda(keys, mint), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+fn limited_withdraw_spl_ix(
+    keys: &VaultKeys,
+    owner_ata: &Pubkey,
+    vault_ata: &Pubkey,
+    mint: &Pubkey,
+    amount: u64,
+    with_limit: bool,
+) -> Instruction {
+    let mut ix = withdraw_spl_ix(keys, owner_ata, vault_ata, mint, amount);
+    if with_limit {
+        ix.accounts.insert(
+            MINT_LIMIT_INDEX,
+            AccountMeta::new(limit_pda(keys, mint), false),
+        );
+    }
+    ix
+}
+
+async fn set_time(context: &mut ProgramTestContext, unix_timestamp: i64) {
+    let mut clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    clock.unix_timestamp = unix_timestamp;
+    context.set_sysvar(&clock);
+}
+
+#[tokio::test]
+async fn sol_limit_resets_each_window() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    process(
+        &mut context,
+        &[deposit_ix(&keys, 1_000_000)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    set_time(&mut context, NOW).await;
+    process(
+        &mut context,
+        &[set_withdraw_limit_ix(&keys, 1_000, WINDOW)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    process(&mut context, &[withdraw_ix(&keys, 600)], &[&keys.owner])
+        .await
+        .unwrap();
+    assert!(
+        process(&mut context, &[withdraw_ix(&keys, 500)], &[&keys.owner])
+            .await
+            .is_err()
+    );
+    process(&mut context, &[withdraw_ix(&keys, 400)], &[&keys.owner])
+        .await
+        .unwrap();
+
+    set_time(&mut context, NOW + WINDOW).await;
+    process(&mut context, &[withdraw_ix(&keys, 1_000)], &[&keys.owner])
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn loosening_waits_for_window_end() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    process(
+        &mut context,
+        &[deposit_ix(&keys, 1_000_000)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    set_time(&mut context, NOW).await;
+    process(
+        &mut context,
+        &[set_withdraw_limit_ix(&keys, 1_000, WINDOW)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    // Tightening applies immediately.
+    process(
+        &mut context,
+        &[set_withdraw_limit_ix(&keys, 500, WINDOW)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    assert!(
+        process(&mut context, &[withdraw_ix(&keys, 600)], &[&keys.owner])
+            .await
+            .is_err()
+    );
+
+    // Removing the limit is parked until the window ends.
+    process(
+        &mut context,
+        &[set_withdraw_limit_ix(&keys, 0, 0)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    let limit = vault_state(&mut context, &keys).await.sol_limit;
+    assert_eq!(limit.max_amount, 500);
+    assert_eq!(limit.pending(), Some((0, 0)));
+    assert!(
+        process(&mut context, &[withdraw_ix(&keys, 600)], &[&keys.owner])
+            .await
+            .is_err()
+    );
+
+    set_time(&mut context, NOW + WINDOW).await;
+    process(&mut context, &[withdraw_ix(&keys, 600_000)], &[&keys.owner])
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn mint_limit_is_required_once_configured() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let payer = context.payer.pubkey();
+    let mint = create_mint(&mut context, &payer, 0).await;
+    let other_mint = create_mint(&mut context, &payer, 0).await;
+    let vault_ata = create_ata(&mut context, &keys.vault_auth, &mint).await;
+    let owner_ata = create_ata(&mut context, &keys.owner.pubkey(), &mint).await;
+    let other_vault_ata = create_ata(&mut context, &keys.vault_auth, &other_mint).await;
+    let other_owner_ata = create_ata(&mut context, &keys.owner.pubkey(), &other_mint).await;
+    mint_to(&mut context, &mint, &vault_ata, 1_000).await;
+    mint_to(&mut context, &other_mint, &other_vault_ata, 1_000).await;
+    process(
+        &mut context,
+        &[
+            reconcile_ix(&keys, &mint, true),
+            reconcile_ix(&keys, &other_mint, true),
+        ],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    set_time(&mut context, NOW).await;
+    process(
+        &mut context,
+        &[set_mint_withdraw_limit_ix(&keys, &mint, 100, WINDOW)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    assert_eq!(vault_state(&mut context, &keys).await.mint_limit_count, 1);
+
+    // Omitting the limit account no longer works for any mint.
+    assert!(process(
+        &mut context,
+        &[limited_withdraw_spl_ix(
+            &keys, &owner_ata, &vault_ata, &mint, 10, false
+        )],
+        &[&keys.owner],
+    )
+    .await
+    .is_err());
+    assert!(process(
+        &mut context,
+        &[limited_withdraw_spl_ix(
+            &keys,
+            &other_owner_ata,
+            &other_vault_ata,
+            &other_mint,
+            10,
+            false
+        )],
+        &[&keys.owner],
+    )
+    .await
+    .is_err());
+
+    // A mint without a limit PDA is unrestricted.
+    process(
+        &mut context,
+        &[limited_withdraw_spl_ix(
+            &keys,
+            &other_owner_ata,
+            &other_vault_ata,
+            &other_mint,
+            500,
+            true,
+        )],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    process(
+        &mut context,
+        &[limited_withdraw_spl_ix(
+            &keys, &owner_ata, &vault_ata, &mint, 100, true,
+        )],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    assert!(process(
+        &mut context,
+        &[limited_withdraw_spl_ix(
+            &keys, &owner_ata, &vault_ata, &mint, 1, true
+        )],
+        &[&keys.owner],
+    )
+    .await
+    .is_err());
+    assert_eq!(token_balance(&mut context, &owner_ata).await, 100);
+}
@@ -0,0 +1,204 @@
+mod common;
+
+use borsh::BorshSerialize;
+use common::*;
+use solana_program::{
+    clock::Clock,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    rent::Rent,
+    system_program,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::{
+    account::{Account, AccountSharedData},
+    signature::Signer,
+};
+use wba_vault_program::{id, Vault, WbaVaultError, WbaVaultInstruction};
+
+const NOW: i64 = 10_000;
+
+fn set_lock_ix(keys: &VaultKeys, locked_until: i64, locked_until_epoch: u64) -> Instruction {
+    ix(
+        WbaVaultInstruction::SetLock {
+            locked_until,
+            locked_until_epoch,
+        },
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+async fn set_time(context: &mut ProgramTestContext, unix_timestamp: i64) {
+    let mut clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    clock.unix_timestamp = unix_timestamp;
+    context.set_sysvar(&clock);
+}
+
+#[tokio::test]
+async fn lock_blocks_withdraw_until_expiry() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    process(
+        &mut context,
+        &[deposit_ix(&keys, 1_000_000)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    set_time(&mut context, NOW).await;
+    process(
+        &mut context,
+        &[set_lock_ix(&keys, NOW + 100, 0)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    assert!(
+        process(&mut context, &[withdraw_ix(&keys, 1)], &[&keys.owner])
+            .await
+            .is_err()
+    );
+
+    // Extending is allowed while locked, shortening is not.
+    process(
+        &mut context,
+        &[set_lock_ix(&keys, NOW + 200, 0)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    assert!(process(
+        &mut context,
+        &[set_lock_ix(&keys, NOW + 150, 0)],
+        &[&keys.owner],
+    )
+    .await
+    .is_err());
+
+    set_time(&mut context, NOW + 200).await;
+    process(&mut context, &[withdraw_ix(&keys, 1)], &[&keys.owner])
+        .await
+        .unwrap();
+
+    // Once expired the lock can be cleared.
+    process(&mut context, &[set_lock_ix(&keys, 0, 0)], &[&keys.owner])
+        .await
+        .unwrap();
+    assert_eq!(vault_state(&mut context, &keys).await.locked_until, 0);
+}
+
+#[tokio::test]
+async fn locked_vaults_cannot_be_closed() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    set_time(&mut context, NOW).await;
+    process(
+        &mut context,
+        &[set_lock_ix(&keys, NOW + 100, 0)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    // Closing and re-initializing would otherwise drop the lock.
+    let result = process(&mut context, &[close_account_ix(&keys)], &[&keys.owner]).await;
+    assert_vault_error(result, 0, WbaVaultError::VaultLocked);
+    assert!(account_exists(&mut context, &keys.vault_state.pubkey()).await);
+
+    set_time(&mut context, NOW + 100).await;
+    process(&mut context, &[close_account_ix(&keys)], &[&keys.owner])
+        .await
+        .unwrap();
+    assert!(!account_exists(&mut context, &keys.vault_state.pubkey()).await);
+}
+
+#[tokio::test]
+async fn epoch_lock_blocks_withdraw() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    process(
+        &mut context,
+        &[deposit_ix(&keys, 1_000_000)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    let epoch = context
+        .banks_client
+        .get_sysvar::<Clock>()
+        .await
+        .unwrap()
+        .epoch;
+    process(
+        &mut context,
+        &[set_lock_ix(&keys, 0, epoch + 1)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    assert!(
+        process(&mut context, &[withdraw_ix(&keys, 1)], &[&keys.owner])
+            .await
+            .is_err()
+    );
+
+    context.warp_to_epoch(epoch + 1).unwrap();
+    process(&mut context, &[withdraw_ix(&keys, 1)], &[&keys.owner])
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn legacy_vault_state_is_grown_on_write() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+
+    // Rewrite the state with the original 35-byte layout.
+    let (_, auth_bump) =
+        Pubkey::find_program_address(&[b"auth", keys.vault_state.pubkey().as_ref()], &id());
+    let (_, vault_bump) =
+        Pubkey::find_program_address(&[b"vault", keys.vault_auth.as_ref()], &id());
+    let mut data = Vec::new();
+    keys.owner.pubkey().serialize(&mut data).unwrap();
+    data.extend_from_slice(&[auth_bump, vault_bump, 0]);
+    let legacy = Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: id(),
+        executable: false,
+        rent_epoch: 0,
+    };
+    context.set_account(&keys.vault_state.pubkey(), &AccountSharedData::from(legacy));
+
+    process(&mut context, &[deposit_ix(&keys, 1_000)], &[&keys.owner])
+        .await
+        .unwrap();
+
+    set_time(&mut context, NOW).await;
+    process(
+        &mut context,
+        &[set_lock_ix(&keys, NOW + 1, 0)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    let account = context
+        .banks_client
+        .get_account(keys.vault_state.pubkey())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.data.len(), Vault::space());
+    assert_eq!(
+        account.lamports,
+        Rent::default().minimum_balance(Vault::space())
+    );
+    assert_eq!(Vault::unpack(&account.data).unwrap().locked_until, NOW + 1);
+}
@@ -0,0 +1,171 @@
+mod common;
+
+use common::*;
+use solana_program::instruction::InstructionError;
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+
+#[tokio::test]
+async fn withdraw_needs_threshold_of_distinct_signers() {
+    let mut context = program_test().start_with_context().await;
+    let (keys, [a, b, c]) = setup_multisig(&mut context).await;
+
+    let state = vault_state(&mut context, &keys).await;
+    assert_eq!(state.threshold, 2);
+    assert_eq!(state.signers().len(), 3);
+
+    // The creating key is no longer a member.
+    assert!(
+        process(&mut context, &[withdraw_ix(&keys, 1)], &[&keys.owner])
+            .await
+            .is_err()
+    );
+
+    // One member alone, or the same member twice, is not enough.
+    assert!(process(
+        &mut context,
+        &[signed_by(withdraw_ix(&keys, 1), &a, &[])],
+        &[&a],
+    )
+    .await
+    .is_err());
+    assert!(process(
+        &mut context,
+        &[signed_by(withdraw_ix(&keys, 1), &a, &[&a])],
+        &[&a],
+    )
+    .await
+    .is_err());
+
+    // Co-signers outside the member list do not count either.
+    let outsider = Keypair::new();
+    assert!(process(
+        &mut context,
+        &[signed_by(withdraw_ix(&keys, 1), &a, &[&outsider])],
+        &[&a, &outsider],
+    )
+    .await
+    .is_err());
+
+    let before = lamports(&mut context, &a.pubkey()).await;
+    process(
+        &mut context,
+        &[signed_by(withdraw_ix(&keys, 1_000), &a, &[&c])],
+        &[&a, &c],
+    )
+    .await
+    .unwrap();
+    assert_eq!(lamports(&mut context, &a.pubkey()).await, before + 1_000);
+
+    // Deposits stay open to any single member.
+    process(
+        &mut context,
+        &[signed_by(deposit_ix(&keys, 1_000), &b, &[])],
+        &[&b],
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn admin_changes_need_threshold() {
+    let mut context = program_test().start_with_context().await;
+    let (keys, [a, b, _c]) = setup_multisig(&mut context).await;
+
+    // Invalid configurations are rejected.
+    for (signers, threshold) in [
+        (vec![a.pubkey(), b.pubkey()], 3),
+        (vec![a.pubkey(), a.pubkey()], 1),
+        (vec![a.pubkey()], 0),
+    ] {
+        assert!(process(
+            &mut context,
+            &[signed_by(
+                set_multisig_ix(&keys, signers, threshold),
+                &a,
+                &[&b]
+            )],
+            &[&a, &b],
+        )
+        .await
+        .is_err());
+    }
+
+    assert!(process(
+        &mut context,
+        &[signed_by(set_multisig_ix(&keys, vec![], 0), &a, &[])],
+        &[&a],
+    )
+    .await
+    .is_err());
+
+    // Dropping back to single-owner mode hands the vault to the first signer.
+    process(
+        &mut context,
+        &[signed_by(set_multisig_ix(&keys, vec![], 0), &a, &[&b])],
+        &[&a, &b],
+    )
+    .await
+    .unwrap();
+    let state = vault_state(&mut context, &keys).await;
+    assert_eq!(state.owner, a.pubkey());
+    assert!(!state.is_multisig());
+
+    process(
+        &mut context,
+        &[signed_by(withdraw_ix(&keys, 1), &a, &[])],
+        &[&a],
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn cosigners_follow_optional_accounts() {
+    let mut context = program_test().start_with_context().await;
+    let (keys, [a, b, _c]) = setup_multisig(&mut context).await;
+    let payer = context.payer.pubkey();
+    let mint = create_mint(&mut context, &payer, 0).await;
+    let vault_ata = create_ata(&mut context, &keys.vault_auth, &mint).await;
+    let owner_ata = create_ata(&mut context, &a.pubkey(), &mint).await;
+    mint_to(&mut context, &mint, &vault_ata, 100).await;
+
+    // The optional mint limit account is omitted; the trailing co-signer must
+    // not be mistaken for it.
+    process(
+        &mut context,
+        &[signed_by(
+            withdraw_spl_ix(&keys, &owner_ata, &vault_ata, &mint, 40),
+            &a,
+            &[&b],
+        )],
+        &[&a, &b],
+    )
+    .await
+    .unwrap();
+    assert_eq!(token_balance(&mut context, &owner_ata).await, 40);
+}
+
+#[tokio::test]
+async fn the_creator_cannot_reinitialize_away_the_members() {
+    let mut context = program_test().start_with_context().await;
+    let (keys, _members) = setup_multisig(&mut context).await;
+
+    // The creating key still holds the vault_state keypair.
+    let result = process(
+        &mut context,
+        &[initialize_ix(&keys)],
+        &[&keys.owner, &keys.vault_state],
+    )
+    .await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::AccountAlreadyInitialized),
+    );
+
+    let state = vault_state(&mut context, &keys).await;
+    assert_eq!(state.threshold, 2);
+    assert_eq!(state.signers().len(), 3);
+}
@@ -0,0 +1,221 @@
+mod common;
+
+use common::*;
+use solana_program::{
+    clock::Clock,
+    ed25519_program,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program, sysvar,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::{Keypair, Signer};
+use spl_associated_token_account::get_associated_token_address;
+use wba_vault_program::{Permit, WbaVaultInstruction};
+
+const NOW: i64 = 10_000;
+
+/// Ed25519 program instruction verifying `signer`'s signature over `message`,
+/// with all data inline.
+fn ed25519_ix(signer: &Keypair, message: &[u8]) -> Instruction {
+    let public_key_offset: u16 = 2 + 14;
+    let signature_offset = public_key_offset + 32;
+    let message_offset = signature_offset + 64;
+
+    let mut data = vec![1u8, 0];
+    for value in [
+        signature_offset,
+        u16::MAX,
+        public_key_offset,
+        u16::MAX,
+        message_offset,
+        message.len() as u16,
+        u16::MAX,
+    ] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(signer.pubkey().as_ref());
+    data.extend_from_slice(signer.sign_message(message).as_ref());
+    data.extend_from_slice(message);
+
+    Instruction {
+        program_id: ed25519_program::id(),
+        accounts: vec![],
+        data,
+    }
+}
+
+fn permit(keys: &VaultKeys, mint: Pubkey, amount: u64, recipient: &Pubkey, nonce: u64) -> Permit {
+    Permit {
+        vault_state: keys.vault_state.pubkey(),
+        mint,
+        amount,
+        recipient: *recipient,
+        nonce,
+        expires_at: NOW + 100,
+    }
+}
+
+fn withdraw_with_permit_ix(keys: &VaultKeys, relayer: &Pubkey, permit: Permit) -> Instruction {
+    let mint = permit.mint;
+    let recipient = permit.recipient;
+    let mut accounts = vec![
+        AccountMeta::new(*relayer, true),
+        AccountMeta::new(keys.vault_state.pubkey(), false),
+        AccountMeta::new_readonly(keys.vault_auth, false),
+        AccountMeta::new(keys.vault, false),
+        AccountMeta::new(recipient, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::instructions::id(), false),
+    ];
+    if mint != Pubkey::default() {
+        accounts.extend([
+            AccountMeta::new(get_associated_token_address(&keys.vault_auth, &mint), false),
+            AccountMeta::new(get_associated_token_address(&recipient, &mint), false),
+            AccountMeta::new_readonly(mint, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ]);
+    }
+    ix(WbaVaultInstruction::WithdrawWithPermit { permit }, accounts)
+}
+
+/// The signature check followed by the withdrawal, as a relayer submits it.
+fn signed_permit_ixs(
+    keys: &VaultKeys,
+    signer: &Keypair,
+    permit: Permit,
+    relayer: &Pubkey,
+) -> Vec<Instruction> {
+    vec![
+        ed25519_ix(signer, &permit.message()),
+        withdraw_with_permit_ix(keys, relayer, permit),
+    ]
+}
+
+async fn set_time(context: &mut ProgramTestContext, unix_timestamp: i64) {
+    let mut clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    clock.unix_timestamp = unix_timestamp;
+    context.set_sysvar(&clock);
+}
+
+#[tokio::test]
+async fn relayer_submits_owner_permit_once() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let recipient = Keypair::new().pubkey();
+    let relayer = context.payer.pubkey();
+    let sol = Pubkey::default();
+    let fund = fund_ix(&context, &recipient);
+    set_time(&mut context, NOW).await;
+    process(
+        &mut context,
+        &[fund, deposit_ix(&keys, 1_000_000)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    // Only the context payer signs: the owner never sends a transaction.
+    let ixs = signed_permit_ixs(
+        &keys,
+        &keys.owner,
+        permit(&keys, sol, 5_000, &recipient, 0),
+        &relayer,
+    );
+    let before = lamports(&mut context, &recipient).await;
+    process(&mut context, &ixs, &[]).await.unwrap();
+    assert_eq!(lamports(&mut context, &recipient).await, before + 5_000);
+    assert_eq!(vault_state(&mut context, &keys).await.permit_nonce, 1);
+
+    // Replaying the same permit fails on the nonce.
+    assert!(process(&mut context, &ixs, &[]).await.is_err());
+
+    let next = signed_permit_ixs(
+        &keys,
+        &keys.owner,
+        permit(&keys, sol, 5_000, &recipient, 1),
+        &relayer,
+    );
+    process(&mut context, &next, &[]).await.unwrap();
+    assert_eq!(lamports(&mut context, &recipient).await, before + 10_000);
+}
+
+#[tokio::test]
+async fn rejects_foreign_tampered_and_expired_permits() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let recipient = Keypair::new().pubkey();
+    let relayer = context.payer.pubkey();
+    let sol = Pubkey::default();
+    let fund = fund_ix(&context, &recipient);
+    set_time(&mut context, NOW).await;
+    process(
+        &mut context,
+        &[fund, deposit_ix(&keys, 1_000_000)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    // Signed by someone else.
+    let stranger = Keypair::new();
+    let ixs = signed_permit_ixs(
+        &keys,
+        &stranger,
+        permit(&keys, sol, 1, &recipient, 0),
+        &relayer,
+    );
+    assert!(process(&mut context, &ixs, &[]).await.is_err());
+
+    // Signed by the owner, but the relayer raises the amount.
+    let mut ixs = signed_permit_ixs(
+        &keys,
+        &keys.owner,
+        permit(&keys, sol, 1, &recipient, 0),
+        &relayer,
+    );
+    ixs[1] = withdraw_with_permit_ix(&keys, &relayer, permit(&keys, sol, 500_000, &recipient, 0));
+    assert!(process(&mut context, &ixs, &[]).await.is_err());
+
+    // Without the Ed25519 instruction at all.
+    let ixs = vec![withdraw_with_permit_ix(
+        &keys,
+        &relayer,
+        permit(&keys, sol, 1, &recipient, 0),
+    )];
+    assert!(process(&mut context, &ixs, &[]).await.is_err());
+
+    // Past its expiry.
+    set_time(&mut context, NOW + 100).await;
+    let ixs = signed_permit_ixs(
+        &keys,
+        &keys.owner,
+        permit(&keys, sol, 1, &recipient, 0),
+        &relayer,
+    );
+    assert!(process(&mut context, &ixs, &[]).await.is_err());
+    assert_eq!(vault_state(&mut context, &keys).await.permit_nonce, 0);
+}
+
+#[tokio::test]
+async fn token_permit() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let recipient = Keypair::new().pubkey();
+    let relayer = context.payer.pubkey();
+    let mint = create_mint(&mut context, &relayer, 6).await;
+    let vault_ata = create_ata(&mut context, &keys.vault_auth, &mint).await;
+    let recipient_ata = create_ata(&mut context, &recipient, &mint).await;
+    mint_to(&mut context, &mint, &vault_ata, 1_000).await;
+    set_time(&mut context, NOW).await;
+
+    let ixs = signed_permit_ixs(
+        &keys,
+        &keys.owner,
+        permit(&keys, mint, 250, &recipient, 0),
+        &relayer,
+    );
+    process(&mut context, &ixs, &[]).await.unwrap();
+    assert_eq!(token_balance(&mut context, &recipient_ata).await, 250);
+    assert_eq!(token_balance(&mut context, &vault_ata).await, 750);
+}
@@ -0,0 +1,260 @@
+mod common;
+
+use common::*;
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_instruction, system_program,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::{Keypair, Signer};
+use spl_associated_token_account::get_associated_token_address;
+use wba_vault_program::{id, WbaVaultInstruction};
+
+struct PoolKeys {
+    mint: Pubkey,
+    pool: Pubkey,
+    share_mint: Pubkey,
+}
+
+impl PoolKeys {
+    fn new(keys: &VaultKeys, mint: Pubkey) -> Self {
+        let pool = Pubkey::find_program_address(
+            &[b"pool", keys.vault_state.pubkey().as_ref(), mint.as_ref()],
+            &id(),
+        )
+        .0;
+        let share_mint = Pubkey::find_program_address(&[b"shares", pool.as_ref()], &id()).0;
+        Self {
+            mint,
+            pool,
+            share_mint,
+        }
+    }
+
+    fn is_sol(&self) -> bool {
+        self.mint == Pubkey::default()
+    }
+}
+
+fn create_pool_ix(keys: &VaultKeys, pool: &PoolKeys) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(keys.owner.pubkey(), true),
+        AccountMeta::new(keys.vault_state.pubkey(), false),
+        AccountMeta::new_readonly(keys.vault_auth, false),
+        AccountMeta::new(pool.pool, false),
+        AccountMeta::new(pool.share_mint, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    if !pool.is_sol() {
+        accounts.push(AccountMeta::new_readonly(pool.mint, false));
+    }
+    ix(
+        WbaVaultInstruction::CreatePool { mint: pool.mint },
+        accounts,
+    )
+}
+
+/// `DepositToPool` and `RedeemShares` take the same accounts.
+fn pool_ix(
+    keys: &VaultKeys,
+    pool: &PoolKeys,
+    user: &Pubkey,
+    data: WbaVaultInstruction,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*user, true),
+        AccountMeta::new(keys.vault_state.pubkey(), false),
+        AccountMeta::new_readonly(keys.vault_auth, false),
+        AccountMeta::new(keys.vault, false),
+        AccountMeta::new(pool.pool, false),
+        AccountMeta::new(pool.share_mint, false),
+        AccountMeta::new(get_associated_token_address(user, &pool.share_mint), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    if !pool.is_sol() {
+        let user_ata = get_associated_token_address(user, &pool.mint);
+        let vault_ata = get_associated_token_address(&keys.vault_auth, &pool.mint);
+        let (first, second) = match data {
+            WbaVaultInstruction::DepositToPool { .. } => (user_ata, vault_ata),
+            _ => (vault_ata, user_ata),
+        };
+        accounts.push(AccountMeta::new(first, false));
+        accounts.push(AccountMeta::new(second, false));
+    }
+    ix(data, accounts)
+}
+
+async fn depositor(context: &mut ProgramTestContext, pool: &PoolKeys) -> (Keypair, Pubkey) {
+    let user = Keypair::new();
+    let fund = fund_ix(context, &user.pubkey());
+    process(context, &[fund], &[]).await.unwrap();
+    let shares = create_ata(context, &user.pubkey(), &pool.share_mint).await;
+    (user, shares)
+}
+
+async fn setup_sol_pool(context: &mut ProgramTestContext) -> (VaultKeys, PoolKeys) {
+    let keys = setup_vault(context).await;
+    let pool = PoolKeys::new(&keys, Pubkey::default());
+    process(context, &[create_pool_ix(&keys, &pool)], &[&keys.owner])
+        .await
+        .unwrap();
+    (keys, pool)
+}
+
+#[tokio::test]
+async fn sol_pool_mints_and_redeems_pro_rata() {
+    let mut context = program_test().start_with_context().await;
+    let (keys, pool) = setup_sol_pool(&mut context).await;
+    assert_eq!(vault_state(&mut context, &keys).await.pool_count, 1);
+    let (alice, alice_shares) = depositor(&mut context, &pool).await;
+    let (bob, bob_shares) = depositor(&mut context, &pool).await;
+
+    let deposit = |user: &Keypair, amount| {
+        pool_ix(
+            &keys,
+            &pool,
+            &user.pubkey(),
+            WbaVaultInstruction::DepositToPool { amount },
+        )
+    };
+    process(&mut context, &[deposit(&alice, 1_000_000)], &[&alice])
+        .await
+        .unwrap();
+    process(&mut context, &[deposit(&bob, 500_000)], &[&bob])
+        .await
+        .unwrap();
+    assert_eq!(
+        token_balance(&mut context, &alice_shares).await,
+        1_000_000_000
+    );
+    assert_eq!(token_balance(&mut context, &bob_shares).await, 500_000_000);
+
+    let before = lamports(&mut context, &alice.pubkey()).await;
+    let redeem = pool_ix(
+        &keys,
+        &pool,
+        &alice.pubkey(),
+        WbaVaultInstruction::RedeemShares {
+            shares: 1_000_000_000,
+        },
+    );
+    process(&mut context, &[redeem], &[&alice]).await.unwrap();
+    assert_eq!(token_balance(&mut context, &alice_shares).await, 0);
+    assert_eq!(
+        lamports(&mut context, &alice.pubkey()).await,
+        before + 1_000_000
+    );
+    let stats = vault_state(&mut context, &keys).await.stats();
+    assert_eq!(stats.total_sol_deposited, 1_500_000);
+    assert_eq!(stats.total_sol_withdrawn, 1_000_000);
+    assert_eq!((stats.deposit_count, stats.withdraw_count), (2, 1));
+
+    // The owner can no longer take the depositors' funds.
+    let result = process(&mut context, &[withdraw_ix(&keys, 1)], &[&keys.owner]).await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn donations_do_not_move_the_share_price() {
+    let mut context = program_test().start_with_context().await;
+    let (keys, pool) = setup_sol_pool(&mut context).await;
+    let (attacker, attacker_shares) = depositor(&mut context, &pool).await;
+    let (victim, victim_shares) = depositor(&mut context, &pool).await;
+
+    // The classic attack: be first with one unit, then inflate the price by
+    // sending funds straight to the vault.
+    let first = pool_ix(
+        &keys,
+        &pool,
+        &attacker.pubkey(),
+        WbaVaultInstruction::DepositToPool { amount: 1 },
+    );
+    let donation = system_instruction::transfer(&attacker.pubkey(), &keys.vault, 1_000_000_000);
+    process(&mut context, &[first, donation], &[&attacker])
+        .await
+        .unwrap();
+    assert_eq!(token_balance(&mut context, &attacker_shares).await, 1_000);
+
+    let deposit = pool_ix(
+        &keys,
+        &pool,
+        &victim.pubkey(),
+        WbaVaultInstruction::DepositToPool { amount: 1_000_000 },
+    );
+    process(&mut context, &[deposit], &[&victim]).await.unwrap();
+    assert_eq!(
+        token_balance(&mut context, &victim_shares).await,
+        1_000_000_000
+    );
+
+    // Redemptions round down, so a share too small to be worth a lamport
+    // buys nothing.
+    let dust = pool_ix(
+        &keys,
+        &pool,
+        &attacker.pubkey(),
+        WbaVaultInstruction::RedeemShares { shares: 1 },
+    );
+    let result = process(&mut context, &[dust], &[&attacker]).await;
+    assert!(result.is_err());
+
+    let before = lamports(&mut context, &attacker.pubkey()).await;
+    let redeem = pool_ix(
+        &keys,
+        &pool,
+        &attacker.pubkey(),
+        WbaVaultInstruction::RedeemShares { shares: 1_000 },
+    );
+    process(&mut context, &[redeem], &[&attacker])
+        .await
+        .unwrap();
+    assert_eq!(lamports(&mut context, &attacker.pubkey()).await, before + 1);
+}
+
+#[tokio::test]
+async fn spl_pool_custodies_in_the_vault_ata() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let payer = context.payer.pubkey();
+    let mint = create_mint(&mut context, &payer, 6).await;
+    let vault_ata = create_ata(&mut context, &keys.vault_auth, &mint).await;
+    let pool = PoolKeys::new(&keys, mint);
+    process(
+        &mut context,
+        &[create_pool_ix(&keys, &pool)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    let (user, user_shares) = depositor(&mut context, &pool).await;
+    let user_ata = create_ata(&mut context, &user.pubkey(), &mint).await;
+    mint_to(&mut context, &mint, &user_ata, 300).await;
+    let deposit = pool_ix(
+        &keys,
+        &pool,
+        &user.pubkey(),
+        WbaVaultInstruction::DepositToPool { amount: 300 },
+    );
+    process(&mut context, &[deposit], &[&user]).await.unwrap();
+    assert_eq!(token_balance(&mut context, &vault_ata).await, 300);
+    assert_eq!(token_balance(&mut context, &user_shares).await, 300_000);
+
+    let owner_ata = create_ata(&mut context, &keys.owner.pubkey(), &mint).await;
+    let withdraw = withdraw_spl_ix(&keys, &owner_ata, &vault_ata, &mint, 1);
+    let result = process(&mut context, &[withdraw], &[&keys.owner]).await;
+    assert!(result.is_err());
+
+    let redeem = pool_ix(
+        &keys,
+        &pool,
+        &user.pubkey(),
+        WbaVaultInstruction::RedeemShares { shares: 100_000 },
+    );
+    process(&mut context, &[redeem], &[&user]).await.unwrap();
+    assert_eq!(token_balance(&mut context, &user_ata).await, 100);
+    assert_eq!(token_balance(&mut context, &user_shares).await, 200_000);
+}
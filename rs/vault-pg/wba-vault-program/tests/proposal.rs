@@ -0,0 +1,319 @@
+mod common;
+
+use common::*;
+use solana_program::{
+    clock::Clock,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::{Keypair, Signer};
+use spl_associated_token_account::get_associated_token_address;
+use wba_vault_program::{id, ProposalAction, WbaVaultInstruction};
+
+const NOW: i64 = 10_000;
+const EXPIRES_AT: i64 = NOW + 3_600;
+
+fn proposal_pda(keys: &VaultKeys, seed: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"proposal",
+            keys.vault_state.pubkey().as_ref(),
+            &seed.to_le_bytes(),
+        ],
+        &id(),
+    )
+    .0
+}
+
+fn create_proposal_ix(
+    keys: &VaultKeys,
+    proposer: &Keypair,
+    action: ProposalAction,
+    recipient: Pubkey,
+    seed: u64,
+) -> Instruction {
+    ix(
+        WbaVaultInstruction::CreateProposal {
+            action,
+            recipient,
+            expires_at: EXPIRES_AT,
+            seed,
+        },
+        vec![
+            AccountMeta::new(proposer.pubkey(), true),
+            AccountMeta::new_readonly(keys.vault_state.pubkey(), false),
+            AccountMeta::new(proposal_pda(keys, seed), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+fn approve_ix(keys: &VaultKeys, member: &Keypair, seed: u64) -> Instruction {
+    ix(
+        WbaVaultInstruction::Approve,
+        vec![
+            AccountMeta::new_readonly(member.pubkey(), true),
+            AccountMeta::new_readonly(keys.vault_state.pubkey(), false),
+            AccountMeta::new(proposal_pda(keys, seed), false),
+        ],
+    )
+}
+
+fn execute_ix(
+    keys: &VaultKeys,
+    executor: &Pubkey,
+    proposer: &Pubkey,
+    recipient: &Pubkey,
+    seed: u64,
+    mint: Option<Pubkey>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*executor, true),
+        AccountMeta::new(keys.vault_state.pubkey(), false),
+        AccountMeta::new_readonly(keys.vault_auth, false),
+        AccountMeta::new(keys.vault, false),
+        AccountMeta::new(proposal_pda(keys, seed), false),
+        AccountMeta::new(*proposer, false),
+        AccountMeta::new(*recipient, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    if let Some(mint) = mint {
+        accounts.extend([
+            AccountMeta::new(get_associated_token_address(&keys.vault_auth, &mint), false),
+            AccountMeta::new(get_associated_token_address(recipient, &mint), false),
+            AccountMeta::new_readonly(mint, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ]);
+    }
+    ix(WbaVaultInstruction::Execute, accounts)
+}
+
+fn cancel_proposal_ix(
+    keys: &VaultKeys,
+    signer: &Keypair,
+    proposer: &Pubkey,
+    seed: u64,
+) -> Instruction {
+    ix(
+        WbaVaultInstruction::CancelProposal,
+        vec![
+            AccountMeta::new_readonly(signer.pubkey(), true),
+            AccountMeta::new_readonly(keys.vault_state.pubkey(), false),
+            AccountMeta::new(proposal_pda(keys, seed), false),
+            AccountMeta::new(*proposer, false),
+        ],
+    )
+}
+
+async fn set_time(context: &mut ProgramTestContext, unix_timestamp: i64) {
+    let mut clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    clock.unix_timestamp = unix_timestamp;
+    context.set_sysvar(&clock);
+}
+
+#[tokio::test]
+async fn sol_proposal_executes_after_threshold() {
+    let mut context = program_test().start_with_context().await;
+    let (keys, [a, b, c]) = setup_multisig(&mut context).await;
+    let recipient = Keypair::new().pubkey();
+    let payer = context.payer.pubkey();
+    set_time(&mut context, NOW).await;
+
+    let fund = fund_ix(&context, &recipient);
+    process(
+        &mut context,
+        &[
+            fund,
+            create_proposal_ix(
+                &keys,
+                &a,
+                ProposalAction::WithdrawSol { amount: 5_000 },
+                recipient,
+                0,
+            ),
+        ],
+        &[&a],
+    )
+    .await
+    .unwrap();
+
+    // The proposer's own approval is not enough, nor can it approve twice.
+    assert!(process(
+        &mut context,
+        &[execute_ix(&keys, &payer, &a.pubkey(), &recipient, 0, None)],
+        &[],
+    )
+    .await
+    .is_err());
+    assert!(process(&mut context, &[approve_ix(&keys, &a, 0)], &[&a])
+        .await
+        .is_err());
+
+    // Outsiders cannot approve.
+    let outsider = Keypair::new();
+    assert!(process(
+        &mut context,
+        &[approve_ix(&keys, &outsider, 0)],
+        &[&outsider]
+    )
+    .await
+    .is_err());
+
+    set_time(&mut context, NOW + 600).await;
+    process(&mut context, &[approve_ix(&keys, &c, 0)], &[&c])
+        .await
+        .unwrap();
+
+    // Any payer may execute once approved.
+    let before = lamports(&mut context, &recipient).await;
+    process(
+        &mut context,
+        &[execute_ix(&keys, &payer, &a.pubkey(), &recipient, 0, None)],
+        &[],
+    )
+    .await
+    .unwrap();
+    assert_eq!(lamports(&mut context, &recipient).await, before + 5_000);
+    assert!(!account_exists(&mut context, &proposal_pda(&keys, 0)).await);
+
+    // Late approvals find nothing to approve.
+    assert!(process(&mut context, &[approve_ix(&keys, &b, 0)], &[&b])
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn expired_proposal_can_only_be_cancelled() {
+    let mut context = program_test().start_with_context().await;
+    let (keys, [a, b, c]) = setup_multisig(&mut context).await;
+    let recipient = b.pubkey();
+    let payer = context.payer.pubkey();
+    set_time(&mut context, NOW).await;
+
+    process(
+        &mut context,
+        &[create_proposal_ix(
+            &keys,
+            &a,
+            ProposalAction::WithdrawSol { amount: 5_000 },
+            recipient,
+            1,
+        )],
+        &[&a],
+    )
+    .await
+    .unwrap();
+
+    // Only the proposer can cancel a live proposal.
+    assert!(process(
+        &mut context,
+        &[cancel_proposal_ix(&keys, &c, &a.pubkey(), 1)],
+        &[&c],
+    )
+    .await
+    .is_err());
+
+    set_time(&mut context, EXPIRES_AT).await;
+    assert!(process(&mut context, &[approve_ix(&keys, &b, 1)], &[&b])
+        .await
+        .is_err());
+    assert!(process(
+        &mut context,
+        &[execute_ix(&keys, &payer, &a.pubkey(), &recipient, 1, None)],
+        &[],
+    )
+    .await
+    .is_err());
+
+    let before = lamports(&mut context, &a.pubkey()).await;
+    process(
+        &mut context,
+        &[cancel_proposal_ix(&keys, &c, &a.pubkey(), 1)],
+        &[&c],
+    )
+    .await
+    .unwrap();
+    assert!(!account_exists(&mut context, &proposal_pda(&keys, 1)).await);
+    assert!(lamports(&mut context, &a.pubkey()).await > before);
+}
+
+#[tokio::test]
+async fn spl_proposal_pays_recipient_ata() {
+    let mut context = program_test().start_with_context().await;
+    let (keys, [a, b, _c]) = setup_multisig(&mut context).await;
+    let recipient = Keypair::new().pubkey();
+    let payer = context.payer.pubkey();
+    let mint = create_mint(&mut context, &payer, 6).await;
+    let vault_ata = create_ata(&mut context, &keys.vault_auth, &mint).await;
+    let recipient_ata = create_ata(&mut context, &recipient, &mint).await;
+    mint_to(&mut context, &mint, &vault_ata, 1_000).await;
+    set_time(&mut context, NOW).await;
+
+    process(
+        &mut context,
+        &[
+            create_proposal_ix(
+                &keys,
+                &a,
+                ProposalAction::WithdrawSpl { mint, amount: 250 },
+                recipient,
+                2,
+            ),
+            approve_ix(&keys, &b, 2),
+        ],
+        &[&a, &b],
+    )
+    .await
+    .unwrap();
+
+    // A multi-decimal mint cannot be moved through an NFT proposal.
+    process(
+        &mut context,
+        &[
+            create_proposal_ix(
+                &keys,
+                &a,
+                ProposalAction::WithdrawNft { mint },
+                recipient,
+                3,
+            ),
+            approve_ix(&keys, &b, 3),
+        ],
+        &[&a, &b],
+    )
+    .await
+    .unwrap();
+    assert!(process(
+        &mut context,
+        &[execute_ix(
+            &keys,
+            &payer,
+            &a.pubkey(),
+            &recipient,
+            3,
+            Some(mint)
+        )],
+        &[],
+    )
+    .await
+    .is_err());
+
+    process(
+        &mut context,
+        &[execute_ix(
+            &keys,
+            &payer,
+            &a.pubkey(),
+            &recipient,
+            2,
+            Some(mint),
+        )],
+        &[],
+    )
+    .await
+    .unwrap();
+    assert_eq!(token_balance(&mut context, &recipient_ata).await, 250);
+    assert_eq!(token_balance(&mut context, &vault_ata).await, 750);
+}
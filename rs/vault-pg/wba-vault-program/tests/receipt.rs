@@ -0,0 +1,354 @@
+mod common;
+
+use borsh::BorshDeserialize;
+use common::*;
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::{Keypair, Signer};
+use spl_associated_token_account::get_associated_token_address;
+use wba_vault_program::{id, WbaVaultError, WbaVaultInstruction};
+
+fn receipt_mint(keys: &VaultKeys, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"receipt",
+            keys.vault_state.pubkey().as_ref(),
+            mint.as_ref(),
+        ],
+        &id(),
+    )
+    .0
+}
+
+async fn read_metadata(context: &mut ProgramTestContext, mint: &Pubkey) -> MetadataRecord {
+    let account = context
+        .banks_client
+        .get_account(metadata_address(mint))
+        .await
+        .unwrap()
+        .unwrap();
+    MetadataRecord::deserialize(&mut &account.data[..]).unwrap()
+}
+
+fn enable_receipts_ix(keys: &VaultKeys) -> Instruction {
+    ix(
+        WbaVaultInstruction::EnableReceipts,
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new_readonly(keys.vault, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+fn create_receipt_mint_ix(keys: &VaultKeys, mint: Pubkey) -> Instruction {
+    let receipt_mint = receipt_mint(keys, &mint);
+    let mut accounts = vec![
+        AccountMeta::new(keys.owner.pubkey(), true),
+        AccountMeta::new_readonly(keys.vault_state.pubkey(), false),
+        AccountMeta::new_readonly(keys.vault_auth, false),
+        AccountMeta::new(receipt_mint, false),
+        AccountMeta::new(metadata_address(&receipt_mint), false),
+        AccountMeta::new_readonly(METADATA_PROGRAM_ID, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    if mint != Pubkey::default() {
+        accounts.push(AccountMeta::new_readonly(mint, false));
+        accounts.push(AccountMeta::new_readonly(metadata_address(&mint), false));
+    }
+    ix(WbaVaultInstruction::CreateReceiptMint { mint }, accounts)
+}
+
+/// Appends the receipt mint, the owner's receipt ATA and the token program.
+fn with_receipts(mut ix: Instruction, keys: &VaultKeys, mint: &Pubkey) -> Instruction {
+    let receipt_mint = receipt_mint(keys, mint);
+    ix.accounts.extend([
+        AccountMeta::new(receipt_mint, false),
+        AccountMeta::new(
+            get_associated_token_address(&keys.owner.pubkey(), &receipt_mint),
+            false,
+        ),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ]);
+    ix
+}
+
+async fn setup_receipts(context: &mut ProgramTestContext, mint: Pubkey) -> (VaultKeys, Pubkey) {
+    let keys = setup_vault(context).await;
+    process(
+        context,
+        &[
+            enable_receipts_ix(&keys),
+            create_receipt_mint_ix(&keys, mint),
+        ],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    let receipts = create_ata(context, &keys.owner.pubkey(), &receipt_mint(&keys, &mint)).await;
+    (keys, receipts)
+}
+
+#[tokio::test]
+async fn sol_receipts_follow_deposits_and_withdrawals() {
+    let mut context = program_test().start_with_context().await;
+    let sol = Pubkey::default();
+    let (keys, receipts) = setup_receipts(&mut context, sol).await;
+    assert!(vault_state(&mut context, &keys).await.receipts.get());
+
+    let (_, update_authority, _, name, symbol, _) =
+        read_metadata(&mut context, &receipt_mint(&keys, &sol)).await;
+    assert_eq!(update_authority, keys.vault_auth);
+    assert_eq!(name, "SOL Vault Receipt");
+    assert_eq!(symbol, "rSOL");
+
+    let deposit = with_receipts(deposit_ix(&keys, 1_000_000), &keys, &sol);
+    process(&mut context, &[deposit], &[&keys.owner])
+        .await
+        .unwrap();
+    assert_eq!(token_balance(&mut context, &receipts).await, 1_000_000);
+
+    // Receipts handed to someone else no longer back the owner's withdrawals.
+    let holder = Keypair::new().pubkey();
+    let holder_receipts = create_ata(&mut context, &holder, &receipt_mint(&keys, &sol)).await;
+    let transfer = spl_token::instruction::transfer(
+        &spl_token::id(),
+        &receipts,
+        &holder_receipts,
+        &keys.owner.pubkey(),
+        &[],
+        400_000,
+    )
+    .unwrap();
+    process(&mut context, &[transfer], &[&keys.owner])
+        .await
+        .unwrap();
+
+    let too_much = with_receipts(withdraw_ix(&keys, 700_000), &keys, &sol);
+    let result = process(&mut context, &[too_much], &[&keys.owner]).await;
+    assert!(result.is_err());
+
+    let before = lamports(&mut context, &keys.owner.pubkey()).await;
+    let withdraw = with_receipts(withdraw_ix(&keys, 600_000), &keys, &sol);
+    process(&mut context, &[withdraw], &[&keys.owner])
+        .await
+        .unwrap();
+    assert_eq!(token_balance(&mut context, &receipts).await, 0);
+    assert_eq!(
+        lamports(&mut context, &keys.owner.pubkey()).await,
+        before + 600_000
+    );
+}
+
+#[tokio::test]
+async fn spl_receipts_take_after_the_underlying_mint() {
+    let mut context = program_test().start_with_context().await;
+    let payer = context.payer.pubkey();
+    let mint = create_mint(&mut context, &payer, 6).await;
+
+    // Underlying metadata, created straight through the metadata program.
+    let mut data = vec![33];
+    data.extend(borsh::to_vec(&("Bonk", "BONK", "https://bonk.example/meta.json", 0u16)).unwrap());
+    data.extend([0, 0, 0, 1, 0]);
+    let create_metadata = Instruction {
+        program_id: METADATA_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(metadata_address(&mint), false),
+            AccountMeta::new_readonly(mint, false),
+            AccountMeta::new_readonly(payer, true),
+            AccountMeta::new(payer, true),
+            AccountMeta::new_readonly(payer, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data,
+    };
+    process(&mut context, &[create_metadata], &[])
+        .await
+        .unwrap();
+
+    let (keys, receipts) = setup_receipts(&mut context, mint).await;
+    let (_, _, _, name, symbol, uri) =
+        read_metadata(&mut context, &receipt_mint(&keys, &mint)).await;
+    assert_eq!(name, "Bonk Vault Receipt");
+    assert_eq!(symbol, "rBONK");
+    assert_eq!(uri, "https://bonk.example/meta.json");
+
+    let owner_ata = create_ata(&mut context, &keys.owner.pubkey(), &mint).await;
+    let vault_ata = create_ata(&mut context, &keys.vault_auth, &mint).await;
+    mint_to(&mut context, &mint, &owner_ata, 500).await;
+    let mut deposit = withdraw_spl_ix(&keys, &owner_ata, &vault_ata, &mint, 0);
+    deposit.data = borsh::to_vec(&WbaVaultInstruction::DepositSpl { amount: 500 }).unwrap();
+    let deposit = with_receipts(deposit, &keys, &mint);
+    process(&mut context, &[deposit], &[&keys.owner])
+        .await
+        .unwrap();
+    assert_eq!(token_balance(&mut context, &receipts).await, 500);
+
+    let mut withdraw = withdraw_spl_ix(&keys, &owner_ata, &vault_ata, &mint, 200);
+    let result = process(&mut context, &[withdraw.clone()], &[&keys.owner]).await;
+    assert!(result.is_err());
+
+    // Trailing accounts go after the optional mint limit PDA.
+    let limit = Pubkey::find_program_address(
+        &[b"limit", keys.vault_state.pubkey().as_ref(), mint.as_ref()],
+        &id(),
+    )
+    .0;
+    withdraw
+        .accounts
+        .insert(MINT_LIMIT_INDEX, AccountMeta::new(limit, false));
+    let withdraw = with_receipts(withdraw, &keys, &mint);
+    process(&mut context, &[withdraw], &[&keys.owner])
+        .await
+        .unwrap();
+    assert_eq!(token_balance(&mut context, &receipts).await, 300);
+    assert_eq!(token_balance(&mut context, &owner_ata).await, 200);
+}
+
+#[tokio::test]
+async fn receipt_vaults_need_receipts_for_every_exit() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    process(&mut context, &[enable_receipts_ix(&keys)], &[&keys.owner])
+        .await
+        .unwrap();
+
+    // No receipt mint for SOL yet, so there is nothing to mint against.
+    let sol = Pubkey::default();
+    let deposit = with_receipts(deposit_ix(&keys, 1_000), &keys, &sol);
+    let result = process(&mut context, &[deposit], &[&keys.owner]).await;
+    assert!(result.is_err());
+
+    let close = ix(
+        WbaVaultInstruction::CloseAccount,
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    let result = process(&mut context, &[close], &[&keys.owner]).await;
+    assert!(result.is_err());
+    assert!(account_exists(&mut context, &keys.vault_state.pubkey()).await);
+}
+
+#[tokio::test]
+async fn receipts_are_only_enabled_on_an_empty_vault() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let payer = context.payer.pubkey();
+    let mint = create_mint(&mut context, &payer, 0).await;
+    let owner_ata = create_ata(&mut context, &keys.owner.pubkey(), &mint).await;
+    let vault_ata = create_ata(&mut context, &keys.vault_auth, &mint).await;
+    mint_to(&mut context, &mint, &owner_ata, 10).await;
+    let spl_ix = |data| {
+        let mut ix = withdraw_spl_ix(&keys, &owner_ata, &vault_ata, &mint, 0);
+        ix.data = borsh::to_vec(&data).unwrap();
+        ix
+    };
+
+    // Anything already inside has no receipts to burn for it.
+    process(&mut context, &[deposit_ix(&keys, 1_000)], &[&keys.owner])
+        .await
+        .unwrap();
+    let result = process(&mut context, &[enable_receipts_ix(&keys)], &[&keys.owner]).await;
+    assert_vault_error(result, 0, WbaVaultError::VaultNotEmpty);
+
+    process(
+        &mut context,
+        &[
+            withdraw_ix(&keys, 1_000),
+            spl_ix(WbaVaultInstruction::DepositSpl { amount: 10 }),
+        ],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    let result = process(&mut context, &[enable_receipts_ix(&keys)], &[&keys.owner]).await;
+    assert_vault_error(result, 0, WbaVaultError::VaultNotEmpty);
+
+    process(
+        &mut context,
+        &[
+            spl_ix(WbaVaultInstruction::WithdrawSpl { amount: 10 }),
+            enable_receipts_ix(&keys),
+        ],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    assert!(vault_state(&mut context, &keys).await.receipts.get());
+}
+
+#[tokio::test]
+async fn nft_withdrawals_burn_receipts_too() {
+    let mut context = program_test().start_with_context().await;
+    let payer = context.payer.pubkey();
+    let mint = create_mint(&mut context, &payer, 0).await;
+    seed_master_edition(&mut context, &mint);
+    let (keys, _receipts) = setup_receipts(&mut context, mint).await;
+    let owner_ata = create_ata(&mut context, &keys.owner.pubkey(), &mint).await;
+    let vault_ata = create_ata(&mut context, &keys.vault_auth, &mint).await;
+    mint_to(&mut context, &mint, &owner_ata, 10).await;
+    let mut deposit = withdraw_spl_ix(&keys, &owner_ata, &vault_ata, &mint, 0);
+    deposit.data = borsh::to_vec(&WbaVaultInstruction::DepositSpl { amount: 10 }).unwrap();
+    let deposit = with_receipts(deposit, &keys, &mint);
+    process(&mut context, &[deposit], &[&keys.owner])
+        .await
+        .unwrap();
+
+    // Fungible deposits cannot be pulled back out one unit at a time.
+    let withdraw = nft_ix(&keys, &mint, WbaVaultInstruction::WithdrawNft);
+    let result = process(&mut context, &[withdraw], &[&keys.owner]).await;
+    assert_vault_error(result, 0, WbaVaultError::InvalidTokenAccount);
+    assert_eq!(token_balance(&mut context, &vault_ata).await, 10);
+
+    // A real NFT deposited as SPL still needs its receipt burned.
+    let nft = create_mint(&mut context, &payer, 0).await;
+    seed_master_edition(&mut context, &nft);
+    let owner_nft_ata = create_ata(&mut context, &keys.owner.pubkey(), &nft).await;
+    let vault_nft_ata = create_ata(&mut context, &keys.vault_auth, &nft).await;
+    mint_to(&mut context, &nft, &owner_nft_ata, 1).await;
+    process(
+        &mut context,
+        &[create_receipt_mint_ix(&keys, nft)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    create_ata(
+        &mut context,
+        &keys.owner.pubkey(),
+        &receipt_mint(&keys, &nft),
+    )
+    .await;
+    let mut deposit = withdraw_spl_ix(&keys, &owner_nft_ata, &vault_nft_ata, &nft, 0);
+    deposit.data = borsh::to_vec(&WbaVaultInstruction::DepositSpl { amount: 1 }).unwrap();
+    let deposit = with_receipts(deposit, &keys, &nft);
+    process(&mut context, &[deposit], &[&keys.owner])
+        .await
+        .unwrap();
+
+    let withdraw = nft_ix(&keys, &nft, WbaVaultInstruction::WithdrawNft);
+    let result = process(
+        &mut context,
+        std::slice::from_ref(&withdraw),
+        &[&keys.owner],
+    )
+    .await;
+    assert_vault_error(result, 0, WbaVaultError::MissingReceiptMint);
+
+    let withdraw = with_receipts(withdraw, &keys, &nft);
+    process(&mut context, &[withdraw], &[&keys.owner])
+        .await
+        .unwrap();
+    assert_eq!(token_balance(&mut context, &owner_nft_ata).await, 1);
+}
@@ -0,0 +1,225 @@
+mod common;
+
+use common::*;
+use solana_program::{
+    clock::Clock,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::{Keypair, Signer};
+use wba_vault_program::WbaVaultInstruction;
+
+const NOW: i64 = 10_000;
+const DELAY: i64 = 3_600;
+
+fn recovery_ix(signer: &Pubkey, keys: &VaultKeys, data: WbaVaultInstruction) -> Instruction {
+    ix(
+        data,
+        vec![
+            AccountMeta::new(*signer, true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+fn set_guardians_ix(
+    keys: &VaultKeys,
+    guardians: &[&Keypair],
+    threshold: u8,
+    delay_secs: i64,
+) -> Instruction {
+    recovery_ix(
+        &keys.owner.pubkey(),
+        keys,
+        WbaVaultInstruction::SetGuardians {
+            guardians: guardians.iter().map(|guardian| guardian.pubkey()).collect(),
+            threshold,
+            delay_secs,
+        },
+    )
+}
+
+fn initiate_ix(
+    keys: &VaultKeys,
+    guardian: &Keypair,
+    cosigners: &[&Keypair],
+    new_owner: Pubkey,
+) -> Instruction {
+    signed_by(
+        recovery_ix(
+            &guardian.pubkey(),
+            keys,
+            WbaVaultInstruction::InitiateRecovery { new_owner },
+        ),
+        guardian,
+        cosigners,
+    )
+}
+
+async fn set_time(context: &mut ProgramTestContext, unix_timestamp: i64) {
+    let mut clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    clock.unix_timestamp = unix_timestamp;
+    context.set_sysvar(&clock);
+}
+
+/// Funded vault with a 2-of-3 guardian set.
+async fn setup_guardians(context: &mut ProgramTestContext) -> (VaultKeys, [Keypair; 3]) {
+    let keys = setup_vault(context).await;
+    let guardians = [Keypair::new(), Keypair::new(), Keypair::new()];
+    set_time(context, NOW).await;
+    process(
+        context,
+        &[
+            deposit_ix(&keys, 1_000_000),
+            set_guardians_ix(
+                &keys,
+                &[&guardians[0], &guardians[1], &guardians[2]],
+                2,
+                DELAY,
+            ),
+        ],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    (keys, guardians)
+}
+
+#[tokio::test]
+async fn guardians_rotate_owner_after_delay() {
+    let mut context = program_test().start_with_context().await;
+    let (keys, [a, _b, c]) = setup_guardians(&mut context).await;
+    let new_owner = Keypair::new();
+    let fund = fund_ix(&context, &new_owner.pubkey());
+
+    process(
+        &mut context,
+        &[fund, initiate_ix(&keys, &a, &[&c], new_owner.pubkey())],
+        &[&a, &c],
+    )
+    .await
+    .unwrap();
+
+    let complete = || {
+        recovery_ix(
+            &new_owner.pubkey(),
+            &keys,
+            WbaVaultInstruction::CompleteRecovery,
+        )
+    };
+    set_time(&mut context, NOW + DELAY - 1).await;
+    assert!(process(&mut context, &[complete()], &[&new_owner])
+        .await
+        .is_err());
+
+    set_time(&mut context, NOW + DELAY).await;
+    process(&mut context, &[complete()], &[&new_owner])
+        .await
+        .unwrap();
+    let state = vault_state(&mut context, &keys).await;
+    assert_eq!(state.owner, new_owner.pubkey());
+    assert!(state.recovery().is_none());
+
+    // The old key is out, the new one can withdraw.
+    assert!(
+        process(&mut context, &[withdraw_ix(&keys, 1)], &[&keys.owner])
+            .await
+            .is_err()
+    );
+    let before = lamports(&mut context, &new_owner.pubkey()).await;
+    process(
+        &mut context,
+        &[signed_by(withdraw_ix(&keys, 1_000), &new_owner, &[])],
+        &[&new_owner],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        lamports(&mut context, &new_owner.pubkey()).await,
+        before + 1_000
+    );
+}
+
+#[tokio::test]
+async fn owner_cancels_within_delay() {
+    let mut context = program_test().start_with_context().await;
+    let (keys, [a, b, _c]) = setup_guardians(&mut context).await;
+    let attacker = Keypair::new().pubkey();
+
+    process(
+        &mut context,
+        &[initiate_ix(&keys, &a, &[&b], attacker)],
+        &[&a, &b],
+    )
+    .await
+    .unwrap();
+    process(
+        &mut context,
+        &[recovery_ix(
+            &keys.owner.pubkey(),
+            &keys,
+            WbaVaultInstruction::CancelRecovery,
+        )],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    set_time(&mut context, NOW + DELAY).await;
+    assert!(process(
+        &mut context,
+        &[recovery_ix(
+            &a.pubkey(),
+            &keys,
+            WbaVaultInstruction::CompleteRecovery
+        )],
+        &[&a],
+    )
+    .await
+    .is_err());
+    assert_eq!(
+        vault_state(&mut context, &keys).await.owner,
+        keys.owner.pubkey()
+    );
+}
+
+#[tokio::test]
+async fn recovery_needs_guardian_threshold() {
+    let mut context = program_test().start_with_context().await;
+    let (keys, [a, _b, _c]) = setup_guardians(&mut context).await;
+    let new_owner = Keypair::new().pubkey();
+
+    // One guardian, the same guardian twice, or an outsider co-signing.
+    let outsider = Keypair::new();
+    assert!(process(
+        &mut context,
+        &[initiate_ix(&keys, &a, &[], new_owner)],
+        &[&a]
+    )
+    .await
+    .is_err());
+    assert!(process(
+        &mut context,
+        &[initiate_ix(&keys, &a, &[&a], new_owner)],
+        &[&a]
+    )
+    .await
+    .is_err());
+    assert!(process(
+        &mut context,
+        &[initiate_ix(&keys, &a, &[&outsider], new_owner)],
+        &[&a, &outsider],
+    )
+    .await
+    .is_err());
+
+    // Guardians cannot be set without a cancel window.
+    let no_delay = set_guardians_ix(&keys, &[&a], 1, 0);
+    assert!(process(&mut context, &[no_delay], &[&keys.owner])
+        .await
+        .is_err());
+    assert!(vault_state(&mut context, &keys).await.recovery().is_none());
+}
@@ -0,0 +1,150 @@
+mod common;
+
+use common::*;
+use solana_program::{
+    entrypoint::MAX_PERMITTED_DATA_INCREASE,
+    instruction::{AccountMeta, Instruction},
+    rent::Rent,
+    system_program,
+};
+use solana_sdk::{
+    account::Account,
+    signature::{Keypair, Signer},
+};
+use wba_vault_program::{Vault, WbaVaultInstruction};
+
+fn resize_ix(keys: &VaultKeys, signer: &Keypair, size: usize) -> Instruction {
+    ix(
+        WbaVaultInstruction::Resize { size: size as u64 },
+        vec![
+            AccountMeta::new(signer.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+async fn state_account(
+    context: &mut solana_program_test::ProgramTestContext,
+    keys: &VaultKeys,
+) -> Account {
+    context
+        .banks_client
+        .get_account(keys.vault_state.pubkey())
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn resize_grows_zeroed_and_rent_exempt() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let owner_before = lamports(&mut context, &keys.owner.pubkey()).await;
+    let size = Vault::space() + 1_000;
+
+    process(
+        &mut context,
+        &[resize_ix(&keys, &keys.owner, size)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    let account = state_account(&mut context, &keys).await;
+    let rent = Rent::default();
+    assert_eq!(account.data.len(), size);
+    assert_eq!(account.lamports, rent.minimum_balance(size));
+    assert!(account.data[Vault::space()..].iter().all(|b| *b == 0));
+    assert_eq!(
+        lamports(&mut context, &keys.owner.pubkey()).await,
+        owner_before - (rent.minimum_balance(size) - rent.minimum_balance(Vault::space()))
+    );
+
+    // The vault keeps working at the new size.
+    process(&mut context, &[deposit_ix(&keys, 1_000)], &[&keys.owner])
+        .await
+        .unwrap();
+    assert_eq!(
+        vault_state(&mut context, &keys).await.owner,
+        keys.owner.pubkey()
+    );
+    assert_eq!(state_account(&mut context, &keys).await.data.len(), size);
+}
+
+#[tokio::test]
+async fn resize_shrinks_and_refunds_the_owner() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let owner_before = lamports(&mut context, &keys.owner.pubkey()).await;
+
+    process(
+        &mut context,
+        &[resize_ix(&keys, &keys.owner, Vault::space() + 1_000)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    process(
+        &mut context,
+        &[resize_ix(&keys, &keys.owner, Vault::space())],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    let account = state_account(&mut context, &keys).await;
+    assert_eq!(account.data.len(), Vault::space());
+    assert_eq!(
+        account.lamports,
+        Rent::default().minimum_balance(Vault::space())
+    );
+    assert_eq!(
+        lamports(&mut context, &keys.owner.pubkey()).await,
+        owner_before
+    );
+}
+
+#[tokio::test]
+async fn resize_rejects_bad_sizes_and_non_members() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+
+    // Smaller than the current layout.
+    assert!(process(
+        &mut context,
+        &[resize_ix(&keys, &keys.owner, Vault::space() - 1)],
+        &[&keys.owner],
+    )
+    .await
+    .is_err());
+
+    // More growth than the runtime allows in one instruction.
+    assert!(process(
+        &mut context,
+        &[resize_ix(
+            &keys,
+            &keys.owner,
+            Vault::space() + MAX_PERMITTED_DATA_INCREASE + 1
+        )],
+        &[&keys.owner],
+    )
+    .await
+    .is_err());
+
+    let stranger = Keypair::new();
+    let fund = fund_ix(&context, &stranger.pubkey());
+    process(&mut context, &[fund], &[]).await.unwrap();
+    assert!(process(
+        &mut context,
+        &[resize_ix(&keys, &stranger, Vault::space() + 1_000)],
+        &[&stranger],
+    )
+    .await
+    .is_err());
+
+    assert_eq!(
+        state_account(&mut context, &keys).await.data.len(),
+        Vault::space()
+    );
+}
@@ -0,0 +1,244 @@
+mod common;
+
+use common::*;
+use solana_program::{
+    clock::Clock,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::Signer;
+use spl_associated_token_account::get_associated_token_address;
+use wba_vault_program::{id, NftStake, WbaVaultError, WbaVaultInstruction, ZeroCopy};
+
+const NOW: i64 = 10_000;
+const DAY: i64 = 86_400;
+
+async fn set_time(context: &mut ProgramTestContext, unix_timestamp: i64) {
+    let mut clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    clock.unix_timestamp = unix_timestamp;
+    context.set_sysvar(&clock);
+}
+
+/// Mints a fresh NFT to the owner and deposits it.
+async fn deposit_nft(context: &mut ProgramTestContext, keys: &VaultKeys) -> Pubkey {
+    let payer = context.payer.pubkey();
+    let mint = create_mint(context, &payer, 0).await;
+    seed_master_edition(context, &mint);
+    let owner_ata = create_ata(context, &keys.owner.pubkey(), &mint).await;
+    create_ata(context, &keys.vault_auth, &mint).await;
+    mint_to(context, &mint, &owner_ata, 1).await;
+    let deposit = nft_ix(keys, &mint, WbaVaultInstruction::DepositNft);
+    process(context, &[deposit], &[&keys.owner]).await.unwrap();
+    mint
+}
+
+fn settle_ix(keys: &VaultKeys) -> Instruction {
+    ix(
+        WbaVaultInstruction::Settle,
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+fn reward_mint() -> Pubkey {
+    Pubkey::find_program_address(&[b"rewards"], &id()).0
+}
+
+fn claim_rewards_ix(keys: &VaultKeys) -> Instruction {
+    ix(
+        WbaVaultInstruction::ClaimRewards,
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new(reward_mint(), false),
+            AccountMeta::new(
+                get_associated_token_address(&keys.owner.pubkey(), &reward_mint()),
+                false,
+            ),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+#[tokio::test]
+async fn score_accrues_per_nft_per_day() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    set_time(&mut context, NOW).await;
+    let first = deposit_nft(&mut context, &keys).await;
+    deposit_nft(&mut context, &keys).await;
+
+    let stake = context
+        .banks_client
+        .get_account(nft_stake_address(&keys, &first))
+        .await
+        .unwrap()
+        .unwrap();
+    let stake = NftStake::read(&stake.data).unwrap();
+    assert_eq!(stake.mint, first);
+    assert_eq!(stake.staked_at, NOW);
+    assert_eq!(vault_state(&mut context, &keys).await.staked_nfts, 2);
+
+    // Closing would orphan the stake records.
+    let result = process(&mut context, &[close_account_ix(&keys)], &[&keys.owner]).await;
+    assert_vault_error(result, 0, WbaVaultError::VaultNotEmpty);
+
+    // Two NFTs for a day and a half: three points, nothing lost to rounding.
+    set_time(&mut context, NOW + DAY + DAY / 2).await;
+    process(&mut context, &[settle_ix(&keys)], &[&keys.owner])
+        .await
+        .unwrap();
+    assert_eq!(vault_state(&mut context, &keys).await.score_points, 3);
+
+    set_time(&mut context, NOW + 2 * DAY).await;
+    let withdraw = nft_ix(&keys, &first, WbaVaultInstruction::WithdrawNft);
+    process(&mut context, &[withdraw], &[&keys.owner])
+        .await
+        .unwrap();
+    assert!(!account_exists(&mut context, &nft_stake_address(&keys, &first)).await);
+    let state = vault_state(&mut context, &keys).await;
+    assert_eq!((state.score_points, state.staked_nfts), (4, 1));
+
+    set_time(&mut context, NOW + 3 * DAY).await;
+    process(&mut context, &[settle_ix(&keys)], &[&keys.owner])
+        .await
+        .unwrap();
+    let state = vault_state(&mut context, &keys).await;
+    assert_eq!((state.score_points, state.score), (5, 5));
+}
+
+#[tokio::test]
+async fn rewards_are_minted_once_per_point() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    set_time(&mut context, NOW).await;
+    deposit_nft(&mut context, &keys).await;
+
+    set_time(&mut context, NOW + 2 * DAY).await;
+    process(&mut context, &[claim_rewards_ix(&keys)], &[&keys.owner])
+        .await
+        .unwrap();
+    let rewards = get_associated_token_address(&keys.owner.pubkey(), &reward_mint());
+    assert_eq!(token_balance(&mut context, &rewards).await, 2_000_000);
+
+    let result = process(&mut context, &[claim_rewards_ix(&keys)], &[&keys.owner]).await;
+    assert!(result.is_err());
+
+    set_time(&mut context, NOW + 3 * DAY).await;
+    process(&mut context, &[claim_rewards_ix(&keys)], &[&keys.owner])
+        .await
+        .unwrap();
+    assert_eq!(token_balance(&mut context, &rewards).await, 3_000_000);
+    assert_eq!(vault_state(&mut context, &keys).await.claimed_points, 3);
+}
+
+#[tokio::test]
+async fn only_nfts_with_stake_records_score() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+
+    // A supply of two is not an NFT.
+    let payer = context.payer.pubkey();
+    let mint = create_mint(&mut context, &payer, 0).await;
+    seed_master_edition(&mut context, &mint);
+    let owner_ata = create_ata(&mut context, &keys.owner.pubkey(), &mint).await;
+    create_ata(&mut context, &keys.vault_auth, &mint).await;
+    mint_to(&mut context, &mint, &owner_ata, 2).await;
+    let deposit = nft_ix(&keys, &mint, WbaVaultInstruction::DepositNft);
+    let result = process(&mut context, &[deposit], &[&keys.owner]).await;
+    assert!(result.is_err());
+
+    // Withdrawing without the stake record would stop nothing from accruing.
+    let nft = deposit_nft(&mut context, &keys).await;
+    let mut withdraw = nft_ix(&keys, &nft, WbaVaultInstruction::WithdrawNft);
+    withdraw.accounts.pop();
+    let result = process(&mut context, &[withdraw], &[&keys.owner]).await;
+    assert!(result.is_err());
+    assert_eq!(vault_state(&mut context, &keys).await.staked_nfts, 1);
+}
+
+#[tokio::test]
+async fn staked_nfts_are_released_by_any_outflow() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    set_time(&mut context, NOW).await;
+    let nft = deposit_nft(&mut context, &keys).await;
+    let owner_ata = get_associated_token_address(&keys.owner.pubkey(), &nft);
+    let vault_ata = get_associated_token_address(&keys.vault_auth, &nft);
+
+    // The record must come along, or the NFT would leave still scoring.
+    set_time(&mut context, NOW + DAY).await;
+    let mut withdraw = withdraw_spl_ix(&keys, &owner_ata, &vault_ata, &nft, 1);
+    let result = process(
+        &mut context,
+        std::slice::from_ref(&withdraw),
+        &[&keys.owner],
+    )
+    .await;
+    assert_vault_error(result, 0, WbaVaultError::InvalidNftStake);
+
+    withdraw
+        .accounts
+        .push(AccountMeta::new(nft_stake_address(&keys, &nft), false));
+    process(&mut context, &[withdraw], &[&keys.owner])
+        .await
+        .unwrap();
+    assert_eq!(token_balance(&mut context, &owner_ata).await, 1);
+    assert!(!account_exists(&mut context, &nft_stake_address(&keys, &nft)).await);
+    let state = vault_state(&mut context, &keys).await;
+    assert_eq!((state.score_points, state.staked_nfts), (1, 0));
+
+    // Only the day it was staked for is rewarded.
+    set_time(&mut context, NOW + 3 * DAY).await;
+    process(&mut context, &[claim_rewards_ix(&keys)], &[&keys.owner])
+        .await
+        .unwrap();
+    let rewards = get_associated_token_address(&keys.owner.pubkey(), &reward_mint());
+    assert_eq!(token_balance(&mut context, &rewards).await, 1_000_000);
+    let result = process(&mut context, &[claim_rewards_ix(&keys)], &[&keys.owner]).await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn nfts_deposited_without_a_stake_record_are_held_unstaked() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let payer = context.payer.pubkey();
+    let nft = create_mint(&mut context, &payer, 0).await;
+    seed_master_edition(&mut context, &nft);
+    let owner_ata = create_ata(&mut context, &keys.owner.pubkey(), &nft).await;
+    create_ata(&mut context, &keys.vault_auth, &nft).await;
+    mint_to(&mut context, &nft, &owner_ata, 1).await;
+
+    // The layout from before staking, as older clients still send it.
+    let unstaked = |data| {
+        let mut ix = nft_ix(&keys, &nft, data);
+        ix.accounts.pop();
+        ix
+    };
+    process(
+        &mut context,
+        &[unstaked(WbaVaultInstruction::DepositNft)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    assert!(!account_exists(&mut context, &nft_stake_address(&keys, &nft)).await);
+    assert_eq!(vault_state(&mut context, &keys).await.staked_nfts, 0);
+
+    process(
+        &mut context,
+        &[unstaked(WbaVaultInstruction::WithdrawNft)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    assert_eq!(token_balance(&mut context, &owner_ata).await, 1);
+}
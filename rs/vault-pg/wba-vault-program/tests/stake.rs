@@ -0,0 +1,340 @@
+mod common;
+
+use borsh::BorshDeserialize;
+use common::*;
+use solana_program::{
+    clock::Epoch,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    rent::Rent,
+    stake::{self, state::StakeStateV2},
+    stake_history::StakeHistory,
+    system_instruction, system_program, sysvar,
+    vote::{
+        instruction as vote_instruction,
+        state::{VoteInit, VoteState},
+    },
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::{Keypair, Signer};
+use wba_vault_program::{id, Custody, WbaVaultError, WbaVaultInstruction};
+
+const STAKE_AMOUNT: u64 = 5_000_000_000;
+
+fn stake_pda(keys: &VaultKeys, seed: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"stake",
+            keys.vault_state.pubkey().as_ref(),
+            &seed.to_le_bytes(),
+        ],
+        &id(),
+    )
+    .0
+}
+
+// The stake program still expects the (deprecated) config account.
+#[allow(deprecated)]
+fn stake_from_vault_ix(
+    keys: &VaultKeys,
+    stake: Pubkey,
+    vote: Pubkey,
+    amount: u64,
+    seed: u64,
+) -> Instruction {
+    ix(
+        WbaVaultInstruction::StakeFromVault { amount, seed },
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(keys.vault, false),
+            AccountMeta::new(stake, false),
+            AccountMeta::new_readonly(vote, false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(sysvar::stake_history::id(), false),
+            AccountMeta::new_readonly(stake::config::id(), false),
+            AccountMeta::new_readonly(stake::program::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+fn deactivate_stake_ix(keys: &VaultKeys, stake: Pubkey) -> Instruction {
+    ix(
+        WbaVaultInstruction::DeactivateStake,
+        vec![
+            AccountMeta::new_readonly(keys.owner.pubkey(), true),
+            AccountMeta::new_readonly(keys.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(stake, false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(stake::program::id(), false),
+        ],
+    )
+}
+
+fn withdraw_stake_ix(keys: &VaultKeys, stake: Pubkey, amount: u64) -> Instruction {
+    ix(
+        WbaVaultInstruction::WithdrawStake { amount },
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(keys.vault, false),
+            AccountMeta::new(stake, false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(sysvar::stake_history::id(), false),
+            AccountMeta::new_readonly(stake::program::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+async fn create_vote_account(context: &mut ProgramTestContext) -> Pubkey {
+    let validator = Keypair::new();
+    let vote = Keypair::new();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let payer = context.payer.pubkey();
+
+    let mut instructions = vec![system_instruction::create_account(
+        &payer,
+        &validator.pubkey(),
+        rent.minimum_balance(0),
+        0,
+        &system_program::id(),
+    )];
+    instructions.extend(vote_instruction::create_account_with_config(
+        &payer,
+        &vote.pubkey(),
+        &VoteInit {
+            node_pubkey: validator.pubkey(),
+            authorized_voter: validator.pubkey(),
+            authorized_withdrawer: validator.pubkey(),
+            commission: 0,
+        },
+        rent.minimum_balance(VoteState::size_of()),
+        vote_instruction::CreateVoteAccountConfig {
+            space: VoteState::size_of() as u64,
+            ..Default::default()
+        },
+    ));
+    process(context, &instructions, &[&validator, &vote])
+        .await
+        .unwrap();
+    vote.pubkey()
+}
+
+async fn warp_epochs(context: &mut ProgramTestContext, epochs: u64) -> Epoch {
+    let clock = context
+        .banks_client
+        .get_sysvar::<sysvar::clock::Clock>()
+        .await
+        .unwrap();
+    let target = clock.epoch + epochs;
+    context.warp_to_epoch(target).unwrap();
+    // Stake accounts are frozen while epoch rewards are being paid out.
+    context.warp_forward_force_reward_interval_end().unwrap();
+    target
+}
+
+async fn effective_stake(
+    context: &mut ProgramTestContext,
+    stake: Pubkey,
+    epoch: Epoch,
+) -> (u64, u64) {
+    let account = context
+        .banks_client
+        .get_account(stake)
+        .await
+        .unwrap()
+        .unwrap();
+    let state = StakeStateV2::deserialize(&mut account.data.as_slice()).unwrap();
+    let history = context
+        .banks_client
+        .get_sysvar::<StakeHistory>()
+        .await
+        .unwrap();
+    let status = state
+        .delegation()
+        .unwrap()
+        .stake_activating_and_deactivating(epoch, &history, Some(0));
+    (status.effective, status.deactivating)
+}
+
+#[tokio::test]
+async fn stake_lifecycle_returns_lamports_to_vault() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let vote = create_vote_account(&mut context).await;
+
+    let rent = Rent::default();
+    let stake_lamports = STAKE_AMOUNT + rent.minimum_balance(StakeStateV2::size_of());
+    process(
+        &mut context,
+        &[deposit_ix(&keys, stake_lamports)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    let vault_before = lamports(&mut context, &keys.vault).await;
+
+    let stake = stake_pda(&keys, 0);
+    process(
+        &mut context,
+        &[stake_from_vault_ix(&keys, stake, vote, stake_lamports, 0)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        lamports(&mut context, &keys.vault).await,
+        vault_before - stake_lamports
+    );
+    assert_eq!(
+        vault_state(&mut context, &keys).await.stake_accounts(),
+        [stake]
+    );
+    let stats = vault_state(&mut context, &keys).await.stats();
+    assert_eq!(stats.total_sol_withdrawn, stake_lamports);
+
+    // Only a live vault state can withdraw the stake.
+    let result = process(&mut context, &[close_account_ix(&keys)], &[&keys.owner]).await;
+    assert_vault_error(result, 0, WbaVaultError::VaultNotEmpty);
+
+    let epoch = warp_epochs(&mut context, 1).await;
+    let (effective, _) = effective_stake(&mut context, stake, epoch).await;
+    assert_eq!(effective, STAKE_AMOUNT);
+
+    // Active stake cannot be pulled out before it is deactivated.
+    assert!(process(
+        &mut context,
+        &[withdraw_stake_ix(&keys, stake, stake_lamports)],
+        &[&keys.owner],
+    )
+    .await
+    .is_err());
+
+    process(
+        &mut context,
+        &[deactivate_stake_ix(&keys, stake)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    let epoch = warp_epochs(&mut context, 1).await;
+    assert_eq!(effective_stake(&mut context, stake, epoch).await, (0, 0));
+
+    let balance = lamports(&mut context, &stake).await;
+    process(
+        &mut context,
+        &[withdraw_stake_ix(&keys, stake, balance)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        lamports(&mut context, &keys.vault).await,
+        vault_before - stake_lamports + balance
+    );
+    let stats = vault_state(&mut context, &keys).await.stats();
+    assert_eq!(stats.total_sol_deposited, stake_lamports + balance);
+    assert!(vault_state(&mut context, &keys)
+        .await
+        .stake_accounts()
+        .is_empty());
+    process(&mut context, &[close_account_ix(&keys)], &[&keys.owner])
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn stake_requires_vault_owner() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let vote = create_vote_account(&mut context).await;
+    process(
+        &mut context,
+        &[deposit_ix(&keys, 10_000_000_000)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    let mut intruder = VaultKeys::new();
+    intruder.vault_state = keys.vault_state.insecure_clone();
+    intruder.vault_auth = keys.vault_auth;
+    intruder.vault = keys.vault;
+    let fund = fund_ix(&context, &intruder.owner.pubkey());
+    process(&mut context, &[fund], &[]).await.unwrap();
+
+    let stake = stake_pda(&keys, 0);
+    assert!(process(
+        &mut context,
+        &[stake_from_vault_ix(&intruder, stake, vote, STAKE_AMOUNT, 0)],
+        &[&intruder.owner],
+    )
+    .await
+    .is_err());
+}
+
+#[tokio::test]
+async fn stake_rejects_foreign_stake_account() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let vote = create_vote_account(&mut context).await;
+    process(
+        &mut context,
+        &[deposit_ix(&keys, 10_000_000_000)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    // Seed 1 derives a different address than the one passed in.
+    let stake = stake_pda(&keys, 0);
+    assert!(process(
+        &mut context,
+        &[stake_from_vault_ix(&keys, stake, vote, STAKE_AMOUNT, 1)],
+        &[&keys.owner],
+    )
+    .await
+    .is_err());
+
+    // A stake account the vault never created cannot be deactivated through it.
+    assert!(process(
+        &mut context,
+        &[deactivate_stake_ix(&keys, stake_pda(&keys, 2))],
+        &[&keys.owner],
+    )
+    .await
+    .is_err());
+}
+
+#[tokio::test]
+async fn program_owned_vaults_cannot_stake() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault_with_custody(&mut context, Custody::ProgramOwned).await;
+    let vote = create_vote_account(&mut context).await;
+
+    let rent = Rent::default();
+    let stake_lamports = STAKE_AMOUNT + rent.minimum_balance(StakeStateV2::size_of());
+    process(
+        &mut context,
+        &[deposit_ix(&keys, stake_lamports)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    let stake = stake_pda(&keys, 0);
+    let result = process(
+        &mut context,
+        &[stake_from_vault_ix(&keys, stake, vote, stake_lamports, 0)],
+        &[&keys.owner],
+    )
+    .await;
+    assert!(result.is_err());
+    assert!(!account_exists(&mut context, &stake).await);
+}
@@ -0,0 +1,143 @@
+mod common;
+
+use common::*;
+use solana_program::{pubkey::Pubkey, rent::Rent};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::{
+    account::{Account, AccountSharedData},
+    signature::Signer,
+};
+use wba_vault_program::{id, read_vault_stats, Vault, VaultStats, WbaVaultInstruction};
+
+async fn stats(context: &mut ProgramTestContext, keys: &VaultKeys) -> VaultStats {
+    let account = context
+        .banks_client
+        .get_account(keys.vault_state.pubkey())
+        .await
+        .unwrap()
+        .unwrap();
+    read_vault_stats(&account.data).unwrap()
+}
+
+#[tokio::test]
+async fn sol_flows_are_counted() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    assert_eq!(stats(&mut context, &keys).await.deposit_count, 0);
+
+    process(
+        &mut context,
+        &[deposit_ix(&keys, 1_000_000), deposit_ix(&keys, 500_000)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    context.warp_to_slot(100).unwrap();
+    process(&mut context, &[withdraw_ix(&keys, 400_000)], &[&keys.owner])
+        .await
+        .unwrap();
+
+    let stats = stats(&mut context, &keys).await;
+    assert_eq!(stats.total_sol_deposited, 1_500_000);
+    assert_eq!(stats.total_sol_withdrawn, 400_000);
+    assert_eq!(stats.deposit_count, 2);
+    assert_eq!(stats.withdraw_count, 1);
+    assert!(stats.last_activity_slot >= 100);
+    assert_eq!(stats.mints_held, 0);
+}
+
+#[tokio::test]
+async fn mints_held_follows_token_balances() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let payer = context.payer.pubkey();
+    let mint = create_mint(&mut context, &payer, 0).await;
+    let other_mint = create_mint(&mut context, &payer, 0).await;
+
+    let mut atas = Vec::new();
+    for mint in [mint, other_mint] {
+        let owner_ata = create_ata(&mut context, &keys.owner.pubkey(), &mint).await;
+        let vault_ata = create_ata(&mut context, &keys.vault_auth, &mint).await;
+        mint_to(&mut context, &mint, &owner_ata, 10).await;
+        atas.push((mint, owner_ata, vault_ata));
+    }
+    let transfer = |(mint, owner_ata, vault_ata): (Pubkey, Pubkey, Pubkey), data| {
+        let mut ix = withdraw_spl_ix(&keys, &owner_ata, &vault_ata, &mint, 0);
+        ix.data = borsh::to_vec(&data).unwrap();
+        ix
+    };
+    let deposit = |atas, amount| transfer(atas, WbaVaultInstruction::DepositSpl { amount });
+    let withdraw = |atas, amount| transfer(atas, WbaVaultInstruction::WithdrawSpl { amount });
+
+    process(
+        &mut context,
+        &[
+            deposit(atas[0], 4),
+            deposit(atas[0], 6),
+            deposit(atas[1], 1),
+        ],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    let counted = stats(&mut context, &keys).await;
+    assert_eq!(counted.deposit_count, 3);
+    assert_eq!(counted.mints_held, 2);
+
+    // A partial withdrawal keeps the mint; emptying the account drops it.
+    process(&mut context, &[withdraw(atas[0], 5)], &[&keys.owner])
+        .await
+        .unwrap();
+    assert_eq!(stats(&mut context, &keys).await.mints_held, 2);
+    process(&mut context, &[withdraw(atas[0], 5)], &[&keys.owner])
+        .await
+        .unwrap();
+
+    let counted = stats(&mut context, &keys).await;
+    assert_eq!(counted.withdraw_count, 2);
+    assert_eq!(counted.mints_held, 1);
+    assert_eq!(counted.total_sol_deposited, 0);
+}
+
+#[tokio::test]
+async fn vaults_without_counters_are_grown_on_first_use() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+
+    // Rewrite the state in the Borsh layout the program started with, which
+    // has no counters.
+    let vault = vault_state(&mut context, &keys).await;
+    let data =
+        borsh::to_vec(&(vault.owner, vault.auth_bump, vault.vault_bump, vault.score)).unwrap();
+    assert_eq!(data.len(), Vault::LEGACY_LEN);
+    let legacy = Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: id(),
+        executable: false,
+        rent_epoch: 0,
+    };
+    context.set_account(&keys.vault_state.pubkey(), &AccountSharedData::from(legacy));
+    assert_eq!(stats(&mut context, &keys).await, VaultStats::default());
+
+    process(
+        &mut context,
+        &[deposit_ix(&keys, 1_000), withdraw_ix(&keys, 1_000)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    let account = context
+        .banks_client
+        .get_account(keys.vault_state.pubkey())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.data.len(), Vault::space());
+    let stats = read_vault_stats(&account.data).unwrap();
+    assert_eq!(stats.total_sol_deposited, 1_000);
+    assert_eq!(stats.total_sol_withdrawn, 1_000);
+    assert_eq!(stats.deposit_count, 1);
+    assert_eq!(stats.withdraw_count, 1);
+}
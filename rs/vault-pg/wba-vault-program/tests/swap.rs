@@ -0,0 +1,457 @@
+mod common;
+
+use common::*;
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::{Keypair, Signer};
+use spl_associated_token_account::get_associated_token_address;
+use wba_vault_program::{id, WbaVaultError, WbaVaultInstruction};
+
+const OFFER_AMOUNT: u64 = 100;
+const ASK_AMOUNT: u64 = 40;
+const SEED: u64 = 7;
+
+struct Swap {
+    maker: VaultKeys,
+    offer_mint: Pubkey,
+    ask_mint: Pubkey,
+    offer: Pubkey,
+    escrow: Pubkey,
+}
+
+fn offer_swap_ix(swap: &Swap, seed: u64) -> Instruction {
+    let keys = &swap.maker;
+    ix(
+        WbaVaultInstruction::OfferSwap {
+            offer_amount: OFFER_AMOUNT,
+            ask_amount: ASK_AMOUNT,
+            seed,
+        },
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(
+                get_associated_token_address(&keys.vault_auth, &swap.offer_mint),
+                false,
+            ),
+            AccountMeta::new(swap.offer, false),
+            AccountMeta::new(swap.escrow, false),
+            AccountMeta::new_readonly(swap.offer_mint, false),
+            AccountMeta::new_readonly(swap.ask_mint, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+fn accept_swap_ix(
+    swap: &Swap,
+    taker: &Pubkey,
+    taker_source: Pubkey,
+    taker_destination: Pubkey,
+    taker_vault: Option<&VaultKeys>,
+) -> Instruction {
+    let keys = &swap.maker;
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*taker, true),
+        AccountMeta::new(taker_source, false),
+        AccountMeta::new(taker_destination, false),
+        AccountMeta::new(keys.owner.pubkey(), false),
+        AccountMeta::new(keys.vault_state.pubkey(), false),
+        AccountMeta::new_readonly(keys.vault_auth, false),
+        AccountMeta::new(
+            get_associated_token_address(&keys.vault_auth, &swap.ask_mint),
+            false,
+        ),
+        AccountMeta::new(swap.offer, false),
+        AccountMeta::new(swap.escrow, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+    if let Some(taker_vault) = taker_vault {
+        accounts.push(AccountMeta::new(taker_vault.vault_state.pubkey(), false));
+        accounts.push(AccountMeta::new_readonly(taker_vault.vault_auth, false));
+    }
+    ix(WbaVaultInstruction::AcceptSwap, accounts)
+}
+
+fn cancel_swap_ix(swap: &Swap) -> Instruction {
+    let keys = &swap.maker;
+    ix(
+        WbaVaultInstruction::CancelSwap,
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new_readonly(keys.vault_auth, false),
+            AccountMeta::new(
+                get_associated_token_address(&keys.vault_auth, &swap.offer_mint),
+                false,
+            ),
+            AccountMeta::new(swap.offer, false),
+            AccountMeta::new(swap.escrow, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+    )
+}
+
+fn allowlist_pda(keys: &VaultKeys) -> Pubkey {
+    Pubkey::find_program_address(&[b"allowlist", keys.vault_state.pubkey().as_ref()], &id()).0
+}
+
+fn allowlist_ix(keys: &VaultKeys, data: WbaVaultInstruction) -> Instruction {
+    ix(
+        data,
+        vec![
+            AccountMeta::new(keys.owner.pubkey(), true),
+            AccountMeta::new(keys.vault_state.pubkey(), false),
+            AccountMeta::new(allowlist_pda(keys), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+/// A maker vault holding `OFFER_AMOUNT` of a fresh mint, about to offer it.
+async fn setup_swap(context: &mut ProgramTestContext) -> Swap {
+    let maker = setup_vault(context).await;
+    let payer = context.payer.pubkey();
+    let offer_mint = create_mint(context, &payer, 0).await;
+    let ask_mint = create_mint(context, &payer, 0).await;
+
+    let vault_offer_ata = create_ata(context, &maker.vault_auth, &offer_mint).await;
+    create_ata(context, &maker.vault_auth, &ask_mint).await;
+    mint_to(context, &offer_mint, &vault_offer_ata, OFFER_AMOUNT).await;
+
+    let (offer, _) = Pubkey::find_program_address(
+        &[
+            b"offer",
+            maker.vault_state.pubkey().as_ref(),
+            &SEED.to_le_bytes(),
+        ],
+        &id(),
+    );
+    let (escrow, _) = Pubkey::find_program_address(&[b"escrow", offer.as_ref()], &id());
+    Swap {
+        maker,
+        offer_mint,
+        ask_mint,
+        offer,
+        escrow,
+    }
+}
+
+/// Opens an offer from a maker vault holding `OFFER_AMOUNT` of a fresh mint.
+async fn open_offer(context: &mut ProgramTestContext) -> Swap {
+    let swap = setup_swap(context).await;
+    let vault_offer_ata = get_associated_token_address(&swap.maker.vault_auth, &swap.offer_mint);
+    process(context, &[offer_swap_ix(&swap, SEED)], &[&swap.maker.owner])
+        .await
+        .unwrap();
+    assert_eq!(token_balance(context, &vault_offer_ata).await, 0);
+    assert_eq!(token_balance(context, &swap.escrow).await, OFFER_AMOUNT);
+    swap
+}
+
+#[tokio::test]
+async fn wallet_fills_offer() {
+    let mut context = program_test().start_with_context().await;
+    let swap = open_offer(&mut context).await;
+
+    let taker = Keypair::new();
+    let taker_source = create_ata(&mut context, &taker.pubkey(), &swap.ask_mint).await;
+    let taker_destination = create_ata(&mut context, &taker.pubkey(), &swap.offer_mint).await;
+    mint_to(&mut context, &swap.ask_mint, &taker_source, ASK_AMOUNT).await;
+
+    process(
+        &mut context,
+        &[accept_swap_ix(
+            &swap,
+            &taker.pubkey(),
+            taker_source,
+            taker_destination,
+            None,
+        )],
+        &[&taker],
+    )
+    .await
+    .unwrap();
+
+    let vault_ask_ata = get_associated_token_address(&swap.maker.vault_auth, &swap.ask_mint);
+    assert_eq!(
+        token_balance(&mut context, &vault_ask_ata).await,
+        ASK_AMOUNT
+    );
+    assert_eq!(
+        token_balance(&mut context, &taker_destination).await,
+        OFFER_AMOUNT
+    );
+    assert!(!account_exists(&mut context, &swap.escrow).await);
+    assert!(!account_exists(&mut context, &swap.offer).await);
+    assert_eq!(vault_state(&mut context, &swap.maker).await.open_offers, 0);
+}
+
+#[tokio::test]
+async fn vault_fills_offer() {
+    let mut context = program_test().start_with_context().await;
+    let swap = open_offer(&mut context).await;
+
+    let taker = setup_vault(&mut context).await;
+    let taker_source = create_ata(&mut context, &taker.vault_auth, &swap.ask_mint).await;
+    let taker_destination = create_ata(&mut context, &taker.vault_auth, &swap.offer_mint).await;
+    mint_to(&mut context, &swap.ask_mint, &taker_source, ASK_AMOUNT).await;
+
+    // Another wallet cannot spend the taker vault's tokens.
+    let intruder = Keypair::new();
+    assert!(process(
+        &mut context,
+        &[accept_swap_ix(
+            &swap,
+            &intruder.pubkey(),
+            taker_source,
+            taker_destination,
+            Some(&taker),
+        )],
+        &[&intruder],
+    )
+    .await
+    .is_err());
+
+    process(
+        &mut context,
+        &[accept_swap_ix(
+            &swap,
+            &taker.owner.pubkey(),
+            taker_source,
+            taker_destination,
+            Some(&taker),
+        )],
+        &[&taker.owner],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(token_balance(&mut context, &taker_source).await, 0);
+    assert_eq!(
+        token_balance(&mut context, &taker_destination).await,
+        OFFER_AMOUNT
+    );
+}
+
+#[tokio::test]
+async fn underfunded_taker_cannot_fill() {
+    let mut context = program_test().start_with_context().await;
+    let swap = open_offer(&mut context).await;
+
+    let taker = Keypair::new();
+    let taker_source = create_ata(&mut context, &taker.pubkey(), &swap.ask_mint).await;
+    let taker_destination = create_ata(&mut context, &taker.pubkey(), &swap.offer_mint).await;
+    mint_to(&mut context, &swap.ask_mint, &taker_source, ASK_AMOUNT - 1).await;
+
+    assert!(process(
+        &mut context,
+        &[accept_swap_ix(
+            &swap,
+            &taker.pubkey(),
+            taker_source,
+            taker_destination,
+            None,
+        )],
+        &[&taker],
+    )
+    .await
+    .is_err());
+    assert_eq!(
+        token_balance(&mut context, &swap.escrow).await,
+        OFFER_AMOUNT
+    );
+}
+
+#[tokio::test]
+async fn cancel_returns_escrow_to_vault() {
+    let mut context = program_test().start_with_context().await;
+    let swap = open_offer(&mut context).await;
+
+    // The ask is paid into the maker's vault, so it stays open with the offer.
+    let result = process(
+        &mut context,
+        &[close_account_ix(&swap.maker)],
+        &[&swap.maker.owner],
+    )
+    .await;
+    assert_vault_error(result, 0, WbaVaultError::VaultNotEmpty);
+
+    process(&mut context, &[cancel_swap_ix(&swap)], &[&swap.maker.owner])
+        .await
+        .unwrap();
+    assert_eq!(vault_state(&mut context, &swap.maker).await.open_offers, 0);
+
+    let vault_offer_ata = get_associated_token_address(&swap.maker.vault_auth, &swap.offer_mint);
+    assert_eq!(
+        token_balance(&mut context, &vault_offer_ata).await,
+        OFFER_AMOUNT
+    );
+    assert!(!account_exists(&mut context, &swap.escrow).await);
+    assert!(!account_exists(&mut context, &swap.offer).await);
+}
+
+#[tokio::test]
+async fn allowlist_limits_who_can_fill_an_offer() {
+    let mut context = program_test().start_with_context().await;
+    let swap = open_offer(&mut context).await;
+    let listed = Keypair::new();
+    process(
+        &mut context,
+        &[
+            allowlist_ix(
+                &swap.maker,
+                WbaVaultInstruction::SetAllowlistConfig {
+                    enabled: true,
+                    add_delay_secs: 0,
+                },
+            ),
+            allowlist_ix(
+                &swap.maker,
+                WbaVaultInstruction::AddAllowlistEntry {
+                    address: listed.pubkey(),
+                },
+            ),
+        ],
+        &[&swap.maker.owner],
+    )
+    .await
+    .unwrap();
+
+    let fill = |taker: &Keypair, source, destination| {
+        let mut ix = accept_swap_ix(&swap, &taker.pubkey(), source, destination, None);
+        ix.accounts
+            .push(AccountMeta::new_readonly(allowlist_pda(&swap.maker), false));
+        ix
+    };
+
+    let unlisted = Keypair::new();
+    let source = create_ata(&mut context, &unlisted.pubkey(), &swap.ask_mint).await;
+    let destination = create_ata(&mut context, &unlisted.pubkey(), &swap.offer_mint).await;
+    mint_to(&mut context, &swap.ask_mint, &source, ASK_AMOUNT).await;
+    let result = process(
+        &mut context,
+        &[fill(&unlisted, source, destination)],
+        &[&unlisted],
+    )
+    .await;
+    assert_vault_error(result, 0, WbaVaultError::RecipientNotAllowed);
+
+    // A listed wallet cannot route the escrow to someone else's account.
+    let source = create_ata(&mut context, &listed.pubkey(), &swap.ask_mint).await;
+    mint_to(&mut context, &swap.ask_mint, &source, ASK_AMOUNT).await;
+    let result = process(
+        &mut context,
+        &[fill(&listed, source, destination)],
+        &[&listed],
+    )
+    .await;
+    assert_vault_error(result, 0, WbaVaultError::RecipientNotAllowed);
+
+    let destination = create_ata(&mut context, &listed.pubkey(), &swap.offer_mint).await;
+    let result = process(
+        &mut context,
+        &[accept_swap_ix(
+            &swap,
+            &listed.pubkey(),
+            source,
+            destination,
+            None,
+        )],
+        &[&listed],
+    )
+    .await;
+    assert_vault_error(result, 0, WbaVaultError::MissingAllowlist);
+
+    process(
+        &mut context,
+        &[fill(&listed, source, destination)],
+        &[&listed],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        token_balance(&mut context, &destination).await,
+        OFFER_AMOUNT
+    );
+}
+
+#[tokio::test]
+async fn fills_count_as_deposits_but_not_as_activity() {
+    let mut context = program_test().start_with_context().await;
+    let swap = open_offer(&mut context).await;
+    let before = vault_state(&mut context, &swap.maker).await.stats();
+    assert_eq!(before.withdraw_count, 1);
+
+    let taker = Keypair::new();
+    let taker_source = create_ata(&mut context, &taker.pubkey(), &swap.ask_mint).await;
+    let taker_destination = create_ata(&mut context, &taker.pubkey(), &swap.offer_mint).await;
+    mint_to(&mut context, &swap.ask_mint, &taker_source, ASK_AMOUNT).await;
+    context.warp_to_slot(100).unwrap();
+    process(
+        &mut context,
+        &[accept_swap_ix(
+            &swap,
+            &taker.pubkey(),
+            taker_source,
+            taker_destination,
+            None,
+        )],
+        &[&taker],
+    )
+    .await
+    .unwrap();
+
+    // The ask arriving is a deposit and a new mint held, but the taker's fill
+    // is no activity of the maker's.
+    let after = vault_state(&mut context, &swap.maker).await.stats();
+    assert_eq!(after.mints_held, before.mints_held + 1);
+    assert_eq!(after.deposit_count, before.deposit_count + 1);
+    assert_eq!(after.last_activity_slot, before.last_activity_slot);
+}
+
+#[tokio::test]
+async fn offers_debit_the_ledger_and_cancels_restore_it() {
+    let mut context = program_test().start_with_context().await;
+    let swap = setup_swap(&mut context).await;
+    let keys = &swap.maker;
+    // Syncing attributes the vault's tokens to a new ledger.
+    let sync = reconcile_ix(keys, &swap.offer_mint, true);
+    process(&mut context, &[sync], &[&keys.owner])
+        .await
+        .unwrap();
+    let with_ledger = |mut ix: Instruction| {
+        let ledger = ledger_address(keys, &swap.offer_mint);
+        ix.accounts.push(AccountMeta::new(ledger, false));
+        ix
+    };
+
+    // Once the vault has a ledger, tokens cannot leave or come back past it.
+    let result = process(&mut context, &[offer_swap_ix(&swap, SEED)], &[&keys.owner]).await;
+    assert_vault_error(result, 0, WbaVaultError::MissingLedger);
+    let offer = with_ledger(offer_swap_ix(&swap, SEED));
+    process(&mut context, &[offer], &[&keys.owner])
+        .await
+        .unwrap();
+    assert_eq!(
+        ledger_balance(&mut context, keys, &swap.offer_mint).await,
+        0
+    );
+
+    let result = process(&mut context, &[cancel_swap_ix(&swap)], &[&keys.owner]).await;
+    assert_vault_error(result, 0, WbaVaultError::MissingLedger);
+    let cancel = with_ledger(cancel_swap_ix(&swap));
+    process(&mut context, &[cancel], &[&keys.owner])
+        .await
+        .unwrap();
+    assert_eq!(
+        ledger_balance(&mut context, keys, &swap.offer_mint).await,
+        OFFER_AMOUNT
+    );
+}
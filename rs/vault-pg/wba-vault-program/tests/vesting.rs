@@ -0,0 +1,336 @@
+mod common;
+
+use common::*;
+use solana_program::{
+    clock::Clock,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::{Keypair, Signer};
+use spl_associated_token_account::get_associated_token_address;
+use wba_vault_program::{id, WbaVaultError, WbaVaultInstruction};
+
+const TOTAL: u64 = 1_000_000;
+const START: i64 = 1_000;
+const CLIFF: i64 = 1_250;
+const END: i64 = 2_000;
+
+fn vesting_pda(keys: &VaultKeys, seed: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"vesting",
+            keys.vault_state.pubkey().as_ref(),
+            &seed.to_le_bytes(),
+        ],
+        &id(),
+    )
+    .0
+}
+
+fn escrow_pda(vesting: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"escrow", vesting.as_ref()], &id()).0
+}
+
+fn create_vesting_ix(
+    keys: &VaultKeys,
+    vesting: Pubkey,
+    beneficiary: Pubkey,
+    mint: Option<Pubkey>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(keys.owner.pubkey(), true),
+        AccountMeta::new(keys.vault_state.pubkey(), false),
+        AccountMeta::new_readonly(keys.vault_auth, false),
+        AccountMeta::new(keys.vault, false),
+        AccountMeta::new(vesting, false),
+        AccountMeta::new_readonly(beneficiary, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    if let Some(mint) = mint {
+        accounts.extend([
+            AccountMeta::new(get_associated_token_address(&keys.vault_auth, &mint), false),
+            AccountMeta::new(escrow_pda(&vesting), false),
+            AccountMeta::new_readonly(mint, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ]);
+    }
+    ix(
+        WbaVaultInstruction::CreateVesting {
+            amount: TOTAL,
+            start_ts: START,
+            cliff_ts: CLIFF,
+            end_ts: END,
+            seed: 0,
+        },
+        accounts,
+    )
+}
+
+fn claim_vested_ix(
+    keys: &VaultKeys,
+    vesting: Pubkey,
+    beneficiary: Pubkey,
+    mint: Option<Pubkey>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(beneficiary, true),
+        AccountMeta::new_readonly(keys.vault_state.pubkey(), false),
+        AccountMeta::new_readonly(keys.vault_auth, false),
+        AccountMeta::new(vesting, false),
+    ];
+    if let Some(mint) = mint {
+        accounts.extend([
+            AccountMeta::new(escrow_pda(&vesting), false),
+            AccountMeta::new(get_associated_token_address(&beneficiary, &mint), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ]);
+    }
+    ix(WbaVaultInstruction::ClaimVested, accounts)
+}
+
+fn revoke_vesting_ix(keys: &VaultKeys, vesting: Pubkey, mint: Option<Pubkey>) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(keys.owner.pubkey(), true),
+        AccountMeta::new(keys.vault_state.pubkey(), false),
+        AccountMeta::new_readonly(keys.vault_auth, false),
+        AccountMeta::new(keys.vault, false),
+        AccountMeta::new(vesting, false),
+    ];
+    if let Some(mint) = mint {
+        accounts.extend([
+            AccountMeta::new(get_associated_token_address(&keys.vault_auth, &mint), false),
+            AccountMeta::new(escrow_pda(&vesting), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ]);
+    }
+    ix(WbaVaultInstruction::RevokeVesting, accounts)
+}
+
+async fn set_time(context: &mut ProgramTestContext, unix_timestamp: i64) {
+    let mut clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    clock.unix_timestamp = unix_timestamp;
+    context.set_sysvar(&clock);
+}
+
+#[tokio::test]
+async fn sol_vesting_unlocks_linearly() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let beneficiary = Keypair::new();
+    let fund = fund_ix(&context, &beneficiary.pubkey());
+    process(
+        &mut context,
+        &[fund, deposit_ix(&keys, TOTAL)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    let vesting = vesting_pda(&keys, 0);
+    set_time(&mut context, START).await;
+    process(
+        &mut context,
+        &[create_vesting_ix(
+            &keys,
+            vesting,
+            beneficiary.pubkey(),
+            None,
+        )],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    // Nothing is claimable before the cliff.
+    set_time(&mut context, CLIFF - 1).await;
+    assert!(process(
+        &mut context,
+        &[claim_vested_ix(&keys, vesting, beneficiary.pubkey(), None)],
+        &[&beneficiary],
+    )
+    .await
+    .is_err());
+
+    // Halfway through the schedule half of the amount is unlocked.
+    set_time(&mut context, (START + END) / 2).await;
+    let before = lamports(&mut context, &beneficiary.pubkey()).await;
+    process(
+        &mut context,
+        &[claim_vested_ix(&keys, vesting, beneficiary.pubkey(), None)],
+        &[&beneficiary],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        lamports(&mut context, &beneficiary.pubkey()).await,
+        before + TOTAL / 2
+    );
+
+    // Revoking updates the vault's stats, so its state must be writable.
+    let mut read_only = revoke_vesting_ix(&keys, vesting, None);
+    read_only.accounts[1].is_writable = false;
+    let result = process(&mut context, &[read_only], &[&keys.owner]).await;
+    assert_vault_error(result, 0, WbaVaultError::AccountNotWritable);
+
+    // Revoking returns the unvested half to the vault and closes the schedule.
+    let vault_before = lamports(&mut context, &keys.vault).await;
+    process(
+        &mut context,
+        &[revoke_vesting_ix(&keys, vesting, None)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        lamports(&mut context, &keys.vault).await,
+        vault_before + TOTAL / 2
+    );
+    assert!(!account_exists(&mut context, &vesting).await);
+}
+
+#[tokio::test]
+async fn spl_vesting_keeps_vested_amount_after_revoke() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let beneficiary = Keypair::new();
+    let payer = context.payer.pubkey();
+    let mint = create_mint(&mut context, &payer, 0).await;
+    let vault_ata = create_ata(&mut context, &keys.vault_auth, &mint).await;
+    let beneficiary_ata = create_ata(&mut context, &beneficiary.pubkey(), &mint).await;
+    mint_to(&mut context, &mint, &vault_ata, TOTAL).await;
+
+    let vesting = vesting_pda(&keys, 0);
+    set_time(&mut context, START).await;
+    process(
+        &mut context,
+        &[create_vesting_ix(
+            &keys,
+            vesting,
+            beneficiary.pubkey(),
+            Some(mint),
+        )],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    assert_eq!(token_balance(&mut context, &vault_ata).await, 0);
+
+    // Revoke at 3/4 of the schedule: the vested part stays in escrow.
+    set_time(&mut context, START + (END - START) * 3 / 4).await;
+    process(
+        &mut context,
+        &[revoke_vesting_ix(&keys, vesting, Some(mint))],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    assert_eq!(token_balance(&mut context, &vault_ata).await, TOTAL / 4);
+
+    // Vesting stopped at revocation, even if time moves on.
+    set_time(&mut context, END + 100).await;
+    let fund = fund_ix(&context, &beneficiary.pubkey());
+    process(&mut context, &[fund], &[]).await.unwrap();
+    let mut claim = claim_vested_ix(&keys, vesting, beneficiary.pubkey(), Some(mint));
+    claim.accounts[1].is_writable = true;
+    claim
+        .accounts
+        .push(AccountMeta::new(keys.owner.pubkey(), false));
+    process(&mut context, &[claim], &[&beneficiary])
+        .await
+        .unwrap();
+    assert_eq!(
+        token_balance(&mut context, &beneficiary_ata).await,
+        TOTAL * 3 / 4
+    );
+    assert!(!account_exists(&mut context, &escrow_pda(&vesting)).await);
+    assert!(!account_exists(&mut context, &vesting).await);
+
+    // Only the beneficiary can claim.
+    let intruder = Keypair::new();
+    assert!(process(
+        &mut context,
+        &[claim_vested_ix(
+            &keys,
+            vesting,
+            intruder.pubkey(),
+            Some(mint)
+        )],
+        &[&intruder],
+    )
+    .await
+    .is_err());
+}
+
+#[tokio::test]
+async fn final_sol_claim_returns_the_rent_to_the_creator() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+    let beneficiary = Keypair::new();
+    let fund = fund_ix(&context, &beneficiary.pubkey());
+    process(
+        &mut context,
+        &[fund, deposit_ix(&keys, TOTAL)],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+
+    let vesting = vesting_pda(&keys, 0);
+    set_time(&mut context, START).await;
+    process(
+        &mut context,
+        &[create_vesting_ix(
+            &keys,
+            vesting,
+            beneficiary.pubkey(),
+            None,
+        )],
+        &[&keys.owner],
+    )
+    .await
+    .unwrap();
+    let rent = lamports(&mut context, &vesting).await - TOTAL;
+
+    let claim_with_creator = |creator: Pubkey| {
+        let mut ix = claim_vested_ix(&keys, vesting, beneficiary.pubkey(), None);
+        ix.accounts[1].is_writable = true;
+        ix.accounts.push(AccountMeta::new(creator, false));
+        ix
+    };
+
+    // The vault cannot be closed while the vesting is open.
+    let result = process(&mut context, &[close_account_ix(&keys)], &[&keys.owner]).await;
+    assert_vault_error(result, 0, WbaVaultError::VaultNotEmpty);
+
+    // Only the vault owner gets the rent back.
+    set_time(&mut context, END).await;
+    let result = process(
+        &mut context,
+        &[claim_with_creator(Keypair::new().pubkey())],
+        &[&beneficiary],
+    )
+    .await;
+    assert_vault_error(result, 0, WbaVaultError::InvalidVesting);
+
+    let beneficiary_before = lamports(&mut context, &beneficiary.pubkey()).await;
+    let owner_before = lamports(&mut context, &keys.owner.pubkey()).await;
+    process(
+        &mut context,
+        &[claim_with_creator(keys.owner.pubkey())],
+        &[&beneficiary],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        lamports(&mut context, &beneficiary.pubkey()).await,
+        beneficiary_before + TOTAL
+    );
+    assert_eq!(
+        lamports(&mut context, &keys.owner.pubkey()).await,
+        owner_before + rent
+    );
+    assert!(!account_exists(&mut context, &vesting).await);
+    assert_eq!(vault_state(&mut context, &keys).await.open_vestings, 0);
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use common::*;
+use solana_program::{program_error::ProgramError, rent::Rent};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::{
+    account::{Account, AccountSharedData},
+    signature::Signer,
+};
+use wba_vault_program::{id, Ledger, Vault, ZeroCopy};
+
+async fn state_data(context: &mut ProgramTestContext, keys: &VaultKeys) -> Vec<u8> {
+    context
+        .banks_client
+        .get_account(keys.vault_state.pubkey())
+        .await
+        .unwrap()
+        .unwrap()
+        .data
+}
+
+#[tokio::test]
+async fn new_vaults_are_zero_copy() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+
+    let data = state_data(&mut context, &keys).await;
+    assert_eq!(data.len(), Vault::SPACE);
+    assert_eq!(data[..8], Vault::DISCRIMINATOR);
+    assert_eq!(Vault::load(&data).unwrap().owner, keys.owner.pubkey());
+}
+
+#[tokio::test]
+async fn borsh_vaults_are_read_and_migrated_on_write() {
+    let mut context = program_test().start_with_context().await;
+    let keys = setup_vault(&mut context).await;
+
+    // Rewrite the state in the Borsh layout the program started with.
+    let vault = vault_state(&mut context, &keys).await;
+    let data =
+        borsh::to_vec(&(vault.owner, vault.auth_bump, vault.vault_bump, vault.score)).unwrap();
+    assert_eq!(data.len(), Vault::LEGACY_LEN);
+    let account = Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: id(),
+        executable: false,
+        rent_epoch: 0,
+    };
+    context.set_account(
+        &keys.vault_state.pubkey(),
+        &AccountSharedData::from(account),
+    );
+
+    let state = vault_state(&mut context, &keys).await;
+    assert_eq!(state.owner, keys.owner.pubkey());
+    assert_eq!(state.auth_bump, vault.auth_bump);
+    assert_eq!(state.vault_bump, vault.vault_bump);
+    assert!(Vault::load(&state_data(&mut context, &keys).await).is_err());
+
+    process(&mut context, &[deposit_ix(&keys, 1_000)], &[&keys.owner])
+        .await
+        .unwrap();
+
+    let data = state_data(&mut context, &keys).await;
+    assert_eq!(data.len(), Vault::SPACE);
+    assert_eq!(data[..8], Vault::DISCRIMINATOR);
+    let state = Vault::load(&data).unwrap();
+    assert_eq!(state.owner, keys.owner.pubkey());
+    assert_eq!(state.vault_bump, vault.vault_bump);
+    assert_eq!(state.total_sol_deposited, 1_000);
+}
+
+#[test]
+fn only_vault_states_have_a_borsh_layout() {
+    let mut ledger = Ledger::default();
+    ledger.balance = 7;
+    let mut data = vec![0; Ledger::SPACE];
+    ledger.init(&mut data).unwrap();
+    assert_eq!(Ledger::read(&data).unwrap().balance, 7);
+
+    // Without its discriminator an account is not read or written as
+    // anything else.
+    data[..8].fill(0);
+    assert_eq!(Ledger::read(&data), Err(ProgramError::InvalidAccountData));
+    assert_eq!(
+        ledger.write(&mut data),
+        Err(ProgramError::InvalidAccountData)
+    );
+}
@@ -55,6 +55,13 @@ const [vault] = PublicKey.findProgramAddressSync(
 // Mint address
 const mint = new PublicKey("DPSMu4DeRwdjR7mTpKxQp7jxXFAZLf4FxdHsQByFHNFk");
 
+// Seeds are "nft_stake", vaultState, mint. Optional: without it the NFT is
+// held unstaked and earns no score.
+const [nftStake] = PublicKey.findProgramAddressSync(
+  [Buffer.from("nft_stake"), vaultState.toBuffer(), mint.toBuffer()],
+  programId,
+);
+
 // Execute our deposit transaction
 (async () => {
   try {
@@ -114,6 +121,7 @@ const mint = new PublicKey("DPSMu4DeRwdjR7mTpKxQp7jxXFAZLf4FxdHsQByFHNFk");
         { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
         { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
         { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        { pubkey: nftStake, isSigner: false, isWritable: true },
       ],
     });
 
@@ -62,6 +62,13 @@ const [vault] = PublicKey.findProgramAddressSync(
 // Mint address
 const mint = new PublicKey("GKx8cKAqVA57oMd87YUtUQtLefDxHfVW2g23jR9cDGbS");
 
+// Seeds are "ledger", vaultState, mint. Optional: it records what went in
+// through the program, and can go last after any other trailing accounts.
+const [ledger] = PublicKey.findProgramAddressSync(
+  [Buffer.from("ledger"), vaultState.toBuffer(), mint.toBuffer()],
+  programId,
+);
+
 // Execute our enrollment transaction
 (async () => {
   try {
@@ -99,6 +106,7 @@ const mint = new PublicKey("GKx8cKAqVA57oMd87YUtUQtLefDxHfVW2g23jR9cDGbS");
         { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
         { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
         { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        { pubkey: ledger, isSigner: false, isWritable: true },
       ],
     });
 
@@ -44,6 +44,13 @@ const [vault] = PublicKey.findProgramAddressSync(
   programId,
 );
 
+// Seeds are "config"; new vaults are checked against it even before it exists,
+// so that a config can't be sidestepped by leaving it out
+const [config] = PublicKey.findProgramAddressSync(
+  [Buffer.from("config")],
+  programId,
+);
+
 // Borsh encoding for:
 // enum WbaVaultInstruction { Initialize, Deposit{u64}, Withdraw{u64}, ... }
 // => Initialize discriminant = 0u8
@@ -58,6 +65,7 @@ const ixData = Buffer.from([0]);
       { pubkey: vaultAuth, isSigner: false, isWritable: false },
       { pubkey: vault, isSigner: false, isWritable: true },
       { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+      { pubkey: config, isSigner: false, isWritable: false },
     ];
 
     const ix = new TransactionInstruction({
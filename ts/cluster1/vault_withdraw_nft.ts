@@ -42,6 +42,13 @@ const vaultState = new PublicKey("9HwjRzkGd5VuxtCtRcyVt4NJiVkqBVkS5tZMTkBuB4zK")
 // Mint address
 const mint = new PublicKey("DPSMu4DeRwdjR7mTpKxQp7jxXFAZLf4FxdHsQByFHNFk");
 
+// Seeds are "nft_stake", vaultState, mint. Required if the NFT was staked on
+// deposit: it is closed back to the owner and scoring stops.
+const [nftStake] = PublicKey.findProgramAddressSync(
+  [Buffer.from("nft_stake"), vaultState.toBuffer(), mint.toBuffer()],
+  programId,
+);
+
 // Execute our enrollment transaction
 (async () => {
   try {
@@ -93,7 +100,7 @@ const mint = new PublicKey("DPSMu4DeRwdjR7mTpKxQp7jxXFAZLf4FxdHsQByFHNFk");
       keys: [
         { pubkey: keypair.publicKey, isSigner: true, isWritable: true },
         { pubkey: ownerAta.address, isSigner: false, isWritable: true },
-        { pubkey: vaultState, isSigner: false, isWritable: false },
+        { pubkey: vaultState, isSigner: false, isWritable: true },
         { pubkey: vaultAuth, isSigner: false, isWritable: false },
         { pubkey: vaultAta.address, isSigner: false, isWritable: true },
         { pubkey: mint, isSigner: false, isWritable: false },
@@ -103,6 +110,7 @@ const mint = new PublicKey("DPSMu4DeRwdjR7mTpKxQp7jxXFAZLf4FxdHsQByFHNFk");
         { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
         { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
         { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        { pubkey: nftStake, isSigner: false, isWritable: true },
       ],
     });
 
@@ -31,6 +31,13 @@ const vaultState = new PublicKey("9HwjRzkGd5VuxtCtRcyVt4NJiVkqBVkS5tZMTkBuB4zK")
 // SPL Mint address (set this to the token you deposited)
 const mint = new PublicKey("GKx8cKAqVA57oMd87YUtUQtLefDxHfVW2g23jR9cDGbS");
 
+// Seeds are "ledger", vaultState, mint. Optional: it is debited when passed,
+// and goes last, after the mint limit PDA if the vault has any limits.
+const [ledger] = PublicKey.findProgramAddressSync(
+  [Buffer.from("ledger"), vaultState.toBuffer(), mint.toBuffer()],
+  programId,
+);
+
 (async () => {
   try {
     const [vaultAuth] = PublicKey.findProgramAddressSync(
@@ -65,13 +72,14 @@ const mint = new PublicKey("GKx8cKAqVA57oMd87YUtUQtLefDxHfVW2g23jR9cDGbS");
       keys: [
         { pubkey: keypair.publicKey, isSigner: true, isWritable: true },
         { pubkey: ownerAta.address, isSigner: false, isWritable: true },
-        { pubkey: vaultState, isSigner: false, isWritable: false },
+        { pubkey: vaultState, isSigner: false, isWritable: true },
         { pubkey: vaultAuth, isSigner: false, isWritable: false },
         { pubkey: vaultAta.address, isSigner: false, isWritable: true },
         { pubkey: mint, isSigner: false, isWritable: false },
         { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
         { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
         { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        { pubkey: ledger, isSigner: false, isWritable: true },
       ],
     });
 